@@ -0,0 +1,243 @@
+// src/activity_metrics.rs
+//! Time-of-activity metrics in each author's local time: how much of the work happens outside working hours or
+//! on weekends, how the authors are spread over time zones, and how much of the day their working hours cover.
+use std::error::Error;
+use std::ops::Range;
+
+use chrono::{Datelike, NaiveDateTime, Timelike, Weekday};
+use git2::{Commit, Time};
+use indexmap::IndexMap;
+use serde::Serialize;
+
+use crate::metrics::{MetricProvider, Metrics, Window};
+use crate::output::{OutputFormat, TableWriter};
+use crate::utils::local_time;
+
+/// Working hours on weekdays, in local time
+const WORKING_HOURS: Range<u32> = 9..18;
+
+/// When something happened, in the local time of whoever did it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LocalTimeSlot {
+    WorkingHours,
+    /// A weekday, outside working hours
+    OffHours,
+    Weekend,
+}
+
+impl LocalTimeSlot {
+    pub fn of(local: NaiveDateTime) -> LocalTimeSlot {
+        match local.weekday() {
+            Weekday::Sat | Weekday::Sun => LocalTimeSlot::Weekend,
+            _ if WORKING_HOURS.contains(&local.hour()) => LocalTimeSlot::WorkingHours,
+            _ => LocalTimeSlot::OffHours,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct ActivityMetrics {
+    /// Share of commits made on weekdays outside working hours (9:00-18:00), in the author's local time
+    pub commits_off_hours_ratio: f64,
+    /// Share of commits made on Saturdays and Sundays, in the author's local time
+    pub commits_weekend_ratio: f64,
+    /// Number of distinct UTC offsets of the authors, each author at their most frequent offset
+    pub utc_offsets: usize,
+    /// Authors per UTC offset from west to east, e.g. `-05:00=1;+01:00=3`
+    pub utc_offset_distribution: String,
+    /// Hours between the westernmost and the easternmost authors' offsets
+    pub utc_offset_span_hours: f64,
+    /// Share of the 24 hours of a day that fall in the working hours of at least one author ("follow-the-sun")
+    pub follow_the_sun_coverage: f64,
+}
+
+impl ActivityMetrics {
+    pub fn new(commits: &[Commit]) -> ActivityMetrics {
+        let developers = developers(commits);
+        let ratio = |count: usize| {
+            if commits.is_empty() {
+                0.0
+            } else {
+                count as f64 / commits.len() as f64
+            }
+        };
+        let mut offsets = IndexMap::<i32, usize>::new();
+        for developer in developers.values() {
+            *offsets.entry(developer.utc_offset()).or_default() += 1;
+        }
+        offsets.sort_keys();
+        let covered_hours = (0..24)
+            .filter(|hour| offsets.keys().any(|offset| works_at(*offset, *hour)))
+            .count();
+        ActivityMetrics {
+            commits_off_hours_ratio: ratio(developers.values().map(|d| d.commits_off_hours).sum()),
+            commits_weekend_ratio: ratio(developers.values().map(|d| d.commits_weekend).sum()),
+            utc_offsets: offsets.len(),
+            utc_offset_distribution: format_offsets(&offsets),
+            utc_offset_span_hours: match (offsets.keys().next(), offsets.keys().last()) {
+                (Some(west), Some(east)) => (east - west) as f64 / 60.0,
+                _ => 0.0,
+            },
+            follow_the_sun_coverage: covered_hours as f64 / 24.0,
+        }
+    }
+}
+
+/// The commits of an author, by the local time they were made at
+#[derive(Default)]
+struct DeveloperActivity {
+    name: String,
+    commits: usize,
+    commits_off_hours: usize,
+    commits_weekend: usize,
+    /// Commits per UTC offset in minutes, in the order the offsets were first seen
+    offsets: IndexMap<i32, usize>,
+}
+
+impl DeveloperActivity {
+    fn record(&mut self, time: &Time) {
+        self.commits += 1;
+        match LocalTimeSlot::of(local_time(time).naive_local()) {
+            LocalTimeSlot::OffHours => self.commits_off_hours += 1,
+            LocalTimeSlot::Weekend => self.commits_weekend += 1,
+            LocalTimeSlot::WorkingHours => {}
+        }
+        *self.offsets.entry(time.offset_minutes()).or_default() += 1;
+    }
+
+    /// The most frequent UTC offset, in minutes; the first one seen on ties
+    fn utc_offset(&self) -> i32 {
+        self.offsets
+            .iter()
+            .fold((0, 0), |best, (offset, commits)| if *commits > best.1 { (*offset, *commits) } else { best })
+            .0
+    }
+}
+
+/// The activity of each author of the commits, keyed by email
+fn developers(commits: &[Commit]) -> IndexMap<String, DeveloperActivity> {
+    let mut developers = IndexMap::<String, DeveloperActivity>::new();
+    for commit in commits {
+        let author = commit.author();
+        developers
+            .entry(author.email().unwrap_or("").to_string())
+            .or_insert_with(|| DeveloperActivity {
+                name: author.name().unwrap_or("").to_string(),
+                ..Default::default()
+            })
+            .record(&author.when());
+    }
+    developers
+}
+
+/// Whether an hour of the day (UTC) falls in the working hours of someone at a UTC offset, in minutes
+fn works_at(offset: i32, utc_hour: i32) -> bool {
+    let local_hour = (utc_hour * 60 + offset).rem_euclid(24 * 60) / 60;
+    WORKING_HOURS.contains(&(local_hour as u32))
+}
+
+fn format_offset(minutes: i32) -> String {
+    let sign = if minutes < 0 { '-' } else { '+' };
+    format!("{}{:02}:{:02}", sign, minutes.abs() / 60, minutes.abs() % 60)
+}
+
+/// Counts per UTC offset as `offset=count`, separated by `;`
+fn format_offsets(offsets: &IndexMap<i32, usize>) -> String {
+    offsets
+        .iter()
+        .map(|(offset, count)| format!("{}={}", format_offset(*offset), count))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// Time-of-activity metrics of a window's commits
+pub struct ActivityProvider;
+
+impl MetricProvider for ActivityProvider {
+    fn family(&self) -> &'static str {
+        "activity"
+    }
+
+    fn compute(&mut self, window: &Window, metrics: &mut Metrics) -> Result<(), Box<dyn Error>> {
+        metrics.activity_metrics = ActivityMetrics::new(window.commits);
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Default)]
+struct DeveloperProfile<'a> {
+    project: &'a str,
+    email: &'a str,
+    name: &'a str,
+    commits: usize,
+    /// The most frequent UTC offset
+    utc_offset: String,
+    /// Commits per UTC offset, e.g. `+01:00=40;+02:00=35` for an author who follows daylight saving time
+    utc_offsets: String,
+    commits_off_hours: usize,
+    commits_weekend: usize,
+    off_hours_ratio: f64,
+    weekend_ratio: f64,
+}
+
+/// Writes the activity profile of every author of the commits to the `stem` table: their UTC offsets, and how many
+/// of their commits were made outside working hours or on weekends, in their local time
+pub fn write_developer_profiles(
+    project: &str,
+    commits: &[Commit],
+    stem: &str,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = TableWriter::create(stem, format)?.with_template(&DeveloperProfile::default())?;
+    for (email, developer) in developers(commits).iter() {
+        writer.serialize(DeveloperProfile {
+            project,
+            email,
+            name: &developer.name,
+            commits: developer.commits,
+            utc_offset: format_offset(developer.utc_offset()),
+            utc_offsets: format_offsets(&developer.offsets),
+            commits_off_hours: developer.commits_off_hours,
+            commits_weekend: developer.commits_weekend,
+            off_hours_ratio: developer.commits_off_hours as f64 / developer.commits as f64,
+            weekend_ratio: developer.commits_weekend as f64 / developer.commits as f64,
+        })?;
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_repo::{repository, TestCommit};
+
+    #[test]
+    fn test_activity_in_local_time() {
+        let dir = tempfile::tempdir().unwrap();
+        // 2021-03-15 is a Monday; 2021-03-15 08:00 UTC is 09:00 in Paris and 03:00 in New York
+        let monday_8am_utc = 1615795200;
+        let commit = |email, seconds, offset| TestCommit { email, seconds, offset, ..TestCommit::default() };
+        let (repo, _) = repository(
+            dir.path(),
+            &[
+                commit("alice@example.org", monday_8am_utc, 60),
+                commit("bob@example.org", monday_8am_utc, -300),
+                // Saturday 2021-03-20, 10:00 in Paris
+                commit("alice@example.org", monday_8am_utc + 5 * 86400 + 3600, 60),
+            ],
+        );
+        let mut revwalk = repo.revwalk().unwrap();
+        revwalk.push_head().unwrap();
+        let commits: Vec<Commit> = revwalk.map(|id| repo.find_commit(id.unwrap()).unwrap()).collect();
+
+        let metrics = ActivityMetrics::new(&commits);
+        assert_eq!(metrics.commits_off_hours_ratio, 1.0 / 3.0);
+        assert_eq!(metrics.commits_weekend_ratio, 1.0 / 3.0);
+        assert_eq!(metrics.utc_offsets, 2);
+        assert_eq!(metrics.utc_offset_distribution, "-05:00=1;+01:00=1");
+        assert_eq!(metrics.utc_offset_span_hours, 6.0);
+        // 08:00-17:00 UTC for Paris and 14:00-23:00 UTC for New York
+        assert_eq!(metrics.follow_the_sun_coverage, 15.0 / 24.0);
+        assert_eq!(ActivityMetrics::new(&[]), ActivityMetrics::default());
+    }
+}
@@ -1,18 +1,196 @@
-struct ProjectStats {}
-
-impl ProjectStats {
-    pub fn checkout_master() -> Result<(), Err> {}
-    pub fn find_months() -> Result<(), Err> {}
-    pub fn analyze_commits() -> Result<(), Err> {}
-    pub fn analyze_emails() -> Resulst<(), Err> {}
-    pub fn compute_stats() -> Result<(), Err> {}
+// src/api.rs
+//! The analysis of a local Git repository as a library call, configured with a builder instead of command line
+//! flags:
+//!
+//! ```no_run
+//! use miner::WindowScheme;
+//!
+//! let analysis = miner::Miner::new("../../projects/git/hunter")
+//!     .dates("2020-11-14", "2025-02-05")
+//!     .window(WindowScheme::Quarterly)
+//!     .with_emails(false)
+//!     .run()
+//!     .unwrap();
+//! for metrics in analysis.metrics() {
+//!     println!("{} {}", metrics.measurement_month, metrics.commits);
+//! }
+//! ```
+use std::error::Error;
+use std::path::Path;
+
+use git2::Repository;
+
+use crate::dev_stats::{CommitFileMetrics, DevStats};
+use crate::metrics::{ImputationPolicy, Metrics};
+use crate::repo::Repo;
+use crate::sokrates_metrics::CodeAnalyzer;
+use crate::statistics::Stats;
+use crate::windows::WindowScheme;
+use crate::{java_path, remove_sokrates_temp, Args};
+
+/// The analysis of a local Git repository. The repository is checked out at the last commit of each window
+/// for the code metrics, and reset to its main branch (main/master/trunk) at the end.
+pub struct Miner {
+    repo_path: String,
+    project: String,
+    status: String,
+    /// Start and end dates (Y-m-d); the first and last commit dates when not set
+    dates: Option<(String, String)>,
+    args: Args,
+}
+
+impl Miner {
+    /// Analyzes the repository at `repo_path`, named after its folder, with the command line defaults:
+    /// monthly windows, and every metric family
+    pub fn new(repo_path: &str) -> Miner {
+        let project = Path::new(repo_path)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        Miner {
+            repo_path: repo_path.to_string(),
+            project,
+            status: String::new(),
+            dates: None,
+            args: Args::defaults(),
+        }
+    }
+
+    /// The project's name, which also finds its mailing list archives
+    pub fn project(mut self, project: &str) -> Miner {
+        self.project = project.to_string();
+        self
+    }
+
+    /// The project's status, e.g. graduated or retired
+    pub fn status(mut self, status: &str) -> Miner {
+        self.status = status.to_string();
+        self
+    }
+
+    /// Only analyze the commits between these dates (Y-m-d)
+    pub fn dates(mut self, start_date: &str, end_date: &str) -> Miner {
+        self.dates = Some((start_date.to_string(), end_date.to_string()));
+        self
+    }
+
+    pub fn window(mut self, scheme: WindowScheme) -> Miner {
+        self.args.flag_window = Some(scheme);
+        self
+    }
+
+    /// Whether to compute the email metrics from the project's mailing list archives
+    pub fn with_emails(mut self, emails: bool) -> Miner {
+        self.args.flag_skip_email_analysis = !emails;
+        self
+    }
+
+    /// Whether to compute the Tokei code metrics; without them, Sokrates does not run either
+    pub fn with_tokei(mut self, tokei: bool) -> Miner {
+        self.args.flag_skip_tokei = !tokei;
+        self
+    }
+
+    /// Whether to compute the Sokrates metrics, which need java
+    pub fn with_sokrates(mut self, sokrates: bool) -> Miner {
+        self.args.flag_skip_sokrates = !sokrates;
+        self
+    }
+
+    /// What computes the Sokrates metrics; `CodeAnalyzer::Native` does not need java
+    pub fn code_analyzer(mut self, analyzer: CodeAnalyzer) -> Miner {
+        self.args.flag_code_analyzer = Some(analyzer);
+        self
+    }
+
+    /// How the metric families a window does not observe are filled in
+    pub fn imputation(mut self, policy: ImputationPolicy) -> Miner {
+        self.args.flag_imputation = Some(policy);
+        self
+    }
+
+    /// Also compute the per-file metrics of every commit, as `--commit-devs-files` does
+    pub fn with_commit_files(mut self, commit_files: bool) -> Miner {
+        self.args.flag_commit_devs_files = commit_files;
+        self
+    }
+
+    /// Runs the analysis: the metrics of every window, and the commit file metrics if asked for
+    pub fn run(mut self) -> Result<Analysis, Box<dyn Error>> {
+        let (start_date, end_date) = match self.dates.take() {
+            Some(dates) => dates,
+            None => {
+                self.args.flag_ignore_start_end_dates = true;
+                (String::new(), String::new())
+            }
+        };
+        let git_repo = Repository::open(&self.repo_path)?;
+        remove_sokrates_temp(&git_repo);
+        let args = &self.args;
+        let mut repo = Repo::new(&git_repo, &self.project, &start_date, &end_date, &self.status, args)?;
+        repo.checkout_master_main_trunk(args)?;
+        let java_path = java_path();
+
+        let commit_files = if args.flag_commit_devs_files {
+            DevStats::new(&self.project, &repo, &java_path)
+                .compute_individual_dev_stats(args)?
+                .into_iter()
+                .map(|dev_stats| dev_stats.metrics)
+                .collect()
+        } else {
+            vec![]
+        };
+        let metrics = Stats::new(&self.project, repo.start_date, repo.end_date, &self.status, &java_path)
+            .compute_statistics(&mut repo, args)?
+            .into_iter()
+            .map(Stats::into_metrics)
+            .collect();
+        Ok(Analysis { metrics, commit_files })
+    }
+}
+
+/// The results of a `Miner` run
+pub struct Analysis {
+    metrics: Vec<Metrics>,
+    commit_files: Vec<CommitFileMetrics>,
+}
+
+impl Analysis {
+    /// The metrics of every window, in window order
+    pub fn metrics(&self) -> impl Iterator<Item = &Metrics> {
+        self.metrics.iter()
+    }
+
+    /// One row per file changed by a commit, in window order; empty unless `with_commit_files` was set
+    pub fn commit_files(&self) -> impl Iterator<Item = &CommitFileMetrics> {
+        self.commit_files.iter()
+    }
 }
 
-fn test() {
-    let stats = ProjectStats()
-        .checkout_master()?
-        .find_months()?
-        .analyze_commits()?
-        .analyze_emails()?
-        .compute_stats()?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_repo::{repository, TestCommit};
+
+    #[test]
+    fn test_run_without_a_command_line() {
+        let dir = tempfile::tempdir().unwrap();
+        // 2021-03-15
+        repository(
+            dir.path(),
+            &[TestCommit { message: "add a", files: vec![("a.txt", "one\ntwo\n")], ..TestCommit::default() }],
+        );
+
+        let analysis = Miner::new(dir.path().to_str().unwrap())
+            .dates("2021-03-01", "2021-04-30")
+            .with_emails(false)
+            .with_tokei(false)
+            .with_commit_files(true)
+            .run()
+            .unwrap();
+        let windows: Vec<(usize, usize)> = analysis.metrics().map(|m| (m.measurement_month, m.commits)).collect();
+        assert_eq!(windows, vec![(1, 1), (2, 0)]);
+        let files: Vec<(&str, usize)> = analysis.commit_files().map(|f| (f.filename.as_str(), f.lines_added)).collect();
+        assert_eq!(files, vec![("a.txt", 2)]);
+    }
 }
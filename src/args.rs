@@ -0,0 +1,254 @@
+// src/args.rs
+//! The command line flags. Library callers that do not parse a command line get the defaults of every flag
+//! from `Args::defaults`, as `Miner` does. The subcommands of `cli` set these flags; without a subcommand they are
+//! the deprecated command line.
+use structopt::clap::AppSettings;
+use structopt::StructOpt;
+
+use crate::{cli, diagnostics, forge, metrics, output, sokrates_metrics, windows};
+
+#[derive(StructOpt)]
+#[structopt(setting = AppSettings::ArgsNegateSubcommands)]
+/// Mines the commits, emails, issues and code metrics of open source projects. Run one of the subcommands; the
+/// flags without a subcommand are deprecated aliases of them.
+pub struct Args {
+    #[structopt(subcommand)]
+    pub command: Option<cli::Command>,
+
+    // Existing flags
+
+    #[structopt(name = "force-full-analysis", long)]
+    /// Force to run a full analysis for those projects that do not have pre incubation commits. So we will only have during and post incubation analysis
+    pub flag_force_full_analysis: bool,
+
+    #[structopt(name = "full-analysis", long)]
+    /// Run a full analysis - pre/during/post incubation. This only runs on repositories that have previous/post incubation period commits
+    pub flag_full_analysis: bool,
+
+    #[structopt(name = "threads", long)]
+    /// Number of threads
+    pub flag_threads: Option<usize>,
+
+    #[structopt(name = "download-emails", long)]
+    /// Download all projects' emails
+    pub flag_download_emails: bool,
+
+    #[structopt(name = "project", long)]
+    /// Only parse given project
+    pub flag_parse_single_project: Option<String>,
+
+    #[structopt(name = "list-projects", long)]
+    /// Only show projects
+    pub flag_list_projects: bool,
+
+    #[structopt(name = "skip-tokei", long)]
+    /// Skip tokei & folder analysis
+    pub flag_skip_tokei: bool,
+
+    #[structopt(name = "skip-sokrates", long)]
+    /// Skip sokrates analysis
+    pub flag_skip_sokrates: bool,
+
+    #[structopt(name = "code-analyzer", long)]
+    /// What computes the Sokrates metrics: sokrates (the default, the Sokrates JAR, which needs java) or native (an
+    /// in-process analyzer with the same metrics, which leaves nothing in the repository)
+    pub flag_code_analyzer: Option<sokrates_metrics::CodeAnalyzer>,
+
+    #[structopt(name = "sokrates-jvm-args", long)]
+    /// JVM arguments of the Sokrates runs, separated by spaces (default: "-Xmx2g -Xms2g")
+    pub flag_sokrates_jvm_args: Option<String>,
+
+    #[structopt(name = "sokrates-timeout", long)]
+    /// Seconds after which a Sokrates command is killed and its window skipped (default: 3600)
+    pub flag_sokrates_timeout: Option<u64>,
+
+    #[structopt(name = "sokrates-cache", long)]
+    /// Folder caching the Sokrates results of each analyzed commit (default: sokrates-cache in the output folder)
+    pub flag_sokrates_cache: Option<String>,
+
+    #[structopt(name = "imputation", long)]
+    /// How to fill in the metrics of windows where they were not observed: carry-forward, zero or empty, for all
+    /// families or per family (emails, tokei, sokrates), e.g. tokei:carry-forward,sokrates:empty,emails:zero
+    pub flag_imputation: Option<metrics::ImputationPolicy>,
+
+    #[structopt(name = "skip-emails", long)]
+    /// Skip email analysis
+    pub flag_skip_email_analysis: bool,
+
+    #[structopt(name = "export-email-bodies", long)]
+    /// Export the cleaned body of every email (quoted text, signatures, patches, code and stack traces removed) for NLP
+    pub flag_export_email_bodies: bool,
+
+    #[structopt(name = "sentiment", long)]
+    /// Score sentiment and incivility of issues, comments and commit messages, and write the per-message scores
+    pub flag_sentiment: bool,
+
+    #[structopt(name = "commit-messages", long)]
+    /// Extract commit messages
+    pub flag_commit_messages: bool,
+
+    #[structopt(name = "missing-emails", long)]
+    /// Check for any missing email archives
+    pub flag_missing_emails: bool,
+
+    #[structopt(name = "supported-languages", long)]
+    /// Print supported languages
+    pub flag_print_supported_languages: bool,
+
+    #[structopt(name = "restrict-languages", long)]
+    /// Restrict supported languages
+    pub flag_restrict_languages: bool,
+
+    #[structopt(name = "manual-test", long)]
+    /// Manual test project
+    pub flag_manual_test_project: Option<String>,
+
+    #[structopt(name = "output-folder", long)]
+    /// Set output folder, otherwise default is data
+    pub flag_output_folder: Option<String>,
+
+    #[structopt(name = "format", long)]
+    /// Format of the metrics tables: csv (default), parquet or jsonl
+    pub flag_format: Option<output::OutputFormat>,
+
+    #[structopt(name = "sqlite", long)]
+    /// Also write the analysis to this SQLite database, one transaction per project
+    pub flag_sqlite: Option<String>,
+
+    #[structopt(name = "quality-thresholds", long)]
+    /// Fail the run (exit code 2) when a project's data-quality report exceeds these thresholds: the share of
+    /// windows-without-commits and imputed-windows, and the number of skipped-windows, unparseable messages and
+    /// errors, e.g. skipped-windows:0,imputed-windows:0.2,errors:0
+    pub flag_quality_thresholds: Option<diagnostics::Thresholds>,
+
+    #[structopt(name = "progress-file", long)]
+    /// Keep the progress of a multi-project run in this JSON file: projects done out of the total, the window each
+    /// running project is at, commits per second and the estimated time left
+    pub flag_progress_file: Option<String>,
+
+    #[structopt(name = "metadata-filepath", long)]
+    /// Path to project's metadata
+    pub flag_metadata_filepath: Option<String>,
+
+    #[structopt(name = "commit-devs-files", long)]
+    /// Return a list of commits and the files changed in each file together with who changed the file
+    pub flag_commit_devs_files: bool,
+
+    #[structopt(name = "time-window", long)]
+    /// Instead of analyzing by default incubation-months (start-date until end of month, and then monthly basis),
+    /// analyze per a time window provided by the user. For example time-window=10, each incubation month will be 10 days
+    pub flag_time_window: Option<i64>,
+
+    #[structopt(name = "window", long, conflicts_with = "time-window")]
+    /// How to split the project's lifetime into windows, for every analysis: month (the default incubation months),
+    /// week (ISO weeks), quarter, days:N (N-day windows, like time-window), sliding:N:M (N-day windows starting every
+    /// M days, overlapping when M < N) or release (from one release tag to the next)
+    pub flag_window: Option<windows::WindowScheme>,
+
+    #[structopt(name = "incubation-dates", long)]
+    /// Print for each incubation month (or window), the start and end date
+    pub flag_print_incubation_dates: bool,
+
+    #[structopt(name = "ignore-start-end-date", long)]
+    /// Ignore the start and end date from the Excel sheet input, and run from the first to the last commit in the project
+    pub flag_ignore_start_end_dates: bool,
+
+    #[structopt(name = "ignore-commit-message", long)]
+    /// Use this option together with the commit-devs option, to ignore the commit messages and not send them to output
+    pub flag_ignore_commit_message: bool,
+
+    #[structopt(name = "git-folder", long)]
+    /// Use this option to provide a git folder with the projects. You likely also want to use the flag ignore start end dates
+    pub flag_git_folder: Option<String>,
+
+    #[structopt(name = "fetch-github-issues", long)]
+    /// Fetch GitHub issues and comments for projects or a single repository
+    pub flag_fetch_github_issues: bool,
+
+    #[structopt(name = "fetch-github-pull-requests", long)]
+    /// Fetch GitHub pull requests, reviews, review comments and per-month PR metrics for projects or a single repository
+    pub flag_fetch_github_pull_requests: bool,
+
+    #[structopt(name = "fetch-jira-issues", long)]
+    /// Fetch JIRA issues, comments and changelogs of the project given with --jira-url
+    pub flag_fetch_jira_issues: bool,
+
+    #[structopt(name = "jira-url", long)]
+    /// JIRA project URL (e.g., https://issues.apache.org/jira/browse/HUNTER)
+    pub flag_jira_url: Option<String>,
+
+    #[structopt(name = "jira-export", long)]
+    /// Read the JIRA issues from an XML or JSON search export instead of the JIRA REST API
+    pub flag_jira_export: Option<String>,
+
+    #[structopt(name = "github-offline", long)]
+    /// Generate the GitHub issue exports from the local issue store without fetching updates
+    pub flag_github_offline: bool,
+
+    #[structopt(name = "github-output-folder", long)]
+    /// Specify the output folder for GitHub issues CSV file
+    pub flag_github_output_folder: Option<String>,
+
+    #[structopt(name = "github-url", long)]
+    /// Repository URL to fetch issues from, on GitHub, GitLab or Gitea/Codeberg (e.g., https://github.com/apache/hunter.git)
+    pub flag_github_url: Option<String>,
+
+    #[structopt(name = "forge", long)]
+    /// Forge of the repository URLs on other hosts than github.com, gitlab.com, gitlab.gnome.org,
+    /// gitlab.freedesktop.org, codeberg.org and gitea.com, such as self-hosted instances: github, gitlab or gitea
+    pub flag_forge: Option<forge::ForgeKind>,
+
+    // ==== NEW FLAGS FOR ONLINE COMMIT ANALYSIS ====
+    #[structopt(name = "git-online-url", long)]
+    /// Provide an online Git repository URL (.git) to clone and analyze commits
+    pub flag_git_online_url: Option<String>,
+
+    #[structopt(name = "git-online-urls", long)]
+    /// File of online repository URLs to analyze in parallel, one `url[,start_date,end_date,status]` per line
+    pub flag_git_online_urls: Option<String>,
+
+    #[structopt(name = "clone-cache", long)]
+    /// Keep bare mirrors of online repositories in this folder and fetch their updates instead of cloning again
+    pub flag_clone_cache: Option<String>,
+
+    #[structopt(name = "shallow-clone", long)]
+    /// Only clone the history since --online-start-date (needs the git command line)
+    pub flag_shallow_clone: bool,
+
+    #[structopt(name = "blobless-clone", long)]
+    /// Clone without file contents (needs the git command line); the CSV then lists the changed files without line counts
+    pub flag_blobless_clone: bool,
+
+    #[structopt(name = "commit-graphql", long)]
+    /// Use GraphQL API to fetch commit metrics for the online repository instead of cloning
+    pub flag_commit_graphql: bool,
+
+    #[structopt(name = "online-start-date", long)]
+    /// Start date for online repository analysis (format YYYY-MM-DD)
+    pub flag_online_start_date: Option<String>,
+
+    #[structopt(name = "online-end-date", long)]
+    /// End date for online repository analysis (format YYYY-MM-DD)
+    pub flag_online_end_date: Option<String>,
+
+    #[structopt(name = "online-status", long)]
+    /// Status for online repository analysis (e.g., graduated, retired)
+    pub flag_online_status: Option<String>,
+
+    // ==== NEW FLAG: Developer statistics grouped output ====
+    #[structopt(name = "dev-stats-grouped", long)]
+    /// If set, write separate CSV files grouped by developer (per incubation month)
+    pub flag_dev_stats_grouped: bool,
+
+    // ==== NEW FLAG: Issue statistics grouped output ====
+    #[structopt(name = "issue-stats-grouped", long)]
+    /// If set, write separate CSV files grouped by developer (per month) for issues and comments
+    pub flag_issue_stats_grouped: bool,
+}
+
+impl Args {
+    /// The flags of a command line without any flag
+    pub fn defaults() -> Args {
+        Args::from_iter(["miner"])
+    }
+}
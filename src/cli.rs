@@ -0,0 +1,694 @@
+// src/cli.rs
+//! The subcommands of the command line: `miner stats`, `miner devs`, `miner emails download|check|analyze`,
+//! `miner issues`, `miner online`, `miner dates` and `miner languages`. Each subcommand only takes the options that
+//! apply to it and sets the matching `Args` flags, which the analyses read. Without a subcommand, the flags of the
+//! old command line select the same analyses for a deprecation period, and combinations that used to be silently
+//! ignored are errors.
+use structopt::StructOpt;
+
+use crate::{diagnostics, forge, metrics, output, sokrates_metrics, windows, Args};
+
+#[derive(StructOpt)]
+pub enum Command {
+    /// Metrics of every window of each project: commits, releases, activity, emails and the code metrics
+    Stats(StatsOptions),
+    /// The files changed by every commit of each project and who changed them, or the commit messages
+    Devs(DevsOptions),
+    /// The dev mailing list archives of each project
+    Emails(EmailsCommand),
+    /// Issues and comments of a forge repository or a JIRA project, or the pull requests of a forge repository
+    Issues(IssuesOptions),
+    /// Online repositories, cloned or over the forge API
+    Online(OnlineOptions),
+    /// Write the start and end dates of every window of each project to incubation-dates-{scheme}.csv
+    Dates(ProjectOptions),
+    /// Print the languages whose files are analyzed
+    Languages,
+}
+
+#[derive(StructOpt)]
+pub enum EmailsCommand {
+    /// Download the monthly archives that are not downloaded yet
+    Download(ProjectOptions),
+    /// Report the monthly archives that are missing or empty
+    Check(ProjectOptions),
+    /// Export the cleaned body of every email (quoted text, signatures, patches, code and stack traces removed) for NLP
+    Analyze(EmailsAnalyzeOptions),
+}
+
+/// The local projects to analyze, and their windows
+#[derive(StructOpt)]
+pub struct ProjectOptions {
+    #[structopt(name = "git-folder", long, conflicts_with = "metadata-filepath", requires = "ignore-start-end-date")]
+    /// Folder of the projects' git repositories; they have no start and end dates, so this needs
+    /// --ignore-start-end-date
+    pub git_folder: Option<String>,
+
+    #[structopt(name = "metadata-filepath", long)]
+    /// Spreadsheet of the projects, with their repository, status and incubation dates
+    pub metadata_filepath: Option<String>,
+
+    #[structopt(name = "project", long)]
+    /// Only analyze this project
+    pub project: Option<String>,
+
+    #[structopt(name = "ignore-start-end-date", long)]
+    /// Analyze from the first to the last commit of each project instead of its metadata dates
+    pub ignore_start_end_dates: bool,
+
+    #[structopt(name = "window", long)]
+    /// How to split each project's lifetime into windows: month (the default incubation months), week, quarter,
+    /// days:N, sliding:N:M or release
+    pub window: Option<windows::WindowScheme>,
+
+    #[structopt(name = "restrict-languages", long)]
+    /// Only analyze the files of the languages in tokei.toml
+    pub restrict_languages: bool,
+
+    #[structopt(name = "threads", long)]
+    /// Number of projects analyzed at a time (default 4)
+    pub threads: Option<usize>,
+
+    #[structopt(name = "output-folder", long)]
+    /// Output folder (default data)
+    pub output_folder: Option<String>,
+}
+
+impl ProjectOptions {
+    fn apply(self, args: &mut Args) {
+        args.flag_git_folder = self.git_folder;
+        args.flag_metadata_filepath = self.metadata_filepath;
+        args.flag_parse_single_project = self.project;
+        args.flag_ignore_start_end_dates = self.ignore_start_end_dates;
+        args.flag_window = self.window;
+        args.flag_restrict_languages = self.restrict_languages;
+        args.flag_threads = self.threads;
+        args.flag_output_folder = self.output_folder;
+    }
+}
+
+/// The metric families of the window metrics
+#[derive(StructOpt)]
+pub struct MetricsOptions {
+    #[structopt(name = "skip-emails", long)]
+    /// Skip the email metrics
+    pub skip_emails: bool,
+
+    #[structopt(name = "skip-tokei", long)]
+    /// Skip the checkouts, and so the Tokei and Sokrates metrics
+    pub skip_tokei: bool,
+
+    #[structopt(name = "skip-sokrates", long)]
+    /// Skip the Sokrates metrics
+    pub skip_sokrates: bool,
+
+    #[structopt(name = "code-analyzer", long)]
+    /// What computes the Sokrates metrics: sokrates (the default, needs java) or native
+    pub code_analyzer: Option<sokrates_metrics::CodeAnalyzer>,
+
+    #[structopt(name = "sokrates-jvm-args", long)]
+    /// JVM arguments of the Sokrates runs, separated by spaces (default: "-Xmx2g -Xms2g")
+    pub sokrates_jvm_args: Option<String>,
+
+    #[structopt(name = "sokrates-timeout", long)]
+    /// Seconds after which a Sokrates command is killed (default: 3600)
+    pub sokrates_timeout: Option<u64>,
+
+    #[structopt(name = "sokrates-cache", long)]
+    /// Folder caching the Sokrates results of each analyzed commit (default: sokrates-cache in the output folder)
+    pub sokrates_cache: Option<String>,
+
+    #[structopt(name = "imputation", long)]
+    /// How to fill in the metrics of windows where they were not observed: carry-forward, zero or empty, for all
+    /// families or per family (emails, tokei, sokrates), e.g. tokei:carry-forward,sokrates:empty,emails:zero
+    pub imputation: Option<metrics::ImputationPolicy>,
+}
+
+impl MetricsOptions {
+    fn apply(self, args: &mut Args) {
+        args.flag_skip_email_analysis = self.skip_emails;
+        args.flag_skip_tokei = self.skip_tokei;
+        args.flag_skip_sokrates = self.skip_sokrates;
+        args.flag_code_analyzer = self.code_analyzer;
+        args.flag_sokrates_jvm_args = self.sokrates_jvm_args;
+        args.flag_sokrates_timeout = self.sokrates_timeout;
+        args.flag_sokrates_cache = self.sokrates_cache;
+        args.flag_imputation = self.imputation;
+    }
+}
+
+#[derive(StructOpt)]
+pub struct OutputOptions {
+    #[structopt(name = "format", long)]
+    /// Format of the metrics tables: csv (default), parquet or jsonl
+    pub format: Option<output::OutputFormat>,
+
+    #[structopt(name = "sqlite", long)]
+    /// Also write the analysis to this SQLite database, one transaction per project
+    pub sqlite: Option<String>,
+
+    #[structopt(name = "quality-thresholds", long)]
+    /// Fail the run (exit code 2) when a project's data-quality report exceeds these thresholds, e.g.
+    /// skipped-windows:0,imputed-windows:0.2,errors:0
+    pub quality_thresholds: Option<diagnostics::Thresholds>,
+
+    #[structopt(name = "progress-file", long)]
+    /// Keep the progress of the run in this JSON file, for headless servers
+    pub progress_file: Option<String>,
+}
+
+impl OutputOptions {
+    fn apply(self, args: &mut Args) {
+        args.flag_format = self.format;
+        args.flag_sqlite = self.sqlite;
+        args.flag_quality_thresholds = self.quality_thresholds;
+        args.flag_progress_file = self.progress_file;
+    }
+}
+
+#[derive(StructOpt)]
+pub struct StatsOptions {
+    #[structopt(flatten)]
+    pub projects: ProjectOptions,
+
+    #[structopt(flatten)]
+    pub metrics: MetricsOptions,
+
+    #[structopt(flatten)]
+    pub output: OutputOptions,
+
+    #[structopt(name = "pre-post", long)]
+    /// Analyze the pre-, during and post-incubation periods separately, for the projects with commits before and
+    /// after incubation
+    pub pre_post: bool,
+
+    #[structopt(name = "force", long, requires = "pre-post")]
+    /// Also analyze the during and post-incubation periods of the projects without commits before incubation
+    pub force: bool,
+}
+
+#[derive(StructOpt)]
+pub struct DevsOptions {
+    #[structopt(flatten)]
+    pub projects: ProjectOptions,
+
+    #[structopt(flatten)]
+    pub output: OutputOptions,
+
+    #[structopt(name = "grouped", long, conflicts_with = "messages")]
+    /// Write one CSV per developer and window instead of one table of all commits
+    pub grouped: bool,
+
+    #[structopt(name = "ignore-commit-message", long, conflicts_with = "messages")]
+    /// Leave the commit messages out of the commit file table
+    pub ignore_commit_message: bool,
+
+    #[structopt(name = "messages", long)]
+    /// Write the commit messages instead of the changed files
+    pub messages: bool,
+
+    #[structopt(name = "sentiment", long, requires = "messages")]
+    /// Score the sentiment and incivility of the commit messages
+    pub sentiment: bool,
+}
+
+#[derive(StructOpt)]
+pub struct EmailsAnalyzeOptions {
+    #[structopt(flatten)]
+    pub projects: ProjectOptions,
+
+    #[structopt(name = "format", long)]
+    /// Format of the email tables: csv (default), parquet or jsonl
+    pub format: Option<output::OutputFormat>,
+
+    #[structopt(name = "sqlite", long)]
+    /// Also write the emails to this SQLite database
+    pub sqlite: Option<String>,
+}
+
+#[derive(StructOpt)]
+pub struct IssuesOptions {
+    #[structopt(name = "url", long)]
+    /// Repository on GitHub, GitLab or Gitea/Codeberg (e.g. https://github.com/apache/hunter.git); without it, the
+    /// repositories of the metadata spreadsheet
+    pub url: Option<String>,
+
+    #[structopt(name = "metadata-filepath", long, conflicts_with_all = &["url", "jira-url"])]
+    /// Spreadsheet of the projects, with their repository URL
+    pub metadata_filepath: Option<String>,
+
+    #[structopt(name = "forge", long, conflicts_with = "jira-url")]
+    /// Forge of a self-hosted repository, whose host does not tell it: github, gitlab or gitea
+    pub forge: Option<forge::ForgeKind>,
+
+    #[structopt(name = "jira-url", long, conflicts_with_all = &["url", "pull-requests", "grouped", "offline"])]
+    /// JIRA project to fetch the issues, comments and changelogs of (e.g. https://issues.apache.org/jira/browse/HUNTER)
+    pub jira_url: Option<String>,
+
+    #[structopt(name = "jira-export", long, requires = "jira-url")]
+    /// Read the JIRA issues from an XML or JSON search export instead of the JIRA REST API
+    pub jira_export: Option<String>,
+
+    #[structopt(name = "pull-requests", long, conflicts_with_all = &["grouped", "offline", "sentiment"])]
+    /// Fetch the pull requests, reviews, review comments and per-month pull request metrics instead of the issues
+    pub pull_requests: bool,
+
+    #[structopt(name = "grouped", long, conflicts_with = "sentiment")]
+    /// Write one CSV of issues and comments per developer and month
+    pub grouped: bool,
+
+    #[structopt(name = "offline", long)]
+    /// Write the issues from the local issue store without fetching updates
+    pub offline: bool,
+
+    #[structopt(name = "sentiment", long)]
+    /// Score the sentiment and incivility of the issues and comments
+    pub sentiment: bool,
+
+    #[structopt(name = "output-folder", long)]
+    /// Output folder (default github_issues, github_pull_requests or jira_issues)
+    pub output_folder: Option<String>,
+
+    #[structopt(name = "format", long)]
+    /// Format of the issue, comment and metrics tables: csv (default), parquet or jsonl
+    pub format: Option<output::OutputFormat>,
+
+    #[structopt(name = "sqlite", long)]
+    /// Also write the issues and comments to this SQLite database
+    pub sqlite: Option<String>,
+}
+
+#[derive(StructOpt)]
+pub struct OnlineOptions {
+    #[structopt(name = "url", long, required_unless = "urls", conflicts_with = "urls")]
+    /// Online Git repository to clone and analyze
+    pub url: Option<String>,
+
+    #[structopt(name = "urls", long)]
+    /// File of online repositories to analyze in parallel, one `url[,start_date,end_date,status]` per line
+    pub urls: Option<String>,
+
+    #[structopt(name = "start-date", long)]
+    /// Start date of the repositories without their own (YYYY-MM-DD)
+    pub start_date: Option<String>,
+
+    #[structopt(name = "end-date", long)]
+    /// End date of the repositories without their own (YYYY-MM-DD)
+    pub end_date: Option<String>,
+
+    #[structopt(name = "status", long)]
+    /// Status of the repositories without their own (e.g. graduated, retired)
+    pub status: Option<String>,
+
+    #[structopt(name = "forge", long)]
+    /// Forge of self-hosted repositories, whose host does not tell it: github, gitlab or gitea
+    pub forge: Option<forge::ForgeKind>,
+
+    #[structopt(name = "ignore-start-end-date", long)]
+    /// Analyze from the first to the last commit of each repository instead of its dates
+    pub ignore_start_end_dates: bool,
+
+    #[structopt(name = "window", long)]
+    /// How to split each repository's lifetime into windows: month (the default), week, quarter, days:N,
+    /// sliding:N:M or release
+    pub window: Option<windows::WindowScheme>,
+
+    #[structopt(name = "devs", long)]
+    /// Write the files changed by every commit and who changed them, instead of the window metrics
+    pub devs: bool,
+
+    #[structopt(
+        name = "graphql",
+        long,
+        requires = "devs",
+        conflicts_with_all = &["clone-cache", "shallow-clone", "blobless-clone"]
+    )]
+    /// Fetch the commits over the forge API instead of cloning
+    pub graphql: bool,
+
+    #[structopt(name = "ignore-commit-message", long, requires = "devs")]
+    /// Leave the commit messages out of the commit file table
+    pub ignore_commit_message: bool,
+
+    #[structopt(name = "clone-cache", long)]
+    /// Keep bare mirrors of the repositories in this folder and fetch their updates instead of cloning again
+    pub clone_cache: Option<String>,
+
+    #[structopt(name = "shallow-clone", long)]
+    /// Only clone the history since the start date (needs the git command line)
+    pub shallow_clone: bool,
+
+    #[structopt(name = "blobless-clone", long, requires = "devs")]
+    /// Clone without file contents (needs the git command line); the changed files are listed without line counts
+    pub blobless_clone: bool,
+
+    #[structopt(name = "threads", long)]
+    /// Number of repositories analyzed at a time (default 4)
+    pub threads: Option<usize>,
+
+    #[structopt(name = "output-folder", long)]
+    /// Output folder (default data)
+    pub output_folder: Option<String>,
+
+    #[structopt(flatten)]
+    pub metrics: MetricsOptions,
+
+    #[structopt(flatten)]
+    pub output: OutputOptions,
+}
+
+impl Command {
+    /// Sets the options on the flags the analyses read, and returns the mode the command runs
+    fn apply(self, args: &mut Args) -> Mode {
+        match self {
+            Command::Stats(options) => {
+                options.projects.apply(args);
+                options.metrics.apply(args);
+                options.output.apply(args);
+                args.flag_force_full_analysis = options.force;
+                if options.pre_post {
+                    Mode::PrePost
+                } else {
+                    Mode::Stats
+                }
+            }
+            Command::Devs(options) => {
+                options.projects.apply(args);
+                options.output.apply(args);
+                args.flag_dev_stats_grouped = options.grouped;
+                args.flag_ignore_commit_message = options.ignore_commit_message;
+                args.flag_sentiment = options.sentiment;
+                if options.messages {
+                    Mode::CommitMessages
+                } else {
+                    args.flag_commit_devs_files = true;
+                    Mode::Devs
+                }
+            }
+            Command::Emails(EmailsCommand::Download(projects)) => {
+                projects.apply(args);
+                Mode::EmailsDownload
+            }
+            Command::Emails(EmailsCommand::Check(projects)) => {
+                projects.apply(args);
+                Mode::EmailsCheck
+            }
+            Command::Emails(EmailsCommand::Analyze(options)) => {
+                options.projects.apply(args);
+                args.flag_format = options.format;
+                args.flag_sqlite = options.sqlite;
+                Mode::EmailsAnalyze
+            }
+            Command::Issues(options) => {
+                args.flag_github_url = options.url;
+                args.flag_forge = options.forge;
+                args.flag_metadata_filepath = options.metadata_filepath;
+                args.flag_jira_export = options.jira_export;
+                args.flag_issue_stats_grouped = options.grouped;
+                args.flag_github_offline = options.offline;
+                args.flag_sentiment = options.sentiment;
+                args.flag_github_output_folder = options.output_folder;
+                args.flag_format = options.format;
+                args.flag_sqlite = options.sqlite;
+                if options.jira_url.is_some() {
+                    args.flag_jira_url = options.jira_url;
+                    Mode::JiraIssues
+                } else if options.pull_requests {
+                    Mode::PullRequests
+                } else {
+                    Mode::Issues
+                }
+            }
+            Command::Online(options) => {
+                args.flag_git_online_url = options.url;
+                args.flag_git_online_urls = options.urls;
+                args.flag_online_start_date = options.start_date;
+                args.flag_online_end_date = options.end_date;
+                args.flag_online_status = options.status;
+                args.flag_forge = options.forge;
+                args.flag_ignore_start_end_dates = options.ignore_start_end_dates;
+                args.flag_window = options.window;
+                args.flag_commit_devs_files = options.devs;
+                args.flag_commit_graphql = options.graphql;
+                args.flag_ignore_commit_message = options.ignore_commit_message;
+                args.flag_clone_cache = options.clone_cache;
+                args.flag_shallow_clone = options.shallow_clone;
+                args.flag_blobless_clone = options.blobless_clone;
+                args.flag_threads = options.threads;
+                args.flag_output_folder = options.output_folder;
+                options.metrics.apply(args);
+                options.output.apply(args);
+                Mode::Online
+            }
+            Command::Dates(projects) => {
+                projects.apply(args);
+                Mode::Dates
+            }
+            Command::Languages => Mode::Languages,
+        }
+    }
+}
+
+/// What a run does, selected by a subcommand or by the flags of the old command line
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Stats,
+    PrePost,
+    Devs,
+    CommitMessages,
+    EmailsDownload,
+    EmailsCheck,
+    EmailsAnalyze,
+    Issues,
+    PullRequests,
+    JiraIssues,
+    Online,
+    Dates,
+    Languages,
+    ListProjects,
+    ManualTest,
+}
+
+impl Mode {
+    /// The mode of the subcommand, after setting its options on the flags, or the mode of the old flags
+    pub fn resolve(args: &mut Args) -> Result<Mode, String> {
+        if let Some(command) = args.command.take() {
+            return Ok(command.apply(args));
+        }
+        let mode = legacy_mode(args)?;
+        if let Some(subcommand) = mode.subcommand() {
+            log::warn!("The command line without a subcommand is deprecated; use `miner {}` instead", subcommand);
+        }
+        if args.flag_force_full_analysis && mode != Mode::PrePost {
+            log::warn!("--force-full-analysis has no effect without --full-analysis");
+        }
+        Ok(mode)
+    }
+
+    /// The subcommand that runs this mode; the project listing and the manual test only have their flags
+    pub fn subcommand(self) -> Option<&'static str> {
+        match self {
+            Mode::Stats => Some("stats"),
+            Mode::PrePost => Some("stats --pre-post"),
+            Mode::Devs => Some("devs"),
+            Mode::CommitMessages => Some("devs --messages"),
+            Mode::EmailsDownload => Some("emails download"),
+            Mode::EmailsCheck => Some("emails check"),
+            Mode::EmailsAnalyze => Some("emails analyze"),
+            Mode::Issues => Some("issues"),
+            Mode::PullRequests => Some("issues --pull-requests"),
+            Mode::JiraIssues => Some("issues --jira-url"),
+            Mode::Online => Some("online"),
+            Mode::Dates => Some("dates"),
+            Mode::Languages => Some("languages"),
+            Mode::ListProjects | Mode::ManualTest => None,
+        }
+    }
+}
+
+/// The mode selected by the flags of the old command line. Flags selecting different modes, and flags that do not
+/// apply to the selected mode, are errors instead of being ignored.
+fn legacy_mode(args: &Args) -> Result<Mode, String> {
+    let online = args.flag_git_online_url.is_some() || args.flag_git_online_urls.is_some();
+    let selected: Vec<(&str, Mode)> = [
+        (args.flag_fetch_github_issues, "--fetch-github-issues", Mode::Issues),
+        (args.flag_fetch_jira_issues, "--fetch-jira-issues", Mode::JiraIssues),
+        (args.flag_fetch_github_pull_requests, "--fetch-github-pull-requests", Mode::PullRequests),
+        (online, "--git-online-url", Mode::Online),
+        (args.flag_list_projects, "--list-projects", Mode::ListProjects),
+        (args.flag_missing_emails, "--missing-emails", Mode::EmailsCheck),
+        (args.flag_export_email_bodies, "--export-email-bodies", Mode::EmailsAnalyze),
+        (args.flag_print_incubation_dates, "--incubation-dates", Mode::Dates),
+        (args.flag_print_supported_languages, "--supported-languages", Mode::Languages),
+        (args.flag_commit_messages, "--commit-messages", Mode::CommitMessages),
+        (args.flag_manual_test_project.is_some(), "--manual-test", Mode::ManualTest),
+        (args.flag_download_emails, "--download-emails", Mode::EmailsDownload),
+        (args.flag_full_analysis, "--full-analysis", Mode::PrePost),
+    ]
+    .into_iter()
+    .filter(|(set, ..)| *set)
+    .map(|(_, flag, mode)| (flag, mode))
+    .collect();
+    let mode = match selected.as_slice() {
+        [] if args.flag_commit_devs_files => Mode::Devs,
+        [] => Mode::Stats,
+        [(_, mode)] => *mode,
+        [(first, _), (second, _), ..] => {
+            return Err(format!("{} and {} select different analyses; run them one at a time", first, second))
+        }
+    };
+
+    if args.flag_git_online_url.is_some() && args.flag_git_online_urls.is_some() {
+        return Err("--git-online-url and --git-online-urls cannot be used together".to_string());
+    }
+    if mode == Mode::JiraIssues && args.flag_jira_url.is_none() {
+        return Err("--fetch-jira-issues needs the project's --jira-url".to_string());
+    }
+    let local = !matches!(
+        mode,
+        Mode::Issues | Mode::PullRequests | Mode::JiraIssues | Mode::Online | Mode::Languages | Mode::ManualTest
+    );
+    if local && args.flag_git_folder.is_some() && !args.flag_ignore_start_end_dates {
+        return Err("the projects of --git-folder have no start and end dates; add --ignore-start-end-date".to_string());
+    }
+    let devs = mode == Mode::Devs || (mode == Mode::Online && args.flag_commit_devs_files);
+    let online_flags = [
+        (args.flag_clone_cache.is_some(), "--clone-cache"),
+        (args.flag_shallow_clone, "--shallow-clone"),
+        (args.flag_online_start_date.is_some(), "--online-start-date"),
+        (args.flag_online_end_date.is_some(), "--online-end-date"),
+        (args.flag_online_status.is_some(), "--online-status"),
+    ];
+    for (set, flag) in online_flags {
+        requires(set, flag, mode == Mode::Online, "--git-online-url or --git-online-urls")?;
+    }
+    requires(
+        args.flag_commit_devs_files,
+        "--commit-devs-files",
+        mode == Mode::Devs || mode == Mode::Online,
+        "a local or online commit analysis",
+    )?;
+    let online_devs = "--git-online-url and --commit-devs-files";
+    requires(args.flag_commit_graphql, "--commit-graphql", mode == Mode::Online && devs, online_devs)?;
+    requires(args.flag_blobless_clone, "--blobless-clone", mode == Mode::Online && devs, online_devs)?;
+    requires(args.flag_dev_stats_grouped, "--dev-stats-grouped", mode == Mode::Devs, "--commit-devs-files")?;
+    requires(args.flag_ignore_commit_message, "--ignore-commit-message", devs, "--commit-devs-files")?;
+    requires(args.flag_issue_stats_grouped, "--issue-stats-grouped", mode == Mode::Issues, "--fetch-github-issues")?;
+    requires(args.flag_github_offline, "--github-offline", mode == Mode::Issues, "--fetch-github-issues")?;
+    requires(
+        args.flag_github_url.is_some(),
+        "--github-url",
+        mode == Mode::Issues || mode == Mode::PullRequests,
+        "--fetch-github-issues or --fetch-github-pull-requests",
+    )?;
+    requires(
+        args.flag_forge.is_some(),
+        "--forge",
+        matches!(mode, Mode::Issues | Mode::PullRequests | Mode::Online),
+        "--fetch-github-issues, --fetch-github-pull-requests or --git-online-url",
+    )?;
+    requires(args.flag_jira_url.is_some(), "--jira-url", mode == Mode::JiraIssues, "--fetch-jira-issues")?;
+    requires(args.flag_jira_export.is_some(), "--jira-export", mode == Mode::JiraIssues, "--fetch-jira-issues")?;
+    requires(
+        args.flag_sentiment,
+        "--sentiment",
+        matches!(mode, Mode::Issues | Mode::JiraIssues | Mode::CommitMessages) && !args.flag_issue_stats_grouped,
+        "--fetch-github-issues, --fetch-jira-issues or --commit-messages",
+    )?;
+    Ok(mode)
+}
+
+fn requires(set: bool, flag: &str, applies: bool, needed: &str) -> Result<(), String> {
+    if set && !applies {
+        Err(format!("{} only applies with {}", flag, needed))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(command_line: &[&str]) -> Result<(Mode, Args), String> {
+        let mut args = Args::from_iter_safe(command_line).map_err(|e| e.message)?;
+        let mode = Mode::resolve(&mut args)?;
+        Ok((mode, args))
+    }
+
+    #[test]
+    fn test_subcommands() {
+        let (mode, args) = resolve(&[
+            "miner",
+            "stats",
+            "--git-folder=input",
+            "--ignore-start-end-date",
+            "--window=week",
+            "--skip-emails",
+            "--code-analyzer=native",
+            "--sokrates-timeout=600",
+            "--format=jsonl",
+            "--quality-thresholds=errors:0",
+            "--progress-file=progress.json",
+        ])
+        .unwrap();
+        assert_eq!(mode, Mode::Stats);
+        assert_eq!(args.flag_git_folder.as_deref(), Some("input"));
+        assert!(args.flag_ignore_start_end_dates && args.flag_skip_email_analysis);
+        assert_eq!(args.flag_code_analyzer, Some(sokrates_metrics::CodeAnalyzer::Native));
+        assert_eq!(args.flag_sokrates_timeout, Some(600));
+        assert_eq!(args.flag_window, Some(windows::WindowScheme::Weekly));
+        assert_eq!(args.flag_format, Some(output::OutputFormat::JsonLines));
+        assert_eq!(args.flag_quality_thresholds, Some("errors:0".parse().unwrap()));
+        assert_eq!(args.flag_progress_file.as_deref(), Some("progress.json"));
+
+        let (mode, args) = resolve(&["miner", "devs", "--grouped", "--metadata-filepath=projects.xlsx"]).unwrap();
+        assert_eq!(mode, Mode::Devs);
+        assert!(args.flag_commit_devs_files && args.flag_dev_stats_grouped);
+
+        let (mode, _) = resolve(&["miner", "emails", "check"]).unwrap();
+        assert_eq!(mode, Mode::EmailsCheck);
+        let (mode, args) = resolve(&["miner", "issues", "--jira-url=https://issues.apache.org/jira/browse/HUNTER"]).unwrap();
+        assert_eq!(mode, Mode::JiraIssues);
+        assert!(args.flag_jira_url.is_some());
+        let (mode, args) = resolve(&["miner", "online", "--url=https://github.com/apache/hunter.git", "--devs", "--graphql"]).unwrap();
+        assert_eq!(mode, Mode::Online);
+        assert!(args.flag_commit_devs_files && args.flag_commit_graphql);
+        let (_, args) = resolve(&["miner", "issues", "--url=https://git.example.com/a/b", "--forge=gitlab"]).unwrap();
+        assert_eq!(args.flag_forge, Some(forge::ForgeKind::GitLab));
+
+        // invalid groups are rejected by the parser
+        assert!(resolve(&["miner", "online"]).is_err());
+        assert!(resolve(&["miner", "online", "--url=u", "--graphql"]).is_err());
+        assert!(resolve(&["miner", "issues", "--pull-requests", "--grouped"]).is_err());
+        assert!(resolve(&["miner", "stats", "--git-folder=input"]).is_err());
+        assert!(resolve(&["miner", "devs", "--sentiment"]).is_err());
+        assert!(resolve(&["miner", "emails"]).is_err());
+        // and the old flags cannot be mixed with a subcommand
+        assert!(resolve(&["miner", "--skip-emails", "stats"]).is_err());
+    }
+
+    #[test]
+    fn test_legacy_flags() {
+        let (mode, _) = resolve(&["miner", "--skip-emails", "--git-folder=input", "--ignore-start-end-date"]).unwrap();
+        assert_eq!(mode, Mode::Stats);
+        let (mode, _) = resolve(&["miner", "--full-analysis", "--force-full-analysis"]).unwrap();
+        assert_eq!(mode, Mode::PrePost);
+        let (mode, _) = resolve(&["miner", "--commit-devs-files", "--dev-stats-grouped"]).unwrap();
+        assert_eq!(mode, Mode::Devs);
+        let (mode, _) = resolve(&["miner", "--commit-devs-files", "--git-online-url=u", "--commit-graphql"]).unwrap();
+        assert_eq!(mode, Mode::Online);
+        let (mode, _) = resolve(&["miner", "--fetch-github-issues", "--issue-stats-grouped", "--github-url=u"]).unwrap();
+        assert_eq!(mode, Mode::Issues);
+
+        let err = resolve(&["miner", "--issue-stats-grouped"]).err().unwrap();
+        assert_eq!(err, "--issue-stats-grouped only applies with --fetch-github-issues");
+        let err = resolve(&["miner", "--git-online-url=u", "--commit-graphql"]).err().unwrap();
+        assert_eq!(err, "--commit-graphql only applies with --git-online-url and --commit-devs-files");
+        let err = resolve(&["miner", "--download-emails", "--full-analysis"]).err().unwrap();
+        assert_eq!(err, "--download-emails and --full-analysis select different analyses; run them one at a time");
+        assert!(resolve(&["miner", "--fetch-jira-issues"]).is_err());
+        assert!(resolve(&["miner", "--full-analysis", "--commit-devs-files"]).is_err());
+        assert!(resolve(&["miner", "--incubation-dates", "--git-folder=input"]).is_err());
+    }
+}
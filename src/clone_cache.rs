@@ -0,0 +1,275 @@
+// src/clone_cache.rs
+//! Clones of online repositories for the clone-based commit analysis. Repositories are kept as bare
+//! mirrors, either in a persistent cache folder (updated with a fetch on later runs) or in a temporary
+//! folder removed after the analysis. Full clones and updates go through git2 and log their transfer progress;
+//! blobless and shallow clones need the `git` command line, since libgit2 supports neither. Analyses that
+//! read the files of a checkout work on a temporary working copy of the mirror.
+use std::error::Error;
+use std::path::Path;
+use std::process::Command;
+
+use git2::build::RepoBuilder;
+use git2::{AutotagOption, FetchOptions, FetchPrune, Progress, RemoteCallbacks, Repository};
+use log::info;
+use tempfile::TempDir;
+
+/// Branches checked for HEAD, in the order of `Repo::checkout_master_main_trunk`
+const DEFAULT_BRANCHES: [&str; 4] = ["master", "main", "trunk", "develop"];
+
+const BRANCHES_REFSPEC: &str = "+refs/heads/*:refs/heads/*";
+
+#[derive(Debug, Default, Clone)]
+pub struct CloneOptions {
+    /// Folder of the persistent mirrors; a temporary folder is used when not set
+    pub cache_folder: Option<String>,
+    /// Skip file contents (`--filter=blob:none`), for analyses that only read commits and trees
+    pub blobless: bool,
+    /// Only fetch the history from this date (YYYY-MM-DD) on
+    pub shallow_since: Option<String>,
+}
+
+/// A bare clone of an online repository. The temporary folder, if any, is removed on drop.
+pub struct ClonedRepo {
+    pub repo: Repository,
+    _temp_dir: Option<TempDir>,
+}
+
+/// Opens the cached mirror of `url` and fetches its updates, or clones it when it is not cached yet.
+/// HEAD is set to master, main, trunk or develop, whichever exists first, and otherwise to the remote's
+/// default branch.
+pub fn open_or_clone(url: &str, options: &CloneOptions) -> Result<ClonedRepo, Box<dyn Error>> {
+    let (path, temp_dir) = match &options.cache_folder {
+        Some(folder) => {
+            std::fs::create_dir_all(folder)?;
+            (Path::new(folder).join(mirror_name(url, options)), None)
+        }
+        None => {
+            let temp_dir = TempDir::new()?;
+            (temp_dir.path().join("repo.git"), Some(temp_dir))
+        }
+    };
+
+    let cached = path.join("HEAD").exists();
+    if cached {
+        info!("Updating the mirror of {} in {:?}", url, path);
+    } else {
+        info!("Cloning {} into {:?}", url, path);
+    }
+
+    let (repo, remote_head) = if options.blobless || options.shallow_since.is_some() {
+        if cached {
+            git_fetch(&path, options)?;
+        } else {
+            git_clone(url, &path, options)?;
+        }
+        (Repository::open_bare(&path)?, None)
+    } else {
+        let repo = if cached {
+            Repository::open_bare(&path)?
+        } else {
+            let repo = Repository::init_bare(&path)?;
+            repo.remote_with_fetch("origin", url, BRANCHES_REFSPEC)?;
+            repo
+        };
+        let remote_head = fetch(&repo)?;
+        (repo, remote_head)
+    };
+    set_default_head(&repo, remote_head)?;
+    Ok(ClonedRepo { repo, _temp_dir: temp_dir })
+}
+
+/// Clones a mirror into a temporary working copy with its HEAD branch checked out, for analyses that
+/// need the files on disk. The clone is local, so nothing is downloaded again.
+pub fn working_copy(mirror: &ClonedRepo, options: &CloneOptions) -> Result<ClonedRepo, Box<dyn Error>> {
+    let temp_dir = TempDir::new()?;
+    let path = temp_dir.path().join("repo");
+    let mirror_path = mirror.repo.path().to_str().ok_or("The mirror path is not valid UTF-8")?;
+    info!("Checking out a working copy of {} into {:?}", mirror_path, path);
+    let repo = if options.shallow_since.is_some() {
+        // libgit2 cannot clone a shallow repository
+        let path = path.to_str().ok_or("The clone path is not valid UTF-8")?;
+        run_git(&["clone", "--local", mirror_path, path])?;
+        Repository::open(path)?
+    } else {
+        RepoBuilder::new().clone(mirror_path, &path)?
+    };
+    Ok(ClonedRepo { repo, _temp_dir: Some(temp_dir) })
+}
+
+/// Cache folder name of a repository URL and clone mode, e.g. `github.com_apache_hunter-0c8d2a1e.git`, or
+/// `github.com_apache_hunter-0c8d2a1e-blobless-shallow.git`. The readable part maps the characters that cannot
+/// be in a folder name to `_`, so a hash of the URL tells apart URLs that map to the same name. Blobless and
+/// shallow mirrors lack part of the repository, so they are kept apart from the full mirror of the URL.
+fn mirror_name(url: &str, options: &CloneOptions) -> String {
+    let trimmed = url.trim().trim_end_matches('/').trim_end_matches(".git");
+    let without_scheme = trimmed.split_once("://").map(|(_, rest)| rest).unwrap_or(trimmed);
+    let name: String = without_scheme
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+        .collect();
+    // FNV-1a, which does not change with the Rust version
+    let hash = trimmed
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+    let mut mode = String::new();
+    if options.blobless {
+        mode.push_str("-blobless");
+    }
+    if options.shallow_since.is_some() {
+        mode.push_str("-shallow");
+    }
+    format!("{}-{:08x}{}.git", name.trim_matches('_'), hash as u32, mode)
+}
+
+/// Logs the transfer progress every 10% of the received objects
+fn log_progress(progress: &Progress, last_logged: &mut usize) {
+    let total = progress.total_objects();
+    if total == 0 {
+        return;
+    }
+    let percent = progress.received_objects() * 100 / total;
+    if percent >= *last_logged + 10 || (percent == 100 && *last_logged < 100) {
+        *last_logged = percent;
+        info!(
+            "Received {}/{} objects ({} KiB), indexed {}",
+            progress.received_objects(),
+            total,
+            progress.received_bytes() / 1024,
+            progress.indexed_objects()
+        );
+    }
+}
+
+fn fetch_options<'a>() -> FetchOptions<'a> {
+    let mut last_logged = 0;
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.transfer_progress(move |progress| {
+        log_progress(&progress, &mut last_logged);
+        true
+    });
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(callbacks);
+    options
+}
+
+/// Fetches all branches and tags, returning the remote's default branch
+fn fetch(repo: &Repository) -> Result<Option<String>, Box<dyn Error>> {
+    let mut remote = repo.find_remote("origin")?;
+    let mut options = fetch_options();
+    options.download_tags(AutotagOption::All).prune(FetchPrune::On);
+    remote.fetch(&[BRANCHES_REFSPEC], Some(&mut options), None)?;
+    let default_branch = remote.default_branch().ok().and_then(|b| b.as_str().map(|b| b.to_string()));
+    Ok(default_branch)
+}
+
+fn run_git(args: &[&str]) -> Result<(), Box<dyn Error>> {
+    let status = Command::new("git").args(args).status()?;
+    if !status.success() {
+        return Err(format!("git {} failed with {}", args.join(" "), status).into());
+    }
+    Ok(())
+}
+
+fn git_clone(url: &str, path: &Path, options: &CloneOptions) -> Result<(), Box<dyn Error>> {
+    let path = path.to_str().ok_or("The clone path is not valid UTF-8")?;
+    let mut args = vec!["clone".to_string(), "--bare".to_string(), "--progress".to_string()];
+    if options.blobless {
+        args.push("--filter=blob:none".to_string());
+    }
+    if let Some(since) = &options.shallow_since {
+        args.push(format!("--shallow-since={}", since));
+    }
+    args.push(url.to_string());
+    args.push(path.to_string());
+    run_git(&args.iter().map(String::as_str).collect::<Vec<_>>())?;
+    // A bare clone has no fetch refspec, which later updates need
+    run_git(&["-C", path, "config", "remote.origin.fetch", BRANCHES_REFSPEC])
+}
+
+fn git_fetch(path: &Path, options: &CloneOptions) -> Result<(), Box<dyn Error>> {
+    let path = path.to_str().ok_or("The clone path is not valid UTF-8")?;
+    let mut args = vec!["-C", path, "fetch", "--prune", "--tags", "--progress"];
+    let shallow_since = options.shallow_since.as_ref().map(|since| format!("--shallow-since={}", since));
+    if let Some(shallow_since) = &shallow_since {
+        args.push(shallow_since);
+    }
+    args.push("origin");
+    run_git(&args)
+}
+
+/// Points HEAD at the first of `DEFAULT_BRANCHES` that exists, falling back to `remote_head`
+fn set_default_head(repo: &Repository, remote_head: Option<String>) -> Result<(), Box<dyn Error>> {
+    let branch = DEFAULT_BRANCHES
+        .iter()
+        .map(|b| format!("refs/heads/{}", b))
+        .find(|name| repo.find_reference(name).is_ok())
+        .or(remote_head);
+    if let Some(branch) = branch {
+        repo.set_head(&branch)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{Oid, Signature};
+
+    /// Commits an empty tree on top of `branch` of a bare repository
+    fn commit(repo: &Repository, branch: &str, message: &str) -> Oid {
+        let tree = repo.find_tree(repo.treebuilder(None).unwrap().write().unwrap()).unwrap();
+        let signature = Signature::now("Alice", "alice@example.org").unwrap();
+        let reference = format!("refs/heads/{}", branch);
+        let parent = repo.find_reference(&reference).and_then(|r| r.peel_to_commit()).ok();
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(Some(&reference), &signature, &signature, message, &tree, &parents)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_mirror_name() {
+        let full = CloneOptions::default();
+        let name = mirror_name("https://github.com/apache/hunter.git", &full);
+        assert!(name.starts_with("github.com_apache_hunter-") && name.ends_with(".git"));
+        assert_eq!(name, mirror_name("https://github.com/apache/hunter/", &full));
+        assert!(mirror_name("file:///tmp/fixture.git/", &full).starts_with("tmp_fixture-"));
+        // names that map to the same characters, and other clone modes, get their own mirrors
+        assert_ne!(mirror_name("https://example.org/a-b/c", &full), mirror_name("https://example.org/a_b/c", &full));
+        let blobless = CloneOptions { blobless: true, ..CloneOptions::default() };
+        let shallow = CloneOptions { shallow_since: Some("2021-01-01".to_string()), ..CloneOptions::default() };
+        let url = "https://github.com/apache/hunter.git";
+        assert_eq!(mirror_name(url, &blobless), name.replace(".git", "-blobless.git"));
+        assert_eq!(mirror_name(url, &shallow), name.replace(".git", "-shallow.git"));
+    }
+
+    #[test]
+    fn test_clone_and_update_cached_mirror() {
+        let fixture_dir = tempfile::tempdir().unwrap();
+        let origin = Repository::init_bare(fixture_dir.path()).unwrap();
+        commit(&origin, "develop", "first");
+        let first_main = commit(&origin, "main", "first");
+        let url = format!("file://{}", fixture_dir.path().display());
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let options = CloneOptions {
+            cache_folder: Some(cache_dir.path().to_str().unwrap().to_string()),
+            ..CloneOptions::default()
+        };
+        let cloned = open_or_clone(&url, &options).unwrap();
+        assert!(cloned.repo.is_bare());
+        assert_eq!(cloned.repo.head().unwrap().target(), Some(first_main));
+        drop(cloned);
+
+        let second_main = commit(&origin, "main", "second");
+        let cloned = open_or_clone(&url, &options).unwrap();
+        assert!(cache_dir.path().join(mirror_name(&url, &options)).join("HEAD").exists());
+        assert_eq!(cloned.repo.head().unwrap().shorthand(), Some("main"));
+        assert_eq!(cloned.repo.head().unwrap().target(), Some(second_main));
+
+        let working = working_copy(&cloned, &options).unwrap();
+        assert!(!working.repo.is_bare());
+        assert!(working.repo.workdir().is_some());
+        assert_eq!(working.repo.head().unwrap().shorthand(), Some("main"));
+        assert_eq!(working.repo.head().unwrap().target(), Some(second_main));
+    }
+}
@@ -0,0 +1,687 @@
+// src/code_analysis.rs
+//! A native analyzer of the source tree for the Sokrates metric family, selected with `--code-analyzer=native`. It
+//! measures the checkout in process, without java, and leaves nothing in the working copy. The metrics have the
+//! ids of Sokrates' `analysisResults.json`, and go through the same `SokratesMetrics` columns.
+//!
+//! The analysis is lexical: comments and the contents of string literals are removed with the syntax of each
+//! language, units (functions, methods, constructors, lambdas outside of other units) are found from the header
+//! before their opening brace (from `def` and the indentation in Python), and their McCabe index is 1 plus their
+//! decision points (`if`, `for`, `while`, `case`, `catch`, `&&`, `||`, `?`...). The risk categories are the
+//! defaults of Sokrates. As in Sokrates, the unit, file size and duplication metrics are of the main code.
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::error::Error;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use git2::Repository;
+use indexmap::{IndexMap, IndexSet};
+use regex::Regex;
+use walkdir::WalkDir;
+
+use crate::sokrates_metrics::SokratesMetrics;
+
+/// The upper bounds of the negligible, low, medium and high risk categories; larger values are very high risk
+const FILE_SIZE_RISK: [usize; 4] = [100, 200, 500, 1000];
+const UNIT_SIZE_RISK: [usize; 4] = [10, 20, 50, 100];
+const CONDITIONAL_COMPLEXITY_RISK: [usize; 4] = [5, 10, 25, 50];
+const RISK_CATEGORIES: [&str; 5] = ["negligible", "low", "medium", "high", "very_high"];
+
+/// The fewest consecutive cleaned lines that count as a duplicate
+const DUPLICATION_BLOCK: usize = 6;
+
+/// Build and deployment scripts, by file name
+const BUILD_FILES: [&str; 16] = [
+    "pom.xml",
+    "build.xml",
+    "build.gradle",
+    "build.gradle.kts",
+    "settings.gradle",
+    "settings.gradle.kts",
+    "build.sbt",
+    "makefile",
+    "cmakelists.txt",
+    "dockerfile",
+    "docker-compose.yml",
+    "jenkinsfile",
+    "package.json",
+    "setup.py",
+    "cargo.toml",
+    "go.mod",
+];
+
+/// Folders that are not part of the source tree
+const SKIPPED_FOLDERS: [&str; 3] = [".git", "_sokrates", "node_modules"];
+
+/// The part of the code base a file belongs to, as in Sokrates' logical decomposition
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scope {
+    Main,
+    Test,
+    Generated,
+    Build,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Blocks {
+    Braces,
+    Indentation,
+}
+
+/// The lexical syntax of a language, enough to strip its comments and strings and to find its units
+struct Syntax {
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+    /// Quotes of strings that may span lines, e.g. JavaScript template literals
+    multiline_quotes: &'static [char],
+    /// Delimiters of triple-quoted strings, e.g. `"""`
+    triple_quotes: &'static [&'static str],
+    /// A `'` that does not close a character literal is a lifetime, in Rust
+    lifetimes: bool,
+    /// `and` and `or` are decision points, in Python
+    word_operators: bool,
+    blocks: Blocks,
+}
+
+const C_LIKE: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+    multiline_quotes: &[],
+    triple_quotes: &[],
+    lifetimes: false,
+    word_operators: false,
+    blocks: Blocks::Braces,
+};
+
+fn syntax(extension: &str) -> Option<Syntax> {
+    let syntax = match extension {
+        "c" | "h" | "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" | "cs" | "java" | "groovy" => C_LIKE,
+        "kt" | "kts" | "scala" | "swift" => Syntax { triple_quotes: &["\"\"\""], ..C_LIKE },
+        "js" | "jsx" | "mjs" | "cjs" | "ts" | "tsx" | "go" => Syntax { multiline_quotes: &['`'], ..C_LIKE },
+        "rs" => Syntax { lifetimes: true, ..C_LIKE },
+        "php" => Syntax { line_comments: &["//", "#"], ..C_LIKE },
+        "py" => Syntax {
+            line_comments: &["#"],
+            block_comment: None,
+            triple_quotes: &["\"\"\"", "'''"],
+            word_operators: true,
+            blocks: Blocks::Indentation,
+            ..C_LIKE
+        },
+        _ => return None,
+    };
+    Some(syntax)
+}
+
+/// The lines of a source file without comments, and with the contents of string literals removed, so that
+/// the braces and keywords in them are not counted. Literals are kept as `""`, so their lines stay code.
+fn clean(source: &str, syntax: &Syntax) -> Vec<String> {
+    enum State {
+        Code,
+        BlockComment,
+        /// A string literal: its quote, and whether it is a triple-quoted one
+        Literal(char, bool),
+    }
+    let chars: Vec<char> = source.chars().collect();
+    let starts_with = |i: usize, pattern: &str| pattern.chars().enumerate().all(|(k, c)| chars.get(i + k) == Some(&c));
+    let mut lines = vec![];
+    let mut line = String::new();
+    let mut state = State::Code;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\n' {
+            lines.push(std::mem::take(&mut line));
+            if let State::Literal(quote, false) = state {
+                // an unterminated literal, unless its quote may span lines
+                if !syntax.multiline_quotes.contains(&quote) {
+                    state = State::Code;
+                }
+            }
+            i += 1;
+            continue;
+        }
+        match state {
+            State::Code => {
+                if syntax.line_comments.iter().any(|comment| starts_with(i, comment)) {
+                    while i < chars.len() && chars[i] != '\n' {
+                        i += 1;
+                    }
+                    continue;
+                }
+                if let Some((start, _)) = syntax.block_comment.filter(|(start, _)| starts_with(i, start)) {
+                    state = State::BlockComment;
+                    i += start.len();
+                    continue;
+                }
+                if syntax.triple_quotes.iter().any(|quotes| starts_with(i, quotes)) {
+                    line.push('"');
+                    state = State::Literal(c, true);
+                    i += 3;
+                    continue;
+                }
+                let char_literal = c != '\'' || !syntax.lifetimes || chars.get(i + 1) == Some(&'\\') || chars.get(i + 2) == Some(&'\'');
+                if (syntax.quotes.contains(&c) && char_literal) || syntax.multiline_quotes.contains(&c) {
+                    line.push('"');
+                    state = State::Literal(c, false);
+                } else {
+                    line.push(c);
+                }
+                i += 1;
+            }
+            State::BlockComment => {
+                let end = syntax.block_comment.map(|(_, end)| end).unwrap_or_default();
+                if starts_with(i, end) {
+                    state = State::Code;
+                    i += end.len();
+                } else {
+                    i += 1;
+                }
+            }
+            State::Literal(quote, triple) => {
+                if c == '\\' {
+                    // an escaped newline still ends the line
+                    i += if chars.get(i + 1) == Some(&'\n') { 1 } else { 2 };
+                } else if triple && chars[i..].iter().take(3).all(|q| *q == quote) && chars.len() - i >= 3 {
+                    line.push('"');
+                    state = State::Code;
+                    i += 3;
+                } else if !triple && c == quote {
+                    line.push('"');
+                    state = State::Code;
+                    i += 1;
+                } else {
+                    // the indentation of the lines a literal spans
+                    if c.is_whitespace() && line.trim().is_empty() {
+                        line.push(c);
+                    }
+                    i += 1;
+                }
+            }
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// A function, method, constructor or lambda, from its first to its last line (from 0)
+#[derive(Clone, Debug, PartialEq)]
+struct Unit {
+    start: usize,
+    end: usize,
+}
+
+fn regex(cell: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    cell.get_or_init(|| Regex::new(pattern).unwrap())
+}
+
+/// Whether the code before an opening brace is the header of a unit
+fn is_unit_header(header: &str) -> bool {
+    static WORD: OnceLock<Regex> = OnceLock::new();
+    static END: OnceLock<Regex> = OnceLock::new();
+    let header = header.trim();
+    let words: Vec<&str> = regex(&WORD, r"[A-Za-z_$][A-Za-z0-9_$]*").find_iter(header).map(|m| m.as_str()).collect();
+    let control = ["if", "else", "for", "foreach", "while", "do", "switch", "try", "catch", "finally", "synchronized", "using", "lock", "fixed", "return", "match", "when", "select", "with"];
+    if words.first().is_none_or(|first| control.contains(first)) {
+        return false;
+    }
+    if header.contains("=>") || words.iter().any(|word| ["fn", "func", "function", "fun", "def"].contains(word)) {
+        return true;
+    }
+    // types, and anonymous classes
+    let before_parameters = header.split('(').next().unwrap_or_default();
+    let types = ["class", "interface", "enum", "record", "struct", "union", "namespace", "new", "object", "trait", "impl"];
+    if regex(&WORD, r"[A-Za-z_$][A-Za-z0-9_$]*").find_iter(before_parameters).any(|m| types.contains(&m.as_str())) {
+        return false;
+    }
+    // the parameters, then modifiers, exceptions or a return type
+    let end = regex(&END, r"\)(\s*(const|override|final|noexcept|mutable|throws\s+[\w.$<>,\s]+|->[^{]*|:[^{()]*))*\s*$");
+    header.contains('(') && end.is_match(header)
+}
+
+/// The units of a file in a brace language
+fn brace_units(lines: &[String]) -> Vec<Unit> {
+    let mut units = vec![];
+    let mut header = String::new();
+    let mut header_start: Option<usize> = None;
+    let mut depth = 0;
+    let mut parentheses = 0;
+    // the start line and the depth of the unit being read
+    let mut unit: Option<(usize, usize)> = None;
+    for (number, line) in lines.iter().enumerate() {
+        for c in line.chars() {
+            match c {
+                '{' => {
+                    if unit.is_none() && is_unit_header(&header) {
+                        unit = Some((header_start.unwrap_or(number), depth));
+                    }
+                    depth += 1;
+                    parentheses = 0;
+                }
+                '}' => {
+                    depth = usize::saturating_sub(depth, 1);
+                    if let Some((start, _)) = unit.filter(|(_, unit_depth)| *unit_depth == depth) {
+                        units.push(Unit { start, end: number });
+                        unit = None;
+                    }
+                    parentheses = 0;
+                }
+                ';' if parentheses == 0 => {}
+                '(' => {
+                    parentheses += 1;
+                    header.push(c);
+                    continue;
+                }
+                ')' => {
+                    parentheses = usize::saturating_sub(parentheses, 1);
+                    header.push(c);
+                    continue;
+                }
+                _ => {
+                    if header_start.is_none() && !c.is_whitespace() {
+                        header_start = Some(number);
+                    }
+                    header.push(c);
+                    continue;
+                }
+            }
+            header.clear();
+            header_start = None;
+        }
+        header.push(' ');
+    }
+    units
+}
+
+/// The units of a file in an indentation language: each `def` with the more indented lines after it
+fn indentation_units(lines: &[String]) -> Vec<Unit> {
+    let indentation = |line: &str| line.len() - line.trim_start().len();
+    let mut units = vec![];
+    let mut number = 0;
+    while number < lines.len() {
+        let line = lines[number].trim_start();
+        if !(line.starts_with("def ") || line.starts_with("async def ")) {
+            number += 1;
+            continue;
+        }
+        let def_indentation = indentation(&lines[number]);
+        let mut end = number;
+        let mut next = number + 1;
+        while next < lines.len() {
+            if !lines[next].trim().is_empty() {
+                if indentation(&lines[next]) <= def_indentation {
+                    break;
+                }
+                end = next;
+            }
+            next += 1;
+        }
+        units.push(Unit { start: number, end });
+        number = end + 1;
+    }
+    units
+}
+
+/// The McCabe index of a unit: 1 plus its decision points
+fn mccabe_index(lines: &[String], syntax: &Syntax) -> usize {
+    static DECISIONS: OnceLock<Regex> = OnceLock::new();
+    static WORD_OPERATORS: OnceLock<Regex> = OnceLock::new();
+    let decisions = regex(&DECISIONS, r"\b(if|elif|for|foreach|while|case|catch|except)\b|&&|\|\||\s\?\s");
+    let word_operators = regex(&WORD_OPERATORS, r"\b(and|or)\b");
+    1 + lines
+        .iter()
+        .map(|line| {
+            decisions.find_iter(line).count() + if syntax.word_operators { word_operators.find_iter(line).count() } else { 0 }
+        })
+        .sum::<usize>()
+}
+
+/// A line as compared for duplication, or none for lines too trivial to be a duplicate: blank lines, braces,
+/// imports and includes
+fn normalized(line: &str) -> Option<String> {
+    let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+    let trivial = line.chars().all(|c| "{}()[];,".contains(c))
+        || ["import ", "package ", "using ", "#include", "from "].iter().any(|prefix| line.starts_with(prefix));
+    (!trivial).then_some(line)
+}
+
+fn hash<T: Hash>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The category of a value, from the upper bounds of the negligible, low, medium and high risk categories
+fn risk(value: usize, bounds: &[usize; 4]) -> &'static str {
+    let category = bounds.iter().position(|bound| value <= *bound).unwrap_or(bounds.len());
+    RISK_CATEGORIES[category]
+}
+
+/// A file of the source tree, by its path relative to the root
+pub struct SourceFile {
+    pub path: String,
+    pub contents: String,
+}
+
+impl SourceFile {
+    fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+
+    fn extension(&self) -> Option<String> {
+        Path::new(self.name()).extension().map(|e| e.to_string_lossy().to_lowercase())
+    }
+
+    fn is_build_file(&self) -> bool {
+        BUILD_FILES.contains(&self.name().to_lowercase().as_str())
+    }
+
+    /// The syntax of the file's language, when it is known and `extensions` has its extension if given
+    fn language(&self, extensions: Option<&IndexSet<String>>) -> Option<Syntax> {
+        let extension = self.extension().unwrap_or_default();
+        syntax(&extension).filter(|_| extensions.is_none_or(|allowed| allowed.contains(&extension)))
+    }
+
+    /// Whether the file is measured, which only depends on its path: the other files are not read
+    fn is_measured(&self, extensions: Option<&IndexSet<String>>) -> bool {
+        self.is_build_file() || self.language(extensions).is_some()
+    }
+
+    fn scope(&self) -> Scope {
+        let lower = self.path.to_lowercase();
+        if self.is_build_file() {
+            return Scope::Build;
+        }
+        let folders: Vec<&str> = lower.split('/').rev().skip(1).collect();
+        let header = self.contents.lines().take(10).collect::<Vec<_>>().join("\n").to_lowercase();
+        if folders.iter().any(|folder| folder.starts_with("generated"))
+            || [".min.js", ".pb.go", "_pb2.py", ".designer.cs"].iter().any(|suffix| lower.ends_with(suffix))
+            || ["@generated", "generated by", "do not edit"].iter().any(|marker| header.contains(marker))
+        {
+            return Scope::Generated;
+        }
+        if is_test_path(&self.path) {
+            return Scope::Test;
+        }
+        Scope::Main
+    }
+}
+
+/// Whether a path, relative to the repository, is test code by the usual conventions: a test folder, or a test
+/// file name such as `FooTest.java`, `test_foo.py`, `foo_test.go` or `foo.spec.ts`
+pub fn is_test_path(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let lower = path.to_lowercase();
+    let folders: Vec<&str> = lower.split('/').rev().skip(1).collect();
+    let stem = name.split('.').next().unwrap_or(name);
+    let stem_lower = stem.to_lowercase();
+    folders.iter().any(|folder| ["test", "tests", "__tests__", "testing", "spec"].contains(folder))
+        || ["Test", "Tests", "IT"].iter().any(|suffix| stem.ends_with(suffix))
+        || stem_lower.starts_with("test_")
+        || stem_lower.ends_with("_test")
+        || [".test.", ".spec."].iter().any(|infix| name.contains(infix))
+}
+
+/// Accumulates the metrics of the files, by the ids of Sokrates' metrics
+#[derive(Default)]
+struct Measurements {
+    values: IndexMap<String, f64>,
+}
+
+impl Measurements {
+    fn add(&mut self, id: &str, value: usize) {
+        *self.values.entry(id.to_string()).or_default() += value as f64;
+    }
+
+    fn get(&self, id: &str) -> f64 {
+        self.values.get(id).copied().unwrap_or_default()
+    }
+
+    fn set(&mut self, id: &str, value: f64) {
+        self.values.insert(id.to_string(), value);
+    }
+}
+
+/// The metrics of the files, by the ids of Sokrates' metrics. Files are analyzed when their language is known, and
+/// `extensions` has their extension if given; build and deployment scripts are always counted.
+pub fn measure(files: &[SourceFile], extensions: Option<&IndexSet<String>>) -> IndexMap<String, f64> {
+    let mut m = Measurements::default();
+    let mut most_complex: (usize, usize) = (0, 0);
+    // the normalized lines of every main file, for duplication
+    let mut main_lines: Vec<Vec<Option<String>>> = vec![];
+    let mut unit_bodies: HashMap<u64, usize> = HashMap::new();
+    for file in files {
+        let scope = file.scope();
+        let language = file.language(extensions);
+        let (scope_name, lines) = match (scope, &language) {
+            (Scope::Build, _) => ("build_and_deployment", file.contents.lines().map(String::from).collect()),
+            (_, None) => continue,
+            (Scope::Main, Some(syntax)) => ("main", clean(&file.contents, syntax)),
+            (Scope::Test, Some(syntax)) => ("test", clean(&file.contents, syntax)),
+            (Scope::Generated, Some(syntax)) => ("generated", clean(&file.contents, syntax)),
+        };
+        let is_code = |line: &String| !line.trim().is_empty();
+        let loc = lines.iter().filter(|line| is_code(line)).count();
+        m.add("total_number_of_files", 1);
+        m.add(&format!("number_of_files_{}", scope_name), 1);
+        m.add(&format!("lines_of_code_{}", scope_name), loc);
+        let Some(syntax) = language.filter(|_| scope == Scope::Main) else {
+            continue;
+        };
+
+        let category = risk(loc, &FILE_SIZE_RISK);
+        m.add(&format!("{}_risk_file_size_count", category), 1);
+        m.add(&format!("{}_risk_file_size_loc", category), loc);
+
+        let units = match syntax.blocks {
+            Blocks::Braces => brace_units(&lines),
+            Blocks::Indentation => indentation_units(&lines),
+        };
+        for unit in units {
+            let body = &lines[unit.start..=unit.end];
+            let unit_loc = body.iter().filter(|line| is_code(line)).count();
+            let index = mccabe_index(body, &syntax);
+            m.add("number_of_units", 1);
+            m.add("lines_of_code_in_units", unit_loc);
+            let size = risk(unit_loc, &UNIT_SIZE_RISK);
+            m.add(&format!("unit_size_{}_risk_count", size), 1);
+            m.add(&format!("unit_size_{}_risk_loc", size), unit_loc);
+            let complexity = risk(index, &CONDITIONAL_COMPLEXITY_RISK);
+            m.add(&format!("conditional_complexity_{}_risk_count", complexity), 1);
+            m.add(&format!("conditional_complexity_{}_risk_loc", complexity), unit_loc);
+            if index > most_complex.1 {
+                most_complex = (unit_loc, index);
+            }
+            let normalized_body: Vec<String> = body.iter().skip(1).filter_map(|line| normalized(line)).collect();
+            if normalized_body.len() >= DUPLICATION_BLOCK {
+                *unit_bodies.entry(hash(&normalized_body)).or_default() += 1;
+            }
+        }
+        main_lines.push(lines.iter().map(|line| normalized(line)).collect());
+    }
+
+    let lines_of_code_main = m.get("lines_of_code_main");
+    m.set("lines_of_code_outside_units", lines_of_code_main - m.get("lines_of_code_in_units"));
+    if lines_of_code_main > 0.0 {
+        let percentage = m.get("lines_of_code_test") / lines_of_code_main * 100.0;
+        m.set("test_vs_main_lines_of_code_percentage", (percentage * 100.0).round() / 100.0);
+    }
+    for category in ["high", "very_high"] {
+        m.add("conditional_complexity_high_plus_risk_count", m.get(&format!("conditional_complexity_{}_risk_count", category)) as usize);
+        m.add("conditional_complexity_high_plus_risk_loc", m.get(&format!("conditional_complexity_{}_risk_loc", category)) as usize);
+    }
+    m.set("most_complex_unit_loc", most_complex.0 as f64);
+    m.set("most_complex_unit_mcabe_index", most_complex.1 as f64);
+    m.set("unit_duplicates_count", unit_bodies.values().filter(|count| **count > 1).sum::<usize>() as f64);
+    duplication(&main_lines, &mut m);
+    m.values
+}
+
+/// Marks the lines in blocks of `DUPLICATION_BLOCK` normalized lines that appear more than once in the main code.
+/// A duplicate is a run of marked lines in a file.
+fn duplication(files: &[Vec<Option<String>>], m: &mut Measurements) {
+    let files: Vec<Vec<&String>> = files.iter().map(|lines| lines.iter().flatten().collect()).collect();
+    let mut blocks: HashMap<u64, Vec<(usize, usize)>> = HashMap::new();
+    for (file, lines) in files.iter().enumerate() {
+        for (position, block) in lines.windows(DUPLICATION_BLOCK).enumerate() {
+            blocks.entry(hash(&block)).or_default().push((file, position));
+        }
+    }
+    let mut duplicated: Vec<Vec<bool>> = files.iter().map(|lines| vec![false; lines.len()]).collect();
+    for occurrences in blocks.values().filter(|occurrences| occurrences.len() > 1) {
+        for (file, position) in occurrences {
+            duplicated[*file][*position..*position + DUPLICATION_BLOCK].iter_mut().for_each(|line| *line = true);
+        }
+    }
+    let cleaned_lines: usize = files.iter().map(Vec::len).sum();
+    let duplicated_lines: usize = duplicated.iter().map(|lines| lines.iter().filter(|line| **line).count()).sum();
+    let duplicates: usize = duplicated
+        .iter()
+        .map(|lines| lines.iter().enumerate().filter(|(i, line)| **line && (*i == 0 || !lines[i - 1])).count())
+        .sum();
+    m.set("duplication_number_of_duplicates", duplicates as f64);
+    m.set("duplication_number_of_files_with_duplicates", duplicated.iter().filter(|lines| lines.contains(&true)).count() as f64);
+    m.set("duplication_number_of_duplicated_lines", duplicated_lines as f64);
+    let percentage = if cleaned_lines > 0 { duplicated_lines as f64 / cleaned_lines as f64 * 100.0 } else { 0.0 };
+    m.set("duplication_percentage", percentage);
+}
+
+/// The files of the working copy that are measured, without the folders that are not part of the source tree
+fn source_files(root: &Path, extensions: Option<&IndexSet<String>>) -> Vec<SourceFile> {
+    WalkDir::new(root)
+        .into_iter()
+        .filter_entry(|entry| {
+            let name = entry.file_name().to_string_lossy();
+            !(entry.file_type().is_dir() && entry.depth() > 0 && (SKIPPED_FOLDERS.contains(&name.as_ref()) || name.starts_with('.')))
+        })
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            let path = entry.path().strip_prefix(root).ok()?.to_string_lossy().replace('\\', "/");
+            let mut file = SourceFile { path, contents: String::new() };
+            if !file.is_measured(extensions) {
+                return None;
+            }
+            file.contents = String::from_utf8_lossy(&std::fs::read(entry.path()).ok()?).to_string();
+            Some(file)
+        })
+        .collect()
+}
+
+/// The number of authors of the history of the checked out commit, by email
+fn contributors(repo: &Repository) -> Result<usize, git2::Error> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push_head()?;
+    let mut authors = IndexSet::new();
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        authors.insert(commit.author().email().unwrap_or("").to_lowercase());
+    }
+    Ok(authors.len())
+}
+
+/// The Sokrates metrics of the repository's working copy, measured natively
+pub fn metrics(repo: &Repository, extensions: Option<&IndexSet<String>>) -> Result<SokratesMetrics, Box<dyn Error>> {
+    let root = repo.workdir().ok_or("the repository has no working copy")?;
+    let mut values = measure(&source_files(root, extensions), extensions);
+    values.insert("number_of_contributors".to_string(), contributors(repo)? as f64);
+    Ok(SokratesMetrics::from(values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, contents: &str) -> SourceFile {
+        SourceFile { path: path.to_string(), contents: contents.to_string() }
+    }
+
+    #[test]
+    fn test_clean_and_units() {
+        let java = "/** A class { */\npublic class A {\n    // if (x) {\n    @Override\n    public String f(int x) throws IOException {\n        if (x > 0 && x < 10) {\n            return \"{ if\";\n        }\n        for (int i = 0; i < x; i++) { x--; }\n        return x > 1 ? \"a\" : \"b\";\n    }\n\n    A() { }\n}\n";
+        let lines = clean(java, &syntax("java").unwrap());
+        assert_eq!(lines[0], "");
+        assert_eq!(lines[2].trim(), "");
+        assert_eq!(lines[6].trim(), "return \"\";");
+        let units = brace_units(&lines);
+        assert_eq!(units, vec![Unit { start: 3, end: 10 }, Unit { start: 12, end: 12 }]);
+        // if, &&, for, ?
+        assert_eq!(mccabe_index(&lines[3..=10], &C_LIKE), 5);
+
+        let rust = "fn f<'a>(s: &'a str) -> char {\n    let c = '{';\n    if s.is_empty() { c } else { 'x' }\n}\n";
+        let lines = clean(rust, &syntax("rs").unwrap());
+        assert_eq!(lines[1].trim(), "let c = \"\";");
+        assert_eq!(brace_units(&lines), vec![Unit { start: 0, end: 3 }]);
+
+        let python = "class A:\n    def f(self, x):\n        \"\"\"Doc {\n        \"\"\"\n        if x and self.y:\n            return 1\n\n        return 2\n\ndef g():\n    pass\n";
+        let lines = clean(python, &syntax("py").unwrap());
+        assert_eq!(indentation_units(&lines), vec![Unit { start: 1, end: 7 }, Unit { start: 9, end: 10 }]);
+        assert_eq!(mccabe_index(&lines[1..=7], &syntax("py").unwrap()), 3);
+
+        assert!(is_unit_header("auto f() const -> int"));
+        assert!(is_unit_header("const f = (a) =>"));
+        assert!(!is_unit_header("} else if (a)"));
+        assert!(!is_unit_header("Runnable r = new Runnable()"));
+        assert!(!is_unit_header("@SuppressWarnings(\"x\") public class B"));
+    }
+
+    #[test]
+    fn test_measure() {
+        let block = "        a = b + c;\n        d = e + f;\n        g = h + i;\n        j = k + l;\n        m = n + o;\n        p = q + r;\n";
+        let main = format!("package x;\nimport y;\nclass Main {{\n    void f(int a) {{\n{}        if (a > 1) {{ a++; }}\n    }}\n    void g() {{\n{}    }}\n}}\n", block, block);
+        let files = vec![
+            file("src/main/java/x/Main.java", &main),
+            file("src/test/java/x/MainTest.java", "class MainTest {\n    void t() { }\n}\n"),
+            file("src/main/java/x/Gen.java", "// Generated by a tool. DO NOT EDIT\nclass Gen { }\n"),
+            file("pom.xml", "<project>\n</project>\n"),
+            file("README.md", "# x\n"),
+            file("scripts/run.py", "def run():\n    return 1\n"),
+        ];
+        let values = measure(&files, None);
+        let metrics = |id: &str| values.get(id).copied().unwrap_or_default();
+        assert_eq!(metrics("total_number_of_files"), 5.0);
+        assert_eq!(metrics("number_of_files_main"), 2.0);
+        assert_eq!(metrics("lines_of_code_main"), 23.0);
+        assert_eq!(metrics("lines_of_code_test"), 3.0);
+        assert_eq!(metrics("number_of_files_generated"), 1.0);
+        assert_eq!(metrics("number_of_files_build_and_deployment"), 1.0);
+        assert_eq!(metrics("test_vs_main_lines_of_code_percentage"), 13.04);
+        assert_eq!(metrics("number_of_units"), 3.0);
+        assert_eq!(metrics("lines_of_code_in_units"), 19.0);
+        assert_eq!(metrics("lines_of_code_outside_units"), 4.0);
+        assert_eq!(metrics("unit_size_negligible_risk_count"), 3.0);
+        assert_eq!(metrics("unit_size_negligible_risk_loc"), 19.0);
+        assert_eq!(metrics("conditional_complexity_negligible_risk_count"), 3.0);
+        assert_eq!(metrics("most_complex_unit_mcabe_index"), 2.0);
+        assert_eq!(metrics("most_complex_unit_loc"), 9.0);
+        assert_eq!(metrics("negligible_risk_file_size_count"), 2.0);
+        assert_eq!(metrics("duplication_number_of_duplicates"), 2.0);
+        assert_eq!(metrics("duplication_number_of_files_with_duplicates"), 1.0);
+        assert_eq!(metrics("duplication_number_of_duplicated_lines"), 12.0);
+        assert_eq!(metrics("unit_duplicates_count"), 0.0);
+
+        let only_python: IndexSet<String> = ["py".to_string()].into_iter().collect();
+        let values = measure(&files, Some(&only_python));
+        assert_eq!(values.get("number_of_files_main"), Some(&1.0));
+
+        // only the measured files are read
+        let dir = tempfile::tempdir().unwrap();
+        for path in ["pom.xml", "README.md", "logo.png", "src/Main.java", "scripts/run.py", "node_modules/x/Out.java"] {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "x\n").unwrap();
+        }
+        let paths = |extensions| {
+            let mut paths: Vec<String> = source_files(dir.path(), extensions).into_iter().map(|f| f.path).collect();
+            paths.sort();
+            paths
+        };
+        assert_eq!(paths(None), vec!["pom.xml", "scripts/run.py", "src/Main.java"]);
+        assert_eq!(paths(Some(&only_python)), vec!["pom.xml", "scripts/run.py"]);
+    }
+}
@@ -1,13 +1,71 @@
 // commit_metrics_clone.rs
-use git2::Repository;
 use std::error::Error;
 use std::fs;
-use tempfile::TempDir;
+use std::path::Path;
+use crate::activity_metrics;
+use crate::clone_cache::{self, CloneOptions};
+use crate::database;
 use crate::repo::Repo;
 use crate::dev_stats::DevStats;
+use crate::statistics::Stats;
+use crate::tokei_metrics::LanguageRow;
+use crate::forge::RepoRef;
+use crate::output;
+use crate::windows::WindowScheme;
 use crate::Args;
-use std::sync::{Arc, RwLock};
-use log::{info, error};
+use log::info;
+
+/// Clone options of an online repository; a shallow clone starts at the analysis' start date
+fn clone_options(args: &Args, start_date: &str) -> CloneOptions {
+    CloneOptions {
+        cache_folder: args.flag_clone_cache.clone(),
+        blobless: args.flag_blobless_clone,
+        shallow_since: if args.flag_shallow_clone { Some(start_date.to_string()) } else { None },
+    }
+}
+
+/// Project name of an online repository, e.g. https://github.com/apache/hunter.git => hunter
+pub fn repo_name(online_url: &str) -> String {
+    online_url
+        .trim_end_matches('/')
+        .trim_end_matches(".git")
+        .rsplit('/')
+        .next()
+        .filter(|name| !name.is_empty())
+        .unwrap_or("unknown")
+        .to_string()
+}
+
+/// The host, owner and name of an online repository, which key its tables, e.g.
+/// https://github.com/apache/hunter.git => github.com/apache/hunter
+pub fn repo_key(online_url: &str) -> String {
+    let trimmed = online_url.trim().trim_end_matches('/').trim_end_matches(".git");
+    let without_scheme = match trimmed.split_once("://") {
+        Some((_, rest)) => rest.to_string(),
+        None => trimmed.replacen(':', "/", 1),
+    };
+    let without_user = without_scheme.rsplit_once('@').map(|(_, r)| r).unwrap_or(&without_scheme);
+    let parts: Vec<String> = without_user
+        .split('/')
+        // GitLab web URLs may contain `/-/` followed by a page
+        .take_while(|part| *part != "-")
+        .filter(|part| !part.is_empty() && *part != "." && *part != "..")
+        .enumerate()
+        .map(|(i, part)| if i == 0 { part.to_lowercase() } else { part.to_string() })
+        .collect();
+    if parts.is_empty() { "unknown".to_string() } else { parts.join("/") }
+}
+
+/// The stem of the tables of an online repository, `{output_folder}/{host}/{owner}/{repo}`, so that repositories
+/// of the same name on other forges or owners do not write to the same files. Its folders are created.
+pub fn output_stem(args: &Args, online_url: &str) -> Result<String, Box<dyn Error>> {
+    let output_folder = args.flag_output_folder.as_deref().unwrap_or("data");
+    let stem = Path::new(output_folder).join(repo_key(online_url));
+    if let Some(parent) = stem.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(stem.to_string_lossy().to_string())
+}
 
 pub fn analyze_online_repo(
     online_url: &str,
@@ -16,60 +74,99 @@ pub fn analyze_online_repo(
     end_date: &str,
     status: &str,
 ) -> Result<(), Box<dyn Error>> {
-    // 1) Create a temporary directory to clone the repository.
-    let temp_dir = TempDir::new()?;
-    let temp_path = temp_dir.path().join("repo");
-    info!("Cloning repository {} into {:?}", online_url, temp_path);
-    let repo = Repository::clone(online_url, &temp_path)?;
-
-    // 2) Derive project name & owner from the URL
-    //    e.g. https://github.com/apache/hunter.git => owner = "apache", repo_name = "hunter"
+    // 1) Clone the repository as a bare mirror, or update its cached mirror.
+    let clone_options = clone_options(args, start_date);
+    let cloned = clone_cache::open_or_clone(online_url, &clone_options)?;
+
+    // 2) Derive project name from the URL
+    //    e.g. https://github.com/apache/hunter.git => repo_name = "hunter"
     let url_no_dotgit = online_url.trim_end_matches(".git");
-    let parts: Vec<&str> = url_no_dotgit.split('/').collect();
-    let repo_name = parts.last().unwrap_or(&"unknown").to_string();
-    let owner = if parts.len() >= 2 {
-        parts[parts.len() - 2]
-    } else {
-        "unknown"
-    };
+    let repo_name = repo_name(online_url);
 
-    // 3) Create a mutable Repo object using your existing logic.
-    let mut repo_obj = Repo::new(&repo, &repo_name, start_date, end_date, status, args)?;
-    //    Checkout the proper branch (master/main/trunk).
-    repo_obj.checkout_master_main_trunk(args)?;
+    // 3) Create a Repo object using your existing logic. The mirror's HEAD is already
+    //    the proper branch (master/main/trunk).
+    let repo_obj = Repo::new(&cloned.repo, &repo_name, start_date, end_date, status, args)?;
 
     // 4) Compute commit metrics via DevStats
     let java_path = crate::java_path();
     let dev_stats = DevStats::new(&repo_name, &repo_obj, &java_path);
-    let mut stats_output = dev_stats.compute_individual_dev_stats(args)?;
+    //    A blobless clone has no file contents, so only the changed files are listed.
+    let mut stats_output = if clone_options.blobless {
+        dev_stats.compute_commit_file_names(args)?
+    } else {
+        dev_stats.compute_individual_dev_stats(args)?
+    };
 
-    // 5) For each commit row, fill in the commit_url
+    // 5) For each commit row, fill in the commit_url in the forge's own URL scheme
+    let repo_ref = RepoRef::parse_as(online_url, args.flag_forge).ok();
     for ds in stats_output.iter_mut() {
         let sha = &ds.metrics.commit_sha;
-        ds.metrics.commit_url = format!(
-            "https://github.com/{}/{}/commit/{}",
-            owner, repo_name, sha
-        );
+        ds.metrics.commit_url = match &repo_ref {
+            Some(repo_ref) => repo_ref.commit_url(sha),
+            None => format!("{}/commit/{}", url_no_dotgit, sha),
+        };
     }
 
-    // 6) Write the CSV output to the output folder.
-    let output_folder = args.flag_output_folder.as_deref().unwrap_or("data");
-    fs::create_dir_all(output_folder)?;
-    let csv_path = format!("{}/{}-commit-file-dev.csv", output_folder, repo_name);
-
-    let writer = Arc::new(RwLock::new(csv::WriterBuilder::default().has_headers(true).from_path(&csv_path)?));
-    {
-        let mut guard = writer.write().expect("Unable to lock CSV writer");
-        // We can directly serialize the `DevStats` rows now (which embed `commit_url`)
-        for ds in &stats_output {
-            if let Err(e) = guard.serialize(ds) {
-                error!("Cannot serialize a dev_stats row: {}", e);
-            }
-        }
+    // 6) Write the table, in the `--format` format, to the repository's folder in the output folder.
+    let stem = format!("{}-commit-file-dev", output_stem(args, online_url)?);
+    // We can directly serialize the `DevStats` rows now (which embed `commit_url`), typed after `dev_stats`
+    let path = output::write_table(&stem, args.flag_format.unwrap_or_default(), Some(&dev_stats), stats_output)?;
+
+    info!("Clone-based commit analysis completed for repository {}. Output => {}", repo_name, path);
+    // A temporary clone is automatically removed when `cloned` goes out of scope.
+
+    Ok(())
+}
+
+/// Computes the monthly `Stats` metrics of an online repository, as the local analysis does, on a working
+/// copy of its mirror, and writes them to the `{output_folder}/{repo}` table.
+pub fn analyze_online_repo_stats(
+    online_url: &str,
+    args: &Args,
+    start_date: &str,
+    end_date: &str,
+    status: &str,
+) -> Result<(), Box<dyn Error>> {
+    let clone_options = clone_options(args, start_date);
+    if clone_options.blobless {
+        return Err("the full metrics read the file contents, which a blobless clone does not have".into());
     }
+    let cloned = clone_cache::open_or_clone(online_url, &clone_options)?;
+    let working = clone_cache::working_copy(&cloned, &clone_options)?;
+    let repo_name = repo_name(online_url);
+
+    let mut repo_obj = Repo::new(&working.repo, &repo_name, start_date, end_date, status, args)?;
+    repo_obj.checkout_master_main_trunk(args)?;
+    let java_path = crate::java_path();
+    let mut stats = Stats::new(&repo_name, repo_obj.start_date, repo_obj.end_date, status, &java_path);
+    let metrics = stats.compute_statistics(&mut repo_obj, args)?;
+    database::store_analysis(&repo_obj, &WindowScheme::from_args(args).label(), &metrics)?;
+
+    let stem = output_stem(args, online_url)?;
+    let format = args.flag_format.unwrap_or_default();
+    let languages_stem = format!("{}-languages", stem);
+    output::write_table(&languages_stem, format, Some(&LanguageRow::default()), LanguageRow::of(&repo_name, metrics.iter().map(Stats::metrics)))?;
+    let path = output::write_table(&stem, format, Some(&Stats::default()), metrics)?;
 
-    info!("Clone-based commit analysis completed for repository {}. CSV => {}", repo_name, csv_path);
-    // The TempDir is automatically removed when it goes out of scope.
+    let profiles_stem = format!("{}-developer-activity", stem);
+    activity_metrics::write_developer_profiles(&repo_name, &repo_obj.commits, &profiles_stem, format)?;
 
+    info!("Clone-based full analysis completed for repository {}. Output => {}", repo_name, path);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repo_key() {
+        assert_eq!(repo_name("https://github.com/apache/hunter.git"), "hunter");
+        assert_eq!(repo_key("https://github.com/apache/hunter.git"), "github.com/apache/hunter");
+        assert_eq!(repo_key("git@GitHub.com:apache/hunter.git"), "github.com/apache/hunter");
+        assert_eq!(repo_key("https://gitlab.com/b/core/"), "gitlab.com/b/core");
+        assert_eq!(repo_key("https://gitlab.com/group/sub/core/-/issues"), "gitlab.com/group/sub/core");
+        assert_ne!(repo_key("https://github.com/a/core"), repo_key("https://gitlab.com/b/core"));
+        assert_eq!(repo_key("https://example.org/../../etc"), "example.org/etc");
+    }
+}
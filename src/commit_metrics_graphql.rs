@@ -1,26 +1,21 @@
 // commit_metrics_graphql.rs
-use reqwest::blocking::Client;
-use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE, USER_AGENT};
 use serde_json::json;
+use std::collections::HashSet;
 use std::error::Error;
-use std::fs;
-use std::fs::File;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
 use chrono::{DateTime, Utc, NaiveDate};
-use crate::Args;
-use log::{info, error};
+use rayon::prelude::*;
+use crate::{commit_metrics_clone, Args};
+use crate::dev_stats::CommitFileMetrics;
+use crate::forge::{self, FileChange, ForgeCommit, RepoRef};
+use crate::github_client::GitHubClient;
+use crate::output::{self, OutputFormat, TableWriter};
+use crate::windows::{self, WindowScheme};
+use log::{info, error, warn};
 
 // Types for GraphQL response
-#[derive(Debug, serde::Deserialize)]
-struct GraphQLResponse<T> {
-    data: Option<T>,
-    errors: Option<Vec<GraphQLError>>,
-}
-
-#[derive(Debug, serde::Deserialize)]
-struct GraphQLError {
-    message: String,
-}
-
 #[derive(Debug, serde::Deserialize)]
 struct RepositoryData {
     repository: Option<Repository>,
@@ -70,22 +65,9 @@ struct CommitAuthor {
 // Types for REST API commit details
 #[derive(Debug, serde::Deserialize)]
 struct RestCommit {
-    sha: String,
-    commit: InnerCommit,
     files: Option<Vec<RestFile>>,
 }
 
-#[derive(Debug, serde::Deserialize)]
-struct InnerCommit {
-    message: String,
-    author: RestAuthor,
-}
-
-#[derive(Debug, serde::Deserialize)]
-struct RestAuthor {
-    date: String,
-}
-
 #[derive(Debug, serde::Deserialize)]
 struct RestFile {
     filename: String,
@@ -94,60 +76,47 @@ struct RestFile {
     deletions: u32,
 }
 
-// --- Added `commit_url` field below ---
-#[derive(Debug, serde::Serialize)]
+/// Files per page of a commit's REST details; GitHub lists at most 3000 files of a commit
+const FILES_PAGE_SIZE: usize = 100;
+const MAX_FILE_PAGES: usize = 30;
+
+/// Commits whose files are fetched concurrently before their rows are written and checkpointed
+const COMMIT_BATCH_SIZE: usize = 50;
+
+/// Same columns as the `DevStats` rows of the local commit-file-dev analysis
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
 struct CsvRow {
     project: String,
     start_date: String,
     end_date: String,
     status: String,
-    incubation_month: String,
-    commit_sha: String,
-    commit_url: String,                // <--- NEW FIELD
-    email: String,
-    name: String,
-    date: String,
-    timestamp: i64,
-    filename: String,
-    change_type: String,
-    lines_added: u32,
-    lines_deleted: u32,
-    commit_message: String,
+    #[serde(flatten)]
+    metrics: CommitFileMetrics,
 }
 
-pub fn analyze_online_repo(
-    online_url: &str,
-    args: &Args,
-    start_date: &str,
-    end_date: &str,
-    status: &str,
-) -> Result<(), Box<dyn Error>> {
-    // Extract owner and repo name from URL.
-    let url = online_url.trim_end_matches(".git");
-    let parts: Vec<&str> = url.split('/').collect();
-    if parts.len() < 2 {
-        return Err("Invalid GitHub URL provided.".into());
-    }
-    let owner = parts[parts.len()-2];
-    let repo = parts[parts.len()-1];
-
-    // Build HTTP client with headers (using GITHUB_TOKEN from .env)
-    let github_token = std::env::var("GITHUB_TOKEN")
-        .map_err(|_| "GITHUB_TOKEN environment variable is not set.")?;
-    let mut headers = HeaderMap::new();
-    headers.insert(AUTHORIZATION, HeaderValue::from_str(&format!("Bearer {}", github_token))?);
-    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-    headers.insert(USER_AGENT, HeaderValue::from_static("rust-github-client"));
-    let client = Client::builder().default_headers(headers).build()?;
+/// Commit history of the default branch of a GitHub repository, fetched with GraphQL,
+/// only the commits within `since`..=`until` when given
+pub fn fetch_history(
+    owner: &str,
+    repo: &str,
+    since: Option<&str>,
+    until: Option<&str>,
+) -> Result<Vec<ForgeCommit>, Box<dyn Error>> {
+    // Shared GitHub client (using GITHUB_TOKEN from .env)
+    let client = GitHubClient::global()?;
 
     // GraphQL query to fetch commit history from the default branch.
     let query = r#"
-    query($owner: String!, $name: String!, $cursor: String) {
+    query($owner: String!, $name: String!, $cursor: String, $since: GitTimestamp, $until: GitTimestamp) {
+      rateLimit {
+        remaining
+        resetAt
+      }
       repository(owner: $owner, name: $name) {
         defaultBranchRef {
           target {
             ... on Commit {
-              history(first: 100, after: $cursor) {
+              history(first: 100, after: $cursor, since: $since, until: $until) {
                 pageInfo {
                   hasNextPage
                   endCursor
@@ -176,116 +145,344 @@ pub fn analyze_online_repo(
             "owner": owner,
             "name": repo,
             "cursor": cursor,
+            "since": since,
+            "until": until,
         });
-        let body = json!({
-            "query": query,
-            "variables": variables,
-        });
-        let response = client.post("https://api.github.com/graphql")
-            .json(&body)
-            .send()?;
-        if !response.status().is_success() {
-            let status_code = response.status();
-            let text = response.text()?;
-            error!("GraphQL API request failed with status {}: {}", status_code, text);
-            return Err(format!("GraphQL API error: {}", status_code).into());
-        }
-        let resp_json: GraphQLResponse<RepositoryData> = response.json()?;
-        if let Some(errors) = resp_json.errors {
-            for err in errors {
-                error!("GraphQL error: {}", err.message);
-            }
-            return Err("GraphQL query failed.".into());
-        }
-        let data = resp_json.data.ok_or("No data received from GitHub GraphQL API.")?;
+        let data: RepositoryData = client.graphql(query, variables)?;
         let repo_data = data.repository.ok_or("Repository not found or access denied.")?;
         let default_branch = repo_data.defaultBranchRef.ok_or("Default branch not found.")?;
         let commit_history_target = default_branch.target.ok_or("Default branch target not found.")?;
         let history = commit_history_target.history;
 
-        all_commits.extend(history.nodes);
+        all_commits.extend(history.nodes.into_iter().map(|c| ForgeCommit {
+            sha: c.oid,
+            message: c.message,
+            date: c.committedDate,
+            author_name: c.author.as_ref().and_then(|a| a.name.clone()).unwrap_or_default(),
+            author_email: c.author.as_ref().and_then(|a| a.email.clone()).unwrap_or_default(),
+        }));
         if history.pageInfo.hasNextPage {
             cursor = history.pageInfo.endCursor;
         } else {
             break;
         }
     }
-    info!("Fetched {} commits via GraphQL", all_commits.len());
+    Ok(all_commits)
+}
+
+/// Files changed by a GitHub commit, from the REST API. Commits with more files than fit in
+/// a page are paged, up to the 3000 files GitHub returns.
+pub fn fetch_commit_files(owner: &str, repo: &str, sha: &str) -> Result<Vec<FileChange>, Box<dyn Error>> {
+    let client = GitHubClient::global()?;
+    let mut files = Vec::new();
+    for page in 1..=MAX_FILE_PAGES {
+        let commit_rest_path = format!(
+            "/repos/{}/{}/commits/{}?per_page={}&page={}",
+            owner, repo, sha, FILES_PAGE_SIZE, page
+        );
+        let rest_commit: RestCommit = client.get(&commit_rest_path)?;
+        let page_files = rest_commit.files.unwrap_or_default();
+        let count = page_files.len();
+        files.extend(page_files.into_iter().map(|f| FileChange {
+            filename: f.filename,
+            status: f.status,
+            additions: f.additions,
+            deletions: f.deletions,
+        }));
+        if count < FILES_PAGE_SIZE {
+            return Ok(files);
+        }
+    }
+    warn!("Commit {} of {}/{} changes more than {} files, the rest are not listed", sha, owner, repo, files.len());
+    Ok(files)
+}
+
+/// The one-letter change types of the local analysis
+fn change_type(status: &str) -> &'static str {
+    match status {
+        "added" => "A",
+        "removed" => "D",
+        "modified" | "changed" => "M",
+        "renamed" => "R",
+        _ => "U",
+    }
+}
+
+/// Commits already written to the CSV by an interrupted run, one SHA per line
+fn read_checkpoint(path: &Path) -> Result<HashSet<String>, Box<dyn Error>> {
+    if !path.exists() {
+        return Ok(HashSet::new());
+    }
+    let mut done = HashSet::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            done.insert(line.trim().to_string());
+        }
+    }
+    Ok(done)
+}
 
-    // Prepare CSV writer.
-    let output_folder = args.flag_output_folder.as_deref().unwrap_or("data");
-    fs::create_dir_all(output_folder)?;
-    let csv_path = format!("{}/{}-commit-file-dev.csv", output_folder, repo);
-    let file = File::create(&csv_path)?;
-    let mut wtr = csv::WriterBuilder::new().has_headers(true).from_writer(file);
+/// The stem and format of the table the rows are appended to while the commits are fetched: the output table
+/// itself, except for Parquet, which cannot be appended to. Its rows are kept as JSON Lines in
+/// `{stem}.partial.jsonl`, which is written as the Parquet table at the end of each run.
+fn staging(stem: &str, format: OutputFormat) -> (String, OutputFormat) {
+    match format {
+        OutputFormat::Parquet => (format!("{}.partial", stem), OutputFormat::JsonLines),
+        _ => (stem.to_string(), format),
+    }
+}
 
-    // For each commit, fetch detailed commit info (REST API) to get file-level changes.
+/// Keeps only the CSV or JSON Lines rows of checkpointed commits, dropping those of a batch that was
+/// interrupted after its rows were written but before it was checkpointed
+fn truncate_to_checkpoint(path: &str, format: OutputFormat, done: &HashSet<String>) -> Result<(), Box<dyn Error>> {
+    let tmp_path = format!("{}.tmp", path);
+    if format == OutputFormat::JsonLines {
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            // the last line may have been cut by the interruption
+            let Ok(row) = serde_json::from_str::<serde_json::Value>(&line) else {
+                continue;
+            };
+            if row["commit_sha"].as_str().is_some_and(|sha| done.contains(sha)) {
+                writeln!(writer, "{}", line)?;
+            }
+        }
+        writer.flush()?;
+    } else {
+        let mut reader = csv::Reader::from_path(path)?;
+        let headers = reader.headers()?.clone();
+        let sha_column = headers
+            .iter()
+            .position(|h| h == "commit_sha")
+            .ok_or("No commit_sha column in the CSV to resume")?;
+        let mut writer = csv::Writer::from_path(&tmp_path)?;
+        writer.write_record(&headers)?;
+        for record in reader.records() {
+            let record = record?;
+            if record.get(sha_column).is_some_and(|sha| done.contains(sha)) {
+                writer.write_record(&record)?;
+            }
+        }
+        writer.flush()?;
+    }
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// The rows of the JSON Lines staging table of a Parquet output, see `staging`
+fn read_staged_rows(path: &str) -> Result<Vec<CsvRow>, Box<dyn Error>> {
+    let mut rows = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        rows.push(serde_json::from_str(&line?)?);
+    }
+    Ok(rows)
+}
+
+/// Writes the `{repo}-commit-file-dev` table, in the `--format` format, for a repository on GitHub, GitLab or
+/// Gitea, using the forge APIs instead of a clone. The forge is chosen from `online_url`.
+/// Only commits between the start and end dates are fetched, and their windows (`--window`) follow the local
+/// analysis. File details are fetched in batches; each finished batch is recorded in
+/// `{repo}-commit-file-dev.checkpoint`, so an interrupted run resumes where it stopped. Commits whose details
+/// cannot be fetched are skipped and retried by the next run.
+pub fn analyze_online_repo(
+    online_url: &str,
+    args: &Args,
+    start_date: &str,
+    end_date: &str,
+    status: &str,
+) -> Result<(), Box<dyn Error>> {
+    // without an end date, the history up to today, the last commit the forge can have
+    let today = Utc::now().format("%Y-%m-%d").to_string();
+    let end_date = if end_date.is_empty() { today.as_str() } else { end_date };
+    NaiveDate::parse_from_str(start_date, "%Y-%m-%d")?;
+    NaiveDate::parse_from_str(end_date, "%Y-%m-%d")?;
+    let repo_ref = RepoRef::parse_as(online_url, args.flag_forge)?;
+    let repo = repo_ref.repo.as_str();
+    let forge = forge::for_repo(&repo_ref)?;
+    let scheme = WindowScheme::from_args(args);
+    if scheme == WindowScheme::Releases {
+        return Err("release windows need the repository's tags; analyze a clone instead of using --commit-graphql".into());
+    }
+    let windows = scheme.windows(start_date, end_date, &[])?;
+
+    let since = format!("{}T00:00:00Z", start_date);
+    let until = format!("{}T23:59:59Z", end_date);
+    let all_commits = forge.commits(&repo_ref, Some(&since), Some(&until))?;
+    info!("Fetched {} commits of {} from its forge", all_commits.len(), repo);
+
+    let format = args.flag_format.unwrap_or_default();
+    let stem = format!("{}-commit-file-dev", commit_metrics_clone::output_stem(args, online_url)?);
+    let (staging_stem, staging_format) = staging(&stem, format);
+    let staging_path = format!("{}.{}", staging_stem, staging_format.extension());
+    let checkpoint_path = format!("{}.checkpoint", stem);
+
+    // Resume from the checkpoint of an interrupted run, or start a new table
+    let mut done = read_checkpoint(Path::new(&checkpoint_path))?;
+    let mut wtr = if !done.is_empty() && Path::new(&staging_path).exists() {
+        truncate_to_checkpoint(&staging_path, staging_format, &done)?;
+        info!("Resuming the analysis of {}: {} commits were already written", repo, done.len());
+        TableWriter::append(&staging_path, staging_format)?
+    } else {
+        // without the table, the rows of the checkpointed commits are gone: start over
+        done.clear();
+        File::create(&checkpoint_path)?;
+        TableWriter::create(&staging_stem, staging_format)?.with_template(&CsvRow::default())?
+    };
+    let mut checkpoint = OpenOptions::new().append(true).open(&checkpoint_path)?;
+
+    let mut pending = Vec::new();
     for commit in all_commits {
-        let commit_rest_url = format!(
-            "https://api.github.com/repos/{}/{}/commits/{}",
-            owner, repo, commit.oid
-        );
-        let rest_response = client.get(&commit_rest_url).send()?;
-        if !rest_response.status().is_success() {
-            error!("Failed to fetch commit details for {}: status {}", commit.oid, rest_response.status());
+        if done.contains(&commit.sha) {
             continue;
         }
-        let rest_commit: RestCommit = rest_response.json()?;
-        let commit_date = DateTime::parse_from_rfc3339(&commit.committedDate)?
-            .with_timezone(&Utc);
-        let timestamp = commit_date.timestamp();
-        let time_window = args.flag_time_window.unwrap_or(30);
-        let start_naive = NaiveDate::parse_from_str(start_date, "%Y-%m-%d")?;
-        let commit_naive = commit_date.date_naive();
-        let days_diff = (commit_naive - start_naive).num_days();
-        let incubation_month = ((days_diff / time_window) + 1).to_string();
-
-        if let Some(files) = rest_commit.files {
-            for file_detail in files {
-                let row = CsvRow {
-                    project: repo.to_string(),
-                    start_date: start_date.to_string(),
-                    end_date: end_date.to_string(),
-                    status: status.to_string(),
-                    incubation_month: incubation_month.clone(),
-                    commit_sha: commit.oid.clone(),
-                    commit_url: format!("https://github.com/{}/{}/commit/{}", owner, repo, commit.oid), // <--- POPULATE URL
-                    email: commit.author.as_ref().and_then(|a| a.email.clone()).unwrap_or_default(),
-                    name: commit.author.as_ref().and_then(|a| a.name.clone()).unwrap_or_default(),
-                    date: commit.committedDate.clone(),
-                    timestamp,
-                    filename: file_detail.filename,
-                    change_type: file_detail.status,
-                    lines_added: file_detail.additions,
-                    lines_deleted: file_detail.deletions,
-                    commit_message: commit.message.clone(),
-                };
-                wtr.serialize(row)?;
+        let commit_date = match DateTime::parse_from_rfc3339(&commit.date) {
+            Ok(date) => date.with_timezone(&Utc),
+            Err(e) => {
+                error!("Cannot parse the date {} of commit {}: {}", commit.date, commit.sha, e);
+                continue;
             }
+        };
+        let months = windows::windows_of(&windows, commit_date.date_naive());
+        if months.is_empty() {
+            info!("Skipping commit {} of {} outside the start and end dates", commit.sha, commit.date);
         } else {
-            // Write a row with empty file details if none are available.
-            let row = CsvRow {
+            pending.push((commit, commit_date, months));
+        }
+    }
+
+    let mut failed = 0;
+    for batch in pending.chunks(COMMIT_BATCH_SIZE) {
+        // Errors are turned into strings, since the boxed forge errors cannot cross threads
+        let files: Vec<Result<Vec<FileChange>, String>> = batch
+            .par_iter()
+            .map(|(commit, _, _)| forge.commit_files(&repo_ref, &commit.sha).map_err(|e| e.to_string()))
+            .collect();
+
+        let mut written = Vec::new();
+        for ((commit, commit_date, months), files) in batch.iter().zip(files) {
+            let files = match files {
+                Ok(files) => files,
+                Err(e) => {
+                    error!("Failed to fetch commit details for {}: {}", commit.sha, e);
+                    failed += 1;
+                    continue;
+                }
+            };
+            let row = |month: usize, filename: String, change_type: &str, lines_added: u32, lines_deleted: u32| CsvRow {
                 project: repo.to_string(),
                 start_date: start_date.to_string(),
                 end_date: end_date.to_string(),
                 status: status.to_string(),
-                incubation_month: incubation_month.clone(),
-                commit_sha: commit.oid.clone(),
-                commit_url: format!("https://github.com/{}/{}/commit/{}", owner, repo, commit.oid), // <--- POPULATE URL
-                email: commit.author.as_ref().and_then(|a| a.email.clone()).unwrap_or_default(),
-                name: commit.author.as_ref().and_then(|a| a.name.clone()).unwrap_or_default(),
-                date: commit.committedDate.clone(),
-                timestamp,
-                filename: "".to_string(),
-                change_type: "".to_string(),
-                lines_added: 0,
-                lines_deleted: 0,
-                commit_message: commit.message.clone(),
+                metrics: CommitFileMetrics {
+                    incubation_month: month,
+                    commit_sha: commit.sha.clone(),
+                    email: commit.author_email.clone(),
+                    name: commit.author_name.clone(),
+                    date: commit_date.to_string(),
+                    timestamp: commit_date.timestamp(),
+                    filename,
+                    change_type: change_type.to_string(),
+                    lines_added: lines_added as usize,
+                    lines_deleted: lines_deleted as usize,
+                    commit_message: if args.flag_ignore_commit_message {
+                        String::new()
+                    } else {
+                        commit.message.replace('\n', " _nl_ ")
+                    },
+                    commit_url: repo_ref.commit_url(&commit.sha),
+                },
             };
-            wtr.serialize(row)?;
+            // a commit in overlapping sliding windows gets its rows once per window
+            for month in months {
+                if files.is_empty() {
+                    // Write a row with empty file details if none are available.
+                    wtr.serialize(row(*month, String::new(), "", 0, 0))?;
+                }
+                for file_detail in &files {
+                    wtr.serialize(row(
+                        *month,
+                        file_detail.filename.clone(),
+                        change_type(&file_detail.status),
+                        file_detail.additions,
+                        file_detail.deletions,
+                    ))?;
+                }
+            }
+            written.push(commit.sha.as_str());
+        }
+        wtr.flush()?;
+        for sha in written {
+            writeln!(checkpoint, "{}", sha)?;
+        }
+        checkpoint.flush()?;
+    }
+
+    wtr.finish()?;
+    if format == OutputFormat::Parquet {
+        output::write_table(&stem, format, Some(&CsvRow::default()), read_staged_rows(&staging_path)?)?;
+    }
+
+    if failed > 0 {
+        warn!(
+            "Could not fetch the details of {} commits of {}; run again to resume with them",
+            failed, repo
+        );
+    } else {
+        fs::remove_file(&checkpoint_path)?;
+        if staging_format != format {
+            fs::remove_file(&staging_path)?;
         }
     }
-    wtr.flush()?;
-    info!("GraphQL-based commit analysis completed for repository {}", repo);
+    info!("Forge API commit analysis completed for repository {}", repo);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::IncubationMonth;
+    use indexmap::IndexMap;
+
+    #[test]
+    fn test_incubation_months_match_local_analysis() {
+        let of = |windows: &IndexMap<usize, IncubationMonth>, year, month, day| {
+            windows::windows_of(windows, NaiveDate::from_ymd_opt(year, month, day).unwrap())
+        };
+        let time_windows = WindowScheme::Days(30).windows("2010-01-01", "2010-03-05", &[]).unwrap();
+        assert_eq!(of(&time_windows, 2010, 1, 30), vec![1]);
+        assert_eq!(of(&time_windows, 2010, 1, 31), vec![2]);
+        assert_eq!(of(&time_windows, 2010, 3, 5), vec![3]);
+        assert!(of(&time_windows, 2010, 3, 6).is_empty());
+
+        // calendar months are cut at the start and end dates, like the commits of the local analysis
+        let calendar = WindowScheme::Monthly.windows("2010-01-15", "2010-03-05", &[]).unwrap();
+        assert!(of(&calendar, 2010, 1, 2).is_empty());
+        assert_eq!(of(&calendar, 2010, 1, 15), vec![1]);
+        assert_eq!(of(&calendar, 2010, 3, 5), vec![3]);
+        assert!(of(&calendar, 2010, 3, 31).is_empty());
+    }
+
+    #[test]
+    fn test_truncate_to_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let csv_path = dir.path().join("hunter-commit-file-dev.csv");
+        fs::write(&csv_path, "project,commit_sha,filename\nhunter,a,x.rs\nhunter,b,y.rs\nhunter,a,z.rs\n").unwrap();
+        let checkpoint_path = dir.path().join("hunter-commit-file-dev.checkpoint");
+        fs::write(&checkpoint_path, "a\n").unwrap();
+
+        let done = read_checkpoint(&checkpoint_path).unwrap();
+        truncate_to_checkpoint(csv_path.to_str().unwrap(), OutputFormat::Csv, &done).unwrap();
+        assert_eq!(
+            fs::read_to_string(&csv_path).unwrap(),
+            "project,commit_sha,filename\nhunter,a,x.rs\nhunter,a,z.rs\n"
+        );
+
+        // the staging rows of a Parquet output, with a row cut by the interruption
+        let jsonl_path = dir.path().join("hunter-commit-file-dev.partial.jsonl");
+        fs::write(&jsonl_path, "{\"commit_sha\":\"a\"}\n{\"commit_sha\":\"b\"}\n{\"commit_sha\":\"a\",\"fi").unwrap();
+        truncate_to_checkpoint(jsonl_path.to_str().unwrap(), OutputFormat::JsonLines, &done).unwrap();
+        assert_eq!(fs::read_to_string(&jsonl_path).unwrap(), "{\"commit_sha\":\"a\"}\n");
+    }
+}
@@ -1,7 +1,9 @@
 use core::panic;
 
 use crate::utils::{self, convert_time};
-use crate::Repo;
+use crate::metrics::{MetricProvider, Metrics, Window};
+use crate::repo::Repo;
+use crate::sentiment::{SentimentAnalyzer, SentimentSummary};
 use git2::{Commit, Diff, DiffFindOptions, DiffFormat, DiffOptions, Error};
 use indexmap::map::Entry;
 use indexmap::{IndexMap, IndexSet};
@@ -386,6 +388,56 @@ impl<'a> CommitsMetrics<'a> {
     }
 }
 
+/// Commit activity of a window: commits, authors, contributors and changed lines and files. New
+/// contributors are those whose email was not seen in any earlier window.
+#[derive(Default)]
+pub struct CommitsProvider {
+    existing_contributors: IndexSet<String>,
+}
+
+impl MetricProvider for CommitsProvider {
+    fn family(&self) -> &'static str {
+        "commits"
+    }
+
+    fn compute(&mut self, window: &Window, metrics: &mut Metrics) -> Result<(), Box<dyn std::error::Error>> {
+        let month_metrics = CommitsMetrics::new(window.repo, window.commits)?;
+        let commits = month_metrics.commits.len();
+        let files_modified = month_metrics.files_modified();
+        let authors = month_metrics.authors_emails();
+        let (minor_contributors, major_contributors) = month_metrics.major_minor_contributors();
+
+        metrics.active_days = month_metrics.active_days();
+        metrics.added_lines = month_metrics.added_lines();
+        metrics.authors = authors.len();
+        metrics.avg_files_modified_commit = if commits == 0 {
+            0.0
+        } else {
+            files_modified as f64 / commits as f64
+        };
+        metrics.commits = commits;
+        metrics.committers = month_metrics.committers_emails().len();
+        metrics.deleted_lines = month_metrics.deleted_lines();
+        metrics.files_added = month_metrics.files_added();
+        metrics.files_deleted = month_metrics.files_deleted();
+        metrics.files_modified = files_modified;
+        metrics.files_renamed = month_metrics.files_renamed();
+        metrics.major_contributors = major_contributors;
+        metrics.minor_contributors = minor_contributors;
+        metrics.new_contributors = authors
+            .into_iter()
+            .filter(|email| self.existing_contributors.insert(email.clone()))
+            .count();
+        let analyzer = SentimentAnalyzer::global();
+        let scores: Vec<_> = window.commits.iter().map(|c| analyzer.score(c.message().unwrap_or(""))).collect();
+        let sentiment = SentimentSummary::of(&scores);
+        metrics.commits_sentiment_compound_avg = sentiment.compound_avg;
+        metrics.commits_negative = sentiment.negative;
+        metrics.commits_uncivil = sentiment.uncivil;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod test {
 
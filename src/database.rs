@@ -0,0 +1,636 @@
+// src/database.rs
+//! Optional SQLite output of a whole cohort run, enabled with `--sqlite`: normalized tables of the projects,
+//! their analysis windows and metrics, commits and file changes, developers, emails, and issues with their
+//! comments. The rows of a project are prepared first, without holding the connection, then written in one
+//! transaction, so the database of an interrupted run holds every project written so far, and none half-written.
+use std::collections::HashSet;
+use std::error::Error;
+use std::sync::{Mutex, OnceLock};
+
+use git2::{Commit, DiffFindOptions, Patch, Repository};
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, Transaction};
+use serde::Serialize;
+
+use crate::email_content::CleanedEmail;
+use crate::output::{cells, Cell};
+use crate::repo::Repo;
+use crate::utils::convert_time;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS projects (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    status TEXT
+);
+CREATE TABLE IF NOT EXISTS developers (
+    id INTEGER PRIMARY KEY,
+    -- the email address, or the forge login of an issue author without a public email
+    identity TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    name TEXT
+);
+CREATE TABLE IF NOT EXISTS windows (
+    id INTEGER PRIMARY KEY,
+    project_id INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    analysis TEXT NOT NULL,
+    number INTEGER NOT NULL,
+    start_date TEXT NOT NULL,
+    end_date TEXT NOT NULL,
+    UNIQUE (project_id, analysis, number)
+);
+CREATE TABLE IF NOT EXISTS metrics (
+    window_id INTEGER NOT NULL REFERENCES windows(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    value,
+    PRIMARY KEY (window_id, name)
+);
+CREATE TABLE IF NOT EXISTS commits (
+    id INTEGER PRIMARY KEY,
+    project_id INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    sha TEXT NOT NULL,
+    author_id INTEGER REFERENCES developers(id),
+    committer_id INTEGER REFERENCES developers(id),
+    authored_at TEXT NOT NULL,
+    committed_at TEXT NOT NULL,
+    utc_offset_minutes INTEGER NOT NULL,
+    parents INTEGER NOT NULL,
+    message TEXT NOT NULL,
+    UNIQUE (project_id, sha)
+);
+CREATE INDEX IF NOT EXISTS commits_author ON commits(author_id);
+CREATE INDEX IF NOT EXISTS commits_date ON commits(project_id, committed_at);
+CREATE TABLE IF NOT EXISTS file_changes (
+    commit_id INTEGER NOT NULL REFERENCES commits(id) ON DELETE CASCADE,
+    path TEXT NOT NULL,
+    change_type TEXT NOT NULL,
+    lines_added INTEGER NOT NULL,
+    lines_deleted INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS file_changes_commit ON file_changes(commit_id);
+CREATE INDEX IF NOT EXISTS file_changes_path ON file_changes(path);
+CREATE TABLE IF NOT EXISTS emails (
+    id INTEGER PRIMARY KEY,
+    project_id INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    message_id TEXT NOT NULL,
+    sender_id INTEGER REFERENCES developers(id),
+    sent_at TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    is_reply INTEGER NOT NULL,
+    original_words INTEGER NOT NULL,
+    quoted_words INTEGER NOT NULL,
+    has_patch INTEGER NOT NULL,
+    has_stack_trace INTEGER NOT NULL,
+    body TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS emails_date ON emails(project_id, sent_at);
+CREATE INDEX IF NOT EXISTS emails_sender ON emails(sender_id);
+CREATE TABLE IF NOT EXISTS issues (
+    id INTEGER PRIMARY KEY,
+    project_id INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    number INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    state TEXT NOT NULL,
+    author_id INTEGER REFERENCES developers(id),
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    closed_at TEXT,
+    body TEXT,
+    UNIQUE (project_id, number)
+);
+CREATE INDEX IF NOT EXISTS issues_author ON issues(author_id);
+CREATE TABLE IF NOT EXISTS comments (
+    id INTEGER PRIMARY KEY,
+    issue_id INTEGER NOT NULL REFERENCES issues(id) ON DELETE CASCADE,
+    author_id INTEGER REFERENCES developers(id),
+    created_at TEXT NOT NULL,
+    body TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS comments_issue ON comments(issue_id);
+CREATE INDEX IF NOT EXISTS comments_author ON comments(author_id);
+";
+
+/// Columns of the analysis rows stored in other tables than `metrics`: the project's in `projects`, and the
+/// window's in `windows`
+const WINDOW_COLUMNS: [&str; 7] =
+    ["project", "start_date", "end_date", "status", "measurement_month", "window_start_date", "window_end_date"];
+
+pub struct Database {
+    connection: Mutex<Connection>,
+}
+
+/// The global database of `--sqlite`, shared by every analysis of the run
+static GLOBAL: OnceLock<Database> = OnceLock::new();
+
+/// Opens the database of `--sqlite` for the whole run
+pub fn open_global(path: &str) -> Result<(), Box<dyn Error>> {
+    let database = Database::open(path)?;
+    GLOBAL.set(database).map_err(|_| "the SQLite database is already open")?;
+    Ok(())
+}
+
+/// The database of `--sqlite`, if set
+pub fn global() -> Option<&'static Database> {
+    GLOBAL.get()
+}
+
+impl Database {
+    /// Opens the database at `path`, creating it and its tables if needed
+    pub fn open(path: &str) -> Result<Database, Box<dyn Error>> {
+        let connection = Connection::open(path)?;
+        connection.pragma_update(None, "foreign_keys", true)?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Database { connection: Mutex::new(connection) })
+    }
+
+    /// Runs `write` on the tables of `project` in one transaction: either all of its writes are stored, or none
+    pub fn write_project(
+        &self,
+        project: &str,
+        write: impl FnOnce(&ProjectTables) -> Result<(), Box<dyn Error>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut connection = self.connection.lock().map_err(|_| "the SQLite connection is poisoned")?;
+        let transaction = connection.transaction()?;
+        transaction.execute("INSERT INTO projects (name) VALUES (?1) ON CONFLICT (name) DO NOTHING", [project])?;
+        let project_id = transaction.query_row("SELECT id FROM projects WHERE name = ?1", [project], |row| row.get(0))?;
+        write(&ProjectTables { transaction: &transaction, project_id })?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// The SHAs of the project's stored commits
+    fn stored_commits(&self, project: &str) -> Result<HashSet<String>, Box<dyn Error>> {
+        let connection = self.connection.lock().map_err(|_| "the SQLite connection is poisoned")?;
+        let mut statement = connection
+            .prepare("SELECT sha FROM commits JOIN projects ON projects.id = project_id WHERE projects.name = ?1")?;
+        let shas = statement.query_map([project], |row| row.get(0))?.collect::<Result<_, _>>()?;
+        Ok(shas)
+    }
+}
+
+/// A commit and the files it changes, as stored in the `commits` and `file_changes` tables
+pub struct CommitRecord {
+    pub sha: String,
+    pub author: Option<String>,
+    pub author_name: Option<String>,
+    pub committer: Option<String>,
+    pub committer_name: Option<String>,
+    pub authored_at: String,
+    pub committed_at: String,
+    pub utc_offset_minutes: i32,
+    pub parents: usize,
+    pub message: String,
+    pub file_changes: Vec<FileChange>,
+}
+
+/// A file changed by a commit: (path, change type, lines added, lines deleted)
+pub type FileChange = (String, &'static str, usize, usize);
+
+impl CommitRecord {
+    /// The commit's record, with the file changes diffed against its first parent
+    fn new(repo: &Repository, commit: &Commit) -> Result<CommitRecord, Box<dyn Error>> {
+        let (author, committer) = (commit.author(), commit.committer());
+        Ok(CommitRecord {
+            sha: commit.id().to_string(),
+            author: author.email().map(str::to_string),
+            author_name: author.name().map(str::to_string),
+            committer: committer.email().map(str::to_string),
+            committer_name: committer.name().map(str::to_string),
+            authored_at: convert_time(&author.when()).to_rfc3339(),
+            committed_at: convert_time(&commit.time()).to_rfc3339(),
+            utc_offset_minutes: author.when().offset_minutes(),
+            parents: commit.parent_count(),
+            message: commit.message().unwrap_or("").to_string(),
+            file_changes: file_changes(repo, commit)?,
+        })
+    }
+}
+
+/// The rows of one analysis of a project, as the named cells of its output rows
+type AnalysisRecord = (String, Vec<Vec<(String, Cell)>>);
+
+/// The rows of a project to store in the `--sqlite` database, prepared without holding its connection: the
+/// diffs of the commits are the slow part of a write. `store` writes them in one transaction. Without
+/// `--sqlite`, nothing is prepared.
+pub struct ProjectRecords {
+    project: String,
+    status: String,
+    /// The SHAs of the commits stored or prepared
+    known: Option<HashSet<String>>,
+    commits: Vec<CommitRecord>,
+    analyses: Vec<AnalysisRecord>,
+}
+
+impl ProjectRecords {
+    pub fn new(project: &str, status: &str) -> ProjectRecords {
+        ProjectRecords {
+            project: project.to_string(),
+            status: status.to_string(),
+            known: None,
+            commits: vec![],
+            analyses: vec![],
+        }
+    }
+
+    /// Adds the commits of `repo` that are neither stored nor added yet
+    pub fn add_commits(&mut self, repo: &Repo) -> Result<(), Box<dyn Error>> {
+        let Some(database) = global() else {
+            return Ok(());
+        };
+        if self.known.is_none() {
+            self.known = Some(database.stored_commits(&self.project)?);
+        }
+        let known = self.known.get_or_insert_with(HashSet::new);
+        for commit in &repo.commits {
+            if known.insert(commit.id().to_string()) {
+                self.commits.push(CommitRecord::new(repo.repo, commit)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the output rows of one of the project's analyses, e.g. `Stats`
+    pub fn add_analysis<T: Serialize>(&mut self, analysis: &str, rows: &[T]) -> Result<(), Box<dyn Error>> {
+        if global().is_some() {
+            let rows = rows.iter().map(cells).collect::<Result<_, _>>()?;
+            self.analyses.push((analysis.to_string(), rows));
+        }
+        Ok(())
+    }
+
+    /// Writes the prepared rows in one transaction
+    pub fn store(self) -> Result<(), Box<dyn Error>> {
+        match global() {
+            Some(database) => database.write_project(&self.project, |tables| {
+                tables.set_status(&self.status)?;
+                tables.commits(&self.commits)?;
+                for (analysis, rows) in &self.analyses {
+                    tables.analysis(analysis, rows)?;
+                }
+                Ok(())
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// An issue and its comments, as stored in the `issues` and `comments` tables
+pub struct IssueRecord {
+    pub number: u32,
+    pub title: String,
+    pub state: String,
+    /// The author's email, or their login if it is not public
+    pub author: Option<String>,
+    pub author_name: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    pub closed_at: Option<String>,
+    pub body: Option<String>,
+    pub comments: Vec<CommentRecord>,
+}
+
+pub struct CommentRecord {
+    /// The author's email, or their login if it is not public
+    pub author: Option<String>,
+    pub author_name: Option<String>,
+    pub created_at: String,
+    pub body: String,
+}
+
+/// The tables of a project, inside its transaction
+pub struct ProjectTables<'t> {
+    transaction: &'t Transaction<'t>,
+    project_id: i64,
+}
+
+impl<'t> ProjectTables<'t> {
+    pub fn set_status(&self, status: &str) -> Result<(), Box<dyn Error>> {
+        self.transaction
+            .execute("UPDATE projects SET status = ?1 WHERE id = ?2", params![status, self.project_id])?;
+        Ok(())
+    }
+
+    /// The id of the developer with this email (or login), added on first sight with the name it was seen with
+    fn developer(&self, identity: Option<&str>, name: Option<&str>) -> Result<Option<i64>, Box<dyn Error>> {
+        let identity = match identity.map(str::trim).filter(|identity| !identity.is_empty()) {
+            Some(identity) => identity,
+            None => return Ok(None),
+        };
+        self.transaction.execute(
+            "INSERT INTO developers (identity, name) VALUES (?1, ?2) ON CONFLICT (identity) DO NOTHING",
+            params![identity, name],
+        )?;
+        let id = self
+            .transaction
+            .query_row("SELECT id FROM developers WHERE identity = ?1", [identity], |row| row.get(0))?;
+        Ok(Some(id))
+    }
+
+    /// Adds the commits not stored yet, with the lines added and deleted in each file they change. Commits never
+    /// change, so the ones stored by an earlier run or analysis are kept as they are.
+    pub fn commits(&self, commits: &[CommitRecord]) -> Result<(), Box<dyn Error>> {
+        for commit in commits {
+            let author_id = self.developer(commit.author.as_deref(), commit.author_name.as_deref())?;
+            let committer_id = self.developer(commit.committer.as_deref(), commit.committer_name.as_deref())?;
+            let inserted = self.transaction.execute(
+                "INSERT INTO commits (project_id, sha, author_id, committer_id, authored_at, committed_at,
+                 utc_offset_minutes, parents, message) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+                 ON CONFLICT (project_id, sha) DO NOTHING",
+                params![
+                    self.project_id,
+                    commit.sha,
+                    author_id,
+                    committer_id,
+                    commit.authored_at,
+                    commit.committed_at,
+                    commit.utc_offset_minutes,
+                    commit.parents,
+                    commit.message,
+                ],
+            )?;
+            if inserted == 0 {
+                continue;
+            }
+            let commit_id = self.transaction.last_insert_rowid();
+            for (path, change_type, lines_added, lines_deleted) in &commit.file_changes {
+                self.transaction.execute(
+                    "INSERT INTO file_changes (commit_id, path, change_type, lines_added, lines_deleted)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![commit_id, path, change_type, lines_added, lines_deleted],
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the windows of `analysis` with those of its output rows, given as their cells, and stores every
+    /// other column of a row in `metrics`, keyed by column name. The rows' `measurement_month`,
+    /// `window_start_date` and `window_end_date` columns give their window.
+    pub fn analysis(&self, analysis: &str, rows: &[Vec<(String, Cell)>]) -> Result<(), Box<dyn Error>> {
+        self.transaction.execute(
+            "DELETE FROM windows WHERE project_id = ?1 AND analysis = ?2",
+            params![self.project_id, analysis],
+        )?;
+        for row in rows {
+            let (window, metrics): (Vec<_>, Vec<_>) =
+                row.iter().cloned().partition(|(name, _)| WINDOW_COLUMNS.contains(&name.as_str()));
+            let column = |column: &str| {
+                window
+                    .iter()
+                    .find(|(name, _)| name == column)
+                    .map(|(_, cell)| value(cell.clone()))
+                    .ok_or_else(|| format!("the {} rows have no {} column", analysis, column))
+            };
+            self.transaction.execute(
+                "INSERT INTO windows (project_id, analysis, number, start_date, end_date) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    self.project_id,
+                    analysis,
+                    column("measurement_month")?,
+                    column("window_start_date")?,
+                    column("window_end_date")?,
+                ],
+            )?;
+            let window_id = self.transaction.last_insert_rowid();
+            for (name, cell) in metrics {
+                self.transaction.execute(
+                    "INSERT INTO metrics (window_id, name, value) VALUES (?1, ?2, ?3)",
+                    params![window_id, name, value(cell)],
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the project's emails
+    pub fn emails(&self, emails: &[CleanedEmail]) -> Result<(), Box<dyn Error>> {
+        self.transaction.execute("DELETE FROM emails WHERE project_id = ?1", [self.project_id])?;
+        for email in emails {
+            let sender_id = self.developer(Some(&email.from_email), Some(&email.from))?;
+            self.transaction.execute(
+                "INSERT INTO emails (project_id, message_id, sender_id, sent_at, subject, is_reply, original_words,
+                 quoted_words, has_patch, has_stack_trace, body) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    self.project_id,
+                    email.message_id,
+                    sender_id,
+                    email.date,
+                    email.subject,
+                    email.is_reply,
+                    email.original_words,
+                    email.quoted_words,
+                    email.has_patch,
+                    email.has_stack_trace,
+                    email.body,
+                ],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the project's issues and their comments
+    pub fn issues(&self, issues: &[IssueRecord]) -> Result<(), Box<dyn Error>> {
+        self.transaction.execute("DELETE FROM issues WHERE project_id = ?1", [self.project_id])?;
+        for issue in issues {
+            let author_id = self.developer(issue.author.as_deref(), issue.author_name.as_deref())?;
+            self.transaction.execute(
+                "INSERT INTO issues (project_id, number, title, state, author_id, created_at, updated_at, closed_at,
+                 body) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    self.project_id,
+                    issue.number,
+                    issue.title,
+                    issue.state,
+                    author_id,
+                    issue.created_at,
+                    issue.updated_at,
+                    issue.closed_at,
+                    issue.body,
+                ],
+            )?;
+            let issue_id = self.transaction.last_insert_rowid();
+            for comment in &issue.comments {
+                let author_id = self.developer(comment.author.as_deref(), comment.author_name.as_deref())?;
+                self.transaction.execute(
+                    "INSERT INTO comments (issue_id, author_id, created_at, body) VALUES (?1, ?2, ?3, ?4)",
+                    params![issue_id, author_id, comment.created_at, comment.body],
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn value(cell: Cell) -> Value {
+    match cell {
+        Cell::Null => Value::Null,
+        Cell::Bool(value) => Value::Integer(value as i64),
+        Cell::Int(value) => Value::Integer(value),
+        Cell::UInt(value) => Value::Integer(value as i64),
+        Cell::Float(value) => Value::Real(value),
+        Cell::Str(value) => Value::Text(value),
+    }
+}
+
+/// The files a commit changes, compared to its first parent
+fn file_changes(repo: &Repository, commit: &Commit) -> Result<Vec<FileChange>, Box<dyn Error>> {
+    let parent_tree = if commit.parent_count() > 0 { Some(commit.parent(0)?.tree()?) } else { None };
+    let mut diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+    diff.find_similar(Some(DiffFindOptions::new().rename_threshold(50)))?;
+    let mut changes = vec![];
+    for (index, delta) in diff.deltas().enumerate() {
+        let path = delta
+            .new_file()
+            .path()
+            .or_else(|| delta.old_file().path())
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let change_type = match delta.status() {
+            git2::Delta::Added => "A",
+            git2::Delta::Deleted => "D",
+            git2::Delta::Modified => "M",
+            git2::Delta::Renamed => "R",
+            _ => "U",
+        };
+        let (lines_added, lines_deleted) = match Patch::from_diff(&diff, index)? {
+            Some(patch) => {
+                let (_, added, deleted) = patch.line_stats()?;
+                (added, deleted)
+            }
+            None => (0, 0),
+        };
+        changes.push((path, change_type, lines_added, lines_deleted));
+    }
+    Ok(changes)
+}
+
+/// Stores the project's commits, and the output rows of one of its analyses, in the `--sqlite` database if set
+pub fn store_analysis<T: Serialize>(repo: &Repo, analysis: &str, rows: &[T]) -> Result<(), Box<dyn Error>> {
+    let mut records = ProjectRecords::new(repo.project, repo.status);
+    records.add_commits(repo)?;
+    records.add_analysis(analysis, rows)?;
+    records.store()
+}
+
+/// Stores the project's commits in the `--sqlite` database if set
+pub fn store_commits(repo: &Repo) -> Result<(), Box<dyn Error>> {
+    let mut records = ProjectRecords::new(repo.project, repo.status);
+    records.add_commits(repo)?;
+    records.store()
+}
+
+/// Stores the project's emails in the `--sqlite` database if set
+pub fn store_emails(project: &str, emails: &[CleanedEmail]) -> Result<(), Box<dyn Error>> {
+    match global() {
+        Some(database) => database.write_project(project, |tables| tables.emails(emails)),
+        None => Ok(()),
+    }
+}
+
+/// Stores the project's issues in the `--sqlite` database if set
+pub fn store_issues(project: &str, issues: &[IssueRecord]) -> Result<(), Box<dyn Error>> {
+    match global() {
+        Some(database) => database.write_project(project, |tables| tables.issues(issues)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_repo::{repository, TestCommit};
+
+    #[derive(Serialize)]
+    struct Row {
+        project: &'static str,
+        measurement_month: usize,
+        window_start_date: &'static str,
+        window_end_date: &'static str,
+        commits: usize,
+        ratio: Option<f64>,
+    }
+
+    fn count(database: &Database, sql: &str) -> i64 {
+        database.connection.lock().unwrap().query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn test_write_project_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let (repo, ids) = repository(
+            &dir.path().join("repo"),
+            &[TestCommit {
+                name: "Alice",
+                email: "alice@example.org",
+                offset: 60,
+                message: "add a",
+                files: vec![("a.txt", "one\ntwo\n")],
+                ..TestCommit::default()
+            }],
+        );
+        let commits = vec![CommitRecord::new(&repo, &repo.find_commit(ids[0]).unwrap()).unwrap()];
+
+        let database = Database::open(dir.path().join("cohort.sqlite").to_str().unwrap()).unwrap();
+        let row = |month, start, end, commits, ratio| Row {
+            project: "hunter",
+            measurement_month: month,
+            window_start_date: start,
+            window_end_date: end,
+            commits,
+            ratio,
+        };
+        let rows: Vec<_> = [row(1, "2021-03-01", "2021-03-31", 1, Some(0.5)), row(2, "2021-04-01", "2021-04-30", 0, None)]
+            .iter()
+            .map(|row| cells(row).unwrap())
+            .collect();
+        let issues = [IssueRecord {
+            number: 7,
+            title: "crash".to_string(),
+            state: "OPEN".to_string(),
+            author: Some("bob".to_string()),
+            author_name: None,
+            created_at: "2021-03-02T00:00:00Z".to_string(),
+            updated_at: "2021-03-03T00:00:00Z".to_string(),
+            closed_at: None,
+            body: None,
+            comments: vec![CommentRecord {
+                author: Some("alice@example.org".to_string()),
+                author_name: Some("Alice".to_string()),
+                created_at: "2021-03-03T00:00:00Z".to_string(),
+                body: "fixed".to_string(),
+            }],
+        }];
+        // written twice, as by a rerun: the tables are replaced, not duplicated
+        for _ in 0..2 {
+            database
+                .write_project("Hunter", |tables| {
+                    tables.set_status("retired")?;
+                    tables.commits(&commits)?;
+                    tables.analysis("full", &rows)
+                })
+                .unwrap();
+            database.write_project("hunter", |tables| tables.issues(&issues)).unwrap();
+        }
+        assert_eq!(count(&database, "SELECT COUNT(*) FROM projects"), 1);
+        assert_eq!(count(&database, "SELECT COUNT(*) FROM windows"), 2);
+        assert_eq!(count(&database, "SELECT COUNT(*) FROM metrics"), 4);
+        assert_eq!(count(&database, "SELECT COUNT(*) FROM metrics WHERE value IS NULL"), 1);
+        assert_eq!(count(&database, "SELECT lines_added FROM file_changes JOIN commits ON commits.id = commit_id"), 2);
+        // the commit author and the commenter are the same developer
+        assert_eq!(count(&database, "SELECT COUNT(*) FROM developers"), 2);
+        assert_eq!(
+            count(
+                &database,
+                "SELECT COUNT(*) FROM comments JOIN commits ON comments.author_id = commits.author_id"
+            ),
+            1
+        );
+
+        // a failing write leaves the project as it was
+        let failed = database.write_project("hunter", |tables| {
+            tables.analysis("full", &[])?;
+            Err("interrupted".into())
+        });
+        assert!(failed.is_err());
+        assert_eq!(count(&database, "SELECT COUNT(*) FROM windows"), 2);
+    }
+}
@@ -1,10 +1,13 @@
 // dev_stats.rs
+use crate::output;
+use crate::progress;
 use crate::utils::*;
+use crate::windows::WindowScheme;
 use crate::{repo::Repo, Args};
-use git2::{DiffFindOptions, DiffOptions, Error};
-use serde::Serialize;
+use git2::{Commit, DiffFindOptions, DiffOptions, Error};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::fs::File;
 
 #[derive(Serialize, Clone)]
 pub struct DevStats<'a> {
@@ -20,7 +23,7 @@ pub struct DevStats<'a> {
     pub metrics: CommitFileMetrics,
 }
 
-#[derive(Debug, Serialize, Default, Clone)]
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct CommitFileMetrics {
     pub incubation_month: usize,
     pub commit_sha: String,
@@ -52,15 +55,82 @@ impl<'a> DevStats<'a> {
         }
     }
 
-    pub fn compute_individual_dev_stats(&self, args: &Args) -> Result<Vec<DevStats>, Error> {
-        let inc_months_commits = if let None = args.flag_time_window {
-            self.repo.inc_month_commits.clone()
+    /// Commits per window of the `--window` scheme, incubation months by default
+    fn inc_months_commits(&self, args: &Args) -> Result<IndexMap<usize, Vec<Commit<'a>>>, Error> {
+        let scheme = WindowScheme::from_args(args);
+        Ok(self.repo.commits_per_window(&self.repo.windows(&scheme)?))
+    }
+
+    fn commit_message(commit: &Commit, args: &Args) -> String {
+        if args.flag_ignore_commit_message {
+            "".to_string()
         } else {
-            self.repo.commits_to_inc_months_with_time_windows(
-                args.flag_time_window.unwrap(),
-                &self.repo.commits,
-            )?
-        };
+            commit
+                .message()
+                .unwrap_or("")
+                .replace("\n", " _nl_ ")
+                .to_string()
+        }
+    }
+
+    /// File names and change types of each commit, from tree diffs only. Line counts need the file
+    /// contents, which a blobless clone does not have, so they are left at 0.
+    pub fn compute_commit_file_names(&self, args: &Args) -> Result<Vec<DevStats>, Error> {
+        let mut output: Vec<DevStats> = vec![];
+        for (month, commits) in self.inc_months_commits(args)?.iter() {
+            for commit in commits {
+                let parent_tree = if commit.parent_count() == 1 {
+                    commit.parent(0).and_then(|p| p.tree()).ok()
+                } else {
+                    None
+                };
+                let tree = commit.tree()?;
+                let diff = self.repo.repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)?;
+                let author = commit.author();
+                for delta in diff.deltas() {
+                    let filename = delta
+                        .new_file()
+                        .path()
+                        .or_else(|| delta.old_file().path())
+                        .map(|p| p.to_string_lossy().to_string())
+                        .unwrap_or_default();
+                    let change_type = match delta.status() {
+                        git2::Delta::Added => "A",
+                        git2::Delta::Deleted => "D",
+                        git2::Delta::Modified => "M",
+                        git2::Delta::Renamed => "R",
+                        _ => "U",
+                    };
+                    output.push(DevStats {
+                        project: self.project,
+                        status: self.status,
+                        start_date: self.start_date,
+                        end_date: self.end_date,
+                        java_path: self.java_path,
+                        repo: self.repo,
+                        metrics: CommitFileMetrics {
+                            incubation_month: *month,
+                            commit_sha: commit.id().to_string(),
+                            email: author.email().unwrap_or("").to_string(),
+                            name: author.name().unwrap_or("").to_string(),
+                            date: convert_time(&commit.time()).to_string(),
+                            timestamp: commit.time().seconds(),
+                            filename,
+                            change_type: change_type.to_string(),
+                            lines_added: 0,
+                            lines_deleted: 0,
+                            commit_message: Self::commit_message(commit, args),
+                            commit_url: String::new(),
+                        },
+                    });
+                }
+            }
+        }
+        Ok(output)
+    }
+
+    pub fn compute_individual_dev_stats(&self, args: &Args) -> Result<Vec<DevStats>, Error> {
+        let inc_months_commits = self.inc_months_commits(args)?;
 
         log::info!("{}", format!("{} - computing stats", self.project));
         log::info!(
@@ -72,7 +142,8 @@ impl<'a> DevStats<'a> {
         );
 
         let mut output: Vec<DevStats> = vec![];
-        for (month, commits) in inc_months_commits.iter() {
+        for (position, (month, commits)) in inc_months_commits.iter().enumerate() {
+            progress::window(self.project, position + 1, inc_months_commits.len(), commits.len());
             for commit in commits {
                 let author = commit.author();
                 let name = author.name().unwrap_or("");
@@ -176,15 +247,7 @@ impl<'a> DevStats<'a> {
                             change_type: change_type.to_string(),
                             lines_added: added,
                             lines_deleted: deleted,
-                            commit_message: if args.flag_ignore_commit_message {
-                                "".to_string()
-                            } else {
-                                commit
-                                    .message()
-                                    .unwrap_or("")
-                                    .replace("\n", " _nl_ ")
-                                    .to_string()
-                            },
+                            commit_message: Self::commit_message(commit, args),
                             commit_url: String::new(), // default empty; can be set externally
                         };
                         output.push(DevStats {
@@ -203,8 +266,18 @@ impl<'a> DevStats<'a> {
         Ok(output)
     }
 
-    /// Writes grouped developer statistics into separate CSV files
+    /// Writes grouped developer statistics into separate tables, in the `--format` format
     pub fn write_dev_stats_grouped_by_developer(&self, args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+        #[derive(Default, Serialize)]
+        struct DevStatRow {
+            date_time: String,
+            file: String,
+            committer_name: String,
+            committer_email: String,
+            commit_link: String,
+            month: usize,
+        }
+
         let stats = self.compute_individual_dev_stats(args)?;
 
         let mut grouped_stats: HashMap<String, Vec<&CommitFileMetrics>> = HashMap::new();
@@ -214,42 +287,29 @@ impl<'a> DevStats<'a> {
 
         let output_folder = args.flag_output_folder.as_deref().unwrap_or("output");
         std::fs::create_dir_all(output_folder)?;
+        let format = args.flag_format.unwrap_or_default();
 
         for (dev_name, metrics) in grouped_stats {
-            let file_path = format!("{}/{}.csv", output_folder, dev_name);
-            let mut writer = csv::Writer::from_writer(File::create(&file_path)?);
-
-            // Example header row
-            writer.write_record(&[
-                "date_time",
-                "file",
-                "committer_name",
-                "committer_email",
-                "commit_link",
-                "month"
-            ])?;
-
-            for metric in metrics {
-                // We'll build a link in the same style:
-                let commit_link = if metric.commit_url.is_empty() {
-                    // fallback if not set
+            let rows = metrics.into_iter().map(|metric| DevStatRow {
+                date_time: metric.date.clone(),
+                file: metric.filename.clone(),
+                committer_name: metric.name.clone(),
+                committer_email: metric.email.clone(),
+                // We'll build a link in the same style, with a fallback if not set:
+                commit_link: if metric.commit_url.is_empty() {
                     format!("https://github.com/{}/commit/{}", self.project, metric.commit_sha)
                 } else {
                     metric.commit_url.clone()
-                };
-
-                writer.write_record(&[
-                    metric.date.clone(),
-                    metric.filename.clone(),
-                    metric.name.clone(),
-                    metric.email.clone(),
-                    commit_link,
-                    metric.incubation_month.to_string(),
-                ])?;
-            }
-
-            writer.flush()?;
-            log::info!("Developer stats written to {}", file_path);
+                },
+                month: metric.incubation_month,
+            });
+            let path = output::write_table(
+                &format!("{}/{}", output_folder, dev_name),
+                format,
+                Some(&DevStatRow::default()),
+                rows,
+            )?;
+            log::info!("Developer stats written to {}", path);
         }
 
         Ok(())
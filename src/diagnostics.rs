@@ -0,0 +1,416 @@
+// src/diagnostics.rs
+//! Data-quality diagnostics of a run. The analyses report what they could not measure into a shared collector,
+//! per project and analysis: windows without commits, windows skipped and why, imputed metric families, messages
+//! that could not be parsed, the branch the repository was reset to, errors, and the time spent in each phase. The
+//! pre, during and post-incubation analyses of a project each get their own report, next to the project's. At the
+//! end of the run, `write_reports` writes a JSON and an HTML report per project and analysis, and
+//! `--quality-thresholds` turns a report that exceeds them into a failed run.
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+use indexmap::{IndexMap, IndexSet};
+use serde::Serialize;
+
+/// A problem in one window of a project
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct WindowIssue {
+    pub window: usize,
+    /// The analysis phase or metric family, e.g. checkout, emails or sokrates
+    pub phase: String,
+    pub reason: String,
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct ProjectIssue {
+    pub phase: String,
+    pub message: String,
+}
+
+/// The data quality of one project
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ProjectReport {
+    pub project: String,
+    /// The analysis the windows belong to, e.g. pre-incubation; empty for the project's main analysis
+    pub analysis: String,
+    /// The branch the repository was reset to; empty when none was found
+    pub branch: String,
+    pub windows: usize,
+    /// The windows without commits
+    pub windows_without_commits: Vec<usize>,
+    /// The windows left out of the metrics
+    pub skipped_windows: Vec<WindowIssue>,
+    /// The metric families imputed in a window, and why they were not observed
+    pub imputed: Vec<WindowIssue>,
+    /// The number of messages that could not be parsed, per source (e.g. emails)
+    pub unparseable_messages: BTreeMap<String, usize>,
+    /// The unparseable messages per source and archive; the windows reading the same archive count it once
+    #[serde(skip)]
+    unparseable_archives: BTreeMap<(String, String), usize>,
+    pub errors: Vec<ProjectIssue>,
+    /// Seconds spent in each phase, summed over the windows
+    pub phase_seconds: BTreeMap<String, f64>,
+    /// The `--quality-thresholds` this project exceeds
+    pub threshold_violations: Vec<String>,
+}
+
+impl ProjectReport {
+    /// The share of windows without commits
+    fn windows_without_commits_ratio(&self) -> f64 {
+        ratio(self.windows_without_commits.len(), self.windows)
+    }
+
+    /// The share of windows with at least one imputed metric family
+    fn imputed_windows_ratio(&self) -> f64 {
+        let windows: IndexSet<usize> = self.imputed.iter().map(|issue| issue.window).collect();
+        ratio(windows.len(), self.windows)
+    }
+
+    /// Sets the unparseable messages of an archive, and the source's total over its archives
+    fn unparseable_in(&mut self, source: &str, archive: &str, count: usize) {
+        self.unparseable_archives.insert((source.to_string(), archive.to_string()), count);
+        let total = self.unparseable_archives.iter().filter(|((s, _), _)| s == source).map(|(_, c)| c).sum();
+        self.unparseable_messages.insert(source.to_string(), total);
+    }
+
+    fn unparseable(&self) -> usize {
+        self.unparseable_messages.values().sum()
+    }
+}
+
+fn ratio(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64
+    }
+}
+
+/// The collector the analyses report into, keyed by project and analysis
+#[derive(Default)]
+pub struct Diagnostics {
+    projects: Mutex<IndexMap<(String, String), ProjectReport>>,
+}
+
+impl Diagnostics {
+    /// The collector of the run
+    pub fn global() -> &'static Diagnostics {
+        static DIAGNOSTICS: OnceLock<Diagnostics> = OnceLock::new();
+        DIAGNOSTICS.get_or_init(Diagnostics::default)
+    }
+
+    /// Updates the report of a project's analysis (empty for the main one), creating it on first use
+    pub fn record(&self, project: &str, analysis: &str, update: impl FnOnce(&mut ProjectReport)) {
+        let mut projects = self.projects.lock().unwrap_or_else(|e| e.into_inner());
+        let key = (project.to_string(), analysis.to_string());
+        let report = projects.entry(key).or_insert_with(|| ProjectReport {
+            project: project.to_string(),
+            analysis: analysis.to_string(),
+            ..ProjectReport::default()
+        });
+        update(report);
+    }
+
+    pub fn reports(&self) -> Vec<ProjectReport> {
+        self.projects.lock().unwrap_or_else(|e| e.into_inner()).values().cloned().collect()
+    }
+
+    /// Records the thresholds each project exceeds in its report, and returns the number of violations
+    pub fn check(&self, thresholds: &Thresholds) -> usize {
+        let mut projects = self.projects.lock().unwrap_or_else(|e| e.into_inner());
+        projects
+            .values_mut()
+            .map(|report| {
+                report.threshold_violations = thresholds.violations(report);
+                report.threshold_violations.len()
+            })
+            .sum()
+    }
+
+    /// Writes `{project}-quality.json` and `{project}-quality.html` of every project to the folder, and
+    /// `{project}-{analysis}-quality.json/html` of the pre, during and post-incubation analyses
+    pub fn write_reports(&self, folder: &str) -> Result<(), Box<dyn Error>> {
+        for report in self.reports() {
+            let stem = match report.analysis.as_str() {
+                "" => format!("{}/{}-quality", folder, report.project),
+                analysis => format!("{}/{}-{}-quality", folder, report.project, analysis),
+            };
+            std::fs::write(format!("{}.json", stem), serde_json::to_string_pretty(&report)?)?;
+            std::fs::write(format!("{}.html", stem), html(&report))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn branch(project: &str, branch: &str) {
+    Diagnostics::global().record(project, "", |report| report.branch = branch.to_string());
+}
+
+/// The number of windows of a project's analysis, and those without commits
+pub fn windows(project: &str, analysis: &str, windows: usize, without_commits: Vec<usize>) {
+    Diagnostics::global().record(project, analysis, |report| {
+        report.windows = windows;
+        report.windows_without_commits = without_commits;
+    });
+}
+
+pub fn skipped_window(project: &str, analysis: &str, window: usize, phase: &str, reason: &str) {
+    Diagnostics::global().record(project, analysis, |report| {
+        report.skipped_windows.push(WindowIssue {
+            window,
+            phase: phase.to_string(),
+            reason: reason.to_string(),
+        })
+    });
+}
+
+pub fn imputed(project: &str, analysis: &str, window: usize, family: &str, reason: &str) {
+    Diagnostics::global().record(project, analysis, |report| {
+        report.imputed.push(WindowIssue {
+            window,
+            phase: family.to_string(),
+            reason: reason.to_string(),
+        })
+    });
+}
+
+/// The messages of an archive that could not be parsed. The windows overlapping the same archive parse it again,
+/// so the count of an archive replaces the one recorded before instead of adding to it.
+pub fn unparseable(project: &str, analysis: &str, source: &str, archive: &str, count: usize) {
+    if count > 0 {
+        Diagnostics::global().record(project, analysis, |report| report.unparseable_in(source, archive, count));
+    }
+}
+
+pub fn error(project: &str, phase: &str, message: &str) {
+    Diagnostics::global().record(project, "", |report| {
+        report.errors.push(ProjectIssue {
+            phase: phase.to_string(),
+            message: message.to_string(),
+        })
+    });
+}
+
+/// Runs a phase of a project's analysis, adding the time it took to the phase's total
+pub fn timed<T>(project: &str, analysis: &str, phase: &str, run: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = run();
+    let seconds = start.elapsed().as_secs_f64();
+    Diagnostics::global().record(project, analysis, |report| {
+        *report.phase_seconds.entry(phase.to_string()).or_default() += seconds;
+    });
+    result
+}
+
+/// The quality a project must reach, parsed from `--quality-thresholds`, e.g.
+/// `windows-without-commits:0.5,skipped-windows:0,imputed-windows:0.8,unparseable:100,errors:0`. Shares are
+/// between 0 and 1, and counts are per project; the thresholds not given are not checked.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Thresholds {
+    windows_without_commits: Option<f64>,
+    skipped_windows: Option<usize>,
+    imputed_windows: Option<f64>,
+    unparseable: Option<usize>,
+    errors: Option<usize>,
+}
+
+impl Thresholds {
+    /// The thresholds a project's report exceeds
+    pub fn violations(&self, report: &ProjectReport) -> Vec<String> {
+        let mut violations = vec![];
+        let mut share = |name: &str, value: f64, max: Option<f64>| {
+            if let Some(max) = max.filter(|max| value > *max) {
+                violations.push(format!("{} is {:.2}, above {}", name, value, max));
+            }
+        };
+        share("windows-without-commits", report.windows_without_commits_ratio(), self.windows_without_commits);
+        share("imputed-windows", report.imputed_windows_ratio(), self.imputed_windows);
+        let counts = [
+            ("skipped-windows", report.skipped_windows.len(), self.skipped_windows),
+            ("unparseable", report.unparseable(), self.unparseable),
+            ("errors", report.errors.len(), self.errors),
+        ];
+        for (name, value, max) in counts {
+            if let Some(max) = max.filter(|max| value > *max) {
+                violations.push(format!("{} is {}, above {}", name, value, max));
+            }
+        }
+        violations
+    }
+}
+
+impl FromStr for Thresholds {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut thresholds = Thresholds::default();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (name, value) = part
+                .split_once(':')
+                .ok_or_else(|| format!("invalid threshold {}, expected name:value", part))?;
+            let invalid = || format!("invalid value {} of threshold {}", value, name);
+            match name {
+                "windows-without-commits" => thresholds.windows_without_commits = Some(value.parse().map_err(|_| invalid())?),
+                "imputed-windows" => thresholds.imputed_windows = Some(value.parse().map_err(|_| invalid())?),
+                "skipped-windows" => thresholds.skipped_windows = Some(value.parse().map_err(|_| invalid())?),
+                "unparseable" => thresholds.unparseable = Some(value.parse().map_err(|_| invalid())?),
+                "errors" => thresholds.errors = Some(value.parse().map_err(|_| invalid())?),
+                other => {
+                    return Err(format!(
+                        "unknown threshold {}, expected windows-without-commits, imputed-windows, skipped-windows, \
+                         unparseable or errors",
+                        other
+                    ))
+                }
+            }
+        }
+        Ok(thresholds)
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// An HTML table, with a placeholder paragraph when there are no rows
+fn table(html: &mut String, title: &str, headers: &[&str], rows: Vec<Vec<String>>) {
+    let _ = writeln!(html, "<h2>{}</h2>", title);
+    if rows.is_empty() {
+        html.push_str("<p>None</p>\n");
+        return;
+    }
+    html.push_str("<table>\n<tr>");
+    for header in headers {
+        let _ = write!(html, "<th>{}</th>", header);
+    }
+    html.push_str("</tr>\n");
+    for row in rows {
+        html.push_str("<tr>");
+        for cell in row {
+            let _ = write!(html, "<td>{}</td>", escape(&cell));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+}
+
+fn html(report: &ProjectReport) -> String {
+    let project = match report.analysis.as_str() {
+        "" => escape(&report.project),
+        analysis => format!("{} ({})", escape(&report.project), escape(analysis)),
+    };
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{} - data quality</title>\n\
+         <style>body {{ font-family: sans-serif; }} table {{ border-collapse: collapse; }} \
+         th, td {{ border: 1px solid #ccc; padding: 2px 8px; text-align: left; }}</style>\n</head>\n<body>\n\
+         <h1>{} - data quality</h1>\n<p>Branch: {}<br>Windows: {}<br>Windows without commits: {}</p>\n",
+        project,
+        project,
+        if report.branch.is_empty() { "none found".to_string() } else { escape(&report.branch) },
+        report.windows,
+        report.windows_without_commits.iter().map(usize::to_string).collect::<Vec<_>>().join(", "),
+    );
+    let issues = |issues: &[WindowIssue]| {
+        issues
+            .iter()
+            .map(|issue| vec![issue.window.to_string(), issue.phase.clone(), issue.reason.clone()])
+            .collect()
+    };
+    table(
+        &mut html,
+        "Threshold violations",
+        &["Violation"],
+        report.threshold_violations.iter().map(|v| vec![v.clone()]).collect(),
+    );
+    table(&mut html, "Skipped windows", &["Window", "Phase", "Reason"], issues(&report.skipped_windows));
+    table(&mut html, "Imputed metrics", &["Window", "Family", "Reason"], issues(&report.imputed));
+    table(
+        &mut html,
+        "Unparseable messages",
+        &["Source", "Messages"],
+        report.unparseable_messages.iter().map(|(source, count)| vec![source.clone(), count.to_string()]).collect(),
+    );
+    table(
+        &mut html,
+        "Errors",
+        &["Phase", "Message"],
+        report.errors.iter().map(|e| vec![e.phase.clone(), e.message.clone()]).collect(),
+    );
+    table(
+        &mut html,
+        "Time per phase",
+        &["Phase", "Seconds"],
+        report.phase_seconds.iter().map(|(phase, seconds)| vec![phase.clone(), format!("{:.1}", seconds)]).collect(),
+    );
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reports_and_thresholds() {
+        let diagnostics = Diagnostics::default();
+        diagnostics.record("hunter", "", |report| {
+            report.branch = "master".to_string();
+            report.windows = 4;
+            report.windows_without_commits = vec![2];
+            report.imputed.push(WindowIssue {
+                window: 2,
+                phase: "tokei".to_string(),
+                reason: "no commits in the window".to_string(),
+            });
+            report.imputed.push(WindowIssue {
+                window: 2,
+                phase: "emails".to_string(),
+                reason: "no mailing list archive <hunter-dev-202103.mbox>".to_string(),
+            });
+            report.skipped_windows.push(WindowIssue {
+                window: 3,
+                phase: "sokrates".to_string(),
+                reason: "sokrates failed initialization".to_string(),
+            });
+            // the windows of the same month parse its archive again
+            report.unparseable_in("emails", "hunter-dev-202102.mbox", 1);
+            report.unparseable_in("emails", "hunter-dev-202102.mbox", 1);
+            report.unparseable_in("emails", "hunter-dev-202103.mbox", 1);
+        });
+        diagnostics.record("hunter", "pre-incubation", |report| report.windows = 2);
+
+        let thresholds: Thresholds = "windows-without-commits:0.5, imputed-windows:0.2,skipped-windows:0,unparseable:5"
+            .parse()
+            .unwrap();
+        assert_eq!(diagnostics.check(&thresholds), 2);
+        let report = &diagnostics.reports()[0];
+        assert_eq!(report.unparseable_messages["emails"], 2);
+        assert_eq!(
+            report.threshold_violations,
+            vec!["imputed-windows is 0.25, above 0.2", "skipped-windows is 1, above 0"]
+        );
+        assert_eq!(diagnostics.check(&Thresholds::default()), 0);
+
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        diagnostics.write_reports(folder).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(format!("{}/hunter-quality.json", folder)).unwrap()).unwrap();
+        assert_eq!(json["branch"], "master");
+        assert_eq!(json["skipped_windows"][0]["reason"], "sokrates failed initialization");
+        assert_eq!(json["unparseable_messages"]["emails"], 2);
+        let pre: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(format!("{}/hunter-pre-incubation-quality.json", folder)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!((pre["analysis"].as_str(), pre["windows"].as_u64()), (Some("pre-incubation"), Some(2)));
+        let html = std::fs::read_to_string(format!("{}/hunter-quality.html", folder)).unwrap();
+        assert!(html.contains("<td>no mailing list archive &lt;hunter-dev-202103.mbox&gt;</td>"));
+
+        assert!("errors:none".parse::<Thresholds>().is_err());
+        assert!("commits:1".parse::<Thresholds>().is_err());
+    }
+}
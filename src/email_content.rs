@@ -0,0 +1,456 @@
+// email_content.rs
+use std::error::Error;
+use std::sync::OnceLock;
+
+use log::error;
+use mail_parser::HeaderValue;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::diagnostics;
+use crate::emails::EmailsMetrics;
+use crate::output::{OutputFormat, TableWriter};
+use crate::sentiment::{self, DeveloperSentiment, MessageScore, SentimentAnalyzer, SentimentScores};
+
+/// The kind of content a line of an email body carries
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineKind {
+    /// Text written by the sender of this email
+    Original,
+    /// Text quoted from an earlier email (`> ...`, or everything after `-----Original Message-----`)
+    Quoted,
+    /// The "On ... wrote:" line introducing a quote
+    Attribution,
+    /// Signature block (after `-- `) and mailing list footers
+    Signature,
+    /// Inline patch or diff
+    Patch,
+    /// Fenced or indented source code
+    Code,
+    /// Java-style stack trace
+    StackTrace,
+}
+
+/// Measurements of the different parts of an email body
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EmailContent {
+    /// Words written by the sender, excluding quotes, signatures, patches, code and stack traces
+    pub original_words: usize,
+    /// Characters written by the sender, computed the same way as the word count
+    pub original_characters: usize,
+    /// Words quoted from earlier emails
+    pub quoted_words: usize,
+    /// Number of signature and footer lines
+    pub signature_lines: usize,
+    /// Number of lines belonging to an inline patch or diff
+    pub patch_lines: usize,
+    /// Number of source code lines
+    pub code_lines: usize,
+    /// Number of stack trace lines
+    pub stack_trace_lines: usize,
+    /// The original text only, one line per original line
+    pub cleaned_body: String,
+}
+
+impl EmailContent {
+    /// True if the email carries an inline patch or diff
+    pub fn has_patch(&self) -> bool {
+        self.patch_lines > 0
+    }
+
+    /// True if the email contains a stack trace
+    pub fn has_stack_trace(&self) -> bool {
+        self.stack_trace_lines > 0
+    }
+}
+
+/// Words and characters of a line, after removing the punctuation the same way the email metrics always did
+fn words_and_characters(line: &str) -> (usize, usize) {
+    let line = line.replace(['.', ','], "");
+    let words = line.split_whitespace().collect::<Vec<_>>();
+    (words.len(), words.join(" ").chars().count())
+}
+
+fn is_patch_start(line: &str) -> bool {
+    line.starts_with("diff --git ")
+        || line.starts_with("Index: ")
+        || line.starts_with("--- a/")
+        || line.starts_with("+++ b/")
+        || (line.starts_with("@@ ") && line[3..].contains(" @@"))
+}
+
+/// Whether a line continues a patch; lines starting with a space are context lines, which only a hunk has
+fn is_patch_line(line: &str, in_hunk: bool) -> bool {
+    is_patch_start(line)
+        || line.starts_with('+')
+        || line.starts_with('-')
+        || (in_hunk && line.starts_with(' '))
+        || line.starts_with("index ")
+        || line.starts_with("new file mode")
+        || line.starts_with("deleted file mode")
+        || line.starts_with("===")
+        || line.starts_with("\\ No newline")
+}
+
+fn is_signature_start(line: &str) -> bool {
+    line == "-- " || line == "--"
+}
+
+fn is_list_footer(line: &str) -> bool {
+    (line.len() >= 20 && line.chars().all(|c| c == '-'))
+        || line.starts_with("To unsubscribe, e-mail:")
+        || line.starts_with("For additional commands, e-mail:")
+}
+
+/// The openings and endings of the attribution lines of replies, e.g. "On Thu, 25 Feb 2021, Jane <jane@example.org> wrote:"
+const ATTRIBUTIONS: [(&str, &str); 3] = [("On ", "wrote:"), ("Le ", "a écrit :"), ("Am ", "schrieb:")];
+
+/// Whether a line is the attribution of the quoted text that follows: it has the "On … wrote:" shape, possibly
+/// wrapped after the previous line, or it ends like an attribution and precedes a `>` block
+fn is_attribution(line: &str, previous: Option<&str>, precedes_quote: bool) -> bool {
+    let line = line.trim();
+    let previous = previous.unwrap_or_default().trim_start();
+    ATTRIBUTIONS.iter().any(|(opening, ending)| {
+        line.ends_with(ending) && (line.starts_with(opening) || previous.starts_with(opening) || precedes_quote)
+    })
+}
+
+fn looks_like_code(line: &str) -> bool {
+    let indented = line.starts_with('\t') || line.starts_with("    ");
+    let trimmed = line.trim_end();
+    indented
+        && (trimmed.ends_with(';')
+            || trimmed.ends_with('{')
+            || trimmed.ends_with('}')
+            || trimmed.ends_with("/>"))
+}
+
+/// Classifies every line of an email body
+///
+/// Patches and stack traces are detected first, so that a diff pasted into a reply is not counted as quoted text
+/// just because some of its lines start with `-`. Once `-----Original Message-----` is seen, everything that follows is quoted.
+pub fn classify_lines(text: &str) -> Vec<(LineKind, &str)> {
+    static STACK_TRACE: OnceLock<Regex> = OnceLock::new();
+    let stack_trace = STACK_TRACE.get_or_init(|| {
+        Regex::new(
+            r"^\s*(at [\w$.<>/]+\(.*\)|Caused by: .*|Exception in thread .*|\.\.\. \d+ more|([a-z][\w$]*\.)+[A-Z][\w$]*(Exception|Error)(: .*)?)$",
+        )
+        .unwrap()
+    });
+
+    let lines: Vec<&str> = text.lines().collect();
+    // whether the next non-blank line of each line is quoted
+    let mut precedes_quote = vec![false; lines.len()];
+    let mut next_is_quoted = false;
+    for (i, line) in lines.iter().enumerate().rev() {
+        precedes_quote[i] = next_is_quoted;
+        if !line.trim().is_empty() {
+            next_is_quoted = line.trim_start().starts_with('>');
+        }
+    }
+
+    let mut output = vec![];
+    let mut in_patch = false;
+    let mut in_hunk = false;
+    let mut in_fence = false;
+    let mut in_signature = false;
+    let mut in_forwarded = false;
+
+    for (i, line) in lines.iter().copied().enumerate() {
+        let trimmed_start = line.trim_start();
+        if !in_patch {
+            in_hunk = false;
+        }
+
+        let kind = if in_forwarded {
+            LineKind::Quoted
+        } else if line.starts_with("-----Original Message-----") {
+            in_forwarded = true;
+            LineKind::Attribution
+        } else if trimmed_start.starts_with('>') {
+            in_patch = false;
+            LineKind::Quoted
+        } else if trimmed_start.starts_with("```") {
+            in_fence = !in_fence;
+            LineKind::Code
+        } else if in_fence {
+            LineKind::Code
+        } else if is_patch_start(line) {
+            in_patch = true;
+            // a file header starts a new file, whose context lines come after its first hunk header
+            in_hunk = line.starts_with("@@ ");
+            LineKind::Patch
+        } else if in_patch && !line.is_empty() && is_patch_line(line, in_hunk) {
+            LineKind::Patch
+        } else if stack_trace.is_match(line) {
+            in_patch = false;
+            LineKind::StackTrace
+        } else if is_signature_start(line) || is_list_footer(line) {
+            in_patch = false;
+            in_signature = true;
+            LineKind::Signature
+        } else if in_signature {
+            LineKind::Signature
+        } else if is_attribution(line, i.checked_sub(1).map(|previous| lines[previous]), precedes_quote[i]) {
+            in_patch = false;
+            LineKind::Attribution
+        } else if looks_like_code(line) {
+            in_patch = false;
+            LineKind::Code
+        } else {
+            in_patch = false;
+            LineKind::Original
+        };
+        output.push((kind, line));
+    }
+
+    // "On Thu, 25 Feb 2021 at 03:56, Jane Doe <jane@example.org>\n wrote:" is often wrapped on two lines
+    for i in 1..output.len() {
+        if output[i].0 == LineKind::Attribution
+            && output[i - 1].0 == LineKind::Original
+            && output[i - 1].1.trim_start().starts_with("On ")
+        {
+            output[i - 1].0 = LineKind::Attribution;
+        }
+    }
+    output
+}
+
+/// Splits an email body in its original, quoted, signature, patch, code and stack trace parts and measures each of them
+pub fn analyze(text: &str) -> EmailContent {
+    let mut content = EmailContent::default();
+    let mut cleaned_lines = vec![];
+
+    for (kind, line) in classify_lines(text) {
+        match kind {
+            LineKind::Original => {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let (words, characters) = words_and_characters(line);
+                content.original_words += words;
+                content.original_characters += characters;
+                cleaned_lines.push(line.trim());
+            }
+            LineKind::Quoted => {
+                let unquoted = line.trim_start().trim_start_matches(['>', ' ']);
+                content.quoted_words += words_and_characters(unquoted).0;
+            }
+            LineKind::Attribution => {}
+            LineKind::Signature => content.signature_lines += 1,
+            LineKind::Patch => content.patch_lines += 1,
+            LineKind::Code => content.code_lines += 1,
+            LineKind::StackTrace => content.stack_trace_lines += 1,
+        }
+    }
+    content.cleaned_body = cleaned_lines.join("\n");
+    content
+}
+
+/// A row of the cleaned email bodies export
+#[derive(Debug, Default, Serialize)]
+pub struct CleanedEmail {
+    pub project: String,
+    pub mbox: String,
+    pub message_id: String,
+    pub date: String,
+    pub from: String,
+    pub from_email: String,
+    pub subject: String,
+    pub is_reply: bool,
+    pub original_words: usize,
+    pub quoted_words: usize,
+    pub has_patch: bool,
+    pub has_stack_trace: bool,
+    pub sentiment_compound: f64,
+    pub incivility_terms: usize,
+    pub body: String,
+}
+
+/// The cleaned body of every email in the given mbox files, with its headers and content measurements.
+///
+/// Emails from jira@apache.org and svn/cvs commit notifications are skipped, same as in the email metrics.
+pub fn cleaned_emails(project: &str, mbox_paths: &[String]) -> Vec<CleanedEmail> {
+    let mut emails = vec![];
+    for path in mbox_paths {
+        let mut unparseable = 0;
+        for email in EmailsMetrics::parse_mbox_to_emails(path.to_string(), None, None)
+            .into_iter()
+            .flatten()
+        {
+            let parsed_email = match mail_parser::Message::parse(email.contents()) {
+                Some(m) => m,
+                None => {
+                    log::error!("{} - cannot parse an email in {}", project, path);
+                    unparseable += 1;
+                    continue;
+                }
+            };
+            let subject = parsed_email.subject().unwrap_or("");
+            let (from, from_email) = match parsed_email.from() {
+                HeaderValue::Address(x) => (
+                    x.name.as_deref().unwrap_or("").to_string(),
+                    x.address.as_deref().unwrap_or("").to_string(),
+                ),
+                _ => ("".to_string(), "".to_string()),
+            };
+            if from_email == "jira@apache.org"
+                || subject.starts_with("svn commit")
+                || subject.starts_with("cvs commit")
+            {
+                continue;
+            }
+
+            let content = analyze(&parsed_email.body_text(0).unwrap_or_default());
+            let sentiment = SentimentAnalyzer::global().score(&content.cleaned_body);
+            emails.push(CleanedEmail {
+                project: project.to_string(),
+                mbox: path.to_string(),
+                message_id: parsed_email.message_id().unwrap_or("").to_string(),
+                date: parsed_email
+                    .date()
+                    .map(|d| d.to_rfc3339())
+                    .unwrap_or_default(),
+                from,
+                from_email,
+                subject: subject.to_string(),
+                is_reply: !matches!(parsed_email.references(), HeaderValue::Empty),
+                original_words: content.original_words,
+                quoted_words: content.quoted_words,
+                has_patch: content.has_patch(),
+                has_stack_trace: content.has_stack_trace(),
+                sentiment_compound: sentiment.compound,
+                incivility_terms: sentiment.incivility_terms,
+                body: content.cleaned_body,
+            });
+        }
+        diagnostics::unparseable(project, "", "emails", path, unparseable);
+    }
+    emails
+}
+
+/// The sentiment of the emails aggregated per sender (by email address) and yyyy-mm month
+pub fn sentiment_by_developer(emails: &[CleanedEmail]) -> Vec<DeveloperSentiment> {
+    let scores: Vec<MessageScore> = emails
+        .iter()
+        .map(|email| MessageScore {
+            project: email.project.clone(),
+            source: "email".to_string(),
+            id: email.message_id.clone(),
+            author: email.from_email.clone(),
+            window: email.date.get(..7).unwrap_or("NA").to_string(),
+            date: email.date.clone(),
+            scores: SentimentScores {
+                compound: email.sentiment_compound,
+                incivility_terms: email.incivility_terms,
+                ..SentimentScores::default()
+            },
+        })
+        .collect();
+    sentiment::aggregate_by_developer(&scores)
+}
+
+/// Writes the cleaned emails to the `output_stem` table, to be used for NLP
+pub fn export_cleaned_bodies(emails: &[CleanedEmail], output_stem: &str, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let mut writer = TableWriter::create(output_stem, format)?.with_template(&CleanedEmail::default())?;
+    for email in emails {
+        if let Err(e) = writer.serialize(email) {
+            error!("{} - cannot serialize the email {}: {}", email.project, email.message_id, e);
+        }
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quoted_text_and_attribution() {
+        let text = "Sounds good to me.\n\nOn Thu, 25 Feb 2021 at 03:56, Jane Doe <jane@example.org>\nwrote:\n> PR 142?\n> John\n";
+        let content = analyze(text);
+        assert_eq!(content.original_words, 4);
+        assert_eq!(content.quoted_words, 3);
+        assert_eq!(content.cleaned_body, "Sounds good to me.");
+
+        // a sentence ending in "wrote:" is only an attribution before quoted text
+        let text = "This is what I wrote:\nthe build is broken\n";
+        assert_eq!(analyze(text).original_words, 9);
+        let text = "Jane wrote:\n\n> the build is broken\n";
+        assert_eq!(analyze(text).original_words, 0);
+    }
+
+    #[test]
+    fn test_signature_and_footer() {
+        let text = "Thanks for the fix\n-- \nStefan\nhttps://example.org\n";
+        let content = analyze(text);
+        assert_eq!(content.original_words, 4);
+        assert_eq!(content.signature_lines, 3);
+
+        let text = "Done\n---------------------------------------------------------------------\nTo unsubscribe, e-mail: dev-unsubscribe@ant.apache.org\nFor additional commands, e-mail: dev-help@ant.apache.org\n";
+        let content = analyze(text);
+        assert_eq!(content.original_words, 1);
+        assert_eq!(content.signature_lines, 3);
+    }
+
+    #[test]
+    fn test_patch_detection() {
+        let text = "Here is a patch:\n\ndiff --git a/build.xml b/build.xml\nindex 1a2b3c4..5d6e7f8 100644\n--- a/build.xml\n+++ b/build.xml\n@@ -1,2 +1,2 @@\n-<project name=\"a\">\n+<project name=\"b\">\n\nPlease review.\n";
+        let content = analyze(text);
+        assert!(content.has_patch());
+        assert_eq!(content.patch_lines, 7);
+        assert_eq!(content.original_words, 6);
+
+        // indented lines are context lines only in a hunk
+        let text = "--- a/notes.txt is gone\n    indented prose stays\n";
+        let content = analyze(text);
+        assert_eq!(content.patch_lines, 1);
+        assert_eq!(content.original_words, 3);
+        let text = "diff --git a/A.java b/A.java\n@@ -1,3 +1,3 @@\n class A {\n-  int a;\n+  int b;\n }\n";
+        assert_eq!(analyze(text).patch_lines, 6);
+    }
+
+    #[test]
+    fn test_stack_trace_and_code() {
+        let text = "It fails with\njava.lang.NullPointerException: boom\n\tat org.apache.tools.ant.Main.run(Main.java:42)\n\t... 3 more\n```\nint a = 1;\n```\n    foo.bar();\n";
+        let content = analyze(text);
+        assert!(content.has_stack_trace());
+        assert_eq!(content.stack_trace_lines, 3);
+        assert_eq!(content.code_lines, 4);
+        assert_eq!(content.original_words, 3);
+    }
+
+    #[test]
+    fn test_original_message_is_quoted() {
+        let text = "See below\n-----Original Message-----\nFrom: someone\nSubject: hello there\n";
+        let content = analyze(text);
+        assert_eq!(content.original_words, 2);
+        assert_eq!(content.quoted_words, 5);
+    }
+
+    #[test]
+    fn test_mbox_fixture() {
+        let emails = EmailsMetrics::parse_mbox_to_emails(
+            "test/test_resources/mbox/ant-dev-202103.mbox".to_string(),
+            None,
+            None,
+        );
+        let mut original = 0;
+        let mut quoted = 0;
+        for email in emails.into_iter().flatten() {
+            if let Some(parsed_email) = mail_parser::Message::parse(email.contents()) {
+                let content = analyze(&parsed_email.body_text(0).unwrap_or_default());
+                original += content.original_words;
+                quoted += content.quoted_words;
+            }
+        }
+        // ant-dev-202103 is mostly replies that quote the previous email
+        assert!(original > 0);
+        assert!(quoted > 0);
+
+        let emails = cleaned_emails("ant", &["test/test_resources/mbox/ant-dev-202103.mbox".to_string()]);
+        let developers = sentiment_by_developer(&emails);
+        assert!(!developers.is_empty());
+        assert_eq!(developers.iter().map(|d| d.messages).sum::<usize>(), emails.len());
+    }
+}
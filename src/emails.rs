@@ -1,5 +1,10 @@
 use std::fs::File;
 
+use crate::activity_metrics::LocalTimeSlot;
+use crate::diagnostics;
+use crate::email_content;
+use crate::metrics::{Imputation, MetricProvider, Metrics, Nullable, Window};
+use crate::sentiment::SentimentAnalyzer;
 use crate::utils::inc_month_to_date;
 use chrono::NaiveDate;
 use indexmap::IndexSet;
@@ -10,6 +15,7 @@ use mail_parser::{DateTime, HeaderValue};
 use scraper::{Html, Selector};
 use serde::Serialize;
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use ureq::Agent;
 
 pub struct EmailStats {
@@ -17,7 +23,7 @@ pub struct EmailStats {
     pub characters: usize,
 }
 
-#[derive(Clone, Default, Debug, Serialize, PartialEq, Eq)]
+#[derive(Clone, Default, Debug, Serialize, PartialEq)]
 pub struct EmailsMetrics {
     /// Number of total emails
     pub emails: usize,
@@ -43,6 +49,37 @@ pub struct EmailsMetrics {
     pub emails_no_replies_characters: usize,
     /// Number of jira emails
     pub emails_jira: usize,
+    /// The content, sentiment and time metrics, written after the metrics of the first releases
+    #[serde(skip)]
+    pub content: EmailContentMetrics,
+    /// Number of emails that could not be parsed per archive, for the quality report
+    #[serde(skip)]
+    pub unparseable: BTreeMap<String, usize>,
+}
+
+/// The content, sentiment and time metrics of the emails of a window
+#[derive(Clone, Default, Debug, Serialize, PartialEq)]
+pub struct EmailContentMetrics {
+    /// Number of emails that carry an inline patch or diff
+    pub emails_with_patches: usize,
+    /// Number of emails that contain a stack trace
+    pub emails_with_stack_traces: usize,
+    /// Words written by the senders, excluding quoted text, signatures, patches, code and stack traces
+    pub emails_original_word_count: usize,
+    /// Words quoted from earlier emails
+    pub emails_quoted_word_count: usize,
+    /// Share of original words over original and quoted words
+    pub emails_original_words_ratio: f64,
+    /// Average compound sentiment of the emails' original text, from -1 (most negative) to 1 (most positive)
+    pub emails_sentiment_compound_avg: f64,
+    /// Number of emails with a negative compound sentiment
+    pub emails_negative: usize,
+    /// Number of emails containing at least one term of the incivility word list
+    pub emails_uncivil: usize,
+    /// Share of emails sent on weekdays outside working hours (9:00-18:00), in the sender's local time
+    pub emails_off_hours_ratio: f64,
+    /// Share of emails sent on Saturdays and Sundays, in the sender's local time
+    pub emails_weekend_ratio: f64,
 }
 
 impl EmailsMetrics {
@@ -94,124 +131,15 @@ impl EmailsMetrics {
         }
     }
 
-    pub fn preprocess_emails(text: Option<Cow<str>>) -> EmailStats {
-        if let Some(text) = text {
-            // preprocessing the text
-
-            // removing punctuation
-            let text = text.replace(".", "");
-            let text = text.replace(",", "");
-
-            // Filter out empty lines, and lines that start with >
-            let text_lines = text
-                .lines()
-                .filter(|x| !x.is_empty())
-                .filter(|x| *x != " ")
-                .filter(|x| !x.starts_with(">"))
-                .collect::<Vec<_>>()
-                .clone();
-
-            // Remove all the lines that come after, if first lines starts with -----Original Message-----
-            let drop_from_index = text_lines
-                .clone()
-                .into_iter()
-                .position(|x| x.starts_with("-----Original Message-----")); //|| x.starts_with(">"));
-
-            let text_lines = if let Some(idx) = drop_from_index {
-                text_lines[..idx].to_vec()
-            } else {
-                text_lines
-            };
-
-            let last_line = text_lines.last();
-            // if last line starts with On XXXXXX XXXXXX and ends with wrote: we're dealing with a previous email, and we need to remove it
-            let lines = match last_line {
-                Some(last_line) => {
-                    if last_line.starts_with("On") && last_line.ends_with("wrote:") {
-                        text_lines[0..text_lines.len() - 1].to_vec()
-                    } else {
-                        text_lines
-                    }
-                }
-                None => text_lines,
-            };
-
-            let lines = lines
-                .into_iter()
-                .flat_map(|x| x.split_whitespace())
-                //.filter(|x| !stop_words.contains(&x.to_string()))
-                .collect::<Vec<_>>();
-
-            // println!("{:?}", lines);
-
-            // probably not the best to clone, but ...
-            let words = lines.clone().into_iter().count();
-            let characters = lines.join(" ").chars().count();
-            EmailStats { words, characters }
-        } else {
-            EmailStats {
-                words: 0,
-                characters: 0,
-            }
-        }
-    }
     /// Extracts word count and characters from email
-    /// Does some preprocessing removing in-line emails
-    /// Remove all the lines that come after, if first lines starts with -----Original Message-----
+    /// Quoted text, signatures, patches, code and stack traces are not counted, see `email_content::analyze`
     pub fn extract_email_stats(text: Option<Cow<str>>) -> EmailStats {
         if let Some(text) = text {
-            // preprocessing the text
-
-            // removing punctuation
-            let text = text.replace(".", "");
-            let text = text.replace(",", "");
-
-            // Filter out empty lines, and lines that start with >
-            let text_lines = text
-                .lines()
-                .filter(|x| !x.is_empty())
-                .filter(|x| *x != " ")
-                .filter(|x| !x.starts_with(">"))
-                .collect::<Vec<_>>()
-                .clone();
-
-            // Remove all the lines that come after, if first lines starts with -----Original Message-----
-            let drop_from_index = text_lines
-                .clone()
-                .into_iter()
-                .position(|x| x.starts_with("-----Original Message-----")); //|| x.starts_with(">"));
-
-            let text_lines = if let Some(idx) = drop_from_index {
-                text_lines[..idx].to_vec()
-            } else {
-                text_lines
-            };
-
-            let last_line = text_lines.last();
-            // if last line starts with On XXXXXX XXXXXX and ends with wrote: we're dealing with a previous email, and we need to remove it
-            let lines = match last_line {
-                Some(last_line) => {
-                    if last_line.starts_with("On") && last_line.ends_with("wrote:") {
-                        text_lines[0..text_lines.len() - 1].to_vec()
-                    } else {
-                        text_lines
-                    }
-                }
-                None => text_lines,
-            };
-
-            let lines = lines
-                .into_iter()
-                .flat_map(|x| x.split_whitespace())
-                //.filter(|x| !stop_words.contains(&x.to_string()))
-                .collect::<Vec<_>>();
-
-            // println!("{:?}", lines);
-
-            // probably not the best to clone, but ...
-            let words = lines.clone().into_iter().count();
-            let characters = lines.join(" ").chars().count();
-            EmailStats { words, characters }
+            let content = email_content::analyze(&text);
+            EmailStats {
+                words: content.original_words,
+                characters: content.original_characters,
+            }
         } else {
             EmailStats {
                 words: 0,
@@ -220,8 +148,9 @@ impl EmailsMetrics {
         }
     }
 
+    /// The metrics of the emails, each with the path of the archive it was read from
     pub fn parse_emails(
-        emails: Vec<Option<Message>>,
+        emails: Vec<(String, Option<Message>)>,
         incubation_month_start_date: Option<NaiveDate>,
         incubation_month_end_date: Option<NaiveDate>,
         _ignore_emails_from_addresses: Vec<&str>,
@@ -239,10 +168,29 @@ impl EmailsMetrics {
         let mut emails_thread_starter_characters = 0;
         let mut emails_threads_characters = 0;
         let mut emails_jira = 0;
-        for parsed_email in emails {
+        let mut emails_with_patches = 0;
+        let mut emails_with_stack_traces = 0;
+        let mut emails_original_word_count = 0;
+        let mut emails_quoted_word_count = 0;
+        let mut emails_sentiment_compound_sum = 0.0;
+        let mut emails_scored = 0;
+        let mut emails_negative = 0;
+        let mut emails_uncivil = 0;
+        let mut emails_off_hours = 0;
+        let mut emails_weekend = 0;
+        let mut unparseable = BTreeMap::<String, usize>::new();
+        let analyzer = SentimentAnalyzer::global();
+        for (archive, parsed_email) in emails {
             match parsed_email {
                 Some(e) => {
-                    let parsed_email = mail_parser::Message::parse(&e.contents()).unwrap();
+                    let parsed_email = match mail_parser::Message::parse(&e.contents()) {
+                        Some(parsed_email) => parsed_email,
+                        None => {
+                            log::error!("Cannot parse an email in {}", archive);
+                            *unparseable.entry(archive).or_default() += 1;
+                            continue;
+                        }
+                    };
                     let subject = parsed_email.subject().unwrap_or("");
 
                     let email_date = parsed_email.date();
@@ -285,32 +233,24 @@ impl EmailsMetrics {
                         // we cannot differentiate between devs, so let's skip them
                         continue;
                     }
-                    if incubation_month_end_date.is_some() && incubation_month_start_date.is_some()
-                    {
-                        if let Some(date) = email_date {
-                            // TODO
-                            // we need to catch the panic and stop the unwind because sometimes we get weird dates?
-                            // streams-dev-201409 we get 2014-15-09 which is an invalid date
-                            let email_date_nd = std::panic::catch_unwind(|| {
-                                chrono::NaiveDate::from_ymd(
-                                    date.year.into(),
-                                    date.month.into(),
-                                    date.day.into(),
-                                )
-                            });
-
-                            if let Ok(email_date_nd) = email_date_nd {
-                                if incubation_month_start_date.unwrap() <= email_date_nd
-                                    && email_date_nd <= incubation_month_end_date.unwrap()
-                                {
-                                    num_emails += 1;
-                                    emails_devs.insert(dev);
-                                }
-                            }
-                        }
-                    } else {
+                    // the date header holds the sender's local time
+                    let local_date = email_date.and_then(|date| {
+                        NaiveDate::from_ymd_opt(date.year.into(), date.month.into(), date.day.into())?
+                            .and_hms_opt(date.hour.into(), date.minute.into(), 0)
+                    });
+                    let counted = match (incubation_month_start_date, incubation_month_end_date) {
+                        // some archives have invalid dates, e.g. 2014-15-09 in streams-dev-201409
+                        (Some(start), Some(end)) => local_date.is_some_and(|date| start <= date.date() && date.date() <= end),
+                        _ => true,
+                    };
+                    if counted {
                         num_emails += 1;
                         emails_devs.insert(dev);
+                        match local_date.map(LocalTimeSlot::of) {
+                            Some(LocalTimeSlot::OffHours) => emails_off_hours += 1,
+                            Some(LocalTimeSlot::Weekend) => emails_weekend += 1,
+                            _ => {}
+                        }
                     }
 
                     let (thread_starter, is_thread_reply) = match references {
@@ -323,8 +263,28 @@ impl EmailsMetrics {
                             (false, true)
                         }
                     };
-                    let text = parsed_email.body_text(0);
-                    let email_stats = Self::extract_email_stats(text);
+                    let content = email_content::analyze(&parsed_email.body_text(0).unwrap_or_default());
+                    if content.has_patch() {
+                        emails_with_patches += 1;
+                    }
+                    if content.has_stack_trace() {
+                        emails_with_stack_traces += 1;
+                    }
+                    emails_original_word_count += content.original_words;
+                    emails_quoted_word_count += content.quoted_words;
+                    let sentiment = analyzer.score(&content.cleaned_body);
+                    emails_sentiment_compound_sum += sentiment.compound;
+                    emails_scored += 1;
+                    if sentiment.is_negative() {
+                        emails_negative += 1;
+                    }
+                    if sentiment.is_uncivil() {
+                        emails_uncivil += 1;
+                    }
+                    let email_stats = EmailStats {
+                        words: content.original_words,
+                        characters: content.original_characters,
+                    };
                     if thread_starter {
                         emails_thread_starter_word_count += email_stats.words;
                         emails_thread_starter_characters += email_stats.characters;
@@ -335,7 +295,8 @@ impl EmailsMetrics {
                     }
                 }
                 None => {
-                    log::error!("Cannot parse an email");
+                    log::error!("Cannot parse an email in {}", archive);
+                    *unparseable.entry(archive).or_default() += 1;
                 }
             }
         }
@@ -352,6 +313,28 @@ impl EmailsMetrics {
             emails_no_replies_word_count,
             emails_no_replies_characters,
             emails_jira,
+            content: EmailContentMetrics {
+                emails_with_patches,
+                emails_with_stack_traces,
+                emails_original_word_count,
+                emails_quoted_word_count,
+                emails_original_words_ratio: if emails_original_word_count + emails_quoted_word_count > 0 {
+                    emails_original_word_count as f64
+                        / (emails_original_word_count + emails_quoted_word_count) as f64
+                } else {
+                    0.0
+                },
+                emails_sentiment_compound_avg: if emails_scored > 0 {
+                    emails_sentiment_compound_sum / emails_scored as f64
+                } else {
+                    0.0
+                },
+                emails_negative,
+                emails_uncivil,
+                emails_off_hours_ratio: if num_emails > 0 { emails_off_hours as f64 / num_emails as f64 } else { 0.0 },
+                emails_weekend_ratio: if num_emails > 0 { emails_weekend as f64 / num_emails as f64 } else { 0.0 },
+            },
+            unparseable,
         }
     }
 
@@ -363,15 +346,13 @@ impl EmailsMetrics {
         // println!("{:?} {:?}", start_date, end_date);
         let mut emails = vec![];
         for month in Self::dates_to_mbox_months(start_date, end_date) {
-            emails.push(Self::parse_mbox_to_emails(
-                format!("{}{}.mbox", root_path, month),
-                Some(start_date),
-                Some(end_date),
-            ));
+            let path = format!("{}{}.mbox", root_path, month);
+            let archive = Self::parse_mbox_to_emails(path.clone(), Some(start_date), Some(end_date));
+            emails.extend(archive.into_iter().map(|email| (path.clone(), email)));
         }
 
         let email_metrics = Self::parse_emails(
-            emails.into_iter().flatten().collect::<Vec<_>>(),
+            emails,
             Some(start_date),
             Some(end_date),
             vec!["jira@apache.org"],
@@ -382,8 +363,9 @@ impl EmailsMetrics {
 
     pub fn metrics(path: String) -> Self {
         // Self::parse_mbox_file(path, None, None)
+        let emails = Self::parse_mbox_to_emails(path.clone(), None, None);
         Self::parse_emails(
-            Self::parse_mbox_to_emails(path, None, None),
+            emails.into_iter().map(|email| (path.clone(), email)).collect(),
             None,
             None,
             vec!["jira@apache.org"],
@@ -479,6 +461,63 @@ impl EmailsMetrics {
     // }
 }
 
+/// Email metrics of a window, read from the project's dev mailing list archives in `../../projects/emails`.
+/// With monthly windows the window's monthly archive is read; with any other window scheme, the emails between
+/// the window's dates are read from every archive it spans.
+pub struct EmailsProvider {
+    archive_prefix: String,
+}
+
+impl EmailsProvider {
+    pub fn new(project: &str) -> Self {
+        EmailsProvider {
+            archive_prefix: format!("../../projects/emails/{}-dev-", project.to_lowercase()),
+        }
+    }
+
+    /// The archives of the window's months
+    fn archives(&self, window: &Window) -> Vec<String> {
+        let months = match window.calendar_month {
+            Some(month) => vec![month.to_string()],
+            None => EmailsMetrics::dates_to_mbox_months(window.start_date, window.end_date).into_iter().collect(),
+        };
+        months.iter().map(|month| format!("{}{}.mbox", self.archive_prefix, month)).collect()
+    }
+}
+
+impl MetricProvider for EmailsProvider {
+    fn family(&self) -> &'static str {
+        "emails"
+    }
+
+    /// Whether an archive of the window's months was downloaded
+    fn observes(&self, window: &Window) -> bool {
+        self.archives(window).iter().any(|archive| std::path::Path::new(archive).exists())
+    }
+
+    fn unobserved_reason(&self, window: &Window) -> String {
+        format!("no mailing list archive {}", self.archives(window).join(", "))
+    }
+
+    fn compute(&mut self, window: &Window, metrics: &mut Metrics) -> Result<(), Box<dyn std::error::Error>> {
+        let email_metrics = match window.calendar_month {
+            Some(month) => EmailsMetrics::metrics(format!("{}{}.mbox", self.archive_prefix, month)),
+            None => EmailsMetrics::metrics_time_window(window.start_date, window.end_date, self.archive_prefix.clone()),
+        };
+        for (archive, count) in &email_metrics.unparseable {
+            diagnostics::unparseable(window.project, window.analysis, "emails", archive, *count);
+        }
+        metrics.email_content_metrics = Nullable(Some(email_metrics.content.clone()));
+        metrics.email_metrics = Nullable(Some(email_metrics));
+        Ok(())
+    }
+
+    fn impute(&self, metrics: &mut Metrics, imputation: Imputation) {
+        metrics.email_metrics = Nullable::imputed(imputation);
+        metrics.email_content_metrics = Nullable::imputed(imputation);
+    }
+}
+
 pub fn _local_mboxes_ids(project: &str, emails_storage_folder: &str) -> Vec<String> {
     let mbox_path = emails_storage_folder;
     let files = std::fs::read_dir(&mbox_path);
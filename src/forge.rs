@@ -0,0 +1,547 @@
+// src/forge.rs
+//! Forge abstraction over GitHub, GitLab and Gitea (including Codeberg and Forgejo) for issues,
+//! pull/merge requests and commit file details. The forge is chosen from the host of the repository URL, or
+//! given with `--forge` for self-hosted instances, and every backend feeds the same issue, pull request and
+//! commit-file-dev exports.
+use std::error::Error;
+use std::str::FromStr;
+use std::time::Duration;
+
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, ACCEPT, USER_AGENT};
+use serde::de::DeserializeOwned;
+
+use crate::forge_gitea::GiteaForge;
+use crate::forge_gitlab::GitLabForge;
+use crate::github_client::{backoff_delay, header_u64, is_retryable, now_secs, DEFAULT_MAX_RETRIES};
+use crate::github_issues::Issue;
+use crate::github_pull_requests::PullRequest;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForgeKind {
+    GitHub,
+    GitLab,
+    Gitea,
+}
+
+/// The hosts of the public forges. Any other host, such as a self-hosted instance, needs `--forge`.
+const KNOWN_HOSTS: &[(&str, ForgeKind)] = &[
+    ("github.com", ForgeKind::GitHub),
+    ("gitlab.com", ForgeKind::GitLab),
+    ("gitlab.gnome.org", ForgeKind::GitLab),
+    ("gitlab.freedesktop.org", ForgeKind::GitLab),
+    ("codeberg.org", ForgeKind::Gitea),
+    ("gitea.com", ForgeKind::Gitea),
+];
+
+/// A repository on a forge, parsed from its clone or web URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoRef {
+    pub kind: ForgeKind,
+    pub host: String,
+    /// The owner, or the full group path on GitLab (e.g. `gnome/gnome-shell` has owner `gnome`)
+    pub owner: String,
+    pub repo: String,
+}
+
+impl RepoRef {
+    /// Parses `https://host/owner/repo(.git)` and `git@host:owner/repo(.git)` URLs of the public forges
+    pub fn parse(url: &str) -> Result<RepoRef, Box<dyn Error>> {
+        RepoRef::parse_as(url, None)
+    }
+
+    /// Like `parse`, but on the given forge whatever the host, for self-hosted instances
+    pub fn parse_as(url: &str, forge: Option<ForgeKind>) -> Result<RepoRef, Box<dyn Error>> {
+        let trimmed = url.trim().trim_end_matches('/').trim_end_matches(".git");
+        let without_scheme = match trimmed.split_once("://") {
+            Some((_, rest)) => rest.to_string(),
+            None => trimmed.replacen(':', "/", 1),
+        };
+        let without_user = without_scheme.rsplit_once('@').map(|(_, r)| r).unwrap_or(&without_scheme);
+        let mut parts: Vec<&str> = without_user.split('/').filter(|p| !p.is_empty()).collect();
+        if parts.len() < 3 {
+            return Err(format!("Invalid repository URL: {}", url).into());
+        }
+        let host = parts.remove(0).to_lowercase();
+        let kind = forge
+            .or_else(|| ForgeKind::from_host(&host))
+            .ok_or(format!("Cannot tell the forge of {}, name it with --forge", url))?;
+        // GitLab web URLs may contain `/-/` followed by a page, e.g. `/-/issues`
+        if let Some(dash) = parts.iter().position(|p| *p == "-") {
+            parts.truncate(dash);
+        }
+        if parts.len() < 2 {
+            return Err(format!("Invalid repository URL: {}", url).into());
+        }
+        let (owner, repo) = match kind {
+            ForgeKind::GitLab => {
+                let repo = parts.pop().unwrap_or_default().to_string();
+                (parts.join("/"), repo)
+            }
+            _ => (parts[0].to_string(), parts[1].to_string()),
+        };
+        Ok(RepoRef { kind, host, owner, repo })
+    }
+
+    pub fn web_url(&self) -> String {
+        format!("https://{}/{}/{}", self.host, self.owner, self.repo)
+    }
+
+    /// REST API base URL of the forge (GitHub requests go through the shared GitHub client instead)
+    pub fn api_url(&self) -> String {
+        match self.kind {
+            ForgeKind::GitHub if self.host == "github.com" => "https://api.github.com".to_string(),
+            ForgeKind::GitHub => format!("https://{}/api/v3", self.host),
+            ForgeKind::GitLab => format!("https://{}/api/v4", self.host),
+            ForgeKind::Gitea => format!("https://{}/api/v1", self.host),
+        }
+    }
+
+    /// The API path of the repository, relative to `api_url`
+    pub fn api_path(&self) -> String {
+        match self.kind {
+            ForgeKind::GitLab => format!("/projects/{}", format!("{}/{}", self.owner, self.repo).replace('/', "%2F")),
+            _ => format!("/repos/{}/{}", self.owner, self.repo),
+        }
+    }
+
+    pub fn commit_url(&self, sha: &str) -> String {
+        match self.kind {
+            ForgeKind::GitLab => format!("{}/-/commit/{}", self.web_url(), sha),
+            _ => format!("{}/commit/{}", self.web_url(), sha),
+        }
+    }
+
+    pub fn issue_web_url(&self, number: u32) -> String {
+        match self.kind {
+            ForgeKind::GitLab => format!("{}/-/issues/{}", self.web_url(), number),
+            _ => format!("{}/issues/{}", self.web_url(), number),
+        }
+    }
+
+    pub fn pull_request_web_url(&self, number: u32) -> String {
+        match self.kind {
+            ForgeKind::GitHub => format!("{}/pull/{}", self.web_url(), number),
+            ForgeKind::GitLab => format!("{}/-/merge_requests/{}", self.web_url(), number),
+            ForgeKind::Gitea => format!("{}/pulls/{}", self.web_url(), number),
+        }
+    }
+
+    pub fn comment_web_url(&self, number: u32, comment_id: &str) -> String {
+        match self.kind {
+            ForgeKind::GitHub => format!("{}/issues/comments/{}", self.web_url(), comment_id),
+            ForgeKind::GitLab => format!("{}#note_{}", self.issue_web_url(number), comment_id),
+            ForgeKind::Gitea => format!("{}#issuecomment-{}", self.issue_web_url(number), comment_id),
+        }
+    }
+
+    /// API URL of an issue, as written to the `issue_url` column of the issues CSV
+    pub fn issue_api_url(&self, number: u32) -> String {
+        format!("{}{}/issues/{}", self.api_url(), self.api_path(), number)
+    }
+
+    /// API URL of an issue comment, as written to the `comment_url` column of the issues CSV
+    pub fn comment_api_url(&self, number: u32, comment_id: &str) -> String {
+        match self.kind {
+            ForgeKind::GitLab => format!("{}/notes/{}", self.issue_api_url(number), comment_id),
+            _ => format!("{}{}/issues/comments/{}", self.api_url(), self.api_path(), comment_id),
+        }
+    }
+
+    /// Name of the local issue store file of this repository
+    pub fn store_name(&self) -> String {
+        format!("{}_{}", self.owner.replace('/', "_"), self.repo)
+    }
+}
+
+impl ForgeKind {
+    fn from_host(host: &str) -> Option<ForgeKind> {
+        KNOWN_HOSTS.iter().find(|(known, _)| *known == host).map(|(_, kind)| *kind)
+    }
+}
+
+impl FromStr for ForgeKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "github" => Ok(ForgeKind::GitHub),
+            "gitlab" => Ok(ForgeKind::GitLab),
+            "gitea" | "forgejo" => Ok(ForgeKind::Gitea),
+            other => Err(format!("unknown forge {}, expected github, gitlab or gitea", other)),
+        }
+    }
+}
+
+/// An issue or merge request participant
+#[derive(Debug, Clone, Default)]
+pub struct ForgeUser {
+    pub login: String,
+    pub id: Option<u64>,
+    pub name: Option<String>,
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ForgeComment {
+    pub id: u64,
+    pub body: String,
+    pub created_at: String,
+    pub author: Option<ForgeUser>,
+}
+
+/// An issue in forge-neutral form. Reactions use the GitHub reaction names (`THUMBS_UP`, `HEART`, ...)
+#[derive(Debug, Clone, Default)]
+pub struct ForgeIssue {
+    pub id: u64,
+    pub number: u32,
+    pub title: String,
+    pub body: Option<String>,
+    /// `OPEN` or `CLOSED`
+    pub state: String,
+    pub created_at: String,
+    pub updated_at: String,
+    pub closed_at: Option<String>,
+    pub closed_by: Option<String>,
+    pub author: Option<ForgeUser>,
+    pub labels: Vec<String>,
+    pub assignees: Vec<String>,
+    pub milestone: Option<String>,
+    pub reactions: Vec<(String, u64)>,
+    pub comments: Vec<ForgeComment>,
+}
+
+/// A review, approval or review comment. States use the GitHub names (`APPROVED`, `COMMENTED`, ...)
+#[derive(Debug, Clone, Default)]
+pub struct ForgeReview {
+    pub id: u64,
+    pub state: String,
+    pub body: String,
+    pub submitted_at: Option<String>,
+    pub author: Option<String>,
+}
+
+/// A pull request or merge request in forge-neutral form. States use the GitHub names (`OPEN`, `CLOSED`, `MERGED`)
+#[derive(Debug, Clone, Default)]
+pub struct ForgePullRequest {
+    pub id: u64,
+    pub number: u32,
+    pub title: String,
+    pub body: Option<String>,
+    pub state: String,
+    pub is_draft: bool,
+    pub created_at: String,
+    pub updated_at: String,
+    pub closed_at: Option<String>,
+    pub merged_at: Option<String>,
+    pub base_ref: String,
+    pub head_ref: String,
+    pub author: Option<String>,
+    pub merged_by: Option<String>,
+    pub requested_reviewers: Vec<String>,
+    pub reviews: Vec<ForgeReview>,
+    pub commits: Vec<ForgeCommit>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ForgeCommit {
+    pub sha: String,
+    pub message: String,
+    /// RFC 3339 commit date
+    pub date: String,
+    pub author_name: String,
+    pub author_email: String,
+}
+
+/// File level change of a commit. Statuses use the GitHub names (`added`, `removed`, `modified`, `renamed`)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileChange {
+    pub filename: String,
+    pub status: String,
+    pub additions: u32,
+    pub deletions: u32,
+}
+
+pub trait Forge: Send + Sync {
+    /// Issues with their comments, only those updated at or after `since` when given
+    fn issues(&self, repo: &RepoRef, since: Option<&str>) -> Result<Vec<Issue>, Box<dyn Error>>;
+
+    /// Pull or merge requests with their reviews, requested reviewers and commits
+    fn pull_requests(&self, repo: &RepoRef) -> Result<Vec<PullRequest>, Box<dyn Error>>;
+
+    /// Commits of the default branch, only those committed within `since`..=`until` (RFC 3339) when given
+    fn commits(&self, repo: &RepoRef, since: Option<&str>, until: Option<&str>) -> Result<Vec<ForgeCommit>, Box<dyn Error>>;
+
+    /// Files changed by a commit
+    fn commit_files(&self, repo: &RepoRef, sha: &str) -> Result<Vec<FileChange>, Box<dyn Error>>;
+}
+
+/// GitHub backend, on top of the GraphQL issue, pull request and commit queries
+pub struct GitHubForge;
+
+impl Forge for GitHubForge {
+    fn issues(&self, repo: &RepoRef, since: Option<&str>) -> Result<Vec<Issue>, Box<dyn Error>> {
+        crate::github_issues::fetch_issues(&repo.owner, &repo.repo, since)
+    }
+
+    fn pull_requests(&self, repo: &RepoRef) -> Result<Vec<PullRequest>, Box<dyn Error>> {
+        crate::github_pull_requests::fetch_pull_requests(&repo.owner, &repo.repo)
+    }
+
+    fn commits(&self, repo: &RepoRef, since: Option<&str>, until: Option<&str>) -> Result<Vec<ForgeCommit>, Box<dyn Error>> {
+        crate::commit_metrics_graphql::fetch_history(&repo.owner, &repo.repo, since, until)
+    }
+
+    fn commit_files(&self, repo: &RepoRef, sha: &str) -> Result<Vec<FileChange>, Box<dyn Error>> {
+        crate::commit_metrics_graphql::fetch_commit_files(&repo.owner, &repo.repo, sha)
+    }
+}
+
+/// The backend for the forge hosting `repo`
+pub fn for_repo(repo: &RepoRef) -> Result<Box<dyn Forge>, Box<dyn Error>> {
+    Ok(match repo.kind {
+        ForgeKind::GitHub => Box::new(GitHubForge),
+        ForgeKind::GitLab => Box::new(GitLabForge::new(repo)?),
+        ForgeKind::Gitea => Box::new(GiteaForge::new(repo)?),
+    })
+}
+
+/// Minimal paginated REST client for the GitLab and Gitea backends
+pub struct RestClient {
+    client: Client,
+    base_url: String,
+    page_size_param: &'static str,
+    page_size: usize,
+    max_retries: u32,
+    backoff: Duration,
+}
+
+/// Seconds to wait for a rate limit: the `retry-after` header, or the time to the `ratelimit-reset` GitLab
+/// sends once `ratelimit-remaining` is down to 0
+fn rate_limit_wait(headers: &HeaderMap) -> Option<u64> {
+    header_u64(headers, "retry-after").or_else(|| {
+        if header_u64(headers, "ratelimit-remaining") != Some(0) {
+            return None;
+        }
+        header_u64(headers, "ratelimit-reset").map(|reset| reset.saturating_sub(now_secs()))
+    })
+}
+
+impl RestClient {
+    /// `auth` is the authentication header and its value, if a token is configured
+    pub fn new(
+        base_url: &str,
+        auth: Option<(HeaderName, String)>,
+        page_size_param: &'static str,
+        page_size: usize,
+    ) -> Result<RestClient, Box<dyn Error>> {
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_static(concat!("miner/", env!("CARGO_PKG_VERSION"))));
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+        if let Some((name, value)) = auth {
+            headers.insert(name, HeaderValue::from_str(&value)?);
+        }
+        let client = Client::builder()
+            .default_headers(headers)
+            .timeout(Duration::from_secs(120))
+            .build()?;
+        Ok(RestClient {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            page_size_param,
+            page_size,
+            max_retries: DEFAULT_MAX_RETRIES,
+            backoff: Duration::from_secs(2),
+        })
+    }
+
+    /// GETs a path, retrying with exponential backoff on connection errors, 5xx and rate limits, the same way
+    /// as the GitHub client. A rate limit is waited out for as long as the forge says.
+    fn send(&self, path: &str, query: &[(&str, String)]) -> Result<Response, Box<dyn Error>> {
+        let url = format!("{}{}", self.base_url, path);
+        let mut attempt = 0;
+        loop {
+            let response = match self.client.get(&url).query(query).send() {
+                Ok(response) => response,
+                Err(e) if attempt < self.max_retries => {
+                    log::warn!("Forge API request {} failed: {}, retrying", url, e);
+                    std::thread::sleep(backoff_delay(self.backoff, attempt, None));
+                    attempt += 1;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let status = response.status();
+            if status.is_success() {
+                return Ok(response);
+            }
+            let retry_after = rate_limit_wait(response.headers());
+            let text = response.text().unwrap_or_default();
+            if is_retryable(status, retry_after, &text) && attempt < self.max_retries {
+                log::warn!("Forge API request {} failed with status {}, retrying", url, status);
+                std::thread::sleep(backoff_delay(self.backoff, attempt, retry_after));
+                attempt += 1;
+                continue;
+            }
+            log::error!("Forge API request {} failed with status {}: {}", url, status, text);
+            return Err(format!("Forge API request failed with status {}", status).into());
+        }
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, path: &str, query: &[(&str, String)]) -> Result<T, Box<dyn Error>> {
+        Ok(self.send(path, query)?.json()?)
+    }
+
+    pub fn get_text(&self, path: &str) -> Result<String, Box<dyn Error>> {
+        Ok(self.send(path, &[])?.text()?)
+    }
+
+    /// Follows `page=1,2,...` until a page comes back shorter than the page size
+    pub fn get_all<T: DeserializeOwned>(&self, path: &str, query: &[(&str, String)]) -> Result<Vec<T>, Box<dyn Error>> {
+        let mut all = Vec::new();
+        let mut page = 1;
+        loop {
+            let mut page_query = query.to_vec();
+            page_query.push(("page", page.to_string()));
+            page_query.push((self.page_size_param, self.page_size.to_string()));
+            let items: Vec<T> = self.get(path, &page_query)?;
+            let count = items.len();
+            all.extend(items);
+            if count < self.page_size {
+                break;
+            }
+            page += 1;
+        }
+        Ok(all)
+    }
+}
+
+/// `since`/`until` query parameters, as understood by the GitLab and Gitea commit lists
+pub fn date_range_query(since: Option<&str>, until: Option<&str>) -> Vec<(&'static str, String)> {
+    let mut query = Vec::new();
+    if let Some(since) = since {
+        query.push(("since", since.to_string()));
+    }
+    if let Some(until) = until {
+        query.push(("until", until.to_string()));
+    }
+    query
+}
+
+/// Lines added and deleted in the hunks of a unified diff (without the `---`/`+++` file headers)
+pub fn count_diff_lines(diff: &str) -> (u32, u32) {
+    let mut additions = 0;
+    let mut deletions = 0;
+    for line in diff.lines() {
+        if line.starts_with('+') {
+            additions += 1;
+        } else if line.starts_with('-') {
+            deletions += 1;
+        }
+    }
+    (additions, deletions)
+}
+
+/// Splits a `git diff` of several files into per-file changes
+pub fn parse_unified_diff(diff: &str) -> Vec<FileChange> {
+    let mut files = Vec::new();
+    for chunk in diff.split("\ndiff --git ").filter(|c| !c.trim().is_empty()) {
+        let chunk = chunk.strip_prefix("diff --git ").unwrap_or(chunk);
+        let header = chunk.lines().next().unwrap_or_default();
+        let mut filename = header
+            .rsplit_once(" b/")
+            .map(|(_, b)| b.to_string())
+            .unwrap_or_else(|| header.to_string());
+        let mut status = "modified";
+        for line in chunk.lines().take_while(|l| !l.starts_with("@@")) {
+            if line.starts_with("new file mode") {
+                status = "added";
+            } else if line.starts_with("deleted file mode") {
+                status = "removed";
+            } else if let Some(to) = line.strip_prefix("rename to ") {
+                status = "renamed";
+                filename = to.to_string();
+            }
+        }
+        let body: String = chunk
+            .lines()
+            .skip_while(|l| !l.starts_with("@@"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let (additions, deletions) = count_diff_lines(&body);
+        files.push(FileChange { filename, status: status.to_string(), additions, deletions });
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_repo_urls() {
+        let github = RepoRef::parse("https://github.com/apache/hunter.git").unwrap();
+        assert_eq!(github.kind, ForgeKind::GitHub);
+        assert_eq!((github.owner.as_str(), github.repo.as_str()), ("apache", "hunter"));
+        assert_eq!(github.commit_url("abc"), "https://github.com/apache/hunter/commit/abc");
+        assert_eq!(github.issue_api_url(3), "https://api.github.com/repos/apache/hunter/issues/3");
+
+        let gitlab = RepoRef::parse("https://gitlab.com/gnome/sub/project/-/issues").unwrap();
+        assert_eq!(gitlab.kind, ForgeKind::GitLab);
+        assert_eq!((gitlab.owner.as_str(), gitlab.repo.as_str()), ("gnome/sub", "project"));
+        assert_eq!(gitlab.api_path(), "/projects/gnome%2Fsub%2Fproject");
+        assert_eq!(gitlab.commit_url("abc"), "https://gitlab.com/gnome/sub/project/-/commit/abc");
+        assert_eq!(gitlab.store_name(), "gnome_sub_project");
+
+        let gitea = RepoRef::parse("git@codeberg.org:forgejo/forgejo.git").unwrap();
+        assert_eq!(gitea.kind, ForgeKind::Gitea);
+        assert_eq!(gitea.api_url(), "https://codeberg.org/api/v1");
+        assert_eq!(gitea.comment_api_url(1, "42"), "https://codeberg.org/api/v1/repos/forgejo/forgejo/issues/comments/42");
+
+        assert!(RepoRef::parse("https://example.org/a/b").is_err());
+        // only the exact hosts of the public forges are recognized, other hosts need the forge named
+        assert!(RepoRef::parse("https://notgithub.example.com/a/b").is_err());
+        assert!(RepoRef::parse("https://gitlab.example.com/a/b").is_err());
+        let self_hosted = RepoRef::parse_as("https://git.example.com/group/sub/b", Some(ForgeKind::GitLab)).unwrap();
+        assert_eq!((self_hosted.owner.as_str(), self_hosted.repo.as_str()), ("group/sub", "b"));
+        assert_eq!(self_hosted.api_url(), "https://git.example.com/api/v4");
+        assert_eq!("forgejo".parse::<ForgeKind>(), Ok(ForgeKind::Gitea));
+        assert!("bitbucket".parse::<ForgeKind>().is_err());
+        assert!(RepoRef::parse("https://github.com/apache").is_err());
+        let issues_page = RepoRef::parse("https://github.com/apache/hunter/issues/12").unwrap();
+        assert_eq!(issues_page.repo, "hunter");
+    }
+
+    #[test]
+    fn test_parse_unified_diff() {
+        let diff = "diff --git a/src/a.rs b/src/a.rs\nindex 1..2 100644\n--- a/src/a.rs\n+++ b/src/a.rs\n@@ -1,2 +1,2 @@\n-old\n+new\n+more\n context\n\
+diff --git a/new.txt b/new.txt\nnew file mode 100644\n--- /dev/null\n+++ b/new.txt\n@@ -0,0 +1 @@\n+hello\n\
+diff --git a/x.txt b/y.txt\nsimilarity index 100%\nrename from x.txt\nrename to y.txt\n";
+        let files = parse_unified_diff(diff);
+        assert_eq!(
+            files,
+            vec![
+                FileChange { filename: "src/a.rs".into(), status: "modified".into(), additions: 2, deletions: 1 },
+                FileChange { filename: "new.txt".into(), status: "added".into(), additions: 1, deletions: 0 },
+                FileChange { filename: "y.txt".into(), status: "renamed".into(), additions: 0, deletions: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rest_client_retries() {
+        let url = crate::github_client::tests::mock_server(vec![
+            ("503 Service Unavailable", "", "{}"),
+            ("429 Too Many Requests", "ratelimit-remaining: 0\r\nratelimit-reset: 1\r\n", "{}"),
+            ("200 OK", "", r#"{"id": 1}"#),
+        ]);
+        let mut client = RestClient::new(&url, None, "per_page", 100).unwrap();
+        client.backoff = Duration::from_millis(1);
+        let project: serde_json::Value = client.get("/projects/1", &[]).unwrap();
+        assert_eq!(project["id"], 1);
+
+        let url = crate::github_client::tests::mock_server(vec![("404 Not Found", "", "{}"), ("200 OK", "", "{}")]);
+        let client = RestClient::new(&url, None, "per_page", 100).unwrap();
+        assert!(client.get::<serde_json::Value>("/projects/2", &[]).is_err());
+    }
+}
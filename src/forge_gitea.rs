@@ -0,0 +1,324 @@
+// src/forge_gitea.rs
+//! Gitea backend of the forge abstraction (also Codeberg and Forgejo), on the REST API v1.
+//! Authenticates with `GITEA_TOKEN` when set.
+use std::error::Error;
+
+use reqwest::header::AUTHORIZATION;
+use serde::Deserialize;
+
+use crate::forge::{
+    date_range_query, parse_unified_diff, FileChange, Forge, ForgeComment, ForgeCommit, ForgeIssue, ForgePullRequest, ForgeReview,
+    ForgeUser, RepoRef, RestClient,
+};
+use crate::github_issues::Issue;
+use crate::github_pull_requests::PullRequest;
+
+/// Gitea caps `limit` at 50 by default
+const PAGE_SIZE: usize = 50;
+
+#[derive(Debug, Deserialize)]
+struct User {
+    id: Option<u64>,
+    login: String,
+    #[serde(default)]
+    full_name: Option<String>,
+    #[serde(default)]
+    email: Option<String>,
+}
+
+impl From<User> for ForgeUser {
+    fn from(user: User) -> ForgeUser {
+        ForgeUser {
+            login: user.login,
+            id: user.id,
+            name: user.full_name.filter(|n| !n.is_empty()),
+            email: user.email.filter(|e| !e.is_empty()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Label {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Milestone {
+    title: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GiteaIssue {
+    id: u64,
+    number: u32,
+    title: String,
+    body: Option<String>,
+    state: String,
+    created_at: String,
+    updated_at: String,
+    closed_at: Option<String>,
+    user: Option<User>,
+    #[serde(default)]
+    labels: Vec<Label>,
+    #[serde(default)]
+    assignees: Option<Vec<User>>,
+    milestone: Option<Milestone>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Comment {
+    id: u64,
+    body: String,
+    created_at: String,
+    user: Option<User>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Branch {
+    #[serde(rename = "ref")]
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Pull {
+    id: u64,
+    number: u32,
+    title: String,
+    body: Option<String>,
+    state: String,
+    #[serde(default)]
+    draft: bool,
+    created_at: String,
+    updated_at: String,
+    closed_at: Option<String>,
+    merged_at: Option<String>,
+    #[serde(default)]
+    merged: bool,
+    merged_by: Option<User>,
+    base: Option<Branch>,
+    head: Option<Branch>,
+    user: Option<User>,
+    #[serde(default)]
+    requested_reviewers: Option<Vec<User>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Review {
+    id: u64,
+    state: String,
+    #[serde(default)]
+    body: String,
+    submitted_at: Option<String>,
+    user: Option<User>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Commit {
+    sha: String,
+    commit: CommitDetail,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommitDetail {
+    message: String,
+    author: Option<CommitUser>,
+    committer: Option<CommitUser>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommitUser {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    email: String,
+    #[serde(default)]
+    date: String,
+}
+
+impl From<Commit> for ForgeCommit {
+    fn from(c: Commit) -> ForgeCommit {
+        let date = c
+            .commit
+            .committer
+            .as_ref()
+            .or(c.commit.author.as_ref())
+            .map(|u| u.date.clone())
+            .unwrap_or_default();
+        let (author_name, author_email) = c.commit.author.map(|a| (a.name, a.email)).unwrap_or_default();
+        ForgeCommit { sha: c.sha, message: c.commit.message, date, author_name, author_email }
+    }
+}
+
+fn to_forge_issue(issue: GiteaIssue, comments: Vec<Comment>) -> ForgeIssue {
+    ForgeIssue {
+        id: issue.id,
+        number: issue.number,
+        title: issue.title,
+        body: issue.body,
+        state: issue.state.to_uppercase(),
+        created_at: issue.created_at,
+        updated_at: issue.updated_at,
+        closed_at: issue.closed_at,
+        closed_by: None,
+        author: issue.user.map(ForgeUser::from),
+        labels: issue.labels.into_iter().map(|l| l.name).collect(),
+        assignees: issue.assignees.unwrap_or_default().into_iter().map(|u| u.login).collect(),
+        milestone: issue.milestone.map(|m| m.title),
+        reactions: Vec::new(),
+        comments: comments
+            .into_iter()
+            .map(|c| ForgeComment {
+                id: c.id,
+                body: c.body,
+                created_at: c.created_at,
+                author: c.user.map(ForgeUser::from),
+            })
+            .collect(),
+    }
+}
+
+/// Gitea review states are `APPROVED`, `REQUEST_CHANGES`, `COMMENT`, `PENDING` and `REQUEST_REVIEW`
+fn github_review_state(state: &str) -> String {
+    match state {
+        "REQUEST_CHANGES" => "CHANGES_REQUESTED",
+        "COMMENT" => "COMMENTED",
+        other => other,
+    }
+    .to_string()
+}
+
+fn to_forge_pull_request(pull: Pull, reviews: Vec<Review>, commits: Vec<Commit>) -> ForgePullRequest {
+    let state = if pull.merged || pull.merged_at.is_some() {
+        "MERGED".to_string()
+    } else {
+        pull.state.to_uppercase()
+    };
+    ForgePullRequest {
+        id: pull.id,
+        number: pull.number,
+        title: pull.title,
+        body: pull.body,
+        state,
+        is_draft: pull.draft,
+        created_at: pull.created_at,
+        updated_at: pull.updated_at,
+        closed_at: pull.closed_at,
+        merged_at: pull.merged_at,
+        base_ref: pull.base.map(|b| b.name).unwrap_or_default(),
+        head_ref: pull.head.map(|b| b.name).unwrap_or_default(),
+        author: pull.user.map(|u| u.login),
+        merged_by: pull.merged_by.map(|u| u.login),
+        requested_reviewers: pull.requested_reviewers.unwrap_or_default().into_iter().map(|u| u.login).collect(),
+        reviews: reviews
+            .into_iter()
+            .filter(|r| r.state != "PENDING" && r.state != "REQUEST_REVIEW")
+            .map(|r| ForgeReview {
+                id: r.id,
+                state: github_review_state(&r.state),
+                body: r.body,
+                submitted_at: r.submitted_at,
+                author: r.user.map(|u| u.login),
+            })
+            .collect(),
+        commits: commits.into_iter().map(ForgeCommit::from).collect(),
+    }
+}
+
+pub struct GiteaForge {
+    client: RestClient,
+}
+
+impl GiteaForge {
+    pub fn new(repo: &RepoRef) -> Result<GiteaForge, Box<dyn Error>> {
+        let auth = std::env::var("GITEA_TOKEN")
+            .ok()
+            .map(|token| (AUTHORIZATION, format!("token {}", token)));
+        Ok(GiteaForge {
+            client: RestClient::new(&repo.api_url(), auth, "limit", PAGE_SIZE)?,
+        })
+    }
+}
+
+impl Forge for GiteaForge {
+    fn issues(&self, repo: &RepoRef, since: Option<&str>) -> Result<Vec<Issue>, Box<dyn Error>> {
+        let mut query = vec![("state", "all".to_string()), ("type", "issues".to_string())];
+        if let Some(since) = since {
+            query.push(("since", since.to_string()));
+        }
+        let issues: Vec<GiteaIssue> = self.client.get_all(&format!("{}/issues", repo.api_path()), &query)?;
+        let mut all = Vec::with_capacity(issues.len());
+        for issue in issues {
+            let comments: Vec<Comment> = self
+                .client
+                .get_all(&format!("{}/issues/{}/comments", repo.api_path(), issue.number), &[])?;
+            all.push(Issue::from(to_forge_issue(issue, comments)));
+        }
+        Ok(all)
+    }
+
+    fn pull_requests(&self, repo: &RepoRef) -> Result<Vec<PullRequest>, Box<dyn Error>> {
+        let pulls: Vec<Pull> = self
+            .client
+            .get_all(&format!("{}/pulls", repo.api_path()), &[("state", "all".to_string())])?;
+        let mut all = Vec::with_capacity(pulls.len());
+        for pull in pulls {
+            let reviews: Vec<Review> = self
+                .client
+                .get_all(&format!("{}/pulls/{}/reviews", repo.api_path(), pull.number), &[])?;
+            let commits: Vec<Commit> = self
+                .client
+                .get_all(&format!("{}/pulls/{}/commits", repo.api_path(), pull.number), &[])?;
+            all.push(PullRequest::from(to_forge_pull_request(pull, reviews, commits)));
+        }
+        Ok(all)
+    }
+
+    fn commits(&self, repo: &RepoRef, since: Option<&str>, until: Option<&str>) -> Result<Vec<ForgeCommit>, Box<dyn Error>> {
+        let mut query = vec![("stat", "false".to_string()), ("files", "false".to_string())];
+        query.extend(date_range_query(since, until));
+        let commits: Vec<Commit> = self.client.get_all(&format!("{}/commits", repo.api_path()), &query)?;
+        Ok(commits.into_iter().map(ForgeCommit::from).collect())
+    }
+
+    fn commit_files(&self, repo: &RepoRef, sha: &str) -> Result<Vec<FileChange>, Box<dyn Error>> {
+        let diff = self
+            .client
+            .get_text(&format!("{}/git/commits/{}.diff", repo.api_path(), sha))?;
+        Ok(parse_unified_diff(&diff))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_pull_request_mapping() {
+        let pull: Pull = serde_json::from_value(json!({
+            "id": 5, "number": 12, "title": "Add docs", "body": "", "state": "closed",
+            "created_at": "2022-01-01T10:00:00+01:00", "updated_at": "2022-01-02T10:00:00+01:00",
+            "closed_at": "2022-01-02T10:00:00+01:00", "merged_at": "2022-01-02T10:00:00+01:00", "merged": true,
+            "merged_by": {"id": 1, "login": "alice"}, "base": {"ref": "main"}, "head": {"ref": "docs"},
+            "user": {"id": 2, "login": "bob"}, "requested_reviewers": null
+        }))
+        .unwrap();
+        let reviews: Vec<Review> = serde_json::from_value(json!([
+            {"id": 1, "state": "REQUEST_CHANGES", "body": "typo", "submitted_at": "2022-01-01T12:00:00+01:00", "user": {"login": "alice"}},
+            {"id": 2, "state": "PENDING", "body": "", "submitted_at": null, "user": {"login": "alice"}},
+            {"id": 3, "state": "APPROVED", "body": "", "submitted_at": "2022-01-02T09:00:00+01:00", "user": {"login": "alice"}}
+        ]))
+        .unwrap();
+        let commits: Vec<Commit> = serde_json::from_value(json!([
+            {"sha": "abc", "commit": {"message": "Add docs\n\nLong", "author": {"name": "Bob", "email": "bob@example.org", "date": "2022-01-01T09:00:00+01:00"}, "committer": null}}
+        ]))
+        .unwrap();
+        let pr = to_forge_pull_request(pull, reviews, commits);
+        assert_eq!(pr.state, "MERGED");
+        assert_eq!(pr.base_ref, "main");
+        let states: Vec<&str> = pr.reviews.iter().map(|r| r.state.as_str()).collect();
+        assert_eq!(states, vec!["CHANGES_REQUESTED", "APPROVED"]);
+        assert_eq!(pr.commits[0].date, "2022-01-01T09:00:00+01:00");
+        assert_eq!(pr.commits[0].author_email, "bob@example.org");
+    }
+}
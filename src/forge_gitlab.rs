@@ -0,0 +1,367 @@
+// src/forge_gitlab.rs
+//! GitLab backend of the forge abstraction, on the REST API v4. Authenticates with `GITLAB_TOKEN` when set.
+use std::error::Error;
+
+use reqwest::header::HeaderName;
+use serde::Deserialize;
+
+use crate::forge::{
+    count_diff_lines, date_range_query, FileChange, Forge, ForgeComment, ForgeCommit, ForgeIssue, ForgePullRequest, ForgeReview,
+    ForgeUser, RepoRef, RestClient,
+};
+use crate::github_issues::Issue;
+use crate::github_pull_requests::PullRequest;
+
+const PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Deserialize)]
+struct User {
+    id: Option<u64>,
+    username: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    public_email: Option<String>,
+}
+
+impl From<User> for ForgeUser {
+    fn from(user: User) -> ForgeUser {
+        ForgeUser {
+            login: user.username,
+            id: user.id,
+            name: user.name,
+            email: user.public_email.filter(|e| !e.is_empty()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Milestone {
+    title: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitLabIssue {
+    id: u64,
+    iid: u32,
+    title: String,
+    description: Option<String>,
+    state: String,
+    created_at: String,
+    updated_at: String,
+    closed_at: Option<String>,
+    closed_by: Option<User>,
+    author: Option<User>,
+    #[serde(default)]
+    labels: Vec<String>,
+    #[serde(default)]
+    assignees: Vec<User>,
+    milestone: Option<Milestone>,
+    #[serde(default)]
+    upvotes: u64,
+    #[serde(default)]
+    downvotes: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Note {
+    id: u64,
+    body: String,
+    created_at: String,
+    author: Option<User>,
+    #[serde(default)]
+    system: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct MergeRequest {
+    id: u64,
+    iid: u32,
+    title: String,
+    description: Option<String>,
+    state: String,
+    #[serde(default)]
+    draft: bool,
+    created_at: String,
+    updated_at: String,
+    closed_at: Option<String>,
+    merged_at: Option<String>,
+    #[serde(default)]
+    source_branch: String,
+    #[serde(default)]
+    target_branch: String,
+    author: Option<User>,
+    merged_by: Option<User>,
+    #[serde(default)]
+    reviewers: Vec<User>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Commit {
+    id: String,
+    message: String,
+    committed_date: String,
+    #[serde(default)]
+    author_name: String,
+    #[serde(default)]
+    author_email: String,
+}
+
+impl From<Commit> for ForgeCommit {
+    fn from(c: Commit) -> ForgeCommit {
+        ForgeCommit {
+            sha: c.id,
+            message: c.message,
+            date: c.committed_date,
+            author_name: c.author_name,
+            author_email: c.author_email,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Diff {
+    new_path: String,
+    #[serde(default)]
+    new_file: bool,
+    #[serde(default)]
+    deleted_file: bool,
+    #[serde(default)]
+    renamed_file: bool,
+    #[serde(default)]
+    diff: String,
+}
+
+/// GitLab issue states are `opened` and `closed`; merge requests also have `merged` and `locked`
+fn github_state(state: &str) -> String {
+    match state {
+        "opened" => "OPEN",
+        "merged" => "MERGED",
+        _ => "CLOSED",
+    }
+    .to_string()
+}
+
+fn to_forge_issue(issue: GitLabIssue, notes: Vec<Note>) -> ForgeIssue {
+    let mut reactions = Vec::new();
+    if issue.upvotes > 0 {
+        reactions.push(("THUMBS_UP".to_string(), issue.upvotes));
+    }
+    if issue.downvotes > 0 {
+        reactions.push(("THUMBS_DOWN".to_string(), issue.downvotes));
+    }
+    ForgeIssue {
+        id: issue.id,
+        number: issue.iid,
+        title: issue.title,
+        body: issue.description,
+        state: github_state(&issue.state),
+        created_at: issue.created_at,
+        updated_at: issue.updated_at,
+        closed_at: issue.closed_at,
+        closed_by: issue.closed_by.map(|u| u.username),
+        author: issue.author.map(ForgeUser::from),
+        labels: issue.labels,
+        assignees: issue.assignees.into_iter().map(|u| u.username).collect(),
+        milestone: issue.milestone.map(|m| m.title),
+        reactions,
+        // System notes record label changes, mentions and the like, not discussion
+        comments: notes
+            .into_iter()
+            .filter(|n| !n.system)
+            .map(|n| ForgeComment {
+                id: n.id,
+                body: n.body,
+                created_at: n.created_at,
+                author: n.author.map(ForgeUser::from),
+            })
+            .collect(),
+    }
+}
+
+/// Discussion notes by others become `COMMENTED` reviews and approval system notes become `APPROVED` reviews
+fn to_forge_pull_request(mr: MergeRequest, notes: Vec<Note>, commits: Vec<Commit>) -> ForgePullRequest {
+    let author = mr.author.map(|u| u.username);
+    let reviews = notes
+        .into_iter()
+        .filter_map(|n| {
+            let login = n.author.map(|u| u.username);
+            let state = if n.system && n.body.starts_with("approved this merge request") {
+                "APPROVED"
+            } else if !n.system && login != author {
+                "COMMENTED"
+            } else {
+                return None;
+            };
+            Some(ForgeReview {
+                id: n.id,
+                state: state.to_string(),
+                body: if n.system { String::new() } else { n.body },
+                submitted_at: Some(n.created_at),
+                author: login,
+            })
+        })
+        .collect();
+    ForgePullRequest {
+        id: mr.id,
+        number: mr.iid,
+        title: mr.title,
+        body: mr.description,
+        state: github_state(&mr.state),
+        is_draft: mr.draft,
+        created_at: mr.created_at,
+        updated_at: mr.updated_at,
+        closed_at: mr.closed_at.or_else(|| mr.merged_at.clone()),
+        merged_at: mr.merged_at,
+        base_ref: mr.target_branch,
+        head_ref: mr.source_branch,
+        author,
+        merged_by: mr.merged_by.map(|u| u.username),
+        requested_reviewers: mr.reviewers.into_iter().map(|u| u.username).collect(),
+        reviews,
+        commits: commits.into_iter().map(ForgeCommit::from).collect(),
+    }
+}
+
+pub struct GitLabForge {
+    client: RestClient,
+}
+
+impl GitLabForge {
+    pub fn new(repo: &RepoRef) -> Result<GitLabForge, Box<dyn Error>> {
+        let auth = std::env::var("GITLAB_TOKEN")
+            .ok()
+            .map(|token| (HeaderName::from_static("private-token"), token));
+        Ok(GitLabForge {
+            client: RestClient::new(&repo.api_url(), auth, "per_page", PAGE_SIZE)?,
+        })
+    }
+
+    fn notes(&self, repo: &RepoRef, kind: &str, iid: u32) -> Result<Vec<Note>, Box<dyn Error>> {
+        self.client.get_all(
+            &format!("{}/{}/{}/notes", repo.api_path(), kind, iid),
+            &[("sort", "asc".to_string()), ("order_by", "created_at".to_string())],
+        )
+    }
+}
+
+impl Forge for GitLabForge {
+    fn issues(&self, repo: &RepoRef, since: Option<&str>) -> Result<Vec<Issue>, Box<dyn Error>> {
+        let mut query = vec![
+            ("scope", "all".to_string()),
+            ("order_by", "updated_at".to_string()),
+            ("sort", "asc".to_string()),
+        ];
+        if let Some(since) = since {
+            query.push(("updated_after", since.to_string()));
+        }
+        let issues: Vec<GitLabIssue> = self.client.get_all(&format!("{}/issues", repo.api_path()), &query)?;
+        let mut all = Vec::with_capacity(issues.len());
+        for issue in issues {
+            let notes = self.notes(repo, "issues", issue.iid)?;
+            all.push(Issue::from(to_forge_issue(issue, notes)));
+        }
+        Ok(all)
+    }
+
+    fn pull_requests(&self, repo: &RepoRef) -> Result<Vec<PullRequest>, Box<dyn Error>> {
+        let query = [
+            ("scope", "all".to_string()),
+            ("state", "all".to_string()),
+            ("order_by", "created_at".to_string()),
+            ("sort", "asc".to_string()),
+        ];
+        let mrs: Vec<MergeRequest> = self.client.get_all(&format!("{}/merge_requests", repo.api_path()), &query)?;
+        let mut all = Vec::with_capacity(mrs.len());
+        for mr in mrs {
+            let notes = self.notes(repo, "merge_requests", mr.iid)?;
+            let commits: Vec<Commit> = self
+                .client
+                .get_all(&format!("{}/merge_requests/{}/commits", repo.api_path(), mr.iid), &[])?;
+            all.push(PullRequest::from(to_forge_pull_request(mr, notes, commits)));
+        }
+        Ok(all)
+    }
+
+    fn commits(&self, repo: &RepoRef, since: Option<&str>, until: Option<&str>) -> Result<Vec<ForgeCommit>, Box<dyn Error>> {
+        let query = date_range_query(since, until);
+        let commits: Vec<Commit> = self
+            .client
+            .get_all(&format!("{}/repository/commits", repo.api_path()), &query)?;
+        Ok(commits.into_iter().map(ForgeCommit::from).collect())
+    }
+
+    fn commit_files(&self, repo: &RepoRef, sha: &str) -> Result<Vec<FileChange>, Box<dyn Error>> {
+        let diffs: Vec<Diff> = self
+            .client
+            .get_all(&format!("{}/repository/commits/{}/diff", repo.api_path(), sha), &[])?;
+        Ok(diffs
+            .into_iter()
+            .map(|d| {
+                let status = if d.new_file {
+                    "added"
+                } else if d.deleted_file {
+                    "removed"
+                } else if d.renamed_file {
+                    "renamed"
+                } else {
+                    "modified"
+                };
+                let (additions, deletions) = count_diff_lines(&d.diff);
+                FileChange { filename: d.new_path, status: status.to_string(), additions, deletions }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_issue_and_merge_request_mapping() {
+        let issue: GitLabIssue = serde_json::from_value(json!({
+            "id": 900, "iid": 7, "title": "Crash", "description": "It crashes", "state": "closed",
+            "created_at": "2021-03-01T10:00:00.000Z", "updated_at": "2021-03-02T10:00:00.000Z",
+            "closed_at": "2021-03-02T10:00:00.000Z", "closed_by": {"id": 2, "username": "bob"},
+            "author": {"id": 1, "username": "alice", "name": "Alice"},
+            "labels": ["bug"], "assignees": [{"id": 2, "username": "bob"}], "milestone": {"title": "1.0"},
+            "upvotes": 2, "downvotes": 0
+        }))
+        .unwrap();
+        let notes: Vec<Note> = serde_json::from_value(json!([
+            {"id": 1, "body": "added ~bug label", "created_at": "2021-03-01T11:00:00.000Z", "author": {"username": "bob"}, "system": true},
+            {"id": 2, "body": "Fixed in !3", "created_at": "2021-03-02T09:00:00.000Z", "author": {"username": "bob"}, "system": false}
+        ]))
+        .unwrap();
+        let issue = to_forge_issue(issue, notes);
+        assert_eq!(issue.number, 7);
+        assert_eq!(issue.state, "CLOSED");
+        assert_eq!(issue.closed_by.as_deref(), Some("bob"));
+        assert_eq!(issue.reactions, vec![("THUMBS_UP".to_string(), 2)]);
+        assert_eq!(issue.comments.len(), 1);
+        assert_eq!(issue.comments[0].body, "Fixed in !3");
+
+        let mr: MergeRequest = serde_json::from_value(json!({
+            "id": 50, "iid": 3, "title": "Fix crash", "description": null, "state": "merged",
+            "created_at": "2021-03-01T12:00:00.000Z", "updated_at": "2021-03-02T08:00:00.000Z",
+            "closed_at": null, "merged_at": "2021-03-02T08:00:00.000Z",
+            "source_branch": "fix", "target_branch": "main",
+            "author": {"username": "bob"}, "merged_by": {"username": "alice"}, "reviewers": [{"username": "alice"}]
+        }))
+        .unwrap();
+        let notes: Vec<Note> = serde_json::from_value(json!([
+            {"id": 10, "body": "Looks good", "created_at": "2021-03-01T13:00:00.000Z", "author": {"username": "alice"}, "system": false},
+            {"id": 11, "body": "Rebased", "created_at": "2021-03-01T14:00:00.000Z", "author": {"username": "bob"}, "system": false},
+            {"id": 12, "body": "approved this merge request", "created_at": "2021-03-01T15:00:00.000Z", "author": {"username": "alice"}, "system": true}
+        ]))
+        .unwrap();
+        let pr = to_forge_pull_request(mr, notes, vec![]);
+        assert_eq!(pr.state, "MERGED");
+        assert_eq!(pr.closed_at.as_deref(), Some("2021-03-02T08:00:00.000Z"));
+        assert_eq!(pr.requested_reviewers, vec!["alice".to_string()]);
+        let states: Vec<&str> = pr.reviews.iter().map(|r| r.state.as_str()).collect();
+        assert_eq!(states, vec!["COMMENTED", "APPROVED"]);
+    }
+}
@@ -0,0 +1,452 @@
+// src/github_client.rs
+//! Shared GitHub API client used by the issues, pull requests and online commit analysis.
+//!
+//! Configured from the environment:
+//! - `GITHUB_TOKEN`, or several comma-separated tokens in `GITHUB_TOKENS` that are rotated when one is exhausted
+//! - `GITHUB_API_URL` (default `https://api.github.com`), e.g. `https://github.example.com/api/v3` for GitHub Enterprise
+//! - `GITHUB_GRAPHQL_URL`, derived from `GITHUB_API_URL` when not set
+//! - `GITHUB_MAX_CONCURRENCY` (default 2), the number of requests in flight across all threads
+//! - `GITHUB_MAX_RETRIES` (default 5), the retries of a request failing with a 5xx or a rate limit
+use std::env;
+use std::error::Error;
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::{error, warn};
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_TYPE, USER_AGENT};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+const DEFAULT_API_URL: &str = "https://api.github.com";
+pub(crate) const DEFAULT_MAX_RETRIES: u32 = 5;
+const DEFAULT_MAX_CONCURRENCY: usize = 2;
+const MAX_BACKOFF_SECS: u64 = 300;
+/// How long an exhausted token is set aside when GitHub does not tell when its limit resets
+const FALLBACK_RESET_SECS: u64 = 60;
+
+/// Rate limit state of one token, as last reported by GitHub
+#[derive(Debug)]
+struct TokenState {
+    token: String,
+    remaining: Option<u64>,
+    /// Unix time in seconds when the limit resets
+    reset: Option<u64>,
+}
+
+pub struct GitHubClient {
+    client: Client,
+    api_url: String,
+    graphql_url: String,
+    tokens: Mutex<Vec<TokenState>>,
+    in_flight: Mutex<usize>,
+    slot_freed: Condvar,
+    max_concurrency: usize,
+    max_retries: u32,
+    backoff: Duration,
+}
+
+static GLOBAL: OnceLock<Result<GitHubClient, String>> = OnceLock::new();
+
+pub(crate) fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Whether the body of a 403 response is GitHub's secondary rate limit message
+fn is_secondary_rate_limit(body: &str) -> bool {
+    body.to_lowercase().contains("secondary rate limit")
+}
+
+pub(crate) fn header_u64(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+/// Whether a failed request is worth retrying: a 5xx, a 429, or a 403 that is a rate limit, i.e. one with a
+/// `retry-after` header or the secondary rate limit message. Other 403s, such as a missing permission or a bad
+/// token, fail at once.
+pub(crate) fn is_retryable(status: StatusCode, retry_after: Option<u64>, body: &str) -> bool {
+    status.is_server_error()
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || (status == StatusCode::FORBIDDEN && (retry_after.is_some() || is_secondary_rate_limit(body)))
+}
+
+/// How long to wait before retrying after `attempt` failures: the `retry-after` seconds if the server sent them,
+/// otherwise an exponential backoff from `base`, at most `MAX_BACKOFF_SECS` either way
+pub(crate) fn backoff_delay(base: Duration, attempt: u32, retry_after: Option<u64>) -> Duration {
+    let delay = match retry_after {
+        Some(secs) => Duration::from_secs(secs),
+        None => base * 2u32.saturating_pow(attempt),
+    };
+    delay.min(Duration::from_secs(MAX_BACKOFF_SECS))
+}
+
+/// The GraphQL endpoint that belongs to a REST API base URL
+fn graphql_url_for(api_url: &str) -> String {
+    match api_url.strip_suffix("/api/v3") {
+        Some(host) => format!("{}/api/graphql", host),
+        None => format!("{}/graphql", api_url),
+    }
+}
+
+impl GitHubClient {
+    /// Client for the given REST API base URL, authenticating with (and rotating between) `tokens`
+    pub fn new(api_url: &str, tokens: Vec<String>) -> Result<GitHubClient, Box<dyn Error>> {
+        if tokens.is_empty() {
+            return Err("GITHUB_TOKEN environment variable is not set.".into());
+        }
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers.insert(USER_AGENT, HeaderValue::from_static("rust-github-client"));
+        headers.insert(ACCEPT, HeaderValue::from_static("application/vnd.github+json"));
+        let client = Client::builder()
+            .default_headers(headers)
+            .timeout(Duration::from_secs(120))
+            .build()?;
+        let api_url = api_url.trim_end_matches('/').to_string();
+        Ok(GitHubClient {
+            client,
+            graphql_url: graphql_url_for(&api_url),
+            api_url,
+            tokens: Mutex::new(
+                tokens
+                    .into_iter()
+                    .map(|token| TokenState { token, remaining: None, reset: None })
+                    .collect(),
+            ),
+            in_flight: Mutex::new(0),
+            slot_freed: Condvar::new(),
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            max_retries: DEFAULT_MAX_RETRIES,
+            backoff: Duration::from_secs(2),
+        })
+    }
+
+    /// Client configured from the `GITHUB_*` environment variables
+    pub fn from_env() -> Result<GitHubClient, Box<dyn Error>> {
+        let tokens: Vec<String> = env::var("GITHUB_TOKENS")
+            .or_else(|_| env::var("GITHUB_TOKEN"))
+            .unwrap_or_default()
+            .split(',')
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect();
+        let api_url = env::var("GITHUB_API_URL").unwrap_or_else(|_| DEFAULT_API_URL.to_string());
+        let mut client = GitHubClient::new(&api_url, tokens)?;
+        if let Ok(url) = env::var("GITHUB_GRAPHQL_URL") {
+            client.graphql_url = url;
+        }
+        if let Some(n) = env::var("GITHUB_MAX_CONCURRENCY").ok().and_then(|n| n.parse().ok()) {
+            client.max_concurrency = n;
+        }
+        if let Some(n) = env::var("GITHUB_MAX_RETRIES").ok().and_then(|n| n.parse().ok()) {
+            client.max_retries = n;
+        }
+        Ok(client)
+    }
+
+    /// The process-wide client, created from the environment on first use
+    pub fn global() -> Result<&'static GitHubClient, Box<dyn Error>> {
+        GLOBAL
+            .get_or_init(|| GitHubClient::from_env().map_err(|e| e.to_string()))
+            .as_ref()
+            .map_err(|e| e.clone().into())
+    }
+
+    /// Runs a GraphQL query and returns its `data`. If the query selects
+    /// `rateLimit { remaining resetAt }`, the reported budget is used for throttling.
+    pub fn graphql<T: DeserializeOwned>(&self, query: &str, variables: Value) -> Result<T, Box<dyn Error>> {
+        let body = json!({ "query": query, "variables": variables });
+        for attempt in 0..=self.max_retries {
+            let (token, response) = self.send(|c| c.post(&self.graphql_url).json(&body))?;
+            let retry_after = header_u64(response.headers(), "retry-after");
+            let resp_json: Value = response.json()?;
+            if let Some(errors) = resp_json.get("errors").and_then(|e| e.as_array()) {
+                let rate_limited = errors
+                    .iter()
+                    .any(|e| e.get("type").and_then(|t| t.as_str()) == Some("RATE_LIMITED"));
+                if rate_limited && attempt < self.max_retries {
+                    warn!("GraphQL rate limit reached, retrying");
+                    self.update_rate_limit(token, Some(0), retry_after.map(|secs| now_secs() + secs));
+                    continue;
+                }
+                for err in errors {
+                    error!("GraphQL error: {}", err.get("message").and_then(|m| m.as_str()).unwrap_or_default());
+                }
+                return Err("GraphQL query failed.".into());
+            }
+            let data = resp_json
+                .get("data")
+                .cloned()
+                .filter(|d| !d.is_null())
+                .ok_or("No data received from GitHub API.")?;
+            if let Some(limit) = data.get("rateLimit") {
+                let remaining = limit.get("remaining").and_then(|r| r.as_u64());
+                let reset = limit
+                    .get("resetAt")
+                    .and_then(|r| r.as_str())
+                    .and_then(|r| chrono::DateTime::parse_from_rfc3339(r).ok())
+                    .map(|r| r.timestamp() as u64);
+                self.update_rate_limit(token, remaining, reset);
+            }
+            return Ok(serde_json::from_value(data)?);
+        }
+        Err("GraphQL rate limit retries exhausted.".into())
+    }
+
+    /// GET a REST endpoint, `path` being relative to the API base URL (e.g. `/repos/{owner}/{repo}/commits/{sha}`)
+    pub fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, Box<dyn Error>> {
+        let url = format!("{}{}", self.api_url, path);
+        let (_, response) = self.send(|c| c.get(&url))?;
+        Ok(response.json()?)
+    }
+
+    /// Sends a request with the least used token, waiting for the rate limit to reset if all tokens are
+    /// exhausted and retrying with exponential backoff on 5xx and secondary rate limits.
+    /// Returns the index of the token used along with the successful response.
+    fn send<F>(&self, build: F) -> Result<(usize, Response), Box<dyn Error>>
+    where
+        F: Fn(&Client) -> RequestBuilder,
+    {
+        let mut attempt = 0;
+        loop {
+            let (index, token) = self.acquire_token();
+            let response = {
+                let _slot = self.acquire_slot();
+                build(&self.client).bearer_auth(token).send()
+            };
+            let response = match response {
+                Ok(response) => response,
+                Err(e) if attempt < self.max_retries => {
+                    warn!("GitHub request failed: {}, retrying", e);
+                    self.sleep_backoff(attempt, None);
+                    attempt += 1;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            let headers = response.headers();
+            let remaining = header_u64(headers, "x-ratelimit-remaining");
+            let retry_after = header_u64(headers, "retry-after");
+            let reset = header_u64(headers, "x-ratelimit-reset").or(retry_after.map(|secs| now_secs() + secs));
+            self.update_rate_limit(index, remaining, reset);
+
+            let status = response.status();
+            if status.is_success() {
+                return Ok((index, response));
+            }
+            let primary_limit = remaining == Some(0)
+                && (status == StatusCode::FORBIDDEN || status == StatusCode::TOO_MANY_REQUESTS);
+            if primary_limit && attempt < self.max_retries {
+                // acquire_token waits for the reset or picks another token
+                warn!("GitHub rate limit exhausted for token {}", index + 1);
+                attempt += 1;
+                continue;
+            }
+            let text = response.text().unwrap_or_default();
+            if is_retryable(status, retry_after, &text) && attempt < self.max_retries {
+                warn!("GitHub API request failed with status {}, retrying", status);
+                self.sleep_backoff(attempt, retry_after);
+                attempt += 1;
+                continue;
+            }
+            error!("GitHub API request failed with status {}: {}", status, text);
+            return Err(format!("GitHub API request failed with status {}", status).into());
+        }
+    }
+
+    fn sleep_backoff(&self, attempt: u32, retry_after: Option<u64>) {
+        std::thread::sleep(backoff_delay(self.backoff, attempt, retry_after));
+    }
+
+    /// Records the budget of a token. A token exhausted without a known reset is set aside for
+    /// `FALLBACK_RESET_SECS`, so that `acquire_token` does not wait for a reset that never comes.
+    fn update_rate_limit(&self, index: usize, remaining: Option<u64>, reset: Option<u64>) {
+        let mut tokens = self.tokens.lock().unwrap();
+        if let Some(state) = tokens.get_mut(index) {
+            if remaining.is_some() {
+                state.remaining = remaining;
+            }
+            if reset.is_some() {
+                state.reset = reset;
+            } else if remaining == Some(0) && state.reset.is_none() {
+                state.reset = Some(now_secs() + FALLBACK_RESET_SECS);
+            }
+        }
+    }
+
+    /// Picks the token with the most remaining budget, sleeping until the earliest reset if none has any left
+    fn acquire_token(&self) -> (usize, String) {
+        loop {
+            let wait = {
+                let mut tokens = self.tokens.lock().unwrap();
+                let now = now_secs();
+                for state in tokens.iter_mut() {
+                    if state.reset.map(|r| r <= now).unwrap_or(false) {
+                        state.remaining = None;
+                        state.reset = None;
+                    }
+                }
+                let best = tokens
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, s)| s.remaining != Some(0))
+                    .max_by_key(|(_, s)| s.remaining.unwrap_or(u64::MAX));
+                if let Some((index, state)) = best {
+                    return (index, state.token.clone());
+                }
+                tokens.iter().filter_map(|s| s.reset).min().unwrap_or(now + 60) - now
+            };
+            warn!("All GitHub tokens exhausted, waiting {}s for the rate limit to reset", wait);
+            std::thread::sleep(Duration::from_secs(wait + 1));
+        }
+    }
+
+    fn acquire_slot(&self) -> Slot<'_> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        while *in_flight >= self.max_concurrency.max(1) {
+            in_flight = self.slot_freed.wait(in_flight).unwrap();
+        }
+        *in_flight += 1;
+        Slot { client: self }
+    }
+}
+
+/// A request slot, released on drop
+struct Slot<'a> {
+    client: &'a GitHubClient,
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        let mut in_flight = self.client.in_flight.lock().unwrap();
+        *in_flight -= 1;
+        self.client.slot_freed.notify_one();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    /// Serves the canned `(status line, extra headers, body)` responses in order, one per connection
+    pub(crate) fn mock_server(responses: Vec<(&'static str, &'static str, &'static str)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for (status, headers, body) in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        content_length = v.trim().parse().unwrap();
+                    }
+                    if line == "\r\n" || line.is_empty() {
+                        break;
+                    }
+                }
+                let mut request_body = vec![0; content_length];
+                reader.read_exact(&mut request_body).unwrap();
+                let mut stream = reader.into_inner();
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n{}",
+                    status,
+                    body.len(),
+                    headers,
+                    body
+                )
+                .unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn test_graphql_url_for() {
+        assert_eq!(graphql_url_for("https://api.github.com"), "https://api.github.com/graphql");
+        assert_eq!(graphql_url_for("https://github.example.com/api/v3"), "https://github.example.com/api/graphql");
+    }
+
+    #[test]
+    fn test_retries_server_errors() {
+        let url = mock_server(vec![
+            ("502 Bad Gateway", "", "{}"),
+            ("200 OK", "x-ratelimit-remaining: 4999\r\nx-ratelimit-reset: 1\r\n", r#"{"data": {"viewer": {"login": "octocat"}}}"#),
+        ]);
+        let mut client = GitHubClient::new(&url, vec!["t".to_string()]).unwrap();
+        client.backoff = Duration::from_millis(1);
+        let data: Value = client.graphql("query { viewer { login } }", json!({})).unwrap();
+        assert_eq!(data["viewer"]["login"], "octocat");
+    }
+
+    #[test]
+    fn test_retries_only_rate_limited_forbidden() {
+        let secondary = r#"{"message": "You have exceeded a secondary rate limit. Please wait a few minutes."}"#;
+        let url = mock_server(vec![
+            ("403 Forbidden", "", secondary),
+            ("403 Forbidden", "retry-after: 0\r\n", "{}"),
+            ("200 OK", "", r#"{"data": {"viewer": {"login": "octocat"}}}"#),
+        ]);
+        let mut client = GitHubClient::new(&url, vec!["t".to_string()]).unwrap();
+        client.backoff = Duration::from_millis(1);
+        let data: Value = client.graphql("query { viewer { login } }", json!({})).unwrap();
+        assert_eq!(data["viewer"]["login"], "octocat");
+
+        // a missing permission fails at once: the second response is never requested
+        let url = mock_server(vec![
+            ("403 Forbidden", "", r#"{"message": "Resource not accessible by integration"}"#),
+            ("200 OK", "", "{}"),
+        ]);
+        let mut client = GitHubClient::new(&url, vec!["t".to_string()]).unwrap();
+        client.backoff = Duration::from_secs(60);
+        let started = std::time::Instant::now();
+        let result: Result<Value, _> = client.get("/repos/apache/hunter");
+        assert!(result.unwrap_err().to_string().contains("403"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_rotates_exhausted_tokens() {
+        let client = GitHubClient::new("http://localhost", vec!["a".to_string(), "b".to_string()]).unwrap();
+        client.update_rate_limit(0, Some(0), Some(now_secs() + 3600));
+        assert_eq!(client.acquire_token(), (1, "b".to_string()));
+        client.update_rate_limit(1, Some(10), None);
+        client.update_rate_limit(0, None, Some(1));
+        // The reset of token 0 has passed, so its budget is unknown again and preferred over token 1
+        assert_eq!(client.acquire_token().0, 0);
+    }
+
+    #[test]
+    fn test_exhausted_without_reset() {
+        let client = GitHubClient::new("http://localhost", vec!["a".to_string()]).unwrap();
+        client.update_rate_limit(0, Some(0), None);
+        let reset = client.tokens.lock().unwrap()[0].reset.unwrap();
+        assert!(reset > now_secs() && reset <= now_secs() + FALLBACK_RESET_SECS);
+    }
+
+    #[test]
+    fn test_bounds_rate_limit_retries() {
+        // the limit keeps being exhausted, with a reset in the past so that the test does not wait
+        let limited = ("403 Forbidden", "x-ratelimit-remaining: 0\r\nx-ratelimit-reset: 1\r\n", "{}");
+        let url = mock_server(vec![limited, limited, limited]);
+        let mut client = GitHubClient::new(&url, vec!["t".to_string()]).unwrap();
+        client.max_retries = 2;
+        let result: Result<Value, _> = client.get("/rate_limit");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_missing_token() {
+        assert!(GitHubClient::new(DEFAULT_API_URL, vec![]).is_err());
+    }
+}
@@ -1,14 +1,21 @@
 // src/github_issues.rs
 use serde::{Deserialize, Serialize};
-use std::env;
+use std::collections::HashMap;
 use std::error::Error;
-use std::fs::File;
 use std::path::Path;
 
-use reqwest::blocking::Client;
-use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
 use serde_json::json;
-use log::{info, error};
+use log::{info, error, warn};
+
+use indexmap::IndexMap;
+
+use crate::database::{self, CommentRecord, IssueRecord};
+use crate::forge::{ForgeIssue, ForgeUser, RepoRef};
+use crate::github_client::GitHubClient;
+use crate::issue_store;
+use crate::output::{self, OutputFormat, TableWriter};
+use crate::sentiment::{self, MessageScore, SentimentAnalyzer, SentimentScores, SentimentSummary};
+use crate::utils::{hours_between, median};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Issue {
@@ -21,8 +28,14 @@ pub struct Issue {
     pub created_at: String,
     pub updated_at: String,
     pub closed_at: Option<String>,
+    pub state_reason: Option<String>,
     pub author: Option<AuthorNode>,
     pub comments: CommentConnection,
+    pub reaction_groups: Vec<ReactionGroup>,
+    pub labels: Vec<String>,
+    pub assignees: Vec<String>,
+    pub milestone: Option<String>,
+    pub timeline_items: Vec<TimelineItem>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -34,17 +47,6 @@ pub struct Comment {
     pub author: Option<AuthorNode>,
 }
 
-#[derive(Debug, Deserialize)]
-struct GraphQLResponse<T> {
-    data: Option<T>,
-    errors: Option<Vec<GraphQLError>>,
-}
-
-#[derive(Debug, Deserialize)]
-struct GraphQLError {
-    message: String,
-}
-
 #[derive(Debug, Deserialize)]
 struct RepositoryData {
     repository: Option<Repository>,
@@ -73,12 +75,100 @@ struct IssueNode {
     createdAt: String,
     updatedAt: String,
     closedAt: Option<String>,
+    #[serde(default)]
+    stateReason: Option<String>,
     author: Option<AuthorNode>,
     comments: CommentConnection,
+    #[serde(default)]
+    reactionGroups: Vec<ReactionGroup>,
+    #[serde(default)]
+    labels: Option<NamedConnection>,
+    #[serde(default)]
+    assignees: Option<LoginConnection>,
+    #[serde(default)]
+    milestone: Option<Milestone>,
+    #[serde(default)]
+    timelineItems: Option<TimelineConnection>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReactionGroup {
+    content: String,
+    reactors: TotalCount,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TotalCount {
+    totalCount: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NamedConnection {
+    #[serde(default)]
+    pageInfo: Option<PageInfo>,
+    nodes: Vec<Named>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct CommentConnection {
+struct Named {
+    name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LoginConnection {
+    #[serde(default)]
+    pageInfo: Option<PageInfo>,
+    nodes: Vec<Login>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Login {
+    login: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Milestone {
+    title: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TimelineConnection {
+    #[serde(default)]
+    pageInfo: Option<PageInfo>,
+    nodes: Vec<TimelineItem>,
+}
+
+/// A closed, cross-referenced or referenced event of an issue timeline
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TimelineItem {
+    __typename: String,
+    #[serde(default)]
+    createdAt: Option<String>,
+    #[serde(default)]
+    actor: Option<Login>,
+    #[serde(default)]
+    source: Option<ReferenceSource>,
+    #[serde(default)]
+    commit: Option<ReferencedCommit>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReferenceSource {
+    __typename: String,
+    #[serde(default)]
+    number: Option<u32>,
+    #[serde(default)]
+    url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReferencedCommit {
+    oid: String,
+}
+
+/// The comments of an issue, as fetched; `Issue::comments` reads them
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommentConnection {
     pageInfo: PageInfo,
     nodes: Vec<CommentNode>,
 }
@@ -90,19 +180,22 @@ struct CommentNode {
     body: String,
     createdAt: String,
     author: Option<AuthorNode>,
+    #[serde(default)]
+    reactionGroups: Vec<ReactionGroup>,
 }
 
+/// The author of an issue or a comment
 #[derive(Debug, Serialize, Deserialize)]
-struct AuthorNode {
-    login: String,
+pub struct AuthorNode {
+    pub login: String,
     #[serde(default)]
-    databaseId: Option<u64>,
+    pub databaseId: Option<u64>,
     #[serde(default)]
-    name: Option<String>,
+    pub name: Option<String>,
     #[serde(default)]
-    email: Option<String>,
+    pub email: Option<String>,
     #[serde(default)]
-    url: String,
+    pub url: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -111,9 +204,10 @@ struct PageInfo {
     endCursor: Option<String>,
 }
 
+/// Follow-up comment pages for several issues at once; each issue is aliased as `i{number}`
 #[derive(Debug, Deserialize)]
 struct IssueData {
-    issue: Option<CommentedIssue>,
+    repository: Option<HashMap<String, Option<CommentedIssue>>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -121,8 +215,117 @@ struct CommentedIssue {
     comments: CommentConnection,
 }
 
-// CSV row type
-#[derive(Debug, Serialize)]
+/// Number of issues whose next page of comments, labels, assignees or timeline items is requested in a single
+/// follow-up query
+const PAGES_BATCH_SIZE: usize = 20;
+
+/// The timeline items of an issue: who closed it and what references it
+const TIMELINE_FIELDS: &str = r#"
+    fragment TimelineFields on IssueTimelineItems {
+        __typename
+        ... on ClosedEvent {
+            createdAt
+            actor { login }
+        }
+        ... on CrossReferencedEvent {
+            createdAt
+            source {
+                __typename
+                ... on Issue { number url }
+                ... on PullRequest { number url }
+            }
+        }
+        ... on ReferencedEvent {
+            createdAt
+            commit { oid }
+        }
+    }
+"#;
+
+/// A list of an issue, besides its comments, that is fetched page by page
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Connection {
+    Labels,
+    Assignees,
+    TimelineItems,
+}
+
+impl Connection {
+    const ALL: [Connection; 3] = [Connection::Labels, Connection::Assignees, Connection::TimelineItems];
+
+    /// The GraphQL field, its arguments besides the cursor, and the selection of its nodes
+    fn selection(self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            Connection::Labels => ("labels", "first: 100", "nodes { name }"),
+            Connection::Assignees => ("assignees", "first: 100", "nodes { login }"),
+            Connection::TimelineItems => (
+                "timelineItems",
+                "first: 100, itemTypes: [CLOSED_EVENT, CROSS_REFERENCED_EVENT, REFERENCED_EVENT]",
+                "nodes { ...TimelineFields }",
+            ),
+        }
+    }
+
+    /// The cursor of the next page of a fetched issue, while the list has more nodes than fetched
+    fn next_cursor(self, issue: &IssueNode) -> Option<String> {
+        let page_info = match self {
+            Connection::Labels => issue.labels.as_ref().and_then(|c| c.pageInfo.as_ref()),
+            Connection::Assignees => issue.assignees.as_ref().and_then(|c| c.pageInfo.as_ref()),
+            Connection::TimelineItems => issue.timelineItems.as_ref().and_then(|c| c.pageInfo.as_ref()),
+        };
+        page_info.filter(|p| p.hasNextPage).and_then(|p| p.endCursor.clone())
+    }
+
+    /// Appends a follow-up page to the issue and returns the cursor of the page after it, or `None` when the
+    /// response misses the list
+    fn extend(self, issue: &mut Issue, page: IssuePage) -> Option<Option<String>> {
+        let page_info = match self {
+            Connection::Labels => {
+                let labels = page.labels?;
+                issue.labels.extend(labels.nodes.into_iter().map(|n| n.name));
+                labels.pageInfo
+            }
+            Connection::Assignees => {
+                let assignees = page.assignees?;
+                issue.assignees.extend(assignees.nodes.into_iter().map(|n| n.login));
+                assignees.pageInfo
+            }
+            Connection::TimelineItems => {
+                let items = page.timelineItems?;
+                issue.timeline_items.extend(items.nodes);
+                items.pageInfo
+            }
+        };
+        Some(page_info.filter(|p| p.hasNextPage).and_then(|p| p.endCursor))
+    }
+
+    fn len(self, issue: &Issue) -> usize {
+        match self {
+            Connection::Labels => issue.labels.len(),
+            Connection::Assignees => issue.assignees.len(),
+            Connection::TimelineItems => issue.timeline_items.len(),
+        }
+    }
+}
+
+/// Follow-up pages of the labels, assignees or timeline items of several issues at once, aliased as `i{number}`
+#[derive(Debug, Deserialize)]
+struct IssuePages {
+    repository: Option<HashMap<String, Option<IssuePage>>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IssuePage {
+    #[serde(default)]
+    labels: Option<NamedConnection>,
+    #[serde(default)]
+    assignees: Option<LoginConnection>,
+    #[serde(default)]
+    timelineItems: Option<TimelineConnection>,
+}
+
+// Issues table row type
+#[derive(Debug, Default, Serialize)]
 struct CsvRow {
     r#type: String,
     issue_url: String,
@@ -140,22 +343,61 @@ struct CsvRow {
     updated_at: String,
     body: String,
     reactions: String,
+    labels: String,
+    assignees: String,
+    milestone: String,
+    closed_at: String,
+    closed_by: String,
+    state_reason: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct IssueReferenceRow {
+    repo_name: String,
+    issue_num: u32,
+    reference_type: String,
+    reference: String,
+    url: String,
+    created_at: String,
+}
+
+/// Issue metrics for one yyyy-mm window. Issues are counted as opened in the month they were created
+/// and as closed in the month they were closed; time-to-close and closers are attributed to the closing month.
+/// The sentiment covers the issues opened and the comments written in the month.
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct IssueWindowMetrics {
+    pub window: String,
+    pub issues_opened: usize,
+    pub issues_closed: usize,
+    pub median_hours_to_close: f64,
+    pub closed_by_non_author: usize,
+    pub closed_by_non_author_ratio: f64,
+    pub sentiment_compound_avg: f64,
+    pub negative_messages: usize,
+    pub uncivil_messages: usize,
+}
+
+/// Weighted reply edge of the issue social network in one yyyy-mm window
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct IssueNetworkEdge {
+    pub window: String,
+    pub source: String,
+    pub target: String,
+    pub weight: usize,
 }
 
-/// Fetch issues (with comments) from GitHub GraphQL
-fn fetch_issues(owner: &str, repo: &str) -> Result<Vec<Issue>, Box<dyn Error>> {
-    let github_token = env::var("GITHUB_TOKEN")
-        .map_err(|_| "GITHUB_TOKEN environment variable is not set.")?;
-    let mut headers = HeaderMap::new();
-    headers.insert(AUTHORIZATION, HeaderValue::from_str(&format!("Bearer {}", github_token))?);
-    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-    headers.insert("User-Agent", HeaderValue::from_static("rust-github-client"));
-    let client = Client::builder().default_headers(headers).build()?;
+/// Fetch issues (with comments) from GitHub GraphQL, only those updated at or after `since` when given
+pub fn fetch_issues(owner: &str, repo: &str, since: Option<&str>) -> Result<Vec<Issue>, Box<dyn Error>> {
+    let client = GitHubClient::global()?;
 
     let query = r#"
-        query($owner: String!, $name: String!, $cursor: String) {
+        query($owner: String!, $name: String!, $cursor: String, $since: DateTime) {
+            rateLimit {
+                remaining
+                resetAt
+            }
             repository(owner: $owner, name: $name) {
-                issues(first: 100, after: $cursor, orderBy: {field: CREATED_AT, direction: ASC}) {
+                issues(first: 100, after: $cursor, orderBy: {field: UPDATED_AT, direction: ASC}, filterBy: {since: $since}) {
                     pageInfo {
                         hasNextPage
                         endCursor
@@ -170,6 +412,7 @@ fn fetch_issues(owner: &str, repo: &str) -> Result<Vec<Issue>, Box<dyn Error>> {
                         createdAt
                         updatedAt
                         closedAt
+                        stateReason
                         author {
                             login
                             ... on User {
@@ -178,6 +421,23 @@ fn fetch_issues(owner: &str, repo: &str) -> Result<Vec<Issue>, Box<dyn Error>> {
                                 email
                             }
                         }
+                        reactionGroups {
+                            content
+                            reactors { totalCount }
+                        }
+                        labels(first: 20) {
+                            pageInfo { hasNextPage endCursor }
+                            nodes { name }
+                        }
+                        assignees(first: 10) {
+                            pageInfo { hasNextPage endCursor }
+                            nodes { login }
+                        }
+                        milestone { title }
+                        timelineItems(first: 50, itemTypes: [CLOSED_EVENT, CROSS_REFERENCED_EVENT, REFERENCED_EVENT]) {
+                            pageInfo { hasNextPage endCursor }
+                            nodes { ...TimelineFields }
+                        }
                         comments(first: 100) {
                             pageInfo {
                                 hasNextPage
@@ -196,6 +456,10 @@ fn fetch_issues(owner: &str, repo: &str) -> Result<Vec<Issue>, Box<dyn Error>> {
                                         email
                                     }
                                 }
+                                reactionGroups {
+                                    content
+                                    reactors { totalCount }
+                                }
                             }
                         }
                     }
@@ -203,8 +467,10 @@ fn fetch_issues(owner: &str, repo: &str) -> Result<Vec<Issue>, Box<dyn Error>> {
             }
         }
     "#;
+    let query = [query, TIMELINE_FIELDS].concat();
 
     let mut all_issues = Vec::new();
+    let mut pending = Vec::new();
     let mut cursor: Option<String> = None;
 
     loop {
@@ -212,33 +478,18 @@ fn fetch_issues(owner: &str, repo: &str) -> Result<Vec<Issue>, Box<dyn Error>> {
             "owner": owner,
             "name": repo,
             "cursor": cursor,
+            "since": since,
         });
-        let body = json!({
-            "query": query,
-            "variables": variables,
-        });
-        let response = client
-            .post("https://api.github.com/graphql")
-            .json(&body)
-            .send()?;
-        if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text()?;
-            error!("GitHub API request failed with status {}: {}", status, text);
-            return Err(format!("GitHub API request failed with status {}", status).into());
-        }
-        let resp_json: GraphQLResponse<RepositoryData> = response.json()?;
-        if let Some(errors) = resp_json.errors {
-            for err in errors {
-                error!("GraphQL error: {}", err.message);
-            }
-            return Err("GraphQL query failed.".into());
-        }
-        let data = resp_json.data.ok_or("No data received from GitHub API.")?;
+        let data: RepositoryData = client.graphql(&query, variables)?;
         let repository = data.repository.ok_or("Repository not found or access denied.")?;
         let issues_conn = repository.issues.ok_or("Repository issues field is missing.")?;
 
         for issue_node in issues_conn.nodes {
+            for connection in Connection::ALL {
+                if let Some(cursor) = connection.next_cursor(&issue_node) {
+                    pending.push((issue_node.number, connection, cursor));
+                }
+            }
             let issue = Issue {
                 id: issue_node.id,
                 databaseId: issue_node.databaseId,
@@ -249,11 +500,17 @@ fn fetch_issues(owner: &str, repo: &str) -> Result<Vec<Issue>, Box<dyn Error>> {
                 created_at: issue_node.createdAt,
                 updated_at: issue_node.updatedAt,
                 closed_at: issue_node.closedAt,
+                state_reason: issue_node.stateReason,
                 author: issue_node.author,
                 comments: CommentConnection {
                     pageInfo: issue_node.comments.pageInfo,
                     nodes: issue_node.comments.nodes,
                 },
+                reaction_groups: issue_node.reactionGroups,
+                labels: issue_node.labels.map(|l| l.nodes.into_iter().map(|n| n.name).collect()).unwrap_or_default(),
+                assignees: issue_node.assignees.map(|a| a.nodes.into_iter().map(|n| n.login).collect()).unwrap_or_default(),
+                milestone: issue_node.milestone.map(|m| m.title),
+                timeline_items: issue_node.timelineItems.map(|t| t.nodes).unwrap_or_default(),
             };
             all_issues.push(issue);
         }
@@ -264,50 +521,513 @@ fn fetch_issues(owner: &str, repo: &str) -> Result<Vec<Issue>, Box<dyn Error>> {
             break;
         }
     }
+    fetch_remaining_comments(client, owner, repo, &mut all_issues);
+    fetch_remaining_pages(client, owner, repo, &mut all_issues, pending);
     Ok(all_issues)
 }
 
-/// Writes issues + comments to CSV
-pub fn fetch_issues_with_comments_csv(owner: &str, repo: &str, output_csv_path: &str) -> Result<(), Box<dyn Error>> {
-    let issues = fetch_issues(owner, repo)?;
+/// Builds one query fetching the next page of comments for each `(issue number, cursor)` pair
+fn comment_pages_query(pages: &[(u32, String)]) -> String {
+    let issues: Vec<String> = pages
+        .iter()
+        .map(|(number, cursor)| {
+            format!(
+                r#"i{}: issue(number: {}) {{
+                    comments(first: 100, after: {}) {{
+                        pageInfo {{ hasNextPage endCursor }}
+                        nodes {{
+                            id
+                            databaseId
+                            body
+                            createdAt
+                            author {{
+                                login
+                                ... on User {{ databaseId name email }}
+                            }}
+                            reactionGroups {{ content reactors {{ totalCount }} }}
+                        }}
+                    }}
+                }}"#,
+                number,
+                number,
+                serde_json::Value::String(cursor.clone())
+            )
+        })
+        .collect();
+    format!(
+        "query($owner: String!, $name: String!) {{ rateLimit {{ remaining resetAt }} repository(owner: $owner, name: $name) {{ {} }} }}",
+        issues.join("\n")
+    )
+}
+
+/// Follows `comments.pageInfo` for every issue with more than 100 comments, batching several issues per query.
+/// Issues whose comments could not be completed are logged as truncated and kept with what was fetched.
+fn fetch_remaining_comments(client: &GitHubClient, owner: &str, repo: &str, issues: &mut [Issue]) {
+    loop {
+        let pending: Vec<(u32, String)> = issues
+            .iter()
+            .filter(|i| i.comments.pageInfo.hasNextPage)
+            .filter_map(|i| i.comments.pageInfo.endCursor.clone().map(|c| (i.number, c)))
+            .collect();
+        if pending.is_empty() {
+            break;
+        }
+
+        for batch in pending.chunks(PAGES_BATCH_SIZE) {
+            let variables = json!({ "owner": owner, "name": repo });
+            let mut pages = match client.graphql::<IssueData>(&comment_pages_query(batch), variables) {
+                Ok(data) => data.repository.unwrap_or_default(),
+                Err(e) => {
+                    error!("Cannot fetch further comments for {}/{}: {}", owner, repo, e);
+                    HashMap::new()
+                }
+            };
+            for (number, _) in batch {
+                let issue = match issues.iter_mut().find(|i| i.number == *number) {
+                    Some(issue) => issue,
+                    None => continue,
+                };
+                match pages.remove(&format!("i{}", number)).flatten() {
+                    Some(page) => {
+                        issue.comments.nodes.extend(page.comments.nodes);
+                        issue.comments.pageInfo = page.comments.pageInfo;
+                    }
+                    None => {
+                        warn!(
+                            "{}/{} issue #{}: comment list is truncated at {} comments",
+                            owner,
+                            repo,
+                            number,
+                            issue.comments.nodes.len()
+                        );
+                        issue.comments.pageInfo.hasNextPage = false;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Builds one query fetching the next page of `connection` for each `(issue number, cursor)` pair
+fn connection_pages_query(connection: Connection, pages: &[(u32, String)]) -> String {
+    let (field, arguments, nodes) = connection.selection();
+    let issues: Vec<String> = pages
+        .iter()
+        .map(|(number, cursor)| {
+            format!(
+                r#"i{}: issue(number: {}) {{
+                    {}({}, after: {}) {{
+                        pageInfo {{ hasNextPage endCursor }}
+                        {}
+                    }}
+                }}"#,
+                number,
+                number,
+                field,
+                arguments,
+                serde_json::Value::String(cursor.clone()),
+                nodes
+            )
+        })
+        .collect();
+    let fragments = if connection == Connection::TimelineItems { TIMELINE_FIELDS } else { "" };
+    format!(
+        "query($owner: String!, $name: String!) {{ rateLimit {{ remaining resetAt }} repository(owner: $owner, name: $name) {{ {} }} }}\n{}",
+        issues.join("\n"),
+        fragments
+    )
+}
+
+/// Follows the `pageInfo` of the labels, assignees and timeline items of every issue with more than the first
+/// page, given as `(issue number, list, cursor)`, batching several issues per query. Lists that could not be
+/// completed are logged as truncated and kept with what was fetched.
+fn fetch_remaining_pages(
+    client: &GitHubClient,
+    owner: &str,
+    repo: &str,
+    issues: &mut [Issue],
+    mut pending: Vec<(u32, Connection, String)>,
+) {
+    while !pending.is_empty() {
+        let mut next = Vec::new();
+        for connection in Connection::ALL {
+            let pages: Vec<(u32, String)> = pending
+                .iter()
+                .filter(|(_, c, _)| *c == connection)
+                .map(|(number, _, cursor)| (*number, cursor.clone()))
+                .collect();
+            for batch in pages.chunks(PAGES_BATCH_SIZE) {
+                let variables = json!({ "owner": owner, "name": repo });
+                let mut pages = match client.graphql::<IssuePages>(&connection_pages_query(connection, batch), variables) {
+                    Ok(data) => data.repository.unwrap_or_default(),
+                    Err(e) => {
+                        error!("Cannot fetch further {} for {}/{}: {}", connection.selection().0, owner, repo, e);
+                        HashMap::new()
+                    }
+                };
+                for (number, _) in batch {
+                    let issue = match issues.iter_mut().find(|i| i.number == *number) {
+                        Some(issue) => issue,
+                        None => continue,
+                    };
+                    match pages.remove(&format!("i{}", number)).flatten().and_then(|page| connection.extend(issue, page)) {
+                        Some(Some(cursor)) => next.push((*number, connection, cursor)),
+                        Some(None) => {}
+                        None => warn!(
+                            "{}/{} issue #{}: {} list is truncated at {} items",
+                            owner,
+                            repo,
+                            number,
+                            connection.selection().0,
+                            connection.len(issue)
+                        ),
+                    }
+                }
+            }
+        }
+        pending = next;
+    }
+}
+
+/// The yyyy-mm month of a GitHub timestamp, used as the window for sentiment and issue metrics aggregation
+fn month_window(date: &str) -> String {
+    date.get(..7).unwrap_or("NA").to_string()
+}
+
+/// Reactions in the format of the GitHub REST API `reactions` object
+fn reactions_json(url: &str, groups: &[ReactionGroup]) -> String {
+    let count = |content: &str| -> u64 {
+        groups
+            .iter()
+            .filter(|g| g.content == content)
+            .map(|g| g.reactors.totalCount)
+            .sum()
+    };
+    let total: u64 = groups.iter().map(|g| g.reactors.totalCount).sum();
+    json!({
+        "url": url,
+        "total_count": total,
+        "+1": count("THUMBS_UP"),
+        "-1": count("THUMBS_DOWN"),
+        "laugh": count("LAUGH"),
+        "hooray": count("HOORAY"),
+        "confused": count("CONFUSED"),
+        "heart": count("HEART"),
+        "rocket": count("ROCKET"),
+        "eyes": count("EYES"),
+    })
+    .to_string()
+}
+
+fn author_from_forge(user: ForgeUser) -> AuthorNode {
+    AuthorNode {
+        login: user.login,
+        databaseId: user.id,
+        name: user.name,
+        email: user.email,
+        url: String::new(),
+    }
+}
+
+impl From<ForgeIssue> for Issue {
+    fn from(issue: ForgeIssue) -> Issue {
+        let comments = issue
+            .comments
+            .into_iter()
+            .map(|c| CommentNode {
+                id: c.id.to_string(),
+                databaseId: Some(c.id),
+                body: c.body,
+                createdAt: c.created_at,
+                author: c.author.map(author_from_forge),
+                reactionGroups: Vec::new(),
+            })
+            .collect();
+        let timeline_items = match (&issue.closed_by, &issue.closed_at) {
+            (Some(login), closed_at) => vec![TimelineItem {
+                __typename: "ClosedEvent".to_string(),
+                createdAt: closed_at.clone(),
+                actor: Some(Login { login: login.clone() }),
+                source: None,
+                commit: None,
+            }],
+            _ => Vec::new(),
+        };
+        Issue {
+            id: issue.id.to_string(),
+            databaseId: Some(issue.id),
+            number: issue.number,
+            title: issue.title,
+            body: issue.body,
+            state: issue.state,
+            created_at: issue.created_at,
+            updated_at: issue.updated_at,
+            closed_at: issue.closed_at,
+            state_reason: None,
+            author: issue.author.map(author_from_forge),
+            comments: CommentConnection {
+                pageInfo: PageInfo { hasNextPage: false, endCursor: None },
+                nodes: comments,
+            },
+            reaction_groups: issue
+                .reactions
+                .into_iter()
+                .map(|(content, count)| ReactionGroup { content, reactors: TotalCount { totalCount: count } })
+                .collect(),
+            labels: issue.labels,
+            assignees: issue.assignees,
+            milestone: issue.milestone,
+            timeline_items,
+        }
+    }
+}
+
+impl AuthorNode {
+    /// The email of the author if public, else their login, as developers are identified in the database
+    fn identity(&self) -> String {
+        self.email.clone().filter(|email| !email.is_empty()).unwrap_or_else(|| self.login.clone())
+    }
+}
+
+impl Issue {
+    /// The issue and its comments, as stored in the `--sqlite` database
+    fn record(&self) -> IssueRecord {
+        IssueRecord {
+            number: self.number,
+            title: self.title.clone(),
+            state: self.state.clone(),
+            author: self.author.as_ref().map(AuthorNode::identity),
+            author_name: self.author.as_ref().and_then(|a| a.name.clone()),
+            created_at: self.created_at.clone(),
+            updated_at: self.updated_at.clone(),
+            closed_at: self.closed_at.clone(),
+            body: self.body.clone(),
+            comments: self
+                .comments
+                .nodes
+                .iter()
+                .map(|c| CommentRecord {
+                    author: c.author.as_ref().map(AuthorNode::identity),
+                    author_name: c.author.as_ref().and_then(|a| a.name.clone()),
+                    created_at: c.createdAt.clone(),
+                    body: c.body.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Login of whoever closed the issue last, if the timeline records it
+    fn closed_by(&self) -> Option<&str> {
+        self.timeline_items
+            .iter()
+            .rev()
+            .filter(|t| t.__typename == "ClosedEvent")
+            .find_map(|t| t.actor.as_ref().map(|a| a.login.as_str()))
+    }
+}
+
+/// Computes per-month issue metrics, ordered by window
+pub fn compute_issue_window_metrics(issues: &[Issue]) -> Vec<IssueWindowMetrics> {
+    let mut windows: IndexMap<String, IssueWindowMetrics> = IndexMap::new();
+    let mut hours_to_close: IndexMap<String, Vec<f64>> = IndexMap::new();
+    let mut scores: IndexMap<String, Vec<SentimentScores>> = IndexMap::new();
+    let analyzer = SentimentAnalyzer::global();
+
+    for issue in issues {
+        windows.entry(month_window(&issue.created_at)).or_default().issues_opened += 1;
+        let text = format!("{} {}", issue.title, issue.body.as_deref().unwrap_or(""));
+        scores.entry(month_window(&issue.created_at)).or_default().push(analyzer.score(&text));
+        for comment in issue.comments.nodes.iter() {
+            let window = month_window(&comment.createdAt);
+            windows.entry(window.clone()).or_default();
+            scores.entry(window).or_default().push(analyzer.score(&comment.body));
+        }
+        if let Some(closed_at) = &issue.closed_at {
+            let closed = month_window(closed_at);
+            let m = windows.entry(closed.clone()).or_default();
+            m.issues_closed += 1;
+            let author = issue.author.as_ref().map(|a| a.login.as_str());
+            if let Some(closer) = issue.closed_by() {
+                if Some(closer) != author {
+                    m.closed_by_non_author += 1;
+                }
+            }
+            if let Some(h) = hours_between(&issue.created_at, closed_at) {
+                hours_to_close.entry(closed).or_default().push(h);
+            }
+        }
+    }
+
+    windows.sort_keys();
+    windows
+        .into_iter()
+        .map(|(window, mut m)| {
+            m.median_hours_to_close = median(hours_to_close.swap_remove(&window).unwrap_or_default());
+            let sentiment = SentimentSummary::of(scores.get(&window).into_iter().flatten());
+            m.sentiment_compound_avg = sentiment.compound_avg;
+            m.negative_messages = sentiment.negative;
+            m.uncivil_messages = sentiment.uncivil;
+            m.closed_by_non_author_ratio = if m.issues_closed > 0 {
+                m.closed_by_non_author as f64 / m.issues_closed as f64
+            } else {
+                0.0
+            };
+            m.window = window;
+            m
+        })
+        .collect()
+}
+
+/// Builds the reply network of the issue threads: each commenter is linked to the issue author and to
+/// everyone who commented on the issue before them, in the window of the comment. Self-edges are skipped.
+pub fn compute_issue_network(issues: &[Issue]) -> Vec<IssueNetworkEdge> {
+    let mut weights: IndexMap<(String, String, String), usize> = IndexMap::new();
+    for issue in issues {
+        let mut participants: Vec<&str> = issue.author.iter().map(|a| a.login.as_str()).collect();
+        for comment in issue.comments.nodes.iter() {
+            if let Some(author) = &comment.author {
+                let commenter = author.login.as_str();
+                let window = month_window(&comment.createdAt);
+                for target in participants.iter().filter(|p| **p != commenter) {
+                    *weights
+                        .entry((window.clone(), commenter.to_string(), target.to_string()))
+                        .or_default() += 1;
+                }
+                if !participants.contains(&commenter) {
+                    participants.push(commenter);
+                }
+            }
+        }
+    }
+
+    weights.sort_keys();
+    weights
+        .into_iter()
+        .map(|((window, source, target), weight)| IssueNetworkEdge { window, source, target, weight })
+        .collect()
+}
+
+/// The folder of the local issue store, inside the GitHub output folder
+fn store_folder(output_folder: &str) -> String {
+    format!("{}/issue_store", output_folder)
+}
+
+/// The URLs written to the issue CSVs, for forge repositories and JIRA projects
+pub trait IssueLinks {
+    fn issue_api_url(&self, number: u32) -> String;
+    fn comment_api_url(&self, number: u32, comment_id: &str) -> String;
+    fn commit_url(&self, sha: &str) -> String;
+}
+
+impl IssueLinks for RepoRef {
+    fn issue_api_url(&self, number: u32) -> String {
+        RepoRef::issue_api_url(self, number)
+    }
+
+    fn comment_api_url(&self, number: u32, comment_id: &str) -> String {
+        RepoRef::comment_api_url(self, number, comment_id)
+    }
+
+    fn commit_url(&self, sha: &str) -> String {
+        RepoRef::commit_url(self, sha)
+    }
+}
+
+/// Folder of a CSV path, `.` when it has none
+fn csv_folder(path: &Path) -> &str {
+    let folder = path.parent().and_then(|p| p.to_str()).unwrap_or(".");
+    if folder.is_empty() { "." } else { folder }
+}
+
+/// Writes issues + comments to the `output_stem` table, with issue cross-references to `{repo}_issue_references`,
+/// per-month issue metrics to `{repo}_issue_metrics` and the per-month reply network of the
+/// issue threads to `{repo}_issue_network` next to it, all in `format`
+/// The issues come from the local issue store next to the table, synced first unless `offline` is set
+/// If `sentiment` is set, per-message sentiment scores are written to `{repo}_sentiment` and their
+/// per-developer monthly aggregation to `{repo}_sentiment_developers`, next to the issues table
+pub fn fetch_issues_with_comments_csv(
+    repo_ref: &RepoRef,
+    output_stem: &str,
+    format: OutputFormat,
+    sentiment: bool,
+    offline: bool,
+) -> Result<(), Box<dyn Error>> {
+    let folder = csv_folder(Path::new(output_stem));
+    let issues = issue_store::load_issues(repo_ref, &store_folder(folder), offline)?;
+    write_issues_csv(issues, repo_ref, &repo_ref.repo, output_stem, format, sentiment)
+}
+
+/// Writes `issues` in the issues table schema, plus the references, metrics and sentiment side files
+/// described at `fetch_issues_with_comments_csv`. `repo` names the side files and fills `repo_name`.
+/// With `--sqlite`, the issues are also stored in the database under the project named `repo`.
+pub fn write_issues_csv(
+    issues: Vec<Issue>,
+    links: &dyn IssueLinks,
+    repo: &str,
+    output_stem: &str,
+    format: OutputFormat,
+    sentiment: bool,
+) -> Result<(), Box<dyn Error>> {
+    let folder = csv_folder(Path::new(output_stem));
     let count = issues.len();
+    let analyzer = SentimentAnalyzer::global();
+    let mut scores = Vec::<MessageScore>::new();
+
+    let records: Vec<IssueRecord> = issues.iter().map(Issue::record).collect();
+    if let Err(e) = database::store_issues(repo, &records) {
+        error!("{} - cannot store the issues in the database: {}", repo, e);
+    }
 
-    let path = Path::new(output_csv_path);
-    let file = File::create(path)?;
-    let mut wtr = csv::WriterBuilder::new().has_headers(false).from_writer(file);
-
-    // Write CSV header
-    wtr.write_record(&[
-        "type",
-        "issue_url",
-        "comment_url",
-        "repo_name",
-        "id",
-        "issue_num",
-        "title",
-        "user_login",
-        "user_id",
-        "user_name",
-        "user_email",
-        "issue_state",
-        "created_at",
-        "updated_at",
-        "body",
-        "reactions",
-    ])?;
+    output::write_table(
+        &format!("{}/{}_issue_metrics", folder, repo),
+        format,
+        Some(&IssueWindowMetrics::default()),
+        compute_issue_window_metrics(&issues),
+    )?;
+    output::write_table(
+        &format!("{}/{}_issue_network", folder, repo),
+        format,
+        Some(&IssueNetworkEdge::default()),
+        compute_issue_network(&issues),
+    )?;
+    let mut references_wtr = TableWriter::create(&format!("{}/{}_issue_references", folder, repo), format)?
+        .with_template(&IssueReferenceRow::default())?;
+    let mut wtr = TableWriter::create(output_stem, format)?.with_template(&CsvRow::default())?;
 
     // For building final links:
-    let base_issue_url = format!("https://api.github.com/repos/{}/{}/issues", owner, repo);
-    let base_comment_url = format!("https://api.github.com/repos/{}/{}/issues/comments", owner, repo);
 
     let repo_name = repo.to_string();
 
     for issue in issues {
-        // Build JSON-like reactions placeholder
-        let issue_reactions = format!(
-            r#"{{"url": "https://api.github.com/repos/{}/{}/issues/{}/reactions", "total_count": 0, "+1": 0, "-1": 0, "laugh": 0, "hooray": 0, "confused": 0, "heart": 0, "rocket": 0, "eyes": 0}}"#,
-            owner, repo, issue.number
+        let issue_reactions = reactions_json(
+            &format!("{}/reactions", links.issue_api_url(issue.number)),
+            &issue.reaction_groups,
         );
+        let closed_by = issue.closed_by().unwrap_or_default().to_string();
+
+        for item in issue.timeline_items.iter() {
+            let (reference_type, reference, url) = match (item.__typename.as_str(), &item.source, &item.commit) {
+                ("CrossReferencedEvent", Some(source), _) => (
+                    source.__typename.clone(),
+                    source.number.map(|n| n.to_string()).unwrap_or_default(),
+                    source.url.clone().unwrap_or_default(),
+                ),
+                ("ReferencedEvent", _, Some(commit)) => (
+                    "Commit".to_string(),
+                    commit.oid.clone(),
+                    links.commit_url(&commit.oid),
+                ),
+                _ => continue,
+            };
+            references_wtr.serialize(IssueReferenceRow {
+                repo_name: repo_name.clone(),
+                issue_num: issue.number,
+                reference_type,
+                reference,
+                url,
+                created_at: item.createdAt.clone().unwrap_or_default(),
+            })?;
+        }
         // Use numeric databaseId if present:
         let issue_id = match issue.databaseId {
             Some(dbid) => dbid.to_string(),
@@ -324,12 +1044,24 @@ pub fn fetch_issues_with_comments_csv(owner: &str, repo: &str, output_csv_path:
             (String::new(), String::new(), String::new(), String::new())
         };
 
+        if sentiment {
+            scores.push(MessageScore {
+                project: repo_name.clone(),
+                source: "issue".to_string(),
+                id: issue_id.clone(),
+                author: user_login.clone(),
+                window: month_window(&issue.created_at),
+                date: issue.created_at.clone(),
+                scores: analyzer.score(&format!("{} {}", issue.title, issue.body.as_deref().unwrap_or(""))),
+            });
+        }
+
         // Write an "issue" row
         let issue_row = CsvRow {
             r#type: "issue".to_string(),
-            issue_url: format!("{}/{}", base_issue_url, issue.number),
+            issue_url: links.issue_api_url(issue.number),
             // For the issue row, we keep comment_url the same as issue_url (as in your original code).
-            comment_url: format!("{}/{}", base_issue_url, issue.number),
+            comment_url: links.issue_api_url(issue.number),
             repo_name: repo_name.clone(),
             id: issue_id,
             issue_num: issue.number,
@@ -343,25 +1075,26 @@ pub fn fetch_issues_with_comments_csv(owner: &str, repo: &str, output_csv_path:
             updated_at: issue.updated_at,
             body: issue.body.unwrap_or_default(),
             reactions: issue_reactions,
+            labels: issue.labels.join(";"),
+            assignees: issue.assignees.join(";"),
+            milestone: issue.milestone.unwrap_or_default(),
+            closed_at: issue.closed_at.unwrap_or_default(),
+            closed_by,
+            state_reason: issue.state_reason.unwrap_or_default(),
         };
         wtr.serialize(issue_row)?;
 
         // Now for each comment on this issue
         for comment_node in issue.comments.nodes {
-            // Build comment reactions placeholder
             // Use numeric databaseId if present for the final link:
-            let comment_url_id = match comment_node.databaseId {
-                Some(dbid) => dbid.to_string(),
-                None => comment_node.id.clone(),
-            };
-            let comment_reactions = format!(
-                r#"{{"url": "https://api.github.com/repos/{}/{}/issues/comments/{}/reactions", "total_count": 0, "+1": 0, "-1": 0, "laugh": 0, "hooray": 0, "confused": 0, "heart": 0, "rocket": 0, "eyes": 0}}"#,
-                owner, repo, comment_url_id
-            );
             let comment_id = match comment_node.databaseId {
                 Some(dbid) => dbid.to_string(),
                 None => comment_node.id.clone(),
             };
+            let comment_reactions = reactions_json(
+                &format!("{}/reactions", links.comment_api_url(issue.number, &comment_id)),
+                &comment_node.reactionGroups,
+            );
             let (c_user_login, c_user_id, c_user_name, c_user_email) = if let Some(author) = comment_node.author {
                 (
                     author.login,
@@ -373,11 +1106,23 @@ pub fn fetch_issues_with_comments_csv(owner: &str, repo: &str, output_csv_path:
                 (String::new(), String::new(), String::new(), String::new())
             };
 
+            if sentiment {
+                scores.push(MessageScore {
+                    project: repo_name.clone(),
+                    source: "comment".to_string(),
+                    id: comment_id.clone(),
+                    author: c_user_login.clone(),
+                    window: month_window(&comment_node.createdAt),
+                    date: comment_node.createdAt.clone(),
+                    scores: analyzer.score(&comment_node.body),
+                });
+            }
+
             // Write a "comment" row
             let comment_row = CsvRow {
                 r#type: "comment".to_string(),
-                issue_url: format!("{}/{}", base_issue_url, issue.number),
-                comment_url: format!("{}/{}", base_comment_url, comment_id),
+                issue_url: links.issue_api_url(issue.number),
+                comment_url: links.comment_api_url(issue.number, &comment_id),
                 repo_name: repo_name.clone(),
                 id: comment_id,
                 issue_num: issue.number,
@@ -391,32 +1136,56 @@ pub fn fetch_issues_with_comments_csv(owner: &str, repo: &str, output_csv_path:
                 updated_at: comment_node.createdAt,
                 body: comment_node.body,
                 reactions: comment_reactions,
+                labels: String::new(),
+                assignees: String::new(),
+                milestone: String::new(),
+                closed_at: String::new(),
+                closed_by: String::new(),
+                state_reason: String::new(),
             };
             wtr.serialize(comment_row)?;
         }
     }
 
-    wtr.flush()?;
-    info!("Fetched {} issues from {}/{}", count, owner, repo);
+    wtr.finish()?;
+    references_wtr.finish()?;
+    info!("Exported {} issues of {}", count, repo);
+
+    if sentiment {
+        sentiment::write_scores(
+            &scores,
+            &format!("{}/{}_sentiment", folder, repo),
+            &format!("{}/{}_sentiment_developers", folder, repo),
+            format,
+        )?;
+        info!("Wrote sentiment scores for {} issues and comments of {}", scores.len(), repo);
+    }
     Ok(())
 }
 
 /// If you want grouped-by-developer logic (issues + comments)
-pub fn write_issue_stats_grouped_by_developer(owner: &str, repo: &str, output_folder: &str) -> Result<(), Box<dyn Error>> {
+/// The issues come from the local issue store in `output_folder`, synced first unless `offline` is set
+pub fn write_issue_stats_grouped_by_developer(
+    repo_ref: &RepoRef,
+    output_folder: &str,
+    format: OutputFormat,
+    offline: bool,
+) -> Result<(), Box<dyn Error>> {
     use std::collections::HashMap;
     use chrono::DateTime;
 
-    #[derive(Debug, Serialize)]
+    #[derive(Debug, Default, Serialize)]
     struct IssueDevStat {
         date_time: String,
         file: String,
         committer_name: String,
         committer_email: String,
+        #[serde(rename = "comment_url/issue_url")]
         url: String,
         month: String,
     }
 
-    let issues = fetch_issues(owner, repo)?;
+    let issues = issue_store::load_issues(repo_ref, &store_folder(output_folder), offline)?;
     let mut grouped_stats: HashMap<String, Vec<IssueDevStat>> = HashMap::new();
 
     let extract_month = |dt_str: &str| -> String {
@@ -433,7 +1202,7 @@ pub fn write_issue_stats_grouped_by_developer(owner: &str, repo: &str, output_fo
             let name = author.name.clone().unwrap_or(author.login.clone());
             let email = author.email.clone().unwrap_or_default();
             let month = extract_month(&issue.created_at);
-            let url = format!("https://github.com/{}/{}/issues/{}", owner, repo, issue.number);
+            let url = repo_ref.issue_web_url(issue.number);
             let stat = IssueDevStat {
                 date_time: issue.created_at.clone(),
                 file: issue.title.clone(),
@@ -454,7 +1223,7 @@ pub fn write_issue_stats_grouped_by_developer(owner: &str, repo: &str, output_fo
                     Some(dbid) => dbid.to_string(),
                     None => comment.id.clone(),
                 };
-                let url = format!("https://github.com/{}/{}/issues/comments/{}", owner, repo, url_id);
+                let url = repo_ref.comment_web_url(issue.number, &url_id);
                 let stat = IssueDevStat {
                     date_time: comment.createdAt.clone(),
                     file: issue.title.clone(),
@@ -471,24 +1240,132 @@ pub fn write_issue_stats_grouped_by_developer(owner: &str, repo: &str, output_fo
     std::fs::create_dir_all(output_folder)?;
 
     for (dev_name, stats) in grouped_stats {
-        let file_path = format!("{}/{}.csv", output_folder, dev_name);
-        let file = File::create(&file_path)?;
-        let mut writer = csv::Writer::from_writer(file);
-        writer.write_record(&["date_time", "file", "committer_name", "committer_email", "comment_url/issue_url", "month"])?;
-
-        for stat in stats {
-            writer.write_record(&[
-                stat.date_time,
-                stat.file,
-                stat.committer_name,
-                stat.committer_email,
-                stat.url,
-                stat.month,
-            ])?;
-        }
-        writer.flush()?;
-        info!("Issue stats written to {}", file_path);
+        let path = output::write_table(
+            &format!("{}/{}", output_folder, dev_name),
+            format,
+            Some(&IssueDevStat::default()),
+            stats,
+        )?;
+        info!("Issue stats written to {}", path);
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_comment_pages_query() {
+        let query = comment_pages_query(&[(7, "Y3Vyc29yOjEwMA==".to_string()), (12, "abc".to_string())]);
+        assert!(query.contains("i7: issue(number: 7)"));
+        assert!(query.contains("i12: issue(number: 12)"));
+        assert!(query.contains(r#"after: "Y3Vyc29yOjEwMA==""#));
+        assert!(query.starts_with("query($owner: String!, $name: String!)"));
+    }
+
+    fn issue(number: u32, author: &str, created_at: &str, closed_at: Option<&str>, closer: Option<&str>) -> Issue {
+        let timeline: Vec<serde_json::Value> = closer
+            .map(|c| json!({"__typename": "ClosedEvent", "createdAt": closed_at, "actor": {"login": c}}))
+            .into_iter()
+            .collect();
+        serde_json::from_value(json!({
+            "id": format!("I_{}", number), "databaseId": number, "number": number, "title": "t", "body": null,
+            "state": if closed_at.is_some() { "CLOSED" } else { "OPEN" },
+            "created_at": created_at, "updated_at": created_at, "closed_at": closed_at, "state_reason": null,
+            "author": {"login": author, "url": ""},
+            "comments": {"pageInfo": {"hasNextPage": false, "endCursor": null}, "nodes": []},
+            "reaction_groups": [], "labels": [], "assignees": [], "milestone": null,
+            "timeline_items": timeline,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_connection_pages() {
+        let query = connection_pages_query(Connection::TimelineItems, &[(7, "abc".to_string())]);
+        assert!(query.contains(r#"timelineItems(first: 100, itemTypes: [CLOSED_EVENT, CROSS_REFERENCED_EVENT, REFERENCED_EVENT], after: "abc")"#));
+        assert!(query.contains("fragment TimelineFields on IssueTimelineItems"));
+        let query = connection_pages_query(Connection::Labels, &[(7, "abc".to_string())]);
+        assert!(query.contains(r#"labels(first: 100, after: "abc")"#));
+        assert!(!query.contains("fragment"));
+
+        let mut closed = issue(1, "alice", "2021-01-01T00:00:00Z", Some("2021-01-02T00:00:00Z"), Some("bob"));
+        let page: IssuePage = serde_json::from_value(json!({
+            "timelineItems": {"pageInfo": {"hasNextPage": true, "endCursor": "def"}, "nodes": [
+                {"__typename": "ClosedEvent", "createdAt": "2021-01-05T00:00:00Z", "actor": {"login": "carol"}}
+            ]}
+        }))
+        .unwrap();
+        assert_eq!(Connection::TimelineItems.extend(&mut closed, page), Some(Some("def".to_string())));
+        assert_eq!(closed.closed_by(), Some("carol"));
+        let page: IssuePage = serde_json::from_value(json!({"labels": {"nodes": [{"name": "bug"}]}})).unwrap();
+        assert_eq!(Connection::Labels.extend(&mut closed, page), Some(None));
+        assert_eq!(closed.labels, vec!["bug"]);
+        let page: IssuePage = serde_json::from_value(json!({})).unwrap();
+        assert_eq!(Connection::Assignees.extend(&mut closed, page), None);
+    }
+
+    #[test]
+    fn test_compute_issue_network() {
+        let mut first = issue(1, "alice", "2021-01-01T00:00:00Z", None, None);
+        first.comments.nodes = ["bob", "alice", "carol", "bob"]
+            .iter()
+            .enumerate()
+            .map(|(i, login)| {
+                serde_json::from_value(json!({
+                    "id": format!("C_{}", i), "databaseId": i, "body": "", "createdAt": "2021-02-03T00:00:00Z",
+                    "author": {"login": login}
+                }))
+                .unwrap()
+            })
+            .collect();
+        let edges = compute_issue_network(&[first, issue(2, "dave", "2021-01-01T00:00:00Z", None, None)]);
+        let edges: Vec<(&str, &str, usize)> =
+            edges.iter().map(|e| (e.source.as_str(), e.target.as_str(), e.weight)).collect();
+        assert_eq!(edges, vec![("alice", "bob", 1), ("bob", "alice", 2), ("bob", "carol", 1), ("carol", "alice", 1), ("carol", "bob", 1)]);
+    }
+
+    #[test]
+    fn test_compute_issue_window_metrics() {
+        let issues = vec![
+            issue(1, "alice", "2021-03-01T00:00:00Z", Some("2021-03-03T00:00:00Z"), Some("alice")),
+            issue(2, "alice", "2021-03-02T00:00:00Z", Some("2021-04-01T00:00:00Z"), Some("bob")),
+            issue(3, "carol", "2021-04-10T00:00:00Z", None, None),
+        ];
+        let metrics = compute_issue_window_metrics(&issues);
+        assert_eq!(metrics.len(), 2);
+        assert_eq!(metrics[0].window, "2021-03");
+        assert_eq!(metrics[0].issues_opened, 2);
+        assert_eq!(metrics[0].issues_closed, 1);
+        assert_eq!(metrics[0].median_hours_to_close, 48.0);
+        assert_eq!(metrics[0].closed_by_non_author_ratio, 0.0);
+        assert_eq!(metrics[1].window, "2021-04");
+        assert_eq!(metrics[1].issues_opened, 1);
+        assert_eq!(metrics[1].issues_closed, 1);
+        assert_eq!(metrics[1].closed_by_non_author, 1);
+        assert_eq!(metrics[1].closed_by_non_author_ratio, 1.0);
+        assert_eq!((metrics[0].negative_messages, metrics[0].uncivil_messages), (0, 0));
+
+        let mut rude = issue(4, "dave", "2021-05-01T00:00:00Z", None, None);
+        rude.body = Some("This stupid build is broken".to_string());
+        let metrics = compute_issue_window_metrics(&[rude]);
+        assert_eq!((metrics[0].negative_messages, metrics[0].uncivil_messages), (1, 1));
+        assert!(metrics[0].sentiment_compound_avg < 0.0);
+    }
+
+    #[test]
+    fn test_reactions_json() {
+        let groups: Vec<ReactionGroup> = serde_json::from_value(json!([
+            {"content": "THUMBS_UP", "reactors": {"totalCount": 3}},
+            {"content": "HEART", "reactors": {"totalCount": 1}},
+        ]))
+        .unwrap();
+        let reactions: serde_json::Value = serde_json::from_str(&reactions_json("u", &groups)).unwrap();
+        assert_eq!(reactions["total_count"], 4);
+        assert_eq!(reactions["+1"], 3);
+        assert_eq!(reactions["heart"], 1);
+        assert_eq!(reactions["eyes"], 0);
+    }
+}
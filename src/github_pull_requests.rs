@@ -0,0 +1,961 @@
+// src/github_pull_requests.rs
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+
+use indexmap::{IndexMap, IndexSet};
+use log::{error, info, warn};
+use serde_json::json;
+
+use crate::forge::{self, ForgePullRequest, RepoRef};
+use crate::github_client::GitHubClient;
+use crate::output::{self, OutputFormat, TableWriter};
+use crate::utils::{hours_between, median};
+
+#[derive(Debug, Deserialize)]
+struct RepositoryData {
+    repository: Option<Repository>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Repository {
+    #[serde(default)]
+    pull_requests: Option<PullRequestConnection>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    has_next_page: bool,
+    end_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PullRequestConnection {
+    page_info: PageInfo,
+    nodes: Vec<PullRequest>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Actor {
+    login: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PullRequest {
+    id: String,
+    database_id: Option<u64>,
+    pub number: u32,
+    pub title: String,
+    pub body: Option<String>,
+    pub state: String,
+    #[serde(default)]
+    pub is_draft: bool,
+    pub created_at: String,
+    pub updated_at: String,
+    pub closed_at: Option<String>,
+    pub merged_at: Option<String>,
+    #[serde(default)]
+    base_ref_name: String,
+    #[serde(default)]
+    head_ref_name: String,
+    #[serde(default)]
+    additions: u64,
+    #[serde(default)]
+    deletions: u64,
+    #[serde(default)]
+    changed_files: u64,
+    author: Option<Actor>,
+    merged_by: Option<Actor>,
+    review_requests: Nodes<ReviewRequest>,
+    reviews: Nodes<Review>,
+    commits: Nodes<PullRequestCommit>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Nodes<T> {
+    #[serde(default)]
+    total_count: usize,
+    #[serde(default)]
+    page_info: Option<PageInfo>,
+    nodes: Vec<T>,
+}
+
+impl<T> Nodes<T> {
+    fn new(nodes: Vec<T>) -> Nodes<T> {
+        Nodes { total_count: nodes.len(), page_info: None, nodes }
+    }
+
+    /// The cursor of the next page, while the connection has more nodes than fetched
+    fn next_cursor(&self) -> Option<String> {
+        self.page_info.as_ref().filter(|p| p.has_next_page).and_then(|p| p.end_cursor.clone())
+    }
+
+    /// Appends the next page; without one, the connection stays truncated and is not paged further
+    fn extend(&mut self, page: Option<Nodes<T>>) {
+        match page {
+            Some(page) => {
+                self.nodes.extend(page.nodes);
+                self.page_info = page.page_info;
+            }
+            None => self.page_info = None,
+        }
+    }
+
+    fn is_truncated(&self) -> bool {
+        self.total_count > self.nodes.len()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReviewRequest {
+    requested_reviewer: Option<RequestedReviewer>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RequestedReviewer {
+    #[serde(rename = "__typename")]
+    typename: String,
+    #[serde(default)]
+    login: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Review {
+    id: String,
+    database_id: Option<u64>,
+    state: String,
+    #[serde(default)]
+    body: String,
+    submitted_at: Option<String>,
+    author: Option<Actor>,
+    comments: Nodes<ReviewComment>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReviewComment {
+    id: String,
+    database_id: Option<u64>,
+    #[serde(default)]
+    body: String,
+    #[serde(default)]
+    path: String,
+    created_at: String,
+    author: Option<Actor>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PullRequestCommit {
+    commit: CommitNode,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CommitNode {
+    oid: String,
+    committed_date: String,
+    #[serde(default)]
+    message_headline: String,
+    author: Option<GitActor>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitActor {
+    name: Option<String>,
+    email: Option<String>,
+}
+
+impl PullRequest {
+    fn author_login(&self) -> String {
+        self.author.as_ref().map(|a| a.login.clone()).unwrap_or_default()
+    }
+
+    /// The first submitted review that was not written by the pull request author
+    fn first_review_at(&self) -> Option<&str> {
+        let author = self.author_login();
+        self.reviews
+            .nodes
+            .iter()
+            .filter(|r| r.author.as_ref().map(|a| a.login != author).unwrap_or(true))
+            .filter_map(|r| r.submitted_at.as_deref())
+            .min()
+    }
+}
+
+fn actor(login: Option<String>) -> Option<Actor> {
+    login.map(|login| Actor { login })
+}
+
+impl From<ForgePullRequest> for PullRequest {
+    fn from(pr: ForgePullRequest) -> PullRequest {
+        let reviews: Vec<Review> = pr
+            .reviews
+            .into_iter()
+            .map(|r| Review {
+                id: r.id.to_string(),
+                database_id: Some(r.id),
+                state: r.state,
+                body: r.body,
+                submitted_at: r.submitted_at,
+                author: actor(r.author),
+                comments: Nodes::new(Vec::new()),
+            })
+            .collect();
+        let review_requests: Vec<ReviewRequest> = pr
+            .requested_reviewers
+            .into_iter()
+            .map(|login| ReviewRequest {
+                requested_reviewer: Some(RequestedReviewer { typename: "User".to_string(), login: Some(login), name: None }),
+            })
+            .collect();
+        let commits: Vec<PullRequestCommit> = pr
+            .commits
+            .into_iter()
+            .map(|c| PullRequestCommit {
+                commit: CommitNode {
+                    oid: c.sha,
+                    committed_date: c.date,
+                    message_headline: c.message.lines().next().unwrap_or_default().to_string(),
+                    author: Some(GitActor { name: Some(c.author_name), email: Some(c.author_email) }),
+                },
+            })
+            .collect();
+        PullRequest {
+            id: pr.id.to_string(),
+            database_id: Some(pr.id),
+            number: pr.number,
+            title: pr.title,
+            body: pr.body,
+            state: pr.state,
+            is_draft: pr.is_draft,
+            created_at: pr.created_at,
+            updated_at: pr.updated_at,
+            closed_at: pr.closed_at,
+            merged_at: pr.merged_at,
+            base_ref_name: pr.base_ref,
+            head_ref_name: pr.head_ref,
+            additions: 0,
+            deletions: 0,
+            changed_files: 0,
+            author: actor(pr.author),
+            merged_by: actor(pr.merged_by),
+            review_requests: Nodes::new(review_requests),
+            reviews: Nodes::new(reviews),
+            commits: Nodes::new(commits),
+        }
+    }
+}
+
+// Table row types
+#[derive(Debug, Default, Serialize)]
+struct PullRequestRow {
+    repo_name: String,
+    id: String,
+    pr_num: u32,
+    url: String,
+    title: String,
+    user_login: String,
+    state: String,
+    is_draft: bool,
+    base_ref: String,
+    head_ref: String,
+    created_at: String,
+    updated_at: String,
+    closed_at: String,
+    merged_at: String,
+    merged_by: String,
+    additions: u64,
+    deletions: u64,
+    changed_files: u64,
+    reviews: usize,
+    commits: usize,
+    first_review_at: String,
+    body: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct ReviewRow {
+    repo_name: String,
+    pr_num: u32,
+    id: String,
+    user_login: String,
+    state: String,
+    submitted_at: String,
+    body: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct ReviewCommentRow {
+    repo_name: String,
+    pr_num: u32,
+    review_id: String,
+    id: String,
+    user_login: String,
+    path: String,
+    created_at: String,
+    body: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct RequestedReviewerRow {
+    repo_name: String,
+    pr_num: u32,
+    reviewer_type: String,
+    reviewer: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct PullRequestCommitRow {
+    repo_name: String,
+    pr_num: u32,
+    sha: String,
+    committed_date: String,
+    author_name: String,
+    author_email: String,
+    message_headline: String,
+}
+
+/// Pull request metrics for one yyyy-mm window. PRs are counted as opened in the month they were created
+/// and as merged in the month they were merged; time-to-first-review and review participation are
+/// attributed to the month the PR was opened.
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct PullRequestWindowMetrics {
+    pub window: String,
+    pub prs_opened: usize,
+    pub prs_merged: usize,
+    pub prs_closed_unmerged: usize,
+    pub median_hours_to_merge: f64,
+    pub median_hours_to_first_review: f64,
+    pub prs_reviewed: usize,
+    pub prs_reviewed_ratio: f64,
+    pub distinct_reviewers: usize,
+    pub reviews: usize,
+}
+
+/// The fields of the nodes of each pull request connection, shared by the first and the follow-up pages
+const REVIEW_REQUEST_FIELDS: &str = r#"
+    fragment ReviewRequestFields on ReviewRequest {
+        requestedReviewer {
+            __typename
+            ... on User { login }
+            ... on Team { name }
+        }
+    }
+"#;
+
+const REVIEW_COMMENT_FIELDS: &str = r#"
+    fragment ReviewCommentFields on PullRequestReviewComment {
+        id
+        databaseId
+        body
+        path
+        createdAt
+        author { login }
+    }
+"#;
+
+const REVIEW_FIELDS: &str = r#"
+    fragment ReviewFields on PullRequestReview {
+        id
+        databaseId
+        state
+        body
+        submittedAt
+        author { login }
+        comments(first: 50) {
+            totalCount
+            pageInfo { hasNextPage endCursor }
+            nodes { ...ReviewCommentFields }
+        }
+    }
+"#;
+
+const COMMIT_FIELDS: &str = r#"
+    fragment CommitFields on PullRequestCommit {
+        commit {
+            oid
+            committedDate
+            messageHeadline
+            author { name email }
+        }
+    }
+"#;
+
+/// Number of pull requests (or reviews) whose next page is requested in a single follow-up query
+const PAGES_BATCH_SIZE: usize = 20;
+
+/// A connection of a pull request that is fetched page by page
+#[derive(Clone, Copy, Debug)]
+enum Connection {
+    ReviewRequests,
+    Reviews,
+    Commits,
+}
+
+impl Connection {
+    const ALL: [Connection; 3] = [Connection::ReviewRequests, Connection::Reviews, Connection::Commits];
+
+    /// The GraphQL field, its page size, the fragment selecting its nodes, and the fragment definitions
+    fn selection(self) -> (&'static str, usize, &'static str, String) {
+        match self {
+            Connection::ReviewRequests => ("reviewRequests", 100, "ReviewRequestFields", REVIEW_REQUEST_FIELDS.to_string()),
+            // each review brings its first 50 comments along
+            Connection::Reviews => ("reviews", 50, "ReviewFields", format!("{}{}", REVIEW_FIELDS, REVIEW_COMMENT_FIELDS)),
+            Connection::Commits => ("commits", 100, "CommitFields", COMMIT_FIELDS.to_string()),
+        }
+    }
+
+    fn next_cursor(self, pr: &PullRequest) -> Option<String> {
+        match self {
+            Connection::ReviewRequests => pr.review_requests.next_cursor(),
+            Connection::Reviews => pr.reviews.next_cursor(),
+            Connection::Commits => pr.commits.next_cursor(),
+        }
+    }
+
+    fn extend(self, pr: &mut PullRequest, page: Option<PullRequestPage>) {
+        match self {
+            Connection::ReviewRequests => pr.review_requests.extend(page.and_then(|p| p.review_requests)),
+            Connection::Reviews => pr.reviews.extend(page.and_then(|p| p.reviews)),
+            Connection::Commits => pr.commits.extend(page.and_then(|p| p.commits)),
+        }
+    }
+}
+
+/// Follow-up pages of several pull requests at once; each pull request is aliased as `p{number}`
+#[derive(Debug, Deserialize)]
+struct PullRequestPages {
+    repository: Option<HashMap<String, Option<PullRequestPage>>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PullRequestPage {
+    #[serde(default)]
+    review_requests: Option<Nodes<ReviewRequest>>,
+    #[serde(default)]
+    reviews: Option<Nodes<Review>>,
+    #[serde(default)]
+    commits: Option<Nodes<PullRequestCommit>>,
+}
+
+/// A follow-up page of review comments; each review is aliased as `r{index in the batch}`
+#[derive(Debug, Deserialize)]
+struct ReviewPage {
+    #[serde(default)]
+    comments: Option<Nodes<ReviewComment>>,
+}
+
+/// Fetch pull requests (with reviews, review comments, requested reviewers and commits) from GitHub GraphQL
+pub fn fetch_pull_requests(owner: &str, repo: &str) -> Result<Vec<PullRequest>, Box<dyn Error>> {
+    let client = GitHubClient::global()?;
+
+    // Pages of 50 PRs keep the nested reviews x comments node count well below the GraphQL limit
+    let query = format!(
+        r#"
+        query($owner: String!, $name: String!, $cursor: String) {{
+            rateLimit {{
+                remaining
+                resetAt
+            }}
+            repository(owner: $owner, name: $name) {{
+                pullRequests(first: 50, after: $cursor, orderBy: {{field: CREATED_AT, direction: ASC}}) {{
+                    pageInfo {{
+                        hasNextPage
+                        endCursor
+                    }}
+                    nodes {{
+                        id
+                        databaseId
+                        number
+                        title
+                        body
+                        state
+                        isDraft
+                        createdAt
+                        updatedAt
+                        closedAt
+                        mergedAt
+                        baseRefName
+                        headRefName
+                        additions
+                        deletions
+                        changedFiles
+                        author {{ login }}
+                        mergedBy {{ login }}
+                        reviewRequests(first: 20) {{
+                            totalCount
+                            pageInfo {{ hasNextPage endCursor }}
+                            nodes {{ ...ReviewRequestFields }}
+                        }}
+                        reviews(first: 50) {{
+                            totalCount
+                            pageInfo {{ hasNextPage endCursor }}
+                            nodes {{ ...ReviewFields }}
+                        }}
+                        commits(first: 100) {{
+                            totalCount
+                            pageInfo {{ hasNextPage endCursor }}
+                            nodes {{ ...CommitFields }}
+                        }}
+                    }}
+                }}
+            }}
+        }}
+        {}{}{}{}"#,
+        REVIEW_REQUEST_FIELDS, REVIEW_FIELDS, REVIEW_COMMENT_FIELDS, COMMIT_FIELDS
+    );
+
+    let mut all_prs = Vec::new();
+    let mut cursor: Option<String> = None;
+
+    loop {
+        let variables = json!({
+            "owner": owner,
+            "name": repo,
+            "cursor": cursor,
+        });
+        let data: RepositoryData = client.graphql(&query, variables)?;
+        let repository = data.repository.ok_or("Repository not found or access denied.")?;
+        let prs_conn = repository.pull_requests.ok_or("Repository pullRequests field is missing.")?;
+        all_prs.extend(prs_conn.nodes);
+
+        if prs_conn.page_info.has_next_page {
+            cursor = prs_conn.page_info.end_cursor.clone();
+        } else {
+            break;
+        }
+    }
+    fetch_remaining_pages(client, owner, repo, &mut all_prs);
+    fetch_remaining_review_comments(client, owner, repo, &mut all_prs);
+
+    for pr in all_prs.iter() {
+        if pr.reviews.is_truncated()
+            || pr.commits.is_truncated()
+            || pr.review_requests.is_truncated()
+            || pr.reviews.nodes.iter().any(|r| r.comments.is_truncated())
+        {
+            warn!(
+                "{}/{} PR #{} has more reviews, review comments, commits or review requests than fetched; data is truncated",
+                owner,
+                repo,
+                pr.number
+            );
+        }
+    }
+    Ok(all_prs)
+}
+
+/// Builds one query fetching the next page of `connection` for each `(pull request number, cursor)` pair
+fn connection_pages_query(connection: Connection, pages: &[(u32, String)]) -> String {
+    let (field, first, fragment, fragments) = connection.selection();
+    let prs: Vec<String> = pages
+        .iter()
+        .map(|(number, cursor)| {
+            format!(
+                r#"p{}: pullRequest(number: {}) {{
+                    {}(first: {}, after: {}) {{
+                        totalCount
+                        pageInfo {{ hasNextPage endCursor }}
+                        nodes {{ ...{} }}
+                    }}
+                }}"#,
+                number,
+                number,
+                field,
+                first,
+                serde_json::Value::String(cursor.clone()),
+                fragment
+            )
+        })
+        .collect();
+    format!(
+        "query($owner: String!, $name: String!) {{ rateLimit {{ remaining resetAt }} repository(owner: $owner, name: $name) {{ {} }} }}\n{}",
+        prs.join("\n"),
+        fragments
+    )
+}
+
+/// Builds one query fetching the next page of comments for each `(review node id, cursor)` pair
+fn review_comment_pages_query(pages: &[(String, String)]) -> String {
+    let reviews: Vec<String> = pages
+        .iter()
+        .enumerate()
+        .map(|(index, (id, cursor))| {
+            format!(
+                r#"r{}: node(id: {}) {{
+                    ... on PullRequestReview {{
+                        comments(first: 100, after: {}) {{
+                            totalCount
+                            pageInfo {{ hasNextPage endCursor }}
+                            nodes {{ ...ReviewCommentFields }}
+                        }}
+                    }}
+                }}"#,
+                index,
+                serde_json::Value::String(id.clone()),
+                serde_json::Value::String(cursor.clone())
+            )
+        })
+        .collect();
+    format!("query {{ rateLimit {{ remaining resetAt }} {} }}\n{}", reviews.join("\n"), REVIEW_COMMENT_FIELDS)
+}
+
+/// Follows the `pageInfo` of the review requests, reviews and commits of every pull request with more than the
+/// first page, batching several pull requests per query. A page that cannot be fetched leaves the connection
+/// with what was fetched.
+fn fetch_remaining_pages(client: &GitHubClient, owner: &str, repo: &str, prs: &mut [PullRequest]) {
+    for connection in Connection::ALL {
+        loop {
+            let pending: Vec<(u32, String)> =
+                prs.iter().filter_map(|pr| connection.next_cursor(pr).map(|c| (pr.number, c))).collect();
+            if pending.is_empty() {
+                break;
+            }
+
+            for batch in pending.chunks(PAGES_BATCH_SIZE) {
+                let variables = json!({ "owner": owner, "name": repo });
+                let query = connection_pages_query(connection, batch);
+                let mut pages = match client.graphql::<PullRequestPages>(&query, variables) {
+                    Ok(data) => data.repository.unwrap_or_default(),
+                    Err(e) => {
+                        error!("Cannot fetch further {} for {}/{}: {}", connection.selection().0, owner, repo, e);
+                        HashMap::new()
+                    }
+                };
+                for (number, _) in batch {
+                    if let Some(pr) = prs.iter_mut().find(|pr| pr.number == *number) {
+                        connection.extend(pr, pages.remove(&format!("p{}", number)).flatten());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Follows the `pageInfo` of the comments of every review with more than the first page, once all the reviews
+/// are fetched
+fn fetch_remaining_review_comments(client: &GitHubClient, owner: &str, repo: &str, prs: &mut [PullRequest]) {
+    loop {
+        let pending: Vec<(String, String)> = prs
+            .iter()
+            .flat_map(|pr| pr.reviews.nodes.iter())
+            .filter_map(|r| r.comments.next_cursor().map(|c| (r.id.clone(), c)))
+            .collect();
+        if pending.is_empty() {
+            break;
+        }
+
+        for batch in pending.chunks(PAGES_BATCH_SIZE) {
+            let mut pages = match client.graphql::<HashMap<String, Option<ReviewPage>>>(
+                &review_comment_pages_query(batch),
+                json!({}),
+            ) {
+                Ok(pages) => pages,
+                Err(e) => {
+                    error!("Cannot fetch further review comments for {}/{}: {}", owner, repo, e);
+                    HashMap::new()
+                }
+            };
+            for (index, (id, _)) in batch.iter().enumerate() {
+                let page = pages.remove(&format!("r{}", index)).flatten().and_then(|p| p.comments);
+                if let Some(review) = prs.iter_mut().flat_map(|pr| pr.reviews.nodes.iter_mut()).find(|r| &r.id == id) {
+                    review.comments.extend(page);
+                }
+            }
+        }
+    }
+}
+
+/// The yyyy-mm month of a GitHub timestamp
+fn month_window(date: &str) -> String {
+    date.get(..7).unwrap_or("NA").to_string()
+}
+
+/// Computes per-month pull request metrics, ordered by window
+pub fn compute_window_metrics(prs: &[PullRequest]) -> Vec<PullRequestWindowMetrics> {
+    let mut windows: IndexMap<String, PullRequestWindowMetrics> = IndexMap::new();
+    let mut hours_to_merge: IndexMap<String, Vec<f64>> = IndexMap::new();
+    let mut hours_to_first_review: IndexMap<String, Vec<f64>> = IndexMap::new();
+    let mut reviewers: IndexMap<String, IndexSet<String>> = IndexMap::new();
+
+    for pr in prs {
+        let opened = month_window(&pr.created_at);
+        let author = pr.author_login();
+        {
+            let m = windows.entry(opened.clone()).or_default();
+            m.prs_opened += 1;
+            if pr.merged_at.is_none() && pr.closed_at.is_some() {
+                m.prs_closed_unmerged += 1;
+            }
+            let submitted = pr.reviews.nodes.iter().filter(|r| r.submitted_at.is_some());
+            m.reviews += submitted.clone().count();
+            if let Some(first) = pr.first_review_at() {
+                m.prs_reviewed += 1;
+                if let Some(h) = hours_between(&pr.created_at, first) {
+                    hours_to_first_review.entry(opened.clone()).or_default().push(h);
+                }
+            }
+            let window_reviewers = reviewers.entry(opened.clone()).or_default();
+            for review in submitted {
+                if let Some(a) = &review.author {
+                    if a.login != author {
+                        window_reviewers.insert(a.login.clone());
+                    }
+                }
+            }
+        }
+        if let Some(merged_at) = &pr.merged_at {
+            let merged = month_window(merged_at);
+            windows.entry(merged.clone()).or_default().prs_merged += 1;
+            if let Some(h) = hours_between(&pr.created_at, merged_at) {
+                hours_to_merge.entry(merged).or_default().push(h);
+            }
+        }
+    }
+
+    windows.sort_keys();
+    windows
+        .into_iter()
+        .map(|(window, mut m)| {
+            m.median_hours_to_merge = median(hours_to_merge.swap_remove(&window).unwrap_or_default());
+            m.median_hours_to_first_review =
+                median(hours_to_first_review.swap_remove(&window).unwrap_or_default());
+            m.distinct_reviewers = reviewers.get(&window).map(|r| r.len()).unwrap_or(0);
+            m.prs_reviewed_ratio = if m.prs_opened > 0 {
+                m.prs_reviewed as f64 / m.prs_opened as f64
+            } else {
+                0.0
+            };
+            m.window = window;
+            m
+        })
+        .collect()
+}
+
+/// Fetches the pull (or merge) requests of `repo_ref` from its forge and writes them to `output_folder` as
+/// the `{repo}_pull_requests`, `{repo}_pr_reviews`, `{repo}_pr_review_comments`, `{repo}_pr_requested_reviewers`,
+/// `{repo}_pr_commits` and per-month `{repo}_pr_metrics` tables, in `format`
+pub fn fetch_pull_requests_csv(repo_ref: &RepoRef, output_folder: &str, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let (owner, repo) = (repo_ref.owner.as_str(), repo_ref.repo.as_str());
+    let prs = forge::for_repo(repo_ref)?.pull_requests(repo_ref)?;
+    let repo_name = repo.to_string();
+
+    let stem = |name: &str| format!("{}/{}_{}", output_folder, repo, name);
+    let mut prs_wtr = TableWriter::create(&stem("pull_requests"), format)?.with_template(&PullRequestRow::default())?;
+    let mut reviews_wtr = TableWriter::create(&stem("pr_reviews"), format)?.with_template(&ReviewRow::default())?;
+    let mut comments_wtr =
+        TableWriter::create(&stem("pr_review_comments"), format)?.with_template(&ReviewCommentRow::default())?;
+    let mut requested_wtr =
+        TableWriter::create(&stem("pr_requested_reviewers"), format)?.with_template(&RequestedReviewerRow::default())?;
+    let mut commits_wtr =
+        TableWriter::create(&stem("pr_commits"), format)?.with_template(&PullRequestCommitRow::default())?;
+
+    for pr in prs.iter() {
+        prs_wtr.serialize(PullRequestRow {
+            repo_name: repo_name.clone(),
+            id: pr.database_id.map(|id| id.to_string()).unwrap_or_else(|| pr.id.clone()),
+            pr_num: pr.number,
+            url: repo_ref.pull_request_web_url(pr.number),
+            title: pr.title.clone(),
+            user_login: pr.author_login(),
+            state: pr.state.clone(),
+            is_draft: pr.is_draft,
+            base_ref: pr.base_ref_name.clone(),
+            head_ref: pr.head_ref_name.clone(),
+            created_at: pr.created_at.clone(),
+            updated_at: pr.updated_at.clone(),
+            closed_at: pr.closed_at.clone().unwrap_or_default(),
+            merged_at: pr.merged_at.clone().unwrap_or_default(),
+            merged_by: pr.merged_by.as_ref().map(|a| a.login.clone()).unwrap_or_default(),
+            additions: pr.additions,
+            deletions: pr.deletions,
+            changed_files: pr.changed_files,
+            reviews: pr.reviews.total_count,
+            commits: pr.commits.total_count,
+            first_review_at: pr.first_review_at().unwrap_or_default().to_string(),
+            body: pr.body.clone().unwrap_or_default(),
+        })?;
+
+        for review in pr.reviews.nodes.iter() {
+            let review_id = review.database_id.map(|id| id.to_string()).unwrap_or_else(|| review.id.clone());
+            reviews_wtr.serialize(ReviewRow {
+                repo_name: repo_name.clone(),
+                pr_num: pr.number,
+                id: review_id.clone(),
+                user_login: review.author.as_ref().map(|a| a.login.clone()).unwrap_or_default(),
+                state: review.state.clone(),
+                submitted_at: review.submitted_at.clone().unwrap_or_default(),
+                body: review.body.clone(),
+            })?;
+            for comment in review.comments.nodes.iter() {
+                comments_wtr.serialize(ReviewCommentRow {
+                    repo_name: repo_name.clone(),
+                    pr_num: pr.number,
+                    review_id: review_id.clone(),
+                    id: comment.database_id.map(|id| id.to_string()).unwrap_or_else(|| comment.id.clone()),
+                    user_login: comment.author.as_ref().map(|a| a.login.clone()).unwrap_or_default(),
+                    path: comment.path.clone(),
+                    created_at: comment.created_at.clone(),
+                    body: comment.body.clone(),
+                })?;
+            }
+        }
+
+        for request in pr.review_requests.nodes.iter() {
+            if let Some(reviewer) = &request.requested_reviewer {
+                requested_wtr.serialize(RequestedReviewerRow {
+                    repo_name: repo_name.clone(),
+                    pr_num: pr.number,
+                    reviewer_type: reviewer.typename.clone(),
+                    reviewer: reviewer.login.clone().or_else(|| reviewer.name.clone()).unwrap_or_default(),
+                })?;
+            }
+        }
+
+        for c in pr.commits.nodes.iter() {
+            let (author_name, author_email) = match &c.commit.author {
+                Some(a) => (a.name.clone().unwrap_or_default(), a.email.clone().unwrap_or_default()),
+                None => (String::new(), String::new()),
+            };
+            commits_wtr.serialize(PullRequestCommitRow {
+                repo_name: repo_name.clone(),
+                pr_num: pr.number,
+                sha: c.commit.oid.clone(),
+                committed_date: c.commit.committed_date.clone(),
+                author_name,
+                author_email,
+                message_headline: c.commit.message_headline.clone(),
+            })?;
+        }
+    }
+    prs_wtr.finish()?;
+    reviews_wtr.finish()?;
+    comments_wtr.finish()?;
+    requested_wtr.finish()?;
+    commits_wtr.finish()?;
+
+    output::write_table(
+        &stem("pr_metrics"),
+        format,
+        Some(&PullRequestWindowMetrics::default()),
+        compute_window_metrics(&prs),
+    )?;
+
+    info!("Fetched {} pull requests from {}/{}", prs.len(), owner, repo);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pr(json: serde_json::Value) -> PullRequest {
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn sample_prs() -> Vec<PullRequest> {
+        let empty = json!({"totalCount": 0, "nodes": []});
+        vec![
+            pr(json!({
+                "id": "PR_1", "databaseId": 1, "number": 1, "title": "Add feature", "body": null,
+                "state": "MERGED", "createdAt": "2021-03-01T10:00:00Z", "updatedAt": "2021-03-02T10:00:00Z",
+                "closedAt": "2021-04-01T10:00:00Z", "mergedAt": "2021-04-01T10:00:00Z",
+                "author": {"login": "alice"}, "mergedBy": {"login": "bob"},
+                "reviewRequests": empty,
+                "reviews": {"totalCount": 2, "nodes": [
+                    {"id": "R_1", "databaseId": 10, "state": "COMMENTED", "body": "", "submittedAt": "2021-03-01T12:00:00Z",
+                     "author": {"login": "alice"}, "comments": empty},
+                    {"id": "R_2", "databaseId": 11, "state": "APPROVED", "body": "LGTM", "submittedAt": "2021-03-01T14:00:00Z",
+                     "author": {"login": "bob"}, "comments": empty}
+                ]},
+                "commits": empty
+            })),
+            pr(json!({
+                "id": "PR_2", "databaseId": 2, "number": 2, "title": "Fix typo", "body": "typo",
+                "state": "CLOSED", "createdAt": "2021-03-15T00:00:00Z", "updatedAt": "2021-03-16T00:00:00Z",
+                "closedAt": "2021-03-16T00:00:00Z", "mergedAt": null,
+                "author": null, "mergedBy": null,
+                "reviewRequests": empty, "reviews": empty, "commits": empty
+            })),
+        ]
+    }
+
+    #[test]
+    fn test_first_review_ignores_author() {
+        let prs = sample_prs();
+        assert_eq!(prs[0].first_review_at(), Some("2021-03-01T14:00:00Z"));
+        assert_eq!(prs[1].first_review_at(), None);
+    }
+
+    #[test]
+    fn test_pages_queries() {
+        let query = connection_pages_query(Connection::Reviews, &[(7, "Y3Vyc29yOjUw".to_string()), (12, "abc".to_string())]);
+        assert!(query.contains("p7: pullRequest(number: 7)"));
+        assert!(query.contains(r#"reviews(first: 50, after: "Y3Vyc29yOjUw")"#));
+        assert!(query.contains("fragment ReviewFields on PullRequestReview"));
+        assert!(query.contains("fragment ReviewCommentFields on PullRequestReviewComment"));
+        assert!(!query.contains("fragment CommitFields"));
+
+        let query = review_comment_pages_query(&[("PRR_1".to_string(), "abc".to_string())]);
+        assert!(query.contains(r#"r0: node(id: "PRR_1")"#));
+        assert!(query.contains(r#"comments(first: 100, after: "abc")"#));
+    }
+
+    #[test]
+    fn test_extend_pages() {
+        let mut prs = sample_prs();
+        let reviews = &mut prs[0].reviews;
+        reviews.total_count = 3;
+        reviews.page_info = Some(PageInfo { has_next_page: true, end_cursor: Some("c1".to_string()) });
+        assert_eq!(reviews.next_cursor().as_deref(), Some("c1"));
+
+        let page: Nodes<Review> = serde_json::from_value(json!({
+            "totalCount": 3, "pageInfo": {"hasNextPage": false, "endCursor": "c2"},
+            "nodes": [{"id": "R_3", "databaseId": 12, "state": "APPROVED", "body": "", "submittedAt": "2021-03-02T10:00:00Z",
+                       "author": {"login": "carol"}, "comments": {"totalCount": 0, "nodes": []}}]
+        }))
+        .unwrap();
+        reviews.extend(Some(page));
+        assert_eq!((reviews.nodes.len(), reviews.next_cursor(), reviews.is_truncated()), (3, None, false));
+
+        // a page that cannot be fetched stops the paging and leaves the connection truncated
+        let commits = &mut prs[1].commits;
+        commits.total_count = 150;
+        commits.page_info = Some(PageInfo { has_next_page: true, end_cursor: Some("c1".to_string()) });
+        commits.extend(None);
+        assert_eq!((commits.next_cursor(), commits.is_truncated()), (None, true));
+    }
+
+    #[test]
+    fn test_compute_window_metrics() {
+        let metrics = compute_window_metrics(&sample_prs());
+        assert_eq!(metrics.len(), 2);
+
+        let march = &metrics[0];
+        assert_eq!(march.window, "2021-03");
+        assert_eq!(march.prs_opened, 2);
+        assert_eq!(march.prs_merged, 0);
+        assert_eq!(march.prs_closed_unmerged, 1);
+        assert_eq!(march.prs_reviewed, 1);
+        assert_eq!(march.prs_reviewed_ratio, 0.5);
+        assert_eq!(march.distinct_reviewers, 1);
+        assert_eq!(march.reviews, 2);
+        assert_eq!(march.median_hours_to_first_review, 4.0);
+
+        let april = &metrics[1];
+        assert_eq!(april.window, "2021-04");
+        assert_eq!(april.prs_opened, 0);
+        assert_eq!(april.prs_merged, 1);
+        assert_eq!(april.median_hours_to_merge, 31.0 * 24.0);
+    }
+}
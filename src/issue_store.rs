@@ -0,0 +1,139 @@
+// src/issue_store.rs
+//! Local per-repository store of forge issues (with their comments), kept as one JSON issue per line.
+//! Each sync only fetches issues updated since the newest `updated_at` in the store.
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use indexmap::IndexMap;
+use log::info;
+
+use crate::forge::{self, RepoRef};
+use crate::github_issues::Issue;
+
+pub struct IssueStore {
+    path: PathBuf,
+    issues: IndexMap<u32, Issue>,
+}
+
+impl IssueStore {
+    /// Opens the store of `repo` in `folder`, empty if it does not exist yet
+    pub fn open(folder: &str, repo: &RepoRef) -> Result<IssueStore, Box<dyn Error>> {
+        let path = Path::new(folder).join(format!("{}.jsonl", repo.store_name()));
+        let mut issues = IndexMap::new();
+        if path.exists() {
+            let reader = BufReader::new(File::open(&path)?);
+            for line in reader.lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let issue: Issue = serde_json::from_str(&line)?;
+                issues.insert(issue.number, issue);
+            }
+        }
+        Ok(IssueStore { path, issues })
+    }
+
+    /// The most recent `updated_at` of the stored issues
+    pub fn last_updated(&self) -> Option<&str> {
+        self.issues.values().map(|i| i.updated_at.as_str()).max()
+    }
+
+    /// Inserts new issues and replaces stored ones with the same number, returning how many were stored
+    pub fn merge(&mut self, issues: Vec<Issue>) -> usize {
+        let count = issues.len();
+        for issue in issues {
+            self.issues.insert(issue.number, issue);
+        }
+        count
+    }
+
+    /// Writes the store to a temporary file first, so an interrupted save keeps the previous store
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = self.path.with_extension("jsonl.tmp");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        for issue in self.issues.values() {
+            serde_json::to_writer(&mut writer, issue)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    pub fn issue_count(&self) -> usize {
+        self.issues.len()
+    }
+
+    /// The stored issues, ordered by issue number
+    pub fn into_issues(mut self) -> Vec<Issue> {
+        self.issues.sort_keys();
+        self.issues.into_values().collect()
+    }
+}
+
+/// Loads the issues of `repo` from the store in `folder`. Unless `offline` is set, the store is
+/// first updated with the issues changed on the forge since the last sync.
+pub fn load_issues(repo: &RepoRef, folder: &str, offline: bool) -> Result<Vec<Issue>, Box<dyn Error>> {
+    let mut store = IssueStore::open(folder, repo)?;
+    if !offline {
+        let since = store.last_updated().map(|s| s.to_string());
+        let updated = forge::for_repo(repo)?.issues(repo, since.as_deref())?;
+        let count = store.merge(updated);
+        store.save()?;
+        info!(
+            "Synced {} updated issues of {}/{} since {}; the store holds {} issues",
+            count,
+            repo.owner,
+            repo.repo,
+            since.as_deref().unwrap_or("the beginning"),
+            store.issue_count()
+        );
+    }
+    Ok(store.into_issues())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn issue(number: u32, updated_at: &str) -> Issue {
+        serde_json::from_value(json!({
+            "id": format!("I_{}", number), "databaseId": number, "number": number, "title": "t", "body": null,
+            "state": "OPEN", "created_at": "2021-01-01T00:00:00Z", "updated_at": updated_at,
+            "closed_at": null, "state_reason": null, "author": null,
+            "comments": {"pageInfo": {"hasNextPage": false, "endCursor": null}, "nodes": []},
+            "reaction_groups": [], "labels": [], "assignees": [], "milestone": null, "timeline_items": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_merge_save_and_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let repo = RepoRef::parse("https://github.com/apache/hunter").unwrap();
+
+        let mut store = IssueStore::open(folder, &repo).unwrap();
+        assert_eq!(store.last_updated(), None);
+        store.merge(vec![issue(2, "2021-02-01T00:00:00Z"), issue(1, "2021-01-05T00:00:00Z")]);
+        store.save().unwrap();
+
+        let mut store = IssueStore::open(folder, &repo).unwrap();
+        assert_eq!(store.issue_count(), 2);
+        assert_eq!(store.last_updated(), Some("2021-02-01T00:00:00Z"));
+        store.merge(vec![issue(1, "2021-03-01T00:00:00Z")]);
+        assert_eq!(store.issue_count(), 2);
+        assert_eq!(store.last_updated(), Some("2021-03-01T00:00:00Z"));
+
+        let numbers: Vec<u32> = store.into_issues().iter().map(|i| i.number).collect();
+        assert_eq!(numbers, vec![1, 2]);
+    }
+}
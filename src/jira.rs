@@ -0,0 +1,672 @@
+// src/jira.rs
+//! JIRA issue ingestion (e.g. the Apache JIRA at https://issues.apache.org/jira), through the REST API v2
+//! or offline from a JIRA XML (RSS) or JSON search export. Issues and comments are written in the issues
+//! table schema of `github_issues`; issue types, components, fix versions and changelogs go to JIRA tables.
+use std::error::Error;
+use std::fs;
+
+use chrono::DateTime;
+use log::info;
+use reqwest::header::AUTHORIZATION;
+use serde::{Deserialize, Serialize};
+
+use crate::forge::{ForgeComment, ForgeIssue, ForgeUser, RestClient};
+use crate::github_issues::{self, Issue, IssueLinks};
+use crate::output::{OutputFormat, TableWriter};
+
+/// Issues requested per search page; Apache JIRA caps `maxResults` at 100
+const PAGE_SIZE: usize = 100;
+
+const SEARCH_FIELDS: &str = "summary,description,issuetype,status,resolution,priority,reporter,assignee,\
+    created,updated,resolutiondate,labels,components,fixVersions,comment,votes";
+
+/// A JIRA project, parsed from `https://host/(context/)browse/KEY` or `https://host/(context/)projects/KEY`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JiraProject {
+    /// Base URL of the JIRA instance, with its context path (e.g. `https://issues.apache.org/jira`)
+    pub base_url: String,
+    pub key: String,
+}
+
+impl JiraProject {
+    pub fn parse(url: &str) -> Result<JiraProject, Box<dyn Error>> {
+        let trimmed = url.trim().trim_end_matches('/');
+        for marker in ["/browse/", "/projects/"] {
+            if let Some((base, rest)) = trimmed.split_once(marker) {
+                // `browse/HUNTER-12` points at an issue of the HUNTER project
+                let key = rest.split(['/', '?', '-']).next().unwrap_or_default();
+                if !key.is_empty() {
+                    return Ok(JiraProject { base_url: base.to_string(), key: key.to_uppercase() });
+                }
+            }
+        }
+        Err(format!("Invalid JIRA project URL, expected https://host/browse/KEY: {}", url).into())
+    }
+
+    fn api_url(&self) -> String {
+        format!("{}/rest/api/2", self.base_url)
+    }
+
+    fn issue_key(&self, number: u32) -> String {
+        format!("{}-{}", self.key, number)
+    }
+}
+
+impl IssueLinks for JiraProject {
+    fn issue_api_url(&self, number: u32) -> String {
+        format!("{}/issue/{}", self.api_url(), self.issue_key(number))
+    }
+
+    fn comment_api_url(&self, number: u32, comment_id: &str) -> String {
+        format!("{}/comment/{}", self.issue_api_url(number), comment_id)
+    }
+
+    /// JIRA issues carry no commit references
+    fn commit_url(&self, _sha: &str) -> String {
+        String::new()
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchResult {
+    #[serde(default)]
+    total: usize,
+    #[serde(default)]
+    issues: Vec<JiraIssue>,
+}
+
+/// A JSON export is either a saved search result or a plain array of issues
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum JsonExport {
+    Search(SearchResult),
+    Issues(Vec<JiraIssue>),
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JiraIssue {
+    id: String,
+    key: String,
+    fields: Fields,
+    changelog: Option<Changelog>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Fields {
+    #[serde(default)]
+    summary: String,
+    description: Option<String>,
+    issuetype: Option<Named>,
+    status: Option<Status>,
+    resolution: Option<Named>,
+    priority: Option<Named>,
+    reporter: Option<User>,
+    assignee: Option<User>,
+    #[serde(default)]
+    created: String,
+    #[serde(default)]
+    updated: String,
+    resolutiondate: Option<String>,
+    #[serde(default)]
+    labels: Vec<String>,
+    #[serde(default)]
+    components: Vec<Named>,
+    #[serde(default)]
+    fix_versions: Vec<Named>,
+    comment: Option<CommentPage>,
+    votes: Option<Votes>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Named {
+    name: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Status {
+    name: String,
+    status_category: Option<StatusCategory>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct StatusCategory {
+    key: String,
+}
+
+/// JIRA Server identifies users by `name`, JIRA Cloud by `accountId`
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct User {
+    name: Option<String>,
+    account_id: Option<String>,
+    display_name: Option<String>,
+    email_address: Option<String>,
+}
+
+impl User {
+    fn login(&self) -> String {
+        self.name
+            .clone()
+            .or_else(|| self.account_id.clone())
+            .or_else(|| self.display_name.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl From<User> for ForgeUser {
+    fn from(user: User) -> ForgeUser {
+        ForgeUser {
+            login: user.login(),
+            id: None,
+            name: user.display_name,
+            email: user.email_address,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CommentPage {
+    #[serde(default)]
+    comments: Vec<JiraComment>,
+    #[serde(default)]
+    total: usize,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct JiraComment {
+    id: String,
+    author: Option<User>,
+    #[serde(default)]
+    body: String,
+    created: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Changelog {
+    #[serde(default)]
+    histories: Vec<History>,
+    #[serde(default)]
+    total: usize,
+}
+
+/// A page of `/issue/{key}/changelog`
+#[derive(Debug, Default, Deserialize)]
+struct ChangelogPage {
+    #[serde(default)]
+    values: Vec<History>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct History {
+    id: String,
+    author: Option<User>,
+    created: String,
+    #[serde(default)]
+    items: Vec<ChangeItem>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ChangeItem {
+    field: String,
+    #[serde(rename = "fromString")]
+    from: Option<String>,
+    #[serde(rename = "toString")]
+    to: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Votes {
+    votes: u64,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct JiraFieldsRow {
+    issue_key: String,
+    issue_num: u32,
+    issue_type: String,
+    priority: String,
+    status: String,
+    resolution: String,
+    components: String,
+    fix_versions: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct ChangelogRow {
+    issue_key: String,
+    history_id: String,
+    author: String,
+    created_at: String,
+    field: String,
+    from: String,
+    to: String,
+}
+
+impl JiraIssue {
+    /// Resolved issues, or issues in a status of the `done` category, count as closed
+    fn is_closed(&self) -> bool {
+        self.fields.resolution.is_some()
+            || self
+                .fields
+                .status
+                .as_ref()
+                .and_then(|s| s.status_category.as_ref())
+                .is_some_and(|c| c.key == "done")
+    }
+
+    /// Whoever last set the resolution, according to the changelog
+    fn resolved_by(&self) -> Option<String> {
+        self.changelog
+            .as_ref()?
+            .histories
+            .iter()
+            .rev()
+            .filter(|h| h.items.iter().any(|i| i.field == "resolution" && i.to.is_some()))
+            .find_map(|h| h.author.as_ref().map(User::login))
+    }
+
+    fn fields_row(&self) -> JiraFieldsRow {
+        JiraFieldsRow {
+            issue_key: self.key.clone(),
+            issue_num: issue_number(&self.key),
+            issue_type: self.fields.issuetype.as_ref().map(|t| t.name.clone()).unwrap_or_default(),
+            priority: self.fields.priority.as_ref().map(|p| p.name.clone()).unwrap_or_default(),
+            status: self.fields.status.as_ref().map(|s| s.name.clone()).unwrap_or_default(),
+            resolution: self.fields.resolution.as_ref().map(|r| r.name.clone()).unwrap_or_default(),
+            components: join_names(&self.fields.components),
+            fix_versions: join_names(&self.fields.fix_versions),
+        }
+    }
+
+    fn changelog_rows(&self) -> Vec<ChangelogRow> {
+        let histories = self.changelog.iter().flat_map(|c| c.histories.iter());
+        histories
+            .flat_map(|h| {
+                h.items.iter().map(move |item| ChangelogRow {
+                    issue_key: self.key.clone(),
+                    history_id: h.id.clone(),
+                    author: h.author.as_ref().map(User::login).unwrap_or_default(),
+                    created_at: normalize_date(&h.created),
+                    field: item.field.clone(),
+                    from: item.from.clone().unwrap_or_default(),
+                    to: item.to.clone().unwrap_or_default(),
+                })
+            })
+            .collect()
+    }
+}
+
+fn join_names(named: &[Named]) -> String {
+    named.iter().map(|n| n.name.as_str()).collect::<Vec<_>>().join(";")
+}
+
+/// `HUNTER-12` is issue number 12
+fn issue_number(key: &str) -> u32 {
+    key.rsplit('-').next().and_then(|n| n.parse().ok()).unwrap_or(0)
+}
+
+/// REST dates (`2018-01-01T10:00:00.000+0000`) and XML export dates (RFC 2822) as RFC 3339
+fn normalize_date(date: &str) -> String {
+    DateTime::parse_from_rfc3339(date)
+        .or_else(|_| DateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S%.f%z"))
+        .or_else(|_| DateTime::parse_from_rfc2822(date))
+        .map(|d| d.to_rfc3339())
+        .unwrap_or_else(|_| date.to_string())
+}
+
+/// Maps a JIRA issue onto the issue model of the issues CSV. Fix versions become the milestone,
+/// votes become 👍 reactions and the resolution becomes the state reason.
+fn to_issue(issue: JiraIssue) -> Issue {
+    let closed = issue.is_closed();
+    let closed_by = issue.resolved_by();
+    let number = issue_number(&issue.key);
+    let fields = issue.fields;
+    let fix_versions: Vec<String> = fields.fix_versions.into_iter().map(|v| v.name).collect();
+    let comments = fields.comment.map(|c| c.comments).unwrap_or_default();
+    let forge_issue = ForgeIssue {
+        id: issue.id.parse().unwrap_or_default(),
+        number,
+        title: fields.summary,
+        body: fields.description,
+        state: if closed { "CLOSED" } else { "OPEN" }.to_string(),
+        created_at: normalize_date(&fields.created),
+        updated_at: normalize_date(&fields.updated),
+        closed_at: fields.resolutiondate.as_deref().filter(|_| closed).map(normalize_date),
+        closed_by,
+        author: fields.reporter.map(ForgeUser::from),
+        labels: fields.labels,
+        assignees: fields.assignee.map(|u| u.login()).into_iter().collect(),
+        milestone: if fix_versions.is_empty() { None } else { Some(fix_versions.join(";")) },
+        reactions: fields
+            .votes
+            .filter(|v| v.votes > 0)
+            .map(|v| ("THUMBS_UP".to_string(), v.votes))
+            .into_iter()
+            .collect(),
+        comments: comments
+            .into_iter()
+            .map(|c| ForgeComment {
+                id: c.id.parse().unwrap_or_default(),
+                body: c.body,
+                created_at: normalize_date(&c.created),
+                author: c.author.map(ForgeUser::from),
+            })
+            .collect(),
+    };
+    let mut issue = Issue::from(forge_issue);
+    issue.state_reason = fields.resolution.map(|r| r.name);
+    issue
+}
+
+fn rest_client(project: &JiraProject) -> Result<RestClient, Box<dyn Error>> {
+    let auth = std::env::var("JIRA_TOKEN")
+        .ok()
+        .map(|token| (AUTHORIZATION, format!("Bearer {}", token)));
+    RestClient::new(&project.api_url(), auth, "maxResults", PAGE_SIZE)
+}
+
+/// Fetches all issues of the project with their comments and changelogs
+fn fetch_issues(project: &JiraProject) -> Result<Vec<JiraIssue>, Box<dyn Error>> {
+    let client = rest_client(project)?;
+    let jql = format!("project = \"{}\" ORDER BY created ASC", project.key);
+    let mut all: Vec<JiraIssue> = Vec::new();
+    loop {
+        let query = [
+            ("jql", jql.clone()),
+            ("startAt", all.len().to_string()),
+            ("maxResults", PAGE_SIZE.to_string()),
+            ("fields", SEARCH_FIELDS.to_string()),
+            ("expand", "changelog".to_string()),
+        ];
+        let page: SearchResult = client.get("/search", &query)?;
+        let count = page.issues.len();
+        all.extend(page.issues);
+        info!("Fetched {}/{} JIRA issues of {}", all.len(), page.total, project.key);
+        if count == 0 || all.len() >= page.total {
+            break;
+        }
+    }
+
+    for issue in all.iter_mut() {
+        fetch_remaining_comments(&client, issue)?;
+        fetch_remaining_changelog(&client, issue)?;
+    }
+    Ok(all)
+}
+
+/// Search results embed a limited number of comments; the rest are paged from the issue's comment endpoint
+fn fetch_remaining_comments(client: &RestClient, issue: &mut JiraIssue) -> Result<(), Box<dyn Error>> {
+    let page = match issue.fields.comment.as_mut() {
+        Some(page) => page,
+        None => return Ok(()),
+    };
+    while page.comments.len() < page.total {
+        let query = [("startAt", page.comments.len().to_string()), ("maxResults", PAGE_SIZE.to_string())];
+        let next: CommentPage = client.get(&format!("/issue/{}/comment", issue.key), &query)?;
+        if next.comments.is_empty() {
+            break;
+        }
+        page.comments.extend(next.comments);
+    }
+    Ok(())
+}
+
+/// Search results embed a limited number of changelog entries as well; the rest are paged from the issue's
+/// changelog endpoint
+fn fetch_remaining_changelog(client: &RestClient, issue: &mut JiraIssue) -> Result<(), Box<dyn Error>> {
+    let changelog = match issue.changelog.as_mut() {
+        Some(changelog) => changelog,
+        None => return Ok(()),
+    };
+    while changelog.histories.len() < changelog.total {
+        let query = [("startAt", changelog.histories.len().to_string()), ("maxResults", PAGE_SIZE.to_string())];
+        let next: ChangelogPage = client.get(&format!("/issue/{}/changelog", issue.key), &query)?;
+        if next.values.is_empty() {
+            break;
+        }
+        changelog.histories.extend(next.values);
+    }
+    Ok(())
+}
+
+/// Reads a JIRA search export: the JSON of a REST search (or an array of its issues),
+/// or the XML (RSS) export of an issue navigator search
+fn read_export(path: &str) -> Result<Vec<JiraIssue>, Box<dyn Error>> {
+    let content = fs::read_to_string(path)?;
+    if content.trim_start().starts_with('<') {
+        return parse_xml_export(&content);
+    }
+    Ok(match serde_json::from_str(&content)? {
+        JsonExport::Search(search) => search.issues,
+        JsonExport::Issues(issues) => issues,
+    })
+}
+
+/// The XML export has one `<item>` per issue; it carries no changelog
+fn parse_xml_export(content: &str) -> Result<Vec<JiraIssue>, Box<dyn Error>> {
+    let doc = roxmltree::Document::parse(content)?;
+    let mut issues = Vec::new();
+    for item in doc.descendants().filter(|n| n.has_tag_name("item")) {
+        let child = |tag: &str| item.children().find(|n| n.has_tag_name(tag));
+        let text = |tag: &str| child(tag).and_then(|n| n.text()).map(|t| t.trim().to_string());
+        let named = |tag: &str| text(tag).map(|name| Named { name });
+        let all_named = |tag: &str| {
+            item.children()
+                .filter(|n| n.has_tag_name(tag))
+                .filter_map(|n| n.text())
+                .map(|name| Named { name: name.trim().to_string() })
+                .collect::<Vec<_>>()
+        };
+        let user = |tag: &str| {
+            child(tag).and_then(|n| {
+                // Unassigned issues have `username="-1"`
+                let login = n
+                    .attribute("username")
+                    .or_else(|| n.attribute("accountid"))
+                    .filter(|login| *login != "-1")?;
+                Some(User {
+                    name: Some(login.to_string()),
+                    display_name: n.text().map(|t| t.trim().to_string()),
+                    ..User::default()
+                })
+            })
+        };
+        let key = child("key");
+        let comments: Vec<JiraComment> = child("comments")
+            .map(|c| {
+                c.children()
+                    .filter(|n| n.has_tag_name("comment"))
+                    .map(|n| JiraComment {
+                        id: n.attribute("id").unwrap_or_default().to_string(),
+                        author: n.attribute("author").map(|login| User {
+                            name: Some(login.to_string()),
+                            ..User::default()
+                        }),
+                        body: n.text().unwrap_or_default().trim().to_string(),
+                        created: n.attribute("created").unwrap_or_default().to_string(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let status_category = child("statusCategory").and_then(|n| n.attribute("key")).map(|key| StatusCategory {
+            key: key.to_string(),
+        });
+        issues.push(JiraIssue {
+            id: key.and_then(|k| k.attribute("id")).unwrap_or_default().to_string(),
+            key: key.and_then(|k| k.text()).unwrap_or_default().trim().to_string(),
+            fields: Fields {
+                summary: text("summary").unwrap_or_default(),
+                description: text("description"),
+                issuetype: named("type"),
+                status: text("status").map(|name| Status { name, status_category }),
+                resolution: named("resolution").filter(|r| r.name != "Unresolved"),
+                priority: named("priority"),
+                reporter: user("reporter"),
+                assignee: user("assignee"),
+                created: text("created").unwrap_or_default(),
+                updated: text("updated").unwrap_or_default(),
+                resolutiondate: text("resolved"),
+                labels: child("labels")
+                    .map(|l| {
+                        l.children()
+                            .filter(|n| n.has_tag_name("label"))
+                            .filter_map(|n| n.text())
+                            .map(|t| t.trim().to_string())
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                components: all_named("component"),
+                fix_versions: all_named("fixVersion"),
+                comment: Some(CommentPage { total: comments.len(), comments }),
+                votes: text("votes").and_then(|v| v.parse().ok()).map(|votes| Votes { votes }),
+            },
+            changelog: None,
+        });
+    }
+    Ok(issues)
+}
+
+/// Writes the issues of a JIRA project to the `{output_folder}/{KEY}_issues` table with the side files of the
+/// GitHub issue export, plus `{KEY}_jira_fields` (type, priority, status, resolution, components and
+/// fix versions) and `{KEY}_jira_changelog`, all in `format`. The issues are read from `export` when given,
+/// otherwise fetched from the JIRA REST API.
+pub fn fetch_jira_issues_csv(
+    project: &JiraProject,
+    output_folder: &str,
+    export: Option<&str>,
+    format: OutputFormat,
+    sentiment: bool,
+) -> Result<(), Box<dyn Error>> {
+    let jira_issues = match export {
+        Some(path) => read_export(path)?,
+        None => fetch_issues(project)?,
+    };
+
+    let mut fields_wtr = TableWriter::create(&format!("{}/{}_jira_fields", output_folder, project.key), format)?
+        .with_template(&JiraFieldsRow::default())?;
+    let mut changelog_wtr = TableWriter::create(&format!("{}/{}_jira_changelog", output_folder, project.key), format)?
+        .with_template(&ChangelogRow::default())?;
+    let mut issues = Vec::with_capacity(jira_issues.len());
+    for issue in jira_issues {
+        fields_wtr.serialize(issue.fields_row())?;
+        for row in issue.changelog_rows() {
+            changelog_wtr.serialize(row)?;
+        }
+        issues.push(to_issue(issue));
+    }
+    fields_wtr.finish()?;
+    changelog_wtr.finish()?;
+
+    let output_stem = format!("{}/{}_issues", output_folder, project.key);
+    github_issues::write_issues_csv(issues, project, &project.key, &output_stem, format, sentiment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_project_url() {
+        let project = JiraProject::parse("https://issues.apache.org/jira/browse/HUNTER-12").unwrap();
+        assert_eq!(project.base_url, "https://issues.apache.org/jira");
+        assert_eq!(project.key, "HUNTER");
+        assert_eq!(project.issue_api_url(3), "https://issues.apache.org/jira/rest/api/2/issue/HUNTER-3");
+        let project = JiraProject::parse("https://jira.example.org/projects/abc/issues").unwrap();
+        assert_eq!(project.key, "ABC");
+        assert!(JiraProject::parse("https://issues.apache.org/jira").is_err());
+    }
+
+    #[test]
+    fn test_rest_issue_mapping() {
+        let issue: JiraIssue = serde_json::from_value(json!({
+            "id": "1001", "key": "HUNTER-7",
+            "fields": {
+                "summary": "Crash", "description": null, "issuetype": {"name": "Bug"},
+                "status": {"name": "Resolved", "statusCategory": {"key": "done"}},
+                "resolution": {"name": "Fixed"}, "reporter": {"name": "alice", "displayName": "Alice"},
+                "assignee": {"name": "bob"}, "created": "2018-01-01T10:00:00.000+0000",
+                "updated": "2018-01-03T10:00:00.000+0000", "resolutiondate": "2018-01-02T10:00:00.000+0000",
+                "labels": ["crash"], "components": [{"name": "core"}], "fixVersions": [{"name": "1.0"}],
+                "comment": {"total": 1, "comments": [
+                    {"id": "5", "author": {"name": "bob"}, "body": "On it", "created": "2018-01-01T12:00:00.000+0000"}
+                ]},
+                "votes": {"votes": 2}
+            },
+            "changelog": {"total": 1, "histories": [
+                {"id": "9", "author": {"name": "bob"}, "created": "2018-01-02T10:00:00.000+0000",
+                 "items": [{"field": "resolution", "fromString": null, "toString": "Fixed"}]}
+            ]}
+        }))
+        .unwrap();
+        let fields = issue.fields_row();
+        assert_eq!((fields.issue_num, fields.components.as_str()), (7, "core"));
+        assert_eq!(issue.changelog_rows()[0].to, "Fixed");
+
+        let issue = to_issue(issue);
+        assert_eq!(issue.state, "CLOSED");
+        assert_eq!(issue.created_at, "2018-01-01T10:00:00+00:00");
+        assert_eq!(issue.closed_at.as_deref(), Some("2018-01-02T10:00:00+00:00"));
+        assert_eq!(issue.state_reason.as_deref(), Some("Fixed"));
+        assert_eq!(issue.milestone.as_deref(), Some("1.0"));
+        assert_eq!(issue.assignees, vec!["bob"]);
+        let metrics = github_issues::compute_issue_window_metrics(&[issue]);
+        assert_eq!(metrics[0].closed_by_non_author, 1);
+    }
+
+    #[test]
+    fn test_parse_xml_export() {
+        let xml = r#"<rss version="0.92"><channel><title>ASF JIRA</title>
+            <item>
+                <title>[HUNTER-3] Docs</title>
+                <key id="2002">HUNTER-3</key>
+                <summary>Docs</summary>
+                <type id="4">Improvement</type>
+                <status id="1">Open</status>
+                <statusCategory id="2" key="new" colorName="blue-gray"/>
+                <resolution id="-1">Unresolved</resolution>
+                <assignee username="-1">Unassigned</assignee>
+                <reporter username="alice">Alice</reporter>
+                <labels><label>docs</label></labels>
+                <created>Mon, 1 Jan 2018 10:00:00 +0000</created>
+                <updated>Tue, 2 Jan 2018 10:00:00 +0000</updated>
+                <component>site</component>
+                <votes>0</votes>
+                <comments>
+                    <comment id="11" author="bob" created="Mon, 1 Jan 2018 11:00:00 +0000">&lt;p&gt;Agreed&lt;/p&gt;</comment>
+                </comments>
+            </item>
+        </channel></rss>"#;
+        let issues = parse_xml_export(xml).unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].fields_row().components, "site");
+        let issue = to_issue(issues.into_iter().next().unwrap());
+        assert_eq!(issue.number, 3);
+        assert_eq!(issue.state, "OPEN");
+        assert_eq!(issue.state_reason, None);
+        assert_eq!(issue.labels, vec!["docs"]);
+        let edges = github_issues::compute_issue_network(&[issue]);
+        assert_eq!((edges[0].source.as_str(), edges[0].target.as_str()), ("bob", "alice"));
+    }
+
+    #[test]
+    fn test_fetch_remaining_changelog() {
+        let mut issue: JiraIssue = serde_json::from_value(json!({
+            "id": "1001", "key": "HUNTER-7", "fields": {},
+            "changelog": {"total": 3, "histories": [{"id": "1", "created": "2018-01-02T10:00:00.000+0000"}]}
+        }))
+        .unwrap();
+        let page = r#"{"startAt": 1, "total": 3, "isLast": true, "values": [
+            {"id": "2", "created": "2018-01-03T10:00:00.000+0000"}, {"id": "3", "created": "2018-01-04T10:00:00.000+0000"}
+        ]}"#;
+        let url = crate::github_client::tests::mock_server(vec![("200 OK", "", page)]);
+        let client = RestClient::new(&url, None, "maxResults", PAGE_SIZE).unwrap();
+        fetch_remaining_changelog(&client, &mut issue).unwrap();
+        let ids: Vec<_> = issue.changelog.unwrap().histories.into_iter().map(|h| h.id).collect();
+        assert_eq!(ids, vec!["1", "2", "3"]);
+    }
+}
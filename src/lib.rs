@@ -0,0 +1,86 @@
+// src/lib.rs
+//! The miner as a library. `Miner` runs the analysis of a local Git repository without a command line; the
+//! modules are public for tools that need the individual analyses, and the `miner` binary is a command line
+//! over them.
+use git2::Repository;
+use std::path::Path;
+
+pub mod activity_metrics;
+mod api;
+mod args;
+pub mod cli;
+pub mod clone_cache;
+pub mod code_analysis;
+pub mod commit_metrics_clone;
+pub mod commit_metrics_graphql;
+pub mod commits_metrics;
+pub mod database;
+pub mod dev_stats;
+pub mod diagnostics;
+pub mod email_content;
+pub mod emails;
+pub mod forge;
+pub mod forge_gitea;
+pub mod forge_gitlab;
+pub mod github_client;
+pub mod github_issues;
+pub mod github_pull_requests;
+pub mod issue_store;
+pub mod jira;
+pub mod metrics;
+pub mod online_analysis;
+pub mod output;
+pub mod pre_post_incubation_analysis;
+pub mod progress;
+pub mod project;
+pub mod release_metrics;
+pub mod repo;
+pub mod sentiment;
+pub mod sokrates_metrics;
+pub mod statistics;
+#[cfg(test)]
+mod test_repo;
+pub mod tokei_metrics;
+pub mod utils;
+pub mod windows;
+
+pub use api::{Analysis, Miner};
+pub use args::Args;
+pub use dev_stats::CommitFileMetrics;
+pub use metrics::{ImputationPolicy, Metrics};
+pub use sokrates_metrics::CodeAnalyzer;
+pub use windows::WindowScheme;
+
+/// Removes what an earlier Sokrates run left in the working copy; a bare repository has none
+pub fn remove_sokrates_temp(repo: &Repository) {
+    sokrates_metrics::sweep_stale_scratches(repo);
+    let Some(workdir) = repo.workdir().map(Path::to_path_buf) else {
+        return;
+    };
+    std::fs::remove_dir_all(workdir.join("_sokrates")).ok();
+    std::fs::remove_file(workdir.join("git-history.txt")).ok();
+}
+
+/// The java command, from `JAVA_HOME` if set
+pub fn java_path() -> String {
+    let java_path = match std::env::var("JAVA_HOME") {
+        Ok(p) => {
+            if std::env::consts::OS == "windows" {
+                log::info!("OS detected: Windows");
+                format!("{}\\bin\\java", p)
+            } else if std::env::consts::OS == "linux" {
+                log::info!("OS detected: Linux");
+                if p.ends_with("/") {
+                    format!("{}java", p)
+                } else {
+                    format!("{}/java", p)
+                }
+            } else {
+                log::info!("OS is different than Windows or Linux, defaulting to command java. If this command is not available in your system, you have to install java and make it accessible");
+                "java".to_string()
+            }
+        }
+        Err(_e) => "java".to_string(),
+    };
+    java_path
+}
@@ -10,170 +10,30 @@ use rayon::iter::{ParallelBridge, ParallelIterator};
 
 use std::sync::{Arc, RwLock};
 use std::time::Duration;
+use structopt::clap;
 use structopt::StructOpt;
 
 // Load .env file to bring in environment variables (like GITHUB_TOKEN)
 use dotenv::dotenv;
 
-mod commits_metrics;
-mod commit_metrics_clone;    // NEW MODULE for clone-from-online repo analysis
-mod commit_metrics_graphql;   // NEW MODULE for GraphQL-based analysis
-mod dev_stats;
-mod emails;
-mod github_issues;
-mod metrics;
-mod pre_post_incubation_analysis;
-mod project;
-mod repo;
-mod sokrates_metrics;
-mod statistics;
-mod tokei_metrics;
-mod utils;
-
-use crate::dev_stats::DevStats;
-use crate::project::Project;
-use crate::repo::*;
-use crate::statistics::*;
-use crate::utils::*;
-use pre_post_incubation_analysis::pre_post_analysis;
-use github_issues::fetch_issues_with_comments_csv;
-
-#[derive(StructOpt)]
-pub struct Args {
-    // Existing flags
-
-    #[structopt(name = "force-full-analysis", long)]
-    /// Force to run a full analysis for those projects that do not have pre incubation commits. So we will only have during and post incubation analysis
-    flag_force_full_analysis: bool,
-
-    #[structopt(name = "full-analysis", long)]
-    /// Run a full analysis - pre/during/post incubation. This only runs on repositories that have previous/post incubation period commits
-    flag_full_analysis: bool,
-
-    #[structopt(name = "threads", long)]
-    /// Number of threads
-    flag_threads: Option<usize>,
-
-    #[structopt(name = "download-emails", long)]
-    /// Download all projects' emails
-    flag_download_emails: bool,
-
-    #[structopt(name = "project", long)]
-    /// Only parse given project
-    flag_parse_single_project: Option<String>,
-
-    #[structopt(name = "list-projects", long)]
-    /// Only show projects
-    flag_list_projects: bool,
-
-    #[structopt(name = "skip-tokei", long)]
-    /// Skip tokei & folder analysis
-    flag_skip_tokei: bool,
-
-    #[structopt(name = "skip-sokrates", long)]
-    /// Skip sokrates analysis
-    flag_skip_sokrates: bool,
-
-    #[structopt(name = "skip-emails", long)]
-    /// Skip email analysis
-    flag_skip_email_analysis: bool,
-
-    #[structopt(name = "commit-messages", long)]
-    /// Extract commit messages
-    flag_commit_messages: bool,
-
-    #[structopt(name = "missing-emails", long)]
-    /// Check for any missing email archives
-    flag_missing_emails: bool,
-
-    #[structopt(name = "supported-languages", long)]
-    /// Print supported languages
-    flag_print_supported_languages: bool,
-
-    #[structopt(name = "restrict-languages", long)]
-    /// Restrict supported languages
-    flag_restrict_languages: bool,
-
-    #[structopt(name = "manual-test", long)]
-    /// Manual test project
-    flag_manual_test_project: Option<String>,
-
-    #[structopt(name = "output-folder", long)]
-    /// Set output folder, otherwise default is data
-    flag_output_folder: Option<String>,
-
-    #[structopt(name = "metadata-filepath", long)]
-    /// Path to project's metadata
-    flag_metadata_filepath: Option<String>,
-
-    #[structopt(name = "commit-devs-files", long)]
-    /// Return a list of commits and the files changed in each file together with who changed the file
-    flag_commit_devs_files: bool,
-
-    #[structopt(name = "time-window", long)]
-    /// Instead of analyzing by default incubation-months (start-date until end of month, and then monthly basis),
-    /// analyze per a time window provided by the user. For example time-window=10, each incubation month will be 10 days
-    flag_time_window: Option<i64>,
-
-    #[structopt(name = "incubation-dates", long)]
-    /// Print for each incubation month, the start and end date for time window projects
-    flag_print_incubation_dates: bool,
-
-    #[structopt(name = "ignore-start-end-date", long)]
-    /// Ignore the start and end date from the Excel sheet input, and run from the first to the last commit in the project
-    flag_ignore_start_end_dates: bool,
-
-    #[structopt(name = "ignore-commit-message", long)]
-    /// Use this option together with the commit-devs option, to ignore the commit messages and not send them to output
-    flag_ignore_commit_message: bool,
-
-    #[structopt(name = "git-folder", long)]
-    /// Use this option to provide a git folder with the projects. You likely also want to use the flag ignore start end dates
-    flag_git_folder: Option<String>,
-
-    #[structopt(name = "fetch-github-issues", long)]
-    /// Fetch GitHub issues and comments for projects or a single repository
-    flag_fetch_github_issues: bool,
-
-    #[structopt(name = "github-output-folder", long)]
-    /// Specify the output folder for GitHub issues CSV file
-    flag_github_output_folder: Option<String>,
-
-    #[structopt(name = "github-url", long)]
-    /// GitHub repository URL to fetch issues from (e.g., https://github.com/apache/hunter.git)
-    flag_github_url: Option<String>,
-
-    // ==== NEW FLAGS FOR ONLINE COMMIT ANALYSIS ====
-    #[structopt(name = "git-online-url", long)]
-    /// Provide an online Git repository URL (.git) to clone and analyze commits
-    flag_git_online_url: Option<String>,
-
-    #[structopt(name = "commit-graphql", long)]
-    /// Use GraphQL API to fetch commit metrics for the online repository instead of cloning
-    flag_commit_graphql: bool,
-
-    #[structopt(name = "online-start-date", long)]
-    /// Start date for online repository analysis (format YYYY-MM-DD)
-    flag_online_start_date: Option<String>,
-
-    #[structopt(name = "online-end-date", long)]
-    /// End date for online repository analysis (format YYYY-MM-DD)
-    flag_online_end_date: Option<String>,
-
-    #[structopt(name = "online-status", long)]
-    /// Status for online repository analysis (e.g., graduated, retired)
-    flag_online_status: Option<String>,
-
-    // ==== NEW FLAG: Developer statistics grouped output ====
-    #[structopt(name = "dev-stats-grouped", long)]
-    /// If set, write separate CSV files grouped by developer (per incubation month)
-    flag_dev_stats_grouped: bool,
-
-    // ==== NEW FLAG: Issue statistics grouped output ====
-    #[structopt(name = "issue-stats-grouped", long)]
-    /// If set, write separate CSV files grouped by developer (per month) for issues and comments
-    flag_issue_stats_grouped: bool,
-}
+use miner::dev_stats::DevStats;
+use miner::project::Project;
+use miner::repo::*;
+use miner::statistics::*;
+use miner::utils::*;
+use miner::pre_post_incubation_analysis::pre_post_analysis;
+use miner::github_issues::fetch_issues_with_comments_csv;
+use miner::forge::RepoRef;
+use miner::cli::Mode;
+use miner::tokei_metrics::LanguageRow;
+use miner::diagnostics::{self, Diagnostics};
+use miner::{
+    activity_metrics, database, email_content, github_issues, github_pull_requests, java_path, jira,
+    online_analysis, output, progress, release_metrics, remove_sokrates_temp, sentiment, utils, windows, Args,
+};
+
+const PROJECTS_METADATA: &str = "../../apache-projects.xlsx";
+const PODLINGS_METADATA: &str = "../../projects-info-from-podlings-xml-extra-metadata.xlsx";
 
 fn list_projects(metadata_filepath: &str) -> indexmap::IndexSet<Project> {
     let path = metadata_filepath;
@@ -223,30 +83,53 @@ fn list_projects(metadata_filepath: &str) -> indexmap::IndexSet<Project> {
 }
 
 fn print_incubation_dates(projects: IndexSet<Project>, args: &Args) {
-    let time_window = if let Some(time_window) = args.flag_time_window {
-        time_window
-    } else {
-        log::error!("The incubation dates flag requires time-window flag specified");
-        panic!(
-            "Error. Check the logs. The incubation dates flag requires time-window flag specified"
-        );
-    };
+    let scheme = windows::WindowScheme::from_args(args);
     let mut results = Vec::<String>::new();
 
     results.push("project, status, start_date, end_date, incubation_month, incubation_month_start, incubation_month_end".to_string());
     projects.into_iter().for_each(|project| {
-        let incubation_months = utils::parse_date_to_inc_months_with_time_window(
-            &project.start_date,
-            &project.end_date,
-            time_window,
-        );
+        // the commit dates replace the metadata dates with --ignore-start-end-date, and release windows end at the
+        // commit dates of the project's tags
+        let (start_date, end_date, release_dates) =
+            if args.flag_ignore_start_end_dates || scheme == windows::WindowScheme::Releases {
+                let git_repo = match Repository::open(&project.path) {
+                    Ok(git_repo) => git_repo,
+                    Err(e) => {
+                        log::error!("{} - cannot open the repository: {}", project.name, e);
+                        return;
+                    }
+                };
+                let release_dates = if scheme == windows::WindowScheme::Releases {
+                    release_metrics::tag_dates(&git_repo)
+                } else {
+                    vec![]
+                };
+                let dates =
+                    match Repo::new(&git_repo, &project.name, &project.start_date, &project.end_date, &project.status, args) {
+                        Ok(repo) => (repo.start_date.to_string(), repo.end_date.to_string(), release_dates),
+                        Err(e) => {
+                            log::error!("{} - cannot find the project's dates: {}", project.name, e);
+                            return;
+                        }
+                    };
+                dates
+            } else {
+                (project.start_date.clone(), project.end_date.clone(), vec![])
+            };
+        let incubation_months = match scheme.windows(&start_date, &end_date, &release_dates) {
+            Ok(incubation_months) => incubation_months,
+            Err(e) => {
+                log::error!("{} - {}", project.name, e);
+                return;
+            }
+        };
         for (month, data) in incubation_months {
             results.push(format!(
                 "{}, {}, {}, {}, {}, {}, {}",
                 project.name,
                 project.status,
-                project.start_date,
-                project.end_date,
+                start_date,
+                end_date,
                 month,
                 data.start_date,
                 data.end_date
@@ -256,10 +139,7 @@ fn print_incubation_dates(projects: IndexSet<Project>, args: &Args) {
 
     let mut writer = csv::WriterBuilder::default()
         .has_headers(true)
-        .from_path(format!(
-            "incubation-dates-{}days-time-window.csv",
-            time_window
-        ))
+        .from_path(format!("incubation-dates-{}.csv", scheme.label()))
         .unwrap();
 
     for r in results {
@@ -281,14 +161,13 @@ fn _show_branch(repo: &Repository, repo_name: &str) -> Result<(), Error> {
     Ok(())
 }
 
-fn manual_test_project() {
+fn manual_test_project(args: &Args) {
     let writer = Arc::new(RwLock::new(
         csv::WriterBuilder::default()
             .has_headers(true)
             .from_path("test.csv")
             .unwrap(),
     ));
-    let args = Args::from_args();
     let path = args.flag_manual_test_project.clone();
     let p = Project {
         name: "test".to_string(),
@@ -306,11 +185,11 @@ fn manual_test_project() {
             p.start_date.as_str(),
             p.end_date.as_str(),
             p.status.as_str(),
-            &args,
+            args,
         )
         .unwrap();
 
-        repo.checkout_master_main_trunk(&args);
+        repo.checkout_master_main_trunk(args);
         let mut stats = Stats::new(
             p.name.as_str(),
             &p.start_date,
@@ -318,7 +197,7 @@ fn manual_test_project() {
             &p.status,
             &java_path,
         );
-        let metrics = stats.compute_statistics(&mut repo, &args);
+        let metrics = stats.compute_statistics(&mut repo, args);
         if let Ok(metrics) = metrics {
             let writer = writer.clone();
             let mut guard = writer.write().expect("Unable to lock");
@@ -387,21 +266,7 @@ fn analyze_test_project(project: String, metadata_filepath: &str) {
     });
 }
 
-fn remove_sokrates_temp(repo: &Repository) {
-    std::fs::remove_dir_all(format!(
-        "{}/_sokrates",
-        repo.path().parent().unwrap().to_str().unwrap()
-    ))
-    .ok();
-    std::fs::remove_file(format!(
-        "{}/git-history.txt",
-        repo.path().parent().unwrap().to_str().unwrap()
-    ))
-    .ok();
-}
-
-fn check_for_missing_emails(args: &Args, metadata_filepath: &str) {
-    let projects = list_projects(metadata_filepath);
+fn check_for_missing_emails(projects: &IndexSet<Project>, args: &Args) {
     let emails_folder = "../../projects/emails";
 
     projects.iter().par_bridge().for_each(|p| {
@@ -432,6 +297,7 @@ fn check_for_missing_emails(args: &Args, metadata_filepath: &str) {
                             repo.project.to_lowercase(),
                             month
                         );
+                        diagnostics::error(repo.project, "emails", &format!("email archive {} is empty", path));
                     }
                     if !email_path.exists() {
                         log::error!(
@@ -440,6 +306,7 @@ fn check_for_missing_emails(args: &Args, metadata_filepath: &str) {
                             repo.project.to_lowercase(),
                             month
                         );
+                        diagnostics::error(repo.project, "emails", &format!("email archive {} does not exist", path));
                     }
                 }
             }
@@ -447,12 +314,66 @@ fn check_for_missing_emails(args: &Args, metadata_filepath: &str) {
     });
 }
 
-fn commits_messages(data_folder_path: &str, args: &Args, metadata_filepath: &str) {
-    let projects = list_projects(metadata_filepath);
-    let mut writer = csv::WriterBuilder::default()
-        .has_headers(true)
-        .from_path(format!("{}/commit-messages.csv", data_folder_path))
-        .unwrap();
+fn export_email_bodies(projects: &IndexSet<Project>, args: &Args, data_folder_path: &str) {
+    let emails_folder = "../../projects/emails";
+
+    projects.iter().par_bridge().for_each(|p| {
+        let git_repo = Repository::open(p.path.as_str());
+        if let Ok(git_repo) = git_repo {
+            let repo = Repo::new(
+                &git_repo,
+                p.name.as_str(),
+                p.start_date.as_str(),
+                p.end_date.as_str(),
+                p.status.as_str(),
+                &args,
+            );
+            if let Ok(repo) = repo {
+                let mbox_paths = repo
+                    .dates_to_months()
+                    .values()
+                    .map(|month| {
+                        format!(
+                            "{}/{}-dev-{}.mbox",
+                            emails_folder,
+                            repo.project.to_lowercase(),
+                            month
+                        )
+                    })
+                    .filter(|path| std::path::Path::new(path).exists())
+                    .collect::<Vec<_>>();
+                let emails = email_content::cleaned_emails(&p.name, &mbox_paths);
+                let output_stem = format!("{}/{}-email-bodies", data_folder_path, p.name);
+                match email_content::export_cleaned_bodies(&emails, &output_stem, args.flag_format.unwrap_or_default()) {
+                    Ok(()) => info!("{} - exported {} cleaned email bodies", p.name, emails.len()),
+                    Err(e) => error!("{} - cannot export cleaned email bodies: {}", p.name, e),
+                }
+                if let Err(e) = output::write_table(
+                    &format!("{}/{}-email-sentiment-developers", data_folder_path, p.name),
+                    args.flag_format.unwrap_or_default(),
+                    Some(&sentiment::DeveloperSentiment::default()),
+                    email_content::sentiment_by_developer(&emails),
+                ) {
+                    error!("{} - cannot write the email sentiment per developer: {}", p.name, e);
+                }
+                if let Err(e) = database::store_emails(&p.name, &emails) {
+                    error!("{} - cannot store the emails in the database: {}", p.name, e);
+                }
+            }
+        } else {
+            error!("{} cannot find the git repository at {}", p.name.as_str(), p.path.as_str());
+        }
+    });
+}
+
+fn commits_messages(projects: &IndexSet<Project>, data_folder_path: &str, args: &Args) {
+    let mut writer = output::TableWriter::create(
+        &format!("{}/commit-messages", data_folder_path),
+        args.flag_format.unwrap_or_default(),
+    )
+    .unwrap();
+    let analyzer = sentiment::SentimentAnalyzer::global();
+    let mut scores = Vec::<sentiment::MessageScore>::new();
 
     projects.iter().for_each(|p| {
         let git_repo = Repository::open(p.path.as_str());
@@ -477,6 +398,17 @@ fn commits_messages(data_folder_path: &str, args: &Args, metadata_filepath: &str
                             commits.len()
                         );
                         for c in commits {
+                            if args.flag_sentiment {
+                                scores.push(sentiment::MessageScore {
+                                    project: p.name.to_string(),
+                                    source: "commit".to_string(),
+                                    id: c.id().to_string(),
+                                    author: c.author().email().unwrap_or("").to_string(),
+                                    window: month.to_string(),
+                                    date: convert_time(&c.time()).to_rfc3339(),
+                                    scores: analyzer.score(c.message().unwrap_or("")),
+                                });
+                            }
                             match writer.serialize(CommitMessage {
                                 project: p.name.to_string(),
                                 status: p.status.to_string(),
@@ -495,29 +427,20 @@ fn commits_messages(data_folder_path: &str, args: &Args, metadata_filepath: &str
             }
         }
     });
-}
+    if let Err(e) = writer.finish() {
+        error!("Cannot write the commit messages: {}", e);
+    }
 
-pub fn java_path() -> String {
-    let java_path = match std::env::var("JAVA_HOME") {
-        Ok(p) => {
-            if std::env::consts::OS == "windows" {
-                log::info!("OS detected: Windows");
-                format!("{}\\bin\\java", p)
-            } else if std::env::consts::OS == "linux" {
-                log::info!("OS detected: Linux");
-                if p.ends_with("/") {
-                    format!("{}java", p)
-                } else {
-                    format!("{}/java", p)
-                }
-            } else {
-                log::info!("OS is different than Windows or Linux, defaulting to command java. If this command is not available in your system, you have to install java and make it accessible");
-                "java".to_string()
-            }
+    if args.flag_sentiment {
+        if let Err(e) = sentiment::write_scores(
+            &scores,
+            &format!("{}/commit-messages-sentiment", data_folder_path),
+            &format!("{}/commit-messages-sentiment-developers", data_folder_path),
+            args.flag_format.unwrap_or_default(),
+        ) {
+            error!("Cannot write commit messages sentiment scores: {}", e);
         }
-        Err(_e) => "java".to_string(),
-    };
-    java_path
+    }
 }
 
 fn print_supported_languages(exts: IndexSet<String>) {
@@ -535,168 +458,154 @@ fn fetch_github_issues_for_projects(projects: IndexSet<Project>, args: &Args) {
         return;
     }
     projects.into_iter().par_bridge().for_each(|project| {
-        let github_url = project.path.trim();
-        let parts: Vec<&str> = github_url.split('/').collect();
-        if parts.len() < 2 {
-            error!("Invalid GitHub URL for project {}: {}", project.name, github_url);
-            return;
-        }
-        let owner = parts[parts.len() - 2];
-        let repo = parts[parts.len() - 1];
-        let output_path = format!("{}/{}_issues.csv", output_folder, project.name);
-        match fetch_issues_with_comments_csv(owner, repo, &output_path) {
+        let repo_ref = match RepoRef::parse_as(&project.path, args.flag_forge) {
+            Ok(repo_ref) => repo_ref,
+            Err(e) => {
+                error!("Invalid repository URL for project {}: {}", project.name, e);
+                return;
+            }
+        };
+        let (owner, repo) = (&repo_ref.owner, &repo_ref.repo);
+        let output_stem = format!("{}/{}_issues", output_folder, project.name);
+        let format = args.flag_format.unwrap_or_default();
+        match fetch_issues_with_comments_csv(&repo_ref, &output_stem, format, args.flag_sentiment, args.flag_github_offline) {
             Ok(_) => info!("Successfully fetched issues for {}/{}", owner, repo),
             Err(e) => error!("Failed to fetch issues for {}/{}: {}", owner, repo, e),
         }
     });
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Load environment variables from .env file, if it exists.
-    dotenv().ok();
-
-    // **** LOGGING SETUP **** //
-    let start = std::time::Instant::now();
-    log4rs::init_file("log4rs.yaml", Default::default()).unwrap();
-    log::info!("Booting up");
-
-    let args = Args::from_args();
-
-    if args.flag_restrict_languages {
-        let cwd = std::env::current_dir();
-        if let Ok(mut cwd) = cwd {
-            cwd.push("tokei.toml");
-            if !cwd.exists() {
-                log::error!("There is no tokei.toml file in current working directory. Aborting");
-                panic!("There is no tokei.toml file in current working directory")
+/// Fetches pull requests for each project whose path is a GitHub URL, parsed from the metadata file.
+fn fetch_github_pull_requests_for_projects(projects: IndexSet<Project>, args: &Args) {
+    let output_folder = args.flag_github_output_folder.as_deref().unwrap_or("github_pull_requests");
+    if let Err(e) = std::fs::create_dir_all(output_folder) {
+        error!("Failed to create GitHub pull requests output folder {}: {}", output_folder, e);
+        return;
+    }
+    projects.into_iter().par_bridge().for_each(|project| {
+        let repo_ref = match RepoRef::parse_as(&project.path, args.flag_forge) {
+            Ok(repo_ref) => repo_ref,
+            Err(e) => {
+                error!("Invalid repository URL for project {}: {}", project.name, e);
+                return;
             }
-        } else {
-            panic!("Cannot get current working directory when initializing tokei with the configuration")
+        };
+        let (owner, repo) = (&repo_ref.owner, &repo_ref.repo);
+        match github_pull_requests::fetch_pull_requests_csv(&repo_ref, output_folder, args.flag_format.unwrap_or_default()) {
+            Ok(_) => info!("Successfully fetched pull requests for {}/{}", owner, repo),
+            Err(e) => error!("Failed to fetch pull requests for {}/{}: {}", owner, repo, e),
         }
-    }
+    });
+}
 
-    // --- EARLY EXIT: If fetching GitHub issues, do not load metadata ---
-    if args.flag_fetch_github_issues {
-        // NEW: Check for issue-stats-grouped flag for developer grouping on issues.
-        if args.flag_issue_stats_grouped {
-            if let Some(github_url) = &args.flag_github_url {
-                let trimmed = github_url.trim_end_matches(".git");
-                let parts: Vec<&str> = trimmed.split('/').collect();
-                if parts.len() < 2 {
-                    error!("Invalid GitHub URL provided: {}", github_url);
+/// Fetches the issues of the repository given with --github-url, or of every project of the metadata file
+fn fetch_issues(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let output_folder = args.flag_github_output_folder.as_deref().unwrap_or("github_issues");
+    let format = args.flag_format.unwrap_or_default();
+    if args.flag_issue_stats_grouped {
+        if let Some(github_url) = &args.flag_github_url {
+            let repo_ref = match RepoRef::parse_as(github_url, args.flag_forge) {
+                Ok(repo_ref) => repo_ref,
+                Err(e) => {
+                    error!("Invalid repository URL provided: {}", e);
                     return Ok(());
                 }
-                let owner = parts[parts.len() - 2];
-                let repo = parts[parts.len() - 1];
-                let output_folder = args.flag_github_output_folder.as_deref().unwrap_or("github_issues");
-                std::fs::create_dir_all(output_folder)?;
-                github_issues::write_issue_stats_grouped_by_developer(owner, repo, output_folder)?;
-                info!("Successfully wrote grouped issue stats for {}/{}", owner, repo);
-            } else {
-                let metadata_filepath = if let Some(path) = &args.flag_metadata_filepath {
-                    path
-                } else {
-                    "../../apache-projects.xlsx"
-                };
-                let projects = list_projects(metadata_filepath);
-                for project in projects {
-                    let github_url = project.path.trim();
-                    let parts: Vec<&str> = github_url.split('/').collect();
-                    if parts.len() < 2 {
-                        error!("Invalid GitHub URL for project {}: {}", project.name, github_url);
+            };
+            let (owner, repo) = (&repo_ref.owner, &repo_ref.repo);
+            std::fs::create_dir_all(output_folder)?;
+            github_issues::write_issue_stats_grouped_by_developer(&repo_ref, output_folder, format, args.flag_github_offline)?;
+            info!("Successfully wrote grouped issue stats for {}/{}", owner, repo);
+        } else {
+            let projects = list_projects(args.flag_metadata_filepath.as_deref().unwrap_or(PROJECTS_METADATA));
+            for project in projects {
+                let repo_ref = match RepoRef::parse_as(&project.path, args.flag_forge) {
+                    Ok(repo_ref) => repo_ref,
+                    Err(e) => {
+                        error!("Invalid repository URL for project {}: {}", project.name, e);
                         continue;
                     }
-                    let owner = parts[parts.len() - 2];
-                    let repo = parts[parts.len() - 1];
-                    let output_folder = args.flag_github_output_folder.as_deref().unwrap_or("github_issues");
-                    github_issues::write_issue_stats_grouped_by_developer(owner, repo, output_folder)?;
-                    info!("Successfully wrote grouped issue stats for {}/{}", owner, repo);
-                }
+                };
+                let (owner, repo) = (&repo_ref.owner, &repo_ref.repo);
+                github_issues::write_issue_stats_grouped_by_developer(&repo_ref, output_folder, format, args.flag_github_offline)?;
+                info!("Successfully wrote grouped issue stats for {}/{}", owner, repo);
             }
-            let duration = start.elapsed();
-            let seconds = duration.as_secs() % 60;
-            let minutes = (duration.as_secs() / 60) % 60;
-            let hours = (duration.as_secs() / 60) / 60;
-            log::info!("Grouped GitHub issues stats fetching completed in {}h:{}m:{}s", hours, minutes, seconds);
-            return Ok(());
         }
+        return Ok(());
+    }
 
-        if let Some(github_url) = &args.flag_github_url {
-            let trimmed = github_url.trim_end_matches(".git");
-            let parts: Vec<&str> = trimmed.split('/').collect();
-            if parts.len() < 2 {
-                error!("Invalid GitHub URL provided: {}", github_url);
+    if let Some(github_url) = &args.flag_github_url {
+        let repo_ref = match RepoRef::parse_as(github_url, args.flag_forge) {
+            Ok(repo_ref) => repo_ref,
+            Err(e) => {
+                error!("Invalid repository URL provided: {}", e);
                 return Ok(());
             }
-            let owner = parts[parts.len() - 2];
-            let repo = parts[parts.len() - 1];
-            let output_folder = args.flag_github_output_folder.as_deref().unwrap_or("github_issues");
-            std::fs::create_dir_all(output_folder)?;
-            let output_csv = format!("{}/{}_issues.csv", output_folder, repo);
-            match fetch_issues_with_comments_csv(owner, repo, &output_csv) {
-                Ok(_) => info!("Successfully fetched issues for {}/{}", owner, repo),
-                Err(e) => error!("Failed to fetch issues for {}/{}: {}", owner, repo, e),
-            }
-        } else {
-            let metadata_filepath = if let Some(path) = &args.flag_metadata_filepath {
-                path
-            } else {
-                "../../apache-projects.xlsx"
-            };
-            let projects = list_projects(metadata_filepath);
-            fetch_github_issues_for_projects(projects, &args);
+        };
+        let (owner, repo) = (&repo_ref.owner, &repo_ref.repo);
+        std::fs::create_dir_all(output_folder)?;
+        let output_stem = format!("{}/{}_issues", output_folder, repo);
+        match fetch_issues_with_comments_csv(&repo_ref, &output_stem, format, args.flag_sentiment, args.flag_github_offline) {
+            Ok(_) => info!("Successfully fetched issues for {}/{}", owner, repo),
+            Err(e) => error!("Failed to fetch issues for {}/{}: {}", owner, repo, e),
         }
-        let duration = start.elapsed();
-        let seconds = duration.as_secs() % 60;
-        let minutes = (duration.as_secs() / 60) % 60;
-        let hours = (duration.as_secs() / 60) / 60;
-        log::info!("GitHub issues fetching completed in {}h:{}m:{}s", hours, minutes, seconds);
-        return Ok(());
+    } else {
+        let projects = list_projects(args.flag_metadata_filepath.as_deref().unwrap_or(PROJECTS_METADATA));
+        fetch_github_issues_for_projects(projects, args);
     }
-    // --- End early exit for GitHub issues ---
-
-    // ==== NEW: Check for online commit analysis ====
-    if args.flag_commit_devs_files {
-        if let Some(online_url) = &args.flag_git_online_url {
-            // For online repository analysis you must also provide start/end dates (or defaults will be used)
-            let online_start_date = args
-                .flag_online_start_date
-                .clone()
-                .unwrap_or_else(|| "1970-01-01".to_string());
-            let online_end_date = args
-                .flag_online_end_date
-                .clone()
-                .unwrap_or_else(|| "2100-01-01".to_string());
-            let online_status = args.flag_online_status.clone().unwrap_or_default();
-            if args.flag_commit_graphql {
-                commit_metrics_graphql::analyze_online_repo(
-                    online_url,
-                    &args,
-                    &online_start_date,
-                    &online_end_date,
-                    &online_status,
-                )?;
-            } else {
-                commit_metrics_clone::analyze_online_repo(
-                    online_url,
-                    &args,
-                    &online_start_date,
-                    &online_end_date,
-                    &online_status,
-                )?;
+    Ok(())
+}
+
+fn fetch_jira_issues(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let project = jira::JiraProject::parse(args.flag_jira_url.as_deref().unwrap_or_default())?;
+    let output_folder = args.flag_github_output_folder.as_deref().unwrap_or("jira_issues");
+    std::fs::create_dir_all(output_folder)?;
+    let format = args.flag_format.unwrap_or_default();
+    match jira::fetch_jira_issues_csv(&project, output_folder, args.flag_jira_export.as_deref(), format, args.flag_sentiment) {
+        Ok(_) => info!("Successfully fetched JIRA issues for {}", project.key),
+        Err(e) => error!("Failed to fetch JIRA issues for {}: {}", project.key, e),
+    }
+    Ok(())
+}
+
+/// Fetches the pull requests of the repository given with --github-url, or of every project of the metadata file
+fn fetch_pull_requests(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(github_url) = &args.flag_github_url {
+        let repo_ref = match RepoRef::parse_as(github_url, args.flag_forge) {
+            Ok(repo_ref) => repo_ref,
+            Err(e) => {
+                error!("Invalid repository URL provided: {}", e);
+                return Ok(());
             }
-            let duration = start.elapsed();
-            let seconds = duration.as_secs() % 60;
-            let minutes = (duration.as_secs() / 60) % 60;
-            let hours = (duration.as_secs() / 60) / 60;
-            log::info!("Online commit analysis completed in {}h:{}m:{}s", hours, minutes, seconds);
-            return Ok(());
+        };
+        let (owner, repo) = (&repo_ref.owner, &repo_ref.repo);
+        let output_folder = args.flag_github_output_folder.as_deref().unwrap_or("github_pull_requests");
+        std::fs::create_dir_all(output_folder)?;
+        match github_pull_requests::fetch_pull_requests_csv(&repo_ref, output_folder, args.flag_format.unwrap_or_default()) {
+            Ok(_) => info!("Successfully fetched pull requests for {}/{}", owner, repo),
+            Err(e) => error!("Failed to fetch pull requests for {}/{}: {}", owner, repo, e),
         }
+    } else {
+        let projects = list_projects(args.flag_metadata_filepath.as_deref().unwrap_or(PROJECTS_METADATA));
+        fetch_github_pull_requests_for_projects(projects, args);
     }
+    Ok(())
+}
 
-    // Load projects via git folder or metadata
-    let mut projects = if args.flag_git_folder.is_some() {
-        let folder = args.flag_git_folder.clone().unwrap();
+fn analyze_online(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    // Without dates, online repositories are analyzed from 1970-01-01 to their last commit
+    let online_repos = if let Some(path) = &args.flag_git_online_urls {
+        online_analysis::read_online_repos(path, args)?
+    } else {
+        let online_url = args.flag_git_online_url.as_deref().unwrap_or_default();
+        vec![online_analysis::OnlineRepo::from_args(online_url, args)]
+    };
+    online_analysis::analyze_online_repos(&online_repos, args)
+}
+
+/// The projects of --git-folder, or of the metadata file (`default_metadata` unless --metadata-filepath is given),
+/// restricted to --project
+fn load_projects(args: &Args, default_metadata: &str) -> Result<IndexSet<Project>, Box<dyn std::error::Error>> {
+    let projects = if let Some(folder) = &args.flag_git_folder {
         let mut cwd = std::env::current_dir()?;
         cwd.push(folder);
         std::fs::read_dir(cwd)?
@@ -719,242 +628,147 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             })
             .collect::<IndexSet<_>>()
     } else {
-        let metadata_filepath = if let Some(path) = &args.flag_metadata_filepath {
-            path
-        } else {
-            "../../apache-projects.xlsx"
-        };
-        let mut projects = list_projects(metadata_filepath);
-        projects = projects
+        let metadata_filepath = args.flag_metadata_filepath.as_deref().unwrap_or(default_metadata);
+        list_projects(metadata_filepath)
             .into_iter()
             .filter(|x| {
                 x.name != "ODFToolkit" && x.name != "commons-ognl" && !x.name.contains("myfaces")
             })
-            .collect::<indexmap::IndexSet<_>>();
-        projects
-    };
-
-    let data_folder_path = if let Some(folder) = &args.flag_output_folder {
-        folder.as_str()
-    } else {
-        "data"
+            .collect::<indexmap::IndexSet<_>>()
     };
+    Ok(match &args.flag_parse_single_project {
+        Some(project) => projects.into_iter().filter(|x| &x.name == project).collect::<IndexSet<_>>(),
+        None => projects,
+    })
+}
 
-    if let Ok(_res) = std::fs::create_dir_all(data_folder_path) {
-        log::info!("Created output folder: {}", data_folder_path);
-    } else {
-        log::error!("Cannot create folder {}", data_folder_path);
-    }
-
-    if args.flag_parse_single_project.is_some() {
-        projects = projects
-            .into_iter()
-            .filter(|x| &x.name == args.flag_parse_single_project.as_ref().unwrap())
-            .collect::<IndexSet<_>>();
-    }
-
-    if args.flag_list_projects {
-        for p in projects {
-            println!("{:#?}", p.name.to_lowercase());
-        }
-        return Ok(());
-    }
-
-    if args.flag_missing_emails {
-        let metadata_filepath = if let Some(path) = &args.flag_metadata_filepath {
-            path
-        } else {
-            "../../projects-info-from-podlings-xml-extra-metadata.xlsx"
-        };
-        check_for_missing_emails(&args, metadata_filepath);
-        let duration = start.elapsed();
-        let seconds = duration.as_secs() % 60;
-        let minutes = (duration.as_secs() / 60) % 60;
-        let hours = (duration.as_secs() / 60) / 60;
-        log::info!("Analysis completed in {}h:{}m:{}s", hours, minutes, seconds);
-        return Ok(());
-    }
-
-    if args.flag_print_incubation_dates {
-        print_incubation_dates(projects, &args);
-        return Ok(());
-    }
-
-    if args.flag_print_supported_languages {
-        let exts = utils::find_lang_extensions()?;
-        print_supported_languages(exts);
-        return Ok(());
-    }
-
-    if args.flag_restrict_languages {
-        let exts = utils::find_lang_extensions()?;
-        print_supported_languages(exts);
+fn download_emails(projects: &IndexSet<Project>, args: &Args) {
+    let emails_folder = "../../projects/emails";
+    if let Err(result) = std::fs::create_dir_all(emails_folder) {
+        log::error!("Cannot create project/emails directories. Make sure you have writing access. Original error: {}", result);
+        return;
     }
-
-    if args.flag_commit_messages {
-        let metadata_filepath = if let Some(path) = &args.flag_metadata_filepath {
-            path
+    let projects_names_fix = IndexMap::from([
+        ("apex-core", "apex"),
+        ("ant-ivy", "ant"),
+        ("derby", "db-derby"),
+        ("empire-db", "empire"),
+        ("lucene.net", "lucenenet"),
+        ("mynewt-core", "mynewt"),
+        ("pluto", "portals-pluto"),
+        ("creadur-rat", "creadur"),
+        ("warble-server", "warble"),
+    ]);
+    let agent = ureq::AgentBuilder::new()
+        .timeout_read(Duration::from_secs(15))
+        .timeout_write(Duration::from_secs(300))
+        .build();
+    projects.iter().par_bridge().for_each(|p| {
+        let git_repo = Repository::open(p.path.as_str());
+        let end_date = if p.status == "graduated" {
+            chrono::Local::now().format("%Y-%m-%d").to_string()
         } else {
-            "../../projects-info-from-podlings-xml-extra-metadata.xlsx"
+            p.end_date.to_string()
         };
-        commits_messages(data_folder_path, &args, metadata_filepath);
-        let duration = start.elapsed();
-        let seconds = duration.as_secs() % 60;
-        let minutes = (duration.as_secs() / 60) % 60;
-        let hours = (duration.as_secs() / 60) / 60;
-        log::info!("Analysis completed in {}h:{}m:{}s", hours, minutes, seconds);
-        return Ok(());
-    }
-
-    if args.flag_manual_test_project.is_some() {
-        manual_test_project();
-        return Ok(());
-    }
-
-    let threads = args.flag_threads.unwrap_or(4);
-    log::info!("Using {} threads", threads);
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(threads)
-        .build_global()
-        .unwrap();
-
-    if args.flag_download_emails {
-        let emails_folder = "../../projects/emails";
-        if let Err(result) = std::fs::create_dir_all(emails_folder) {
-            log::error!("Cannot create project/emails directories. Make sure you have writing access. Original error: {}", result);
-            return Ok(());
-        }
-        let projects_names_fix = IndexMap::from([
-            ("apex-core", "apex"),
-            ("ant-ivy", "ant"),
-            ("derby", "db-derby"),
-            ("empire-db", "empire"),
-            ("lucene.net", "lucenenet"),
-            ("mynewt-core", "mynewt"),
-            ("pluto", "portals-pluto"),
-            ("creadur-rat", "creadur"),
-            ("warble-server", "warble"),
-        ]);
-        let agent = ureq::AgentBuilder::new()
-            .timeout_read(Duration::from_secs(15))
-            .timeout_write(Duration::from_secs(300))
-            .build();
-        projects.iter().par_bridge().for_each(|p| {
-            let git_repo = Repository::open(p.path.as_str());
-            let end_date = if p.status == "graduated" {
-                chrono::Local::now().format("%Y-%m-%d").to_string()
-            } else {
-                p.end_date.to_string()
-            };
-            if let Ok(git_repo) = git_repo {
-                let repo = Repo::new(
-                    &git_repo,
-                    p.name.as_str(),
-                    &p.start_date,
-                    &end_date,
-                    p.status.as_str(),
-                    &args,
-                );
-                if let Ok(repo) = repo {
-                    for (_, month) in repo.dates_to_months() {
-                        let path = format!(
-                            "{}/{}-dev-{}.mbox",
-                            emails_folder,
-                            repo.project.to_lowercase(),
+        if let Ok(git_repo) = git_repo {
+            let repo = Repo::new(
+                &git_repo,
+                p.name.as_str(),
+                &p.start_date,
+                &end_date,
+                p.status.as_str(),
+                args,
+            );
+            if let Ok(repo) = repo {
+                for (_, month) in repo.dates_to_months() {
+                    let path = format!(
+                        "{}/{}-dev-{}.mbox",
+                        emails_folder,
+                        repo.project.to_lowercase(),
+                        month
+                    );
+                    let email_path = std::path::Path::new(&path);
+                    if !email_path.exists() {
+                        let url = format!(
+                            "https://mail-archives.apache.org/mod_mbox/{}-dev/{}.mbox",
+                            projects_names_fix
+                                .get(repo.project.to_lowercase().as_str())
+                                .unwrap_or(&repo.project.to_lowercase().as_str()),
                             month
                         );
-                        let email_path = std::path::Path::new(&path);
-                        if !email_path.exists() {
-                            let url = format!(
-                                "https://mail-archives.apache.org/mod_mbox/{}-dev/{}.mbox",
-                                projects_names_fix
-                                    .get(repo.project.to_lowercase().as_str())
-                                    .unwrap_or(&repo.project.to_lowercase().as_str()),
-                                month
-                            );
-                            let res = agent.get(&url).call();
-                            if let Ok(res) = res {
-                                if res.status() == 200 {
-                                    let mut file = std::fs::File::create(&path)
-                                        .expect("Cannot create file");
-                                    let result = std::io::copy(&mut res.into_reader(), &mut file);
-                                    if result.is_err() {
-                                        log::error!("{} - cannot download email archive {}", repo.project.to_lowercase(), &month);
-                                    } else {
-                                        log::info!("{} - downloaded email archive {}", repo.project.to_lowercase(), &month);
-                                    }
-                                } else {
+                        let res = agent.get(&url).call();
+                        if let Ok(res) = res {
+                            if res.status() == 200 {
+                                let mut file = std::fs::File::create(&path)
+                                    .expect("Cannot create file");
+                                let result = std::io::copy(&mut res.into_reader(), &mut file);
+                                if result.is_err() {
                                     log::error!("{} - cannot download email archive {}", repo.project.to_lowercase(), &month);
+                                } else {
+                                    log::info!("{} - downloaded email archive {}", repo.project.to_lowercase(), &month);
                                 }
                             } else {
                                 log::error!("{} - cannot download email archive {}", repo.project.to_lowercase(), &month);
                             }
+                        } else {
+                            log::error!("{} - cannot download email archive {}", repo.project.to_lowercase(), &month);
                         }
                     }
                 }
             }
-        });
-        let duration = start.elapsed();
-        let seconds = duration.as_secs() % 60;
-        let minutes = (duration.as_secs() / 60) % 60;
-        let hours = (duration.as_secs() / 60) / 60;
-        log::info!("Analysis completed in {}h:{}m:{}s", hours, minutes, seconds);
-        return Ok(());
-    }
-
-    if args.flag_full_analysis {
-        let java_path = java_path();
-        pre_post_analysis(projects, &args, &java_path, data_folder_path);
-        return Ok(());
-    }
+        }
+    });
+}
 
+/// The metrics of every window of each project, or with --commit-devs-files the files changed by every commit
+fn analyze_projects(projects: &IndexSet<Project>, args: &Args, data_folder_path: &str) {
     log::info!("Analyzing {} projects", projects.len());
     let java_path = java_path();
+    let format = args.flag_format.unwrap_or_default();
+    progress::start(projects.len(), args.flag_progress_file.as_deref());
     projects.iter().par_bridge().for_each(|p| {
+        progress::project_started(p.name.as_str());
+        let mut succeeded = false;
         let git_repo = Repository::open(p.path.as_str());
         if let Ok(git_repo) = git_repo {
-            let repo = Repo::new(
-                &git_repo,
-                p.name.as_str(),
-                p.start_date.as_str(),
-                p.end_date.as_str(),
-                p.status.as_str(),
-                &args,
-            );
+            let repo = diagnostics::timed(p.name.as_str(), "", "commits", || {
+                Repo::new(&git_repo, p.name.as_str(), p.start_date.as_str(), p.end_date.as_str(), p.status.as_str(), args)
+            });
             if let Ok(mut repo) = repo {
                 remove_sokrates_temp(&git_repo);
-                let checkout = repo.checkout_master_main_trunk(&args);
+                let checkout = repo.checkout_master_main_trunk(args);
                 if let Ok(_checkout) = checkout {
                     log::info!("checkout {}", repo.commits.len());
+                    succeeded = true;
                     // --- Here we check if we want grouped developer stats ---
                     if args.flag_commit_devs_files {
+                        if let Err(e) = database::store_commits(&repo) {
+                            error!("{} - cannot store the commits in the database: {}", p.name.as_str(), e);
+                        }
                         if args.flag_dev_stats_grouped {
                             // Use the new function to write separate CSV files grouped by developer per incubation month.
                             let dev_stats = DevStats::new(p.name.as_str(), &repo, &java_path);
-                            match dev_stats.write_dev_stats_grouped_by_developer(&args) {
+                            match dev_stats.write_dev_stats_grouped_by_developer(args) {
                                 Ok(()) => {
                                     info!("Grouped developer stats written for project {}", p.name);
                                 }
                                 Err(e) => {
                                     error!("{} - error writing grouped developer stats: {}", p.name.as_str(), e);
+                                    succeeded = false;
                                 }
                             }
                         } else {
                             // Retain the original functionality: write one CSV with all commit file metrics.
                             let dev_stats = DevStats::new(p.name.as_str(), &repo, &java_path);
-                            let metrics = dev_stats.compute_individual_dev_stats(&args);
+                            let metrics = dev_stats.compute_individual_dev_stats(args);
                             if let Ok(metrics) = metrics {
-                                let mut writer = csv::WriterBuilder::default()
-                                    .has_headers(true)
-                                    .from_path(format!("{}/{}-commit-file-dev.csv", data_folder_path, p.name.as_str()))
-                                    .unwrap();
-                                for m in metrics {
-                                    if let Err(e) = writer.serialize(m) {
-                                        error!("{} - cannot serialize metric value: {}", p.name.as_str(), e);
-                                    }
+                                let stem = format!("{}/{}-commit-file-dev", data_folder_path, p.name.as_str());
+                                if let Err(e) = output::write_table(&stem, format, Some(&dev_stats), metrics) {
+                                    error!("{} - cannot write the commit file metrics: {}", p.name.as_str(), e);
                                 }
                             } else {
                                 error!("{} cannot extract the metrics", p.name.as_str());
+                                succeeded = false;
                             }
                         }
                     } else {
@@ -965,36 +779,175 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                             &p.status,
                             &java_path,
                         );
-                        let metrics = stats.compute_statistics(&mut repo, &args);
+                        let metrics = stats.compute_statistics(&mut repo, args);
                         if let Ok(metrics) = metrics {
-                            let mut writer = csv::WriterBuilder::default()
-                                .has_headers(true)
-                                .from_path(format!("{}/{}.csv", data_folder_path, p.name.as_str()))
-                                .unwrap();
-                            for m in metrics {
-                                if let Err(e) = writer.serialize(m) {
-                                    error!("{} - cannot serialize metric value: {}", p.name.as_str(), e);
-                                }
+                            let analysis = windows::WindowScheme::from_args(args).label();
+                            if let Err(e) = database::store_analysis(&repo, &analysis, &metrics) {
+                                error!("{} - cannot store the metrics in the database: {}", p.name.as_str(), e);
+                            }
+                            let languages = LanguageRow::of(p.name.as_str(), metrics.iter().map(Stats::metrics));
+                            let languages_stem = format!("{}/{}-languages", data_folder_path, p.name.as_str());
+                            if let Err(e) = output::write_table(&languages_stem, format, Some(&LanguageRow::default()), languages) {
+                                error!("{} - cannot write the language metrics: {}", p.name.as_str(), e);
+                            }
+                            let stem = format!("{}/{}", data_folder_path, p.name.as_str());
+                            if let Err(e) = output::write_table(&stem, format, Some(&Stats::default()), metrics) {
+                                error!("{} - cannot write the metrics: {}", p.name.as_str(), e);
                             }
                         } else {
                             error!("{} cannot extract the metrics", p.name.as_str());
+                            succeeded = false;
+                        }
+                        let profiles_stem = format!("{}/{}-developer-activity", data_folder_path, p.name.as_str());
+                        if let Err(e) =
+                            activity_metrics::write_developer_profiles(p.name.as_str(), &repo.commits, &profiles_stem, format)
+                        {
+                            error!("{} - cannot write the developer activity profiles: {}", p.name.as_str(), e);
                         }
                     }
                 } else {
                     error!("{} - cannot reset to main/master/trunk", p.name.as_str());
+                    diagnostics::error(p.name.as_str(), "checkout", "cannot reset to main/master/trunk");
                 }
             } else {
                 error!("{} - cannot parse the repository and extract commits", p.name.as_str());
+                diagnostics::error(p.name.as_str(), "commits", "cannot parse the repository and extract commits");
             }
         } else {
             error!("{} cannot find the git repository at {}", p.name.as_str(), p.path.as_str());
+            let message = format!("cannot find the git repository at {}", p.path);
+            diagnostics::error(p.name.as_str(), "repository", &message);
         }
+        progress::project_finished(p.name.as_str(), succeeded);
     });
+    progress::finish();
+}
+
+/// Runs the modes that analyze the local projects
+fn run_on_projects(mode: Mode, args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    // the email archive check and the commit messages used to read their own metadata file
+    let default_metadata = match mode {
+        Mode::EmailsCheck | Mode::CommitMessages => PODLINGS_METADATA,
+        _ => PROJECTS_METADATA,
+    };
+    let projects = load_projects(args, default_metadata)?;
+
+    let data_folder_path = if let Some(folder) = &args.flag_output_folder {
+        folder.as_str()
+    } else {
+        "data"
+    };
+
+    if let Ok(_res) = std::fs::create_dir_all(data_folder_path) {
+        log::info!("Created output folder: {}", data_folder_path);
+    } else {
+        log::error!("Cannot create folder {}", data_folder_path);
+    }
+
+    if args.flag_restrict_languages {
+        let exts = utils::find_lang_extensions()?;
+        print_supported_languages(exts);
+    }
+
+    let threads = args.flag_threads.unwrap_or(4);
+    log::info!("Using {} threads", threads);
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+        .unwrap();
+
+    match mode {
+        Mode::ListProjects => {
+            for p in projects {
+                println!("{:#?}", p.name.to_lowercase());
+            }
+        }
+        Mode::EmailsDownload => download_emails(&projects, args),
+        Mode::EmailsCheck => check_for_missing_emails(&projects, args),
+        Mode::EmailsAnalyze => export_email_bodies(&projects, args, data_folder_path),
+        Mode::Dates => print_incubation_dates(projects, args),
+        Mode::CommitMessages => commits_messages(&projects, data_folder_path, args),
+        Mode::PrePost => pre_post_analysis(projects, args, &java_path(), data_folder_path),
+        _ => analyze_projects(&projects, args, data_folder_path),
+    }
+    Ok(())
+}
+
+/// Writes the data-quality report of every analyzed project to the output folder, and returns the number of
+/// `--quality-thresholds` they exceed
+fn write_quality_reports(args: &Args) -> Result<usize, Box<dyn std::error::Error>> {
+    let diagnostics = Diagnostics::global();
+    let reports = diagnostics.reports();
+    if reports.is_empty() {
+        return Ok(0);
+    }
+    let violations = diagnostics.check(&args.flag_quality_thresholds.clone().unwrap_or_default());
+    let folder = args.flag_output_folder.as_deref().unwrap_or("data");
+    std::fs::create_dir_all(folder)?;
+    diagnostics.write_reports(folder)?;
+    log::info!("Wrote the data-quality reports of {} projects to {}", reports.len(), folder);
+    for report in diagnostics.reports() {
+        for violation in &report.threshold_violations {
+            log::error!("{} - {}", report.project, violation);
+        }
+    }
+    Ok(violations)
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Load environment variables from .env file, if it exists.
+    dotenv().ok();
+
+    // **** LOGGING SETUP **** //
+    let start = std::time::Instant::now();
+    log4rs::init_file("log4rs.yaml", Default::default()).unwrap();
+    log::info!("Booting up");
+
+    let mut args = Args::from_args();
+    let mode = match Mode::resolve(&mut args) {
+        Ok(mode) => mode,
+        Err(message) => clap::Error::with_description(&message, clap::ErrorKind::ArgumentConflict).exit(),
+    };
+
+    if let Some(path) = &args.flag_sqlite {
+        database::open_global(path)?;
+        log::info!("Writing the analysis to the SQLite database {}", path);
+    }
+
+    if args.flag_restrict_languages {
+        let cwd = std::env::current_dir();
+        if let Ok(mut cwd) = cwd {
+            cwd.push("tokei.toml");
+            if !cwd.exists() {
+                log::error!("There is no tokei.toml file in current working directory. Aborting");
+                panic!("There is no tokei.toml file in current working directory")
+            }
+        } else {
+            panic!("Cannot get current working directory when initializing tokei with the configuration")
+        }
+    }
+
+    match mode {
+        Mode::Issues => fetch_issues(&args)?,
+        Mode::JiraIssues => fetch_jira_issues(&args)?,
+        Mode::PullRequests => fetch_pull_requests(&args)?,
+        Mode::Online => analyze_online(&args)?,
+        Mode::Languages => print_supported_languages(utils::find_lang_extensions()?),
+        Mode::ManualTest => manual_test_project(&args),
+        _ => run_on_projects(mode, &args)?,
+    }
+
+    let violations = write_quality_reports(&args)?;
+
     let duration = start.elapsed();
     let seconds = duration.as_secs() % 60;
     let minutes = (duration.as_secs() / 60) % 60;
     let hours = (duration.as_secs() / 60) / 60;
     log::info!("Analysis completed in {}h:{}m:{}s", hours, minutes, seconds);
+    if violations > 0 {
+        log::error!("{} data-quality thresholds exceeded", violations);
+        std::process::exit(2);
+    }
     Ok(())
 }
 
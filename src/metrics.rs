@@ -1,5 +1,14 @@
-use crate::{emails::EmailsMetrics, sokrates_metrics::SokratesMetrics};
-use serde::Serialize;
+use crate::{activity_metrics::ActivityMetrics, emails::{EmailContentMetrics, EmailsMetrics}, repo::Repo, sokrates_metrics::SokratesMetrics, tokei_metrics::{CodeMetrics, DirectoryMetrics, LanguageMetrics}, Args};
+use chrono::NaiveDate;
+use git2::Commit;
+use indexmap::IndexMap;
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
 #[derive(Clone, Debug, Default, Serialize)]
 
 pub struct Metrics {
@@ -13,8 +22,8 @@ pub struct Metrics {
     pub committers: usize,
     pub minor_contributors: usize,
     pub major_contributors: usize,
-    pub directories: usize,
-    pub top_level_dirs: usize,
+    #[serde(flatten)]
+    pub directory_metrics: Nullable<DirectoryMetrics>,
     pub releases: usize,
 
     // might want to add the commit type metrics
@@ -41,7 +50,7 @@ pub struct Metrics {
     pub deleted_lines: usize,
     /// Email metrics
     #[serde(flatten)]
-    pub email_metrics: EmailsMetrics,
+    pub email_metrics: Nullable<EmailsMetrics>,
     // /// The number of emails
     // pub emails: usize,
     // /// The number of developers involved in these emails
@@ -52,20 +61,271 @@ pub struct Metrics {
     pub avg_files_modified_commit: f64,
 
     // tokei metrics
-    /// SLOC
-    pub code: usize,
-    /// The number of blank lines
-    pub blanks: usize,
-    /// The number of files
-    pub files: usize,
-    /// The number of comments
-    pub comments: usize,
-    /// The number of lines
-    pub lines: usize,
-    /// The programming language with the most code
-    pub programming_lang: String,
+    #[serde(flatten)]
+    pub code_metrics: Nullable<CodeMetrics>,
 
     // sokrates metrics
     #[serde(flatten)]
-    pub sokrates_metrics: SokratesMetrics,
+    pub sokrates_metrics: Nullable<SokratesMetrics>,
+
+    // the columns below were added after the first releases, and follow their columns so that these keep
+    // their positions
+    /// Whether a metric family was not observed in this window, and its metrics were imputed
+    pub is_imputed: bool,
+    /// The imputed metric families, separated by `;`
+    pub imputed_families: String,
+    /// The commit the code metrics were measured at; a carried-forward value keeps an earlier window's commit
+    pub source_commit: String,
+    /// Time-of-activity metrics, in the authors' local time
+    #[serde(flatten)]
+    pub activity_metrics: ActivityMetrics,
+    /// Email content, sentiment and time metrics, imputed with the email metrics
+    #[serde(flatten)]
+    pub email_content_metrics: Nullable<EmailContentMetrics>,
+
+    // commit message sentiment
+    /// Average compound sentiment of the commit messages, from -1 (most negative) to 1 (most positive)
+    pub commits_sentiment_compound_avg: f64,
+    /// Number of commit messages with a negative compound sentiment
+    pub commits_negative: usize,
+    /// Number of commit messages containing at least one term of the incivility word list
+    pub commits_uncivil: usize,
+
+    // tokei language mix
+    #[serde(flatten)]
+    pub language_metrics: Nullable<LanguageMetrics>,
+}
+
+/// The metrics of a family, or empty cells for a family imputed as empty. The default is the family's
+/// zero metrics.
+#[derive(Clone, Debug)]
+pub struct Nullable<T>(pub Option<T>);
+
+impl<T: Default> Default for Nullable<T> {
+    fn default() -> Self {
+        Nullable(Some(T::default()))
+    }
+}
+
+impl<T: Default> Nullable<T> {
+    /// The metrics of a family imputed with zeros or empty cells
+    pub fn imputed(imputation: Imputation) -> Self {
+        match imputation {
+            Imputation::Empty => Nullable(None),
+            _ => Nullable::default(),
+        }
+    }
+}
+
+impl<T: Serialize + Default + 'static> Serialize for Nullable<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &self.0 {
+            Some(metrics) => metrics.serialize(serializer),
+            None => {
+                let columns = column_names::<T>();
+                let mut map = serializer.serialize_map(Some(columns.len()))?;
+                for column in columns {
+                    map.serialize_entry(column, &None::<usize>)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+/// The CSV columns of a metrics struct, in order, worked out once per struct
+fn column_names<T: Serialize + Default + 'static>() -> &'static [String] {
+    static COLUMNS: OnceLock<Mutex<HashMap<TypeId, &'static [String]>>> = OnceLock::new();
+    let mut columns = COLUMNS.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+    columns.entry(TypeId::of::<T>()).or_insert_with(|| Box::leak(header::<T>().into_boxed_slice()))
+}
+
+fn header<T: Serialize + Default>() -> Vec<String> {
+    let mut writer = csv::WriterBuilder::default().has_headers(true).from_writer(vec![]);
+    if writer.serialize(T::default()).is_err() {
+        return vec![];
+    }
+    let data = writer.into_inner().unwrap_or_default();
+    csv::Reader::from_reader(data.as_slice())
+        .headers()
+        .map(|headers| headers.iter().map(String::from).collect())
+        .unwrap_or_default()
+}
+
+/// How the metrics of a family that was not observed in a window are filled in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Imputation {
+    /// Keep the previous window's values
+    CarryForward,
+    Zero,
+    /// Empty cells
+    Empty,
+}
+
+impl FromStr for Imputation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "carry-forward" => Ok(Imputation::CarryForward),
+            "zero" => Ok(Imputation::Zero),
+            "empty" | "null" => Ok(Imputation::Empty),
+            other => Err(format!("unknown imputation {}, expected carry-forward, zero or empty", other)),
+        }
+    }
+}
+
+/// The metric families that can go unobserved: Tokei and Sokrates in windows without commits, whose code was not
+/// checked out, and emails in windows without a mailing list archive
+const IMPUTED_FAMILIES: [&str; 3] = ["emails", "tokei", "sokrates"];
+
+/// The imputation of each metric family, parsed from `--imputation`: a policy for all families (`zero`), or
+/// per family (`tokei:carry-forward,sokrates:empty`). Families not given keep their defaults: carry-forward
+/// for the code metrics and zero for emails.
+#[derive(Clone, Debug, Default)]
+pub struct ImputationPolicy {
+    families: IndexMap<String, Imputation>,
+}
+
+impl ImputationPolicy {
+    pub fn of(&self, family: &str) -> Imputation {
+        match self.families.get(family) {
+            Some(imputation) => *imputation,
+            None if family == "emails" => Imputation::Zero,
+            None => Imputation::CarryForward,
+        }
+    }
+}
+
+impl FromStr for ImputationPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut families = IndexMap::new();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            match part.split_once(':') {
+                Some((family, imputation)) => {
+                    if !IMPUTED_FAMILIES.contains(&family) {
+                        return Err(format!("unknown metric family {}, expected one of {}", family, IMPUTED_FAMILIES.join(", ")));
+                    }
+                    families.insert(family.to_string(), imputation.parse()?);
+                }
+                None => {
+                    let imputation: Imputation = part.parse()?;
+                    for family in IMPUTED_FAMILIES {
+                        families.insert(family.to_string(), imputation);
+                    }
+                }
+            }
+        }
+        Ok(ImputationPolicy { families })
+    }
+}
+
+/// One measurement window of a project, handed to every metric provider
+pub struct Window<'w> {
+    pub project: &'w str,
+    /// The analysis the window's diagnostics are reported under; empty for the main one
+    pub analysis: &'w str,
+    /// Incubation month (window index, from 1)
+    pub month: usize,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    /// Calendar month (YYYYMM) of the window with monthly windows, naming its mbox archive
+    pub calendar_month: Option<&'w str>,
+    /// The window's commits, oldest first
+    pub commits: &'w [Commit<'w>],
+    pub repo: &'w Repo<'w>,
+    pub args: &'w Args,
+}
+
+/// A family of metrics (commits, emails, releases, Tokei, Sokrates...). `Stats` runs each enabled provider
+/// on every window, in order, and each provider fills in its own fields of the window's `Metrics`.
+pub trait MetricProvider {
+    /// Whether the provider reads the source tree, which is checked out at the last commit of the window.
+    /// These providers only run on windows with commits; the other windows keep the previous values.
+    fn reads_source(&self) -> bool {
+        false
+    }
+
+    /// Name of the metric family in `--imputation`
+    fn family(&self) -> &'static str;
+
+    /// Whether the window has the data the metrics are computed from. When it does not, the metrics are
+    /// imputed following the family's policy instead.
+    fn observes(&self, window: &Window) -> bool {
+        !self.reads_source() || !window.commits.is_empty()
+    }
+
+    /// Why the provider does not observe a window, for the quality report
+    fn unobserved_reason(&self, _window: &Window) -> String {
+        "no commits in the window".to_string()
+    }
+
+    /// Computes the provider's metrics of a window. An error skips the window.
+    fn compute(&mut self, window: &Window, metrics: &mut Metrics) -> Result<(), Box<dyn Error>>;
+
+    /// Fills in the provider's metrics with zeros or empty cells, for a window it does not observe
+    fn impute(&self, _metrics: &mut Metrics, _imputation: Imputation) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_imputation_policy() {
+        let policy: ImputationPolicy = "tokei:zero, sokrates:null".parse().unwrap();
+        assert_eq!(policy.of("tokei"), Imputation::Zero);
+        assert_eq!(policy.of("sokrates"), Imputation::Empty);
+        assert_eq!(policy.of("emails"), Imputation::Zero);
+
+        let policy: ImputationPolicy = "empty,tokei:carry-forward".parse().unwrap();
+        assert_eq!(policy.of("tokei"), Imputation::CarryForward);
+        assert_eq!(policy.of("emails"), Imputation::Empty);
+
+        assert!("commits:zero".parse::<ImputationPolicy>().is_err());
+        assert!("tokei:mean".parse::<ImputationPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_column_order() {
+        let cells = crate::output::cells(&Metrics::default()).unwrap();
+        let columns: Vec<&str> = cells.iter().map(|(column, _)| column.as_str()).collect();
+        // the header of the first releases is kept as is, and the new columns follow it; the Sokrates columns
+        // are those of the first releases
+        let mut original = vec![
+            "measurement_month", "window_start_date", "window_end_date", "commits", "authors", "committers",
+            "minor_contributors", "major_contributors", "directories", "top_level_dirs", "releases", "active_days",
+            "files_modified", "files_added", "files_deleted", "files_renamed", "added_lines", "deleted_lines",
+            "emails", "devs", "emails_thread_starter", "emails_thread_starter_word_count",
+            "emails_thread_starter_characters", "emails_threads", "emails_threads_word_count",
+            "emails_threads_characters", "emails_no_replies", "emails_no_replies_word_count",
+            "emails_no_replies_characters", "emails_jira", "new_contributors", "avg_files_modified_commit", "code",
+            "blanks", "files", "comments", "lines", "programming_lang",
+        ];
+        original.extend(column_names::<SokratesMetrics>().iter().map(String::as_str));
+        assert_eq!(columns[..original.len()], original[..]);
+        assert_eq!(columns[original.len()..original.len() + 3], ["is_imputed", "imputed_families", "source_commit"]);
+        assert_eq!(columns[columns.len() - 4..], ["languages", "language_entropy", "comment_density", "test_code_ratio"]);
+        assert_eq!(column_names::<LanguageMetrics>(), ["languages", "language_entropy", "comment_density", "test_code_ratio"]);
+    }
+
+    #[test]
+    fn test_empty_family_keeps_the_columns() {
+        let mut writer = csv::WriterBuilder::default().has_headers(true).from_writer(vec![]);
+        let observed = Metrics::default();
+        let empty = Metrics {
+            email_metrics: Nullable(None),
+            ..Metrics::default()
+        };
+        writer.serialize(&observed).unwrap();
+        writer.serialize(&empty).unwrap();
+        let data = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        let lines: Vec<&str> = data.lines().collect();
+        assert!(lines[0].contains(",emails,devs,"));
+        assert_eq!(lines[1].split(',').count(), lines[2].split(',').count());
+        assert!(lines[1].contains(",0,0,0,"));
+        assert!(lines[2].contains(",,,"));
+    }
 }
@@ -0,0 +1,181 @@
+// src/online_analysis.rs
+//! Analysis of online repositories: the one given with `--git-online-url`, or a batch listed in a
+//! `--git-online-urls` file. Repositories are analyzed in parallel on a pool of `--threads` threads, with
+//! the commit-file analysis (clone-based or over the forge API) or the full monthly metrics.
+use std::error::Error;
+
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use log::{error, info};
+use rayon::prelude::*;
+
+use crate::{commit_metrics_clone, commit_metrics_graphql, progress, Args};
+
+const DEFAULT_START_DATE: &str = "1970-01-01";
+
+#[derive(Debug, Clone)]
+pub struct OnlineRepo {
+    pub url: String,
+    pub start_date: String,
+    /// Empty to analyze up to the repository's last commit
+    pub end_date: String,
+    pub status: String,
+}
+
+impl OnlineRepo {
+    /// The repository given with `--git-online-url`, with the `--online-*` dates and status
+    pub fn from_args(url: &str, args: &Args) -> OnlineRepo {
+        let defaults = Defaults::new(args);
+        OnlineRepo {
+            url: url.to_string(),
+            start_date: defaults.start_date,
+            end_date: defaults.end_date,
+            status: defaults.status,
+        }
+    }
+}
+
+/// Dates and status of the repositories that do not set their own
+struct Defaults {
+    start_date: String,
+    end_date: String,
+    status: String,
+}
+
+impl Defaults {
+    fn new(args: &Args) -> Defaults {
+        Defaults {
+            start_date: args.flag_online_start_date.clone().unwrap_or_else(|| DEFAULT_START_DATE.to_string()),
+            end_date: args.flag_online_end_date.clone().unwrap_or_default(),
+            status: args.flag_online_status.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// Reads a list of repositories, one `url[,start_date,end_date,status]` per line. Blank lines and lines
+/// starting with `#` are skipped, and empty fields fall back to the `--online-*` flags. Two repositories
+/// with the same host, owner and name would write the same tables, and are rejected.
+pub fn read_online_repos(path: &str, args: &Args) -> Result<Vec<OnlineRepo>, Box<dyn Error>> {
+    let content = std::fs::read_to_string(path)?;
+    parse_online_repos(&content, &Defaults::new(args)).map_err(|e| format!("{}: {}", path, e).into())
+}
+
+fn parse_online_repos(content: &str, defaults: &Defaults) -> Result<Vec<OnlineRepo>, String> {
+    let mut repos = Vec::new();
+    // the line of each repository, by the key of its tables
+    let mut keys: HashMap<String, usize> = HashMap::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if fields.len() > 4 {
+            return Err(format!("line {} has more than 4 fields", index + 1));
+        }
+        let field = |i: usize, default: &str| {
+            fields.get(i).filter(|f| !f.is_empty()).map_or_else(|| default.to_string(), |f| f.to_string())
+        };
+        let repo = OnlineRepo {
+            url: fields[0].to_string(),
+            start_date: field(1, &defaults.start_date),
+            end_date: field(2, &defaults.end_date),
+            status: field(3, &defaults.status),
+        };
+        for date in [&repo.start_date, &repo.end_date].into_iter().filter(|date| !date.is_empty()) {
+            if NaiveDate::parse_from_str(date, "%Y-%m-%d").is_err() {
+                return Err(format!("line {} has an invalid date {} (expected YYYY-MM-DD)", index + 1, date));
+            }
+        }
+        let key = commit_metrics_clone::repo_key(&repo.url);
+        if let Some(first) = keys.insert(key.clone(), index + 1) {
+            return Err(format!("line {} repeats the repository {} of line {}", index + 1, key, first));
+        }
+        repos.push(repo);
+    }
+    Ok(repos)
+}
+
+/// Analyzes the repositories in parallel, at most `--threads` at a time. With `--commit-devs-files` each
+/// repository gets the commit-file CSV, cloned or over the forge API with `--commit-graphql`; otherwise
+/// it is cloned for the full monthly metrics. A failing repository is logged and does not stop the others.
+pub fn analyze_online_repos(repos: &[OnlineRepo], args: &Args) -> Result<(), Box<dyn Error>> {
+    if args.flag_commit_graphql && !args.flag_commit_devs_files {
+        return Err("--commit-graphql only supports the commit-file analysis; add --commit-devs-files".into());
+    }
+    let threads = args.flag_threads.unwrap_or(4);
+    info!("Analyzing {} online repositories on {} threads", repos.len(), threads);
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build()?;
+    progress::start(repos.len(), args.flag_progress_file.as_deref());
+    // the errors, as strings since they are collected across threads
+    let failed: Vec<String> = pool.install(|| {
+        repos
+            .par_iter()
+            .filter_map(|repo| {
+                let name = commit_metrics_clone::repo_key(&repo.url);
+                progress::project_started(&name);
+                let result = if !args.flag_commit_devs_files {
+                    commit_metrics_clone::analyze_online_repo_stats(
+                        &repo.url,
+                        args,
+                        &repo.start_date,
+                        &repo.end_date,
+                        &repo.status,
+                    )
+                } else if args.flag_commit_graphql {
+                    commit_metrics_graphql::analyze_online_repo(&repo.url, args, &repo.start_date, &repo.end_date, &repo.status)
+                } else {
+                    commit_metrics_clone::analyze_online_repo(&repo.url, args, &repo.start_date, &repo.end_date, &repo.status)
+                };
+                progress::project_finished(&name, result.is_ok());
+                result.err().map(|e| {
+                    error!("{} - online analysis failed: {}", repo.url, e);
+                    e.to_string()
+                })
+            })
+            .collect()
+    });
+    progress::finish();
+    info!("Analyzed {} of {} online repositories", repos.len() - failed.len(), repos.len());
+    match failed.as_slice() {
+        [] => Ok(()),
+        // a single repository fails with its own error, as with --git-online-url
+        [error] if repos.len() == 1 => Err(error.clone().into()),
+        _ => Err(format!("{} of {} online repositories failed", failed.len(), repos.len()).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_online_repos() {
+        let defaults = Defaults {
+            start_date: DEFAULT_START_DATE.to_string(),
+            end_date: String::new(),
+            status: "graduated".to_string(),
+        };
+        let content = "# url,start,end,status\n\
+                       https://github.com/apache/hunter.git,2020-11-14,2025-02-05,retired\n\
+                       \n\
+                       https://gitlab.com/group/project, , 2021-01-31\n\
+                       https://gitlab.com/other/hunter\n";
+        let repos = parse_online_repos(content, &defaults).unwrap();
+        assert_eq!(repos.len(), 3);
+        assert_eq!(repos[2].end_date, "");
+        assert_eq!(repos[0].start_date, "2020-11-14");
+        assert_eq!(repos[0].status, "retired");
+        assert_eq!(repos[1].url, "https://gitlab.com/group/project");
+        assert_eq!(repos[1].start_date, DEFAULT_START_DATE);
+        assert_eq!(repos[1].end_date, "2021-01-31");
+        assert_eq!(repos[1].status, "graduated");
+
+        let err = parse_online_repos("https://github.com/apache/hunter.git,14/11/2020", &defaults).unwrap_err();
+        assert!(err.contains("line 1"));
+        let err = parse_online_repos("https://github.com/apache/hunter\ngit@github.com:apache/hunter.git", &defaults)
+            .unwrap_err();
+        assert!(err.contains("line 2 repeats the repository github.com/apache/hunter of line 1"));
+    }
+}
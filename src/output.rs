@@ -0,0 +1,902 @@
+// src/output.rs
+//! Output tables written as CSV, Parquet or JSON Lines, selected with `--format`. Rows are any `Serialize`
+//! struct whose fields (flattened structs included) are plain values, as for the CSV writers. Parquet columns
+//! are typed from the Rust types of the fields, and missing values (e.g. a family imputed as empty) are nulls.
+use std::error::Error;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray, UInt64Array};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use indexmap::IndexMap;
+use log::error;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde::ser::{Impossible, SerializeMap, SerializeStruct};
+use serde::{Deserialize, Serialize, Serializer};
+
+/// Rows of a Parquet row group
+const ROW_GROUP_ROWS: usize = 65536;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    Parquet,
+    JsonLines,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::JsonLines => "jsonl",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(OutputFormat::Csv),
+            "parquet" => Ok(OutputFormat::Parquet),
+            "jsonl" | "json-lines" => Ok(OutputFormat::JsonLines),
+            other => Err(format!("unknown output format {}, expected csv, parquet or jsonl", other)),
+        }
+    }
+}
+
+/// An output table; `finish` must be called once all rows are written, which writes the Parquet footer
+pub struct TableWriter {
+    path: String,
+    sink: Sink,
+}
+
+enum Sink {
+    /// The writer, and whether the header is still to be written
+    Csv(Box<csv::Writer<File>>, bool),
+    JsonLines(BufWriter<File>),
+    Parquet(ParquetTable),
+}
+
+impl TableWriter {
+    /// Creates `{stem}.csv`, `{stem}.parquet` or `{stem}.jsonl`
+    pub fn create(stem: &str, format: OutputFormat) -> Result<TableWriter, Box<dyn Error>> {
+        let path = format!("{}.{}", stem, format.extension());
+        let file = File::create(&path)?;
+        let sink = match format {
+            OutputFormat::Csv => {
+                Sink::Csv(Box::new(csv::WriterBuilder::default().has_headers(false).from_writer(file)), true)
+            }
+            OutputFormat::JsonLines => Sink::JsonLines(BufWriter::new(file)),
+            OutputFormat::Parquet => Sink::Parquet(ParquetTable::new(file)),
+        };
+        Ok(TableWriter { path, sink })
+    }
+
+    /// Appends rows to the existing CSV or JSON Lines table at `path`, whose header is already written.
+    /// Parquet tables cannot be appended to.
+    pub fn append(path: &str, format: OutputFormat) -> Result<TableWriter, Box<dyn Error>> {
+        let file = OpenOptions::new().append(true).open(path)?;
+        let sink = match format {
+            OutputFormat::Csv => {
+                Sink::Csv(Box::new(csv::WriterBuilder::default().has_headers(false).from_writer(file)), false)
+            }
+            OutputFormat::JsonLines => Sink::JsonLines(BufWriter::new(file)),
+            OutputFormat::Parquet => return Err("cannot append to a Parquet table".into()),
+        };
+        Ok(TableWriter { path: path.to_string(), sink })
+    }
+
+    /// Types the Parquet columns after a template row, so that columns which are null in every row still
+    /// get their type, e.g. `Stats::default()`, and writes the CSV header even if no row follows
+    pub fn with_template<T: Serialize>(mut self, template: &T) -> Result<TableWriter, Box<dyn Error>> {
+        match &mut self.sink {
+            Sink::Csv(writer, header) => write_header(writer, header, template)?,
+            Sink::Parquet(table) => {
+                for (name, cell) in cells(template)? {
+                    table.add_column(name, cell.data_type());
+                }
+            }
+            Sink::JsonLines(_) => {}
+        }
+        Ok(self)
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn serialize<T: Serialize>(&mut self, row: T) -> Result<(), Box<dyn Error>> {
+        match &mut self.sink {
+            Sink::Csv(writer, header) => {
+                write_header(writer, header, &row)?;
+                writer.serialize(row)?;
+            }
+            Sink::JsonLines(writer) => {
+                serde_json::to_writer(&mut *writer, &row)?;
+                writer.write_all(b"\n")?;
+            }
+            Sink::Parquet(table) => table.push(cells(&row)?)?,
+        }
+        Ok(())
+    }
+
+    /// Flushes the CSV and JSON Lines rows written so far to the file; Parquet rows are written by `finish`
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        match &mut self.sink {
+            Sink::Csv(writer, _) => writer.flush()?,
+            Sink::JsonLines(writer) => writer.flush()?,
+            Sink::Parquet(_) => {}
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<(), Box<dyn Error>> {
+        match self.sink {
+            Sink::Csv(mut writer, _) => writer.flush()?,
+            Sink::JsonLines(mut writer) => writer.flush()?,
+            Sink::Parquet(table) => table.close()?,
+        }
+        Ok(())
+    }
+}
+
+/// Writes the names of the cells of `row` as the CSV header, if it is still to be written
+fn write_header<T: Serialize>(writer: &mut csv::Writer<File>, header: &mut bool, row: &T) -> Result<(), Box<dyn Error>> {
+    if *header {
+        writer.write_record(cells(row)?.iter().map(|(name, _)| name))?;
+        *header = false;
+    }
+    Ok(())
+}
+
+/// Writes rows to a new `stem` table, with the Parquet columns typed after `template` when given, and returns
+/// the table's path. A row that cannot be serialized is logged and skipped.
+pub fn write_table<T: Serialize>(
+    stem: &str,
+    format: OutputFormat,
+    template: Option<&T>,
+    rows: impl IntoIterator<Item = T>,
+) -> Result<String, Box<dyn Error>> {
+    let mut writer = TableWriter::create(stem, format)?;
+    if let Some(template) = template {
+        writer = writer.with_template(template)?;
+    }
+    for row in rows {
+        if let Err(e) = writer.serialize(row) {
+            error!("{} - cannot serialize row: {}", stem, e);
+        }
+    }
+    let path = writer.path().to_string();
+    writer.finish()?;
+    Ok(path)
+}
+
+/// A Parquet file whose schema is fixed once every row is known, so that the column types do not depend on the
+/// order of the rows: each column takes the type of its values, widened to `Int64` or `Float64` for mixed numbers
+/// and to `Utf8` for values of different kinds, and `Utf8` when no row has a value. Until then, every full row
+/// group is spooled to a temporary file.
+struct ParquetTable {
+    file: File,
+    columns: IndexMap<String, Option<DataType>>,
+    rows: Vec<Vec<Cell>>,
+    row_group_rows: usize,
+    /// The spooled row groups, one JSON array of cells per row
+    spool: Option<BufWriter<File>>,
+}
+
+impl ParquetTable {
+    fn new(file: File) -> ParquetTable {
+        ParquetTable { file, columns: IndexMap::new(), rows: vec![], row_group_rows: ROW_GROUP_ROWS, spool: None }
+    }
+
+    /// The index of a column, whose type is widened to hold `data_type`. Every buffered row has a cell for every
+    /// column; spooled rows may miss the columns added after them.
+    fn add_column(&mut self, name: String, data_type: Option<DataType>) -> usize {
+        if let Some((index, _, column_type)) = self.columns.get_full_mut(&name) {
+            *column_type = widen(column_type.take(), data_type);
+            return index;
+        }
+        self.columns.insert(name, data_type);
+        for row in self.rows.iter_mut() {
+            row.push(Cell::Null);
+        }
+        self.columns.len() - 1
+    }
+
+    fn push(&mut self, cells: Vec<(String, Cell)>) -> Result<(), Box<dyn Error>> {
+        let mut row = vec![Cell::Null; self.columns.len()];
+        for (name, cell) in cells {
+            let index = self.add_column(name, cell.data_type());
+            if index >= row.len() {
+                row.resize(index + 1, Cell::Null);
+            }
+            row[index] = cell;
+        }
+        self.rows.push(row);
+        if self.rows.len() >= self.row_group_rows {
+            self.spool_rows()?;
+        }
+        Ok(())
+    }
+
+    fn spool_rows(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.spool.is_none() {
+            self.spool = Some(BufWriter::new(tempfile::tempfile()?));
+        }
+        if let Some(spool) = self.spool.as_mut() {
+            for row in self.rows.drain(..) {
+                serde_json::to_writer(&mut *spool, &row)?;
+                spool.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the spooled and the buffered rows with the schema of all of them, and the footer
+    fn close(self) -> Result<(), Box<dyn Error>> {
+        if self.columns.is_empty() {
+            return Err("cannot write a Parquet table without columns".into());
+        }
+        let fields: Vec<Field> = self
+            .columns
+            .iter()
+            .map(|(name, data_type)| Field::new(name.as_str(), data_type.clone().unwrap_or(DataType::Utf8), true))
+            .collect();
+        let schema = Arc::new(Schema::new(fields));
+        let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+        let mut writer = ArrowWriter::try_new(self.file, schema.clone(), Some(properties))?;
+        if let Some(spool) = self.spool {
+            let mut spool = spool.into_inner().map_err(|e| e.into_error())?;
+            spool.seek(SeekFrom::Start(0))?;
+            let mut rows = Vec::with_capacity(self.row_group_rows);
+            for line in BufReader::new(spool).lines() {
+                rows.push(serde_json::from_str::<Vec<Cell>>(&line?)?);
+                if rows.len() >= self.row_group_rows {
+                    write_row_group(&mut writer, &schema, &rows)?;
+                    rows.clear();
+                }
+            }
+            write_row_group(&mut writer, &schema, &rows)?;
+        }
+        write_row_group(&mut writer, &schema, &self.rows)?;
+        writer.close()?;
+        Ok(())
+    }
+}
+
+/// Writes rows as a row group; the cells missing at the end of a row are nulls
+fn write_row_group(writer: &mut ArrowWriter<File>, schema: &SchemaRef, rows: &[Vec<Cell>]) -> Result<(), Box<dyn Error>> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+    let columns = schema
+        .fields()
+        .iter()
+        .enumerate()
+        .map(|(index, field)| column(field, rows.iter().map(|row| row.get(index).unwrap_or(&Cell::Null))))
+        .collect::<Result<Vec<_>, _>>()?;
+    writer.write(&RecordBatch::try_new(schema.clone(), columns)?)?;
+    Ok(())
+}
+
+/// The type of a column holding values of both types
+fn widen(a: Option<DataType>, b: Option<DataType>) -> Option<DataType> {
+    let numeric = |t: &DataType| matches!(t, DataType::Int64 | DataType::UInt64 | DataType::Float64);
+    match (a, b) {
+        (None, t) | (t, None) => t,
+        (Some(a), Some(b)) if a == b => Some(a),
+        (Some(a), Some(b)) if numeric(&a) && numeric(&b) => {
+            if a == DataType::Float64 || b == DataType::Float64 {
+                Some(DataType::Float64)
+            } else {
+                Some(DataType::Int64)
+            }
+        }
+        _ => Some(DataType::Utf8),
+    }
+}
+
+/// The Arrow array of a column's cells
+fn column<'c>(field: &Field, cells: impl Iterator<Item = &'c Cell>) -> Result<ArrayRef, String> {
+    let mismatch = |cell: &Cell| format!("column {} of type {} cannot hold {:?}", field.name(), field.data_type(), cell);
+    let array: ArrayRef = match field.data_type() {
+        DataType::Boolean => Arc::new(
+            cells
+                .map(|cell| match cell {
+                    Cell::Null => Ok(None),
+                    Cell::Bool(value) => Ok(Some(*value)),
+                    other => Err(mismatch(other)),
+                })
+                .collect::<Result<BooleanArray, _>>()?,
+        ),
+        DataType::Int64 => Arc::new(
+            cells
+                .map(|cell| match cell {
+                    Cell::Null => Ok(None),
+                    Cell::Int(value) => Ok(Some(*value)),
+                    Cell::UInt(value) => i64::try_from(*value).map(Some).map_err(|_| mismatch(cell)),
+                    other => Err(mismatch(other)),
+                })
+                .collect::<Result<Int64Array, _>>()?,
+        ),
+        DataType::UInt64 => Arc::new(
+            cells
+                .map(|cell| match cell {
+                    Cell::Null => Ok(None),
+                    Cell::UInt(value) => Ok(Some(*value)),
+                    Cell::Int(value) => u64::try_from(*value).map(Some).map_err(|_| mismatch(cell)),
+                    other => Err(mismatch(other)),
+                })
+                .collect::<Result<UInt64Array, _>>()?,
+        ),
+        DataType::Float64 => Arc::new(
+            cells
+                .map(|cell| match cell {
+                    Cell::Null => Ok(None),
+                    Cell::Float(value) => Ok(Some(*value)),
+                    Cell::Int(value) => Ok(Some(*value as f64)),
+                    Cell::UInt(value) => Ok(Some(*value as f64)),
+                    other => Err(mismatch(other)),
+                })
+                .collect::<Result<Float64Array, _>>()?,
+        ),
+        DataType::Utf8 => Arc::new(cells.map(Cell::text).collect::<StringArray>()),
+        other => return Err(format!("column {} has the unsupported type {}", field.name(), other)),
+    };
+    Ok(array)
+}
+
+/// A value of a row, with the type it was serialized as
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Cell {
+    Null,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(#[serde(with = "float_bits")] f64),
+    Str(String),
+}
+
+/// Spooled floats are kept by their bits, as JSON has no NaN or infinity
+mod float_bits {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(value.to_bits())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        u64::deserialize(deserializer).map(f64::from_bits)
+    }
+}
+
+impl Cell {
+    fn data_type(&self) -> Option<DataType> {
+        match self {
+            Cell::Null => None,
+            Cell::Bool(_) => Some(DataType::Boolean),
+            Cell::Int(_) => Some(DataType::Int64),
+            Cell::UInt(_) => Some(DataType::UInt64),
+            Cell::Float(_) => Some(DataType::Float64),
+            Cell::Str(_) => Some(DataType::Utf8),
+        }
+    }
+
+    fn text(&self) -> Option<String> {
+        match self {
+            Cell::Null => None,
+            Cell::Bool(value) => Some(value.to_string()),
+            Cell::Int(value) => Some(value.to_string()),
+            Cell::UInt(value) => Some(value.to_string()),
+            Cell::Float(value) => Some(value.to_string()),
+            Cell::Str(value) => Some(value.clone()),
+        }
+    }
+}
+
+/// The named cells of a row, in the order they were serialized
+pub fn cells<T: Serialize>(row: &T) -> Result<Vec<(String, Cell)>, RowError> {
+    let mut serializer = RowSerializer::default();
+    row.serialize(&mut serializer)?;
+    Ok(serializer.cells)
+}
+
+#[derive(Debug)]
+pub struct RowError(String);
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for RowError {}
+
+impl serde::ser::Error for RowError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        RowError(msg.to_string())
+    }
+}
+
+fn unsupported(what: &str) -> RowError {
+    RowError(format!("a table row cannot hold {}", what))
+}
+
+/// Serializes a row: a struct, or a map as structs with flattened fields are serialized
+#[derive(Default)]
+struct RowSerializer {
+    cells: Vec<(String, Cell)>,
+    key: Option<String>,
+}
+
+impl<'r> Serializer for &'r mut RowSerializer {
+    type Ok = ();
+    type Error = RowError;
+    type SerializeSeq = Impossible<(), RowError>;
+    type SerializeTuple = Impossible<(), RowError>;
+    type SerializeTupleStruct = Impossible<(), RowError>;
+    type SerializeTupleVariant = Impossible<(), RowError>;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Impossible<(), RowError>;
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, RowError> {
+        Ok(self)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self, RowError> {
+        Ok(self)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _name: &'static str, value: &T) -> Result<(), RowError> {
+        value.serialize(self)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), RowError> {
+        value.serialize(self)
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<(), RowError> {
+        Err(unsupported("a single value"))
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<(), RowError> {
+        Err(unsupported("a single value"))
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<(), RowError> {
+        Err(unsupported("a single value"))
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<(), RowError> {
+        Err(unsupported("a single value"))
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<(), RowError> {
+        Err(unsupported("a single value"))
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<(), RowError> {
+        Err(unsupported("a single value"))
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<(), RowError> {
+        Err(unsupported("a single value"))
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<(), RowError> {
+        Err(unsupported("a single value"))
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<(), RowError> {
+        Err(unsupported("a single value"))
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<(), RowError> {
+        Err(unsupported("a single value"))
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<(), RowError> {
+        Err(unsupported("a single value"))
+    }
+
+    fn serialize_char(self, _v: char) -> Result<(), RowError> {
+        Err(unsupported("a single value"))
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<(), RowError> {
+        Err(unsupported("a single value"))
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<(), RowError> {
+        Err(unsupported("bytes"))
+    }
+
+    fn serialize_none(self) -> Result<(), RowError> {
+        Err(unsupported("a single value"))
+    }
+
+    fn serialize_unit(self) -> Result<(), RowError> {
+        Err(unsupported("a single value"))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), RowError> {
+        Err(unsupported("a single value"))
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, _variant: &'static str) -> Result<(), RowError> {
+        Err(unsupported("a single value"))
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<(), RowError> {
+        Err(unsupported("an enum"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, RowError> {
+        Err(unsupported("a sequence"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, RowError> {
+        Err(unsupported("a tuple"))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct, RowError> {
+        Err(unsupported("a tuple"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, RowError> {
+        Err(unsupported("an enum"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, RowError> {
+        Err(unsupported("an enum"))
+    }
+}
+
+impl<'r> SerializeStruct for &'r mut RowSerializer {
+    type Ok = ();
+    type Error = RowError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), RowError> {
+        let cell = value.serialize(CellSerializer)?;
+        self.cells.push((key.to_string(), cell));
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), RowError> {
+        Ok(())
+    }
+}
+
+impl<'r> SerializeMap for &'r mut RowSerializer {
+    type Ok = ();
+    type Error = RowError;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), RowError> {
+        match key.serialize(CellSerializer)? {
+            Cell::Str(key) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            other => Err(RowError(format!("a column name must be a string, not {:?}", other))),
+        }
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), RowError> {
+        let key = self.key.take().ok_or_else(|| RowError("a value without a column name".to_string()))?;
+        let cell = value.serialize(CellSerializer)?;
+        self.cells.push((key, cell));
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), RowError> {
+        Ok(())
+    }
+}
+
+/// Serializes a field of a row into a cell
+struct CellSerializer;
+
+impl Serializer for CellSerializer {
+    type Ok = Cell;
+    type Error = RowError;
+    type SerializeSeq = Impossible<Cell, RowError>;
+    type SerializeTuple = Impossible<Cell, RowError>;
+    type SerializeTupleStruct = Impossible<Cell, RowError>;
+    type SerializeTupleVariant = Impossible<Cell, RowError>;
+    type SerializeMap = Impossible<Cell, RowError>;
+    type SerializeStruct = Impossible<Cell, RowError>;
+    type SerializeStructVariant = Impossible<Cell, RowError>;
+
+    fn serialize_bool(self, v: bool) -> Result<Cell, RowError> {
+        Ok(Cell::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Cell, RowError> {
+        Ok(Cell::Int(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Cell, RowError> {
+        Ok(Cell::Int(v.into()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Cell, RowError> {
+        Ok(Cell::Int(v.into()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Cell, RowError> {
+        Ok(Cell::Int(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Cell, RowError> {
+        Ok(Cell::UInt(v.into()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Cell, RowError> {
+        Ok(Cell::UInt(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Cell, RowError> {
+        Ok(Cell::UInt(v.into()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Cell, RowError> {
+        Ok(Cell::UInt(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Cell, RowError> {
+        Ok(Cell::Float(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Cell, RowError> {
+        Ok(Cell::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Cell, RowError> {
+        Ok(Cell::Str(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Cell, RowError> {
+        Ok(Cell::Str(v.to_string()))
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Cell, RowError> {
+        Err(unsupported("bytes"))
+    }
+
+    fn serialize_none(self) -> Result<Cell, RowError> {
+        Ok(Cell::Null)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Cell, RowError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Cell, RowError> {
+        Ok(Cell::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Cell, RowError> {
+        Ok(Cell::Null)
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<Cell, RowError> {
+        Ok(Cell::Str(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _name: &'static str, value: &T) -> Result<Cell, RowError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Cell, RowError> {
+        Err(unsupported("an enum with data"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, RowError> {
+        Err(unsupported("a nested sequence"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, RowError> {
+        Err(unsupported("a nested tuple"))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct, RowError> {
+        Err(unsupported("a nested tuple"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, RowError> {
+        Err(unsupported("an enum with data"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, RowError> {
+        Err(unsupported("a nested map; flatten it into the row"))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct, RowError> {
+        Err(unsupported("a nested struct; flatten it into the row"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, RowError> {
+        Err(unsupported("an enum with data"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Nullable;
+
+    #[derive(Serialize, Default)]
+    struct Code {
+        lines: usize,
+        programming_lang: String,
+    }
+
+    #[derive(Serialize, Default)]
+    struct Row {
+        project: String,
+        timestamp: i64,
+        ratio: f64,
+        imputed: bool,
+        #[serde(flatten)]
+        code: Nullable<Code>,
+    }
+
+    #[test]
+    fn test_cells_keep_types_and_nulls() {
+        let row = Row { project: "hunter".to_string(), timestamp: 7, ratio: 0.5, imputed: true, code: Nullable(None) };
+        let cells = cells(&row).unwrap();
+        let names: Vec<&str> = cells.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["project", "timestamp", "ratio", "imputed", "lines", "programming_lang"]);
+        assert_eq!(cells[1].1, Cell::Int(7));
+        assert_eq!(cells[2].1, Cell::Float(0.5));
+        assert_eq!(cells[4].1, Cell::Null);
+    }
+
+    #[test]
+    fn test_write_every_format() {
+        let dir = tempfile::tempdir().unwrap();
+        for format in [OutputFormat::Csv, OutputFormat::Parquet, OutputFormat::JsonLines] {
+            let stem = dir.path().join("table");
+            let mut writer = TableWriter::create(stem.to_str().unwrap(), format)
+                .unwrap()
+                .with_template(&Row::default())
+                .unwrap();
+            writer.serialize(Row { code: Nullable(None), ..Row::default() }).unwrap();
+            writer.serialize(Row::default()).unwrap();
+            let path = writer.path().to_string();
+            writer.finish().unwrap();
+            assert!(std::fs::metadata(&path).unwrap().len() > 0, "{}", path);
+        }
+        let jsonl = std::fs::read_to_string(dir.path().join("table.jsonl")).unwrap();
+        assert!(jsonl.lines().next().unwrap().contains("\"lines\":null"));
+
+        // a column that is null in the first rows takes the type of its first value
+        let mut parquet = ParquetTable::new(tempfile::tempfile().unwrap());
+        parquet.add_column("lines".to_string(), None);
+        parquet.push(vec![("lines".to_string(), Cell::Null)]).unwrap();
+        assert_eq!(parquet.columns["lines"], None);
+        parquet.push(vec![("lines".to_string(), Cell::UInt(3)), ("ratio".to_string(), Cell::Int(-1))]).unwrap();
+        assert_eq!(parquet.columns["lines"], Some(DataType::UInt64));
+        assert_eq!(parquet.rows[0].len(), 2);
+        parquet.close().unwrap();
+    }
+
+    #[test]
+    fn test_header_append_and_bad_rows() {
+        #[derive(Serialize, Default)]
+        struct Plain {
+            project: String,
+            lines: usize,
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let stem = dir.path().join("plain");
+        let stem = stem.to_str().unwrap();
+        // the template writes the header of a table without rows
+        let path = write_table(stem, OutputFormat::Csv, Some(&Plain::default()), vec![]).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "project,lines\n");
+
+        let mut writer = TableWriter::append(&path, OutputFormat::Csv).unwrap();
+        writer.serialize(Plain { project: "hunter".to_string(), lines: 3 }).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "project,lines\nhunter,3\n");
+        assert!(TableWriter::append(&path, OutputFormat::Parquet).is_err());
+
+        // a row that cannot be serialized is skipped
+        struct Checked(Result<Plain, &'static str>);
+        impl Serialize for Checked {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                match &self.0 {
+                    Ok(row) => row.serialize(serializer),
+                    Err(e) => Err(serde::ser::Error::custom(e)),
+                }
+            }
+        }
+        let rows = vec![Checked(Err("bad row")), Checked(Ok(Plain { project: "hunter".to_string(), lines: 3 }))];
+        let path = write_table(stem, OutputFormat::Csv, None, rows).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "project,lines\nhunter,3\n");
+    }
+
+    #[test]
+    fn test_widen_mixed_numbers() {
+        assert_eq!(widen(Some(DataType::UInt64), Some(DataType::Int64)), Some(DataType::Int64));
+        assert_eq!(widen(Some(DataType::Int64), Some(DataType::Float64)), Some(DataType::Float64));
+        assert_eq!(widen(None, Some(DataType::Boolean)), Some(DataType::Boolean));
+        assert_eq!(widen(Some(DataType::Boolean), Some(DataType::Utf8)), Some(DataType::Utf8));
+    }
+
+    #[test]
+    fn test_types_of_spooled_rows() {
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("table.parquet");
+        let mut parquet = ParquetTable::new(File::create(&path).unwrap());
+        parquet.row_group_rows = 2;
+        let rows = vec![
+            vec![("lines".to_string(), Cell::UInt(3)), ("ratio".to_string(), Cell::Float(f64::NAN))],
+            vec![("lines".to_string(), Cell::UInt(4)), ("ratio".to_string(), Cell::Int(1))],
+            // after the first row group is spooled: a negative number, a string and a new column
+            vec![("lines".to_string(), Cell::Int(-1)), ("ratio".to_string(), Cell::Str("n/a".to_string()))],
+            vec![("lines".to_string(), Cell::Null), ("late".to_string(), Cell::Bool(true))],
+        ];
+        for row in rows {
+            parquet.push(row).unwrap();
+        }
+        parquet.close().unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap().build().unwrap();
+        let batches: Vec<RecordBatch> = reader.collect::<Result<_, _>>().unwrap();
+        let schema = batches[0].schema();
+        let types: Vec<_> = schema.fields().iter().map(|f| (f.name().as_str(), f.data_type().clone())).collect();
+        assert_eq!(types, vec![("lines", DataType::Int64), ("ratio", DataType::Utf8), ("late", DataType::Boolean)]);
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 4);
+        let ratios = batches[0].column(1).as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(ratios.value(0), "NaN");
+    }
+}
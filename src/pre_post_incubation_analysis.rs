@@ -1,6 +1,6 @@
 use crate::{
-    project::Project, remove_sokrates_temp, repo::Repo,
-    statistics::Stats, utils::convert_time, Args,
+    database, output, progress, project::Project, remove_sokrates_temp, repo::Repo,
+    statistics::Stats, tokei_metrics::LanguageRow, utils::convert_time, Args,
 };
 use chrono::{DateTime, Utc};
 use git2::Repository;
@@ -14,6 +14,7 @@ pub fn run(
     data_folder_path: &str,
     p: &Project,
     analysis_name: &str,
+    records: &mut database::ProjectRecords,
 ) {
     remove_sokrates_temp(&repo.repo);
     let checkout = repo.checkout_master_main_trunk(&args);
@@ -24,26 +25,22 @@ pub fn run(
             &repo.end_date,
             &p.status,
             &java_path,
-        );
+        )
+        .with_analysis(analysis_name);
         let metrics = stats.compute_statistics(repo, &args);
         if let Ok(metrics) = metrics {
-            let mut writer = csv::WriterBuilder::default()
-                .has_headers(true)
-                .from_path(format!(
-                    "{}/{}-{}.csv",
-                    data_folder_path,
-                    p.name.as_str(),
-                    analysis_name
-                ))
-                .unwrap();
-
-            for m in metrics {
-                match writer.serialize(m) {
-                    Ok(()) => {}
-                    Err(_e) => {
-                        error!("{} - cannot serialize metric value", p.name.as_str());
-                    }
-                }
+            if let Err(e) = records.add_commits(repo).and_then(|()| records.add_analysis(analysis_name, &metrics)) {
+                error!("{} - cannot prepare the metrics for the database: {}", p.name.as_str(), e);
+            }
+            let format = args.flag_format.unwrap_or_default();
+            let languages = LanguageRow::of(p.name.as_str(), metrics.iter().map(Stats::metrics));
+            let languages_stem = format!("{}/{}-{}-languages", data_folder_path, p.name.as_str(), analysis_name);
+            if let Err(e) = output::write_table(&languages_stem, format, Some(&LanguageRow::default()), languages) {
+                error!("{} - cannot write the language metrics: {}", p.name.as_str(), e);
+            }
+            let stem = format!("{}/{}-{}", data_folder_path, p.name.as_str(), analysis_name);
+            if let Err(e) = output::write_table(&stem, format, Some(&Stats::default()), metrics) {
+                error!("{} - cannot write the metrics: {}", p.name.as_str(), e);
             }
         } else {
             error!("{} cannot extract the metrics", p.name.as_str());
@@ -75,10 +72,15 @@ pub fn pre_post_analysis(
         .collect::<indexmap::IndexSet<_>>();
 
     // let mut projects_to_analyze: Vec<&Project> = vec![];
+    progress::start(projects_to_analyze.len(), args.flag_progress_file.as_deref());
     projects_to_analyze.iter().par_bridge().for_each(|p| {
+        progress::project_started(p.name.as_str());
         let git_repo = Repository::open(p.path.as_str());
+        let opened = git_repo.is_ok();
 
         if let Ok(repo) = git_repo {
+            // the three periods are stored in one transaction
+            let mut records = database::ProjectRecords::new(&p.name, &p.status);
             #[allow(clippy::unwrap_used)]
             let start_timestamp = chrono::DateTime::parse_from_rfc3339(
                 format!("{}{}", p.start_date, "T00:00:00+00:00").as_str(),
@@ -144,9 +146,9 @@ pub fn pre_post_analysis(
                                 p.path
                             );
 
-                            analyze_pre_incubation(&repo, p, first_commit_time, args, java_path, data_folder_path);
-                            analyze_during_incubation(&repo, p, args, java_path, data_folder_path);
-                            analyze_post_incubation(&repo, commit_time, final_timestamp, p, args, java_path, data_folder_path);
+                            analyze_pre_incubation(&repo, p, first_commit_time, args, java_path, data_folder_path, &mut records);
+                            analyze_during_incubation(&repo, p, args, java_path, data_folder_path, &mut records);
+                            analyze_post_incubation(&repo, commit_time, final_timestamp, p, args, java_path, data_folder_path, &mut records);
 
                         }
                     }
@@ -169,19 +171,24 @@ pub fn pre_post_analysis(
                             if let Ok(commit) = commit {
                                 let commit_time = convert_time(&commit.committer().when());
 
-                                analyze_during_incubation(&repo, p, args, java_path, data_folder_path);
-                                analyze_post_incubation(&repo, commit_time, final_timestamp, p, args, java_path, data_folder_path)
+                                analyze_during_incubation(&repo, p, args, java_path, data_folder_path, &mut records);
+                                analyze_post_incubation(&repo, commit_time, final_timestamp, p, args, java_path, data_folder_path, &mut records)
                             }
                         }
                     }
                     
                 }
             }
+            if let Err(e) = records.store() {
+                error!("{} - cannot store the metrics in the database: {}", p.name.as_str(), e);
+            }
         }
+        progress::project_finished(p.name.as_str(), opened);
     });
+    progress::finish();
 }
 
-fn analyze_pre_incubation(repo: &git2::Repository, p: &Project, first_commit_time: String, args: &Args, java_path: &str, data_folder_path: &str) {
+fn analyze_pre_incubation(repo: &git2::Repository, p: &Project, first_commit_time: String, args: &Args, java_path: &str, data_folder_path: &str, records: &mut database::ProjectRecords) {
     let pre_incubation_end_date =
     chrono::NaiveDate::parse_from_str(&p.start_date, "%Y-%m-%d")
         .unwrap()
@@ -213,11 +220,12 @@ fn analyze_pre_incubation(repo: &git2::Repository, p: &Project, first_commit_tim
         data_folder_path,
         p,
         "pre-incubation",
+        records,
     );
 
 }
 
-fn analyze_during_incubation(repo: &git2::Repository, p: &Project, args: &Args, java_path: &str, data_folder_path: &str) {
+fn analyze_during_incubation(repo: &git2::Repository, p: &Project, args: &Args, java_path: &str, data_folder_path: &str, records: &mut database::ProjectRecords) {
     let mut incubation_repo = Repo::new(
         &repo,
         p.name.as_str(),
@@ -241,10 +249,12 @@ fn analyze_during_incubation(repo: &git2::Repository, p: &Project, args: &Args,
         data_folder_path,
         p,
         "during-incubation",
+        records,
     );
 }
 
-fn analyze_post_incubation(repo: &git2::Repository,  commit_time: DateTime<Utc>, final_timestamp: i64, p: &Project, args: &Args, java_path: &str, data_folder_path: &str) {
+#[allow(clippy::too_many_arguments)]
+fn analyze_post_incubation(repo: &git2::Repository,  commit_time: DateTime<Utc>, final_timestamp: i64, p: &Project, args: &Args, java_path: &str, data_folder_path: &str, records: &mut database::ProjectRecords) {
     if commit_time.timestamp() > final_timestamp {
         let time = commit_time.clone().format("%Y-%m-%d").to_string();
 
@@ -278,6 +288,7 @@ fn analyze_post_incubation(repo: &git2::Repository,  commit_time: DateTime<Utc>,
             data_folder_path,
             p,
             "post-incubation",
+            records,
         );
     }
 }
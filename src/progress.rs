@@ -0,0 +1,273 @@
+// src/progress.rs
+//! Live progress of the multi-project runs, which take hours over a cohort: the projects done out of the total, the
+//! window each running project is at, the commits processed per second and the time left. The analyses report into
+//! a shared collector from any rayon thread. A status line goes to stderr, since the console logger is off, and
+//! `--progress-file` keeps a JSON snapshot of the progress up to date for headless servers.
+use std::error::Error;
+use std::io::IsTerminal;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use indexmap::IndexMap;
+use serde::Serialize;
+
+/// How often the status line is redrawn on a terminal
+const TERMINAL_INTERVAL: Duration = Duration::from_millis(500);
+/// How often the status line is printed when stderr is not a terminal, e.g. redirected to a file
+const LOG_INTERVAL: Duration = Duration::from_secs(60);
+/// How often the progress file is rewritten
+const FILE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The window a running project is at
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct Running {
+    pub project: String,
+    /// The window being analyzed, from 1; 0 before the first window
+    pub window: usize,
+    pub windows: usize,
+}
+
+/// The progress of a run, as written to the progress file
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct Snapshot {
+    pub total_projects: usize,
+    /// The projects finished, including the failed ones
+    pub done_projects: usize,
+    pub failed_projects: usize,
+    pub running: Vec<Running>,
+    pub commits: usize,
+    pub commits_per_second: f64,
+    pub elapsed_seconds: u64,
+    /// The estimated seconds left, from the share of projects and windows done so far; none before any progress
+    pub eta_seconds: Option<u64>,
+    pub updated_at: String,
+}
+
+impl Snapshot {
+    /// The status line, e.g. `12/200 projects, 45.3 commits/s, elapsed 1h:02m:03s, ETA 3h:10m:00s | Hunter 34/80`
+    pub fn line(&self) -> String {
+        let mut line = format!("{}/{} projects", self.done_projects, self.total_projects);
+        if self.failed_projects > 0 {
+            line.push_str(&format!(" ({} failed)", self.failed_projects));
+        }
+        line.push_str(&format!(
+            ", {:.1} commits/s, elapsed {}, ETA {}",
+            self.commits_per_second,
+            duration(self.elapsed_seconds),
+            self.eta_seconds.map(duration).unwrap_or_else(|| "unknown".to_string())
+        ));
+        if !self.running.is_empty() {
+            let running: Vec<String> =
+                self.running.iter().map(|r| format!("{} {}/{}", r.project, r.window, r.windows)).collect();
+            line.push_str(&format!(" | {}", running.join(", ")));
+        }
+        line
+    }
+}
+
+fn duration(seconds: u64) -> String {
+    format!("{}h:{:02}m:{:02}s", seconds / 3600, (seconds / 60) % 60, seconds % 60)
+}
+
+struct Run {
+    started: Instant,
+    total: usize,
+    done: usize,
+    failed: usize,
+    /// The window and the number of windows of each running project
+    running: IndexMap<String, (usize, usize)>,
+    commits: usize,
+    file: Option<String>,
+    terminal: bool,
+    printed: Option<Instant>,
+    written: Option<Instant>,
+}
+
+impl Run {
+    fn new(total: usize, file: Option<&str>) -> Run {
+        Run {
+            started: Instant::now(),
+            total,
+            done: 0,
+            failed: 0,
+            running: IndexMap::new(),
+            commits: 0,
+            file: file.map(String::from),
+            terminal: std::io::stderr().is_terminal(),
+            printed: None,
+            written: None,
+        }
+    }
+
+    /// The share of the run that is done: the finished projects, and the windows done of the running ones
+    fn fraction(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        let running: f64 = self
+            .running
+            .values()
+            .filter(|(_, windows)| *windows > 0)
+            .map(|(window, windows)| window.saturating_sub(1) as f64 / *windows as f64)
+            .sum();
+        ((self.done as f64 + running) / self.total as f64).min(1.0)
+    }
+
+    fn snapshot(&self, elapsed: Duration) -> Snapshot {
+        let seconds = elapsed.as_secs_f64();
+        let fraction = self.fraction();
+        Snapshot {
+            total_projects: self.total,
+            done_projects: self.done,
+            failed_projects: self.failed,
+            running: self
+                .running
+                .iter()
+                .map(|(project, (window, windows))| Running {
+                    project: project.clone(),
+                    window: *window,
+                    windows: *windows,
+                })
+                .collect(),
+            commits: self.commits,
+            commits_per_second: if seconds > 0.0 { self.commits as f64 / seconds } else { 0.0 },
+            elapsed_seconds: elapsed.as_secs(),
+            eta_seconds: (fraction > 0.0).then(|| (seconds * (1.0 - fraction) / fraction).round() as u64),
+            updated_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// Prints the status line and writes the progress file, at most once per interval unless forced
+    fn report(&mut self, force: bool) {
+        let now = Instant::now();
+        let due = |last: Option<Instant>, interval: Duration| force || last.is_none_or(|last| now - last >= interval);
+        let print = due(self.printed, if self.terminal { TERMINAL_INTERVAL } else { LOG_INTERVAL });
+        let write = self.file.is_some() && due(self.written, FILE_INTERVAL);
+        if !print && !write {
+            return;
+        }
+        let snapshot = self.snapshot(now - self.started);
+        if print {
+            if self.terminal {
+                eprint!("\r{}\x1b[K", snapshot.line());
+            } else {
+                eprintln!("{}", snapshot.line());
+            }
+            self.printed = Some(now);
+        }
+        if let Some(file) = self.file.as_deref().filter(|_| write) {
+            if let Err(e) = write_snapshot(file, &snapshot) {
+                log::error!("Cannot write the progress file {}: {}", file, e);
+            }
+            self.written = Some(now);
+        }
+    }
+}
+
+/// Writes the snapshot to a temporary file first, so that readers never see a partial file
+fn write_snapshot(path: &str, snapshot: &Snapshot) -> Result<(), Box<dyn Error>> {
+    let temporary = format!("{}.tmp", path);
+    std::fs::write(&temporary, serde_json::to_string_pretty(snapshot)?)?;
+    std::fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+/// The progress of the current run; the analyses report nothing outside of a run, e.g. from the library API
+pub struct Progress {
+    run: Mutex<Option<Run>>,
+}
+
+impl Progress {
+    pub fn global() -> &'static Progress {
+        static PROGRESS: OnceLock<Progress> = OnceLock::new();
+        PROGRESS.get_or_init(|| Progress { run: Mutex::new(None) })
+    }
+
+    fn update(&self, force: bool, change: impl FnOnce(&mut Run)) {
+        let mut run = self.run.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(run) = run.as_mut() {
+            change(run);
+            run.report(force);
+        }
+    }
+}
+
+/// Starts a run over `total` projects, writing its progress to `file` if given
+pub fn start(total: usize, file: Option<&str>) {
+    let mut run = Progress::global().run.lock().unwrap_or_else(|e| e.into_inner());
+    let mut started = Run::new(total, file);
+    started.report(true);
+    *run = Some(started);
+}
+
+pub fn project_started(project: &str) {
+    Progress::global().update(false, |run| {
+        run.running.insert(project.to_string(), (0, 0));
+    });
+}
+
+/// A project starts analyzing one of its windows, with this many commits
+pub fn window(project: &str, window: usize, windows: usize, commits: usize) {
+    Progress::global().update(false, |run| {
+        run.running.insert(project.to_string(), (window, windows));
+        run.commits += commits;
+    });
+}
+
+pub fn project_finished(project: &str, succeeded: bool) {
+    Progress::global().update(true, |run| {
+        run.running.shift_remove(project);
+        run.done += 1;
+        if !succeeded {
+            run.failed += 1;
+        }
+    });
+}
+
+/// Ends the run, with a last status line in the log and a last write of the progress file
+pub fn finish() {
+    let mut run = Progress::global().run.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(mut finished) = run.take() {
+        finished.report(true);
+        if finished.terminal {
+            eprintln!();
+        }
+        log::info!("{}", finished.snapshot(finished.started.elapsed()).line());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_and_eta() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("progress.json");
+        let mut run = Run::new(4, path.to_str());
+        run.done = 1;
+        run.failed = 1;
+        // half way through its windows
+        run.running.insert("Hunter".to_string(), (3, 4));
+        run.running.insert("Kafka".to_string(), (0, 0));
+        run.commits = 300;
+
+        let snapshot = run.snapshot(Duration::from_secs(120));
+        assert_eq!(run.fraction(), 1.5 / 4.0);
+        assert_eq!(snapshot.eta_seconds, Some(200));
+        assert_eq!(snapshot.commits_per_second, 2.5);
+        assert_eq!(
+            snapshot.line(),
+            "1/4 projects (1 failed), 2.5 commits/s, elapsed 0h:02m:00s, ETA 0h:03m:20s | Hunter 3/4, Kafka 0/0"
+        );
+
+        write_snapshot(path.to_str().unwrap(), &snapshot).unwrap();
+        let written: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["done_projects"], 1);
+        assert_eq!(written["running"][0]["project"], "Hunter");
+        assert_eq!(written["eta_seconds"], 200);
+
+        let empty = Run::new(0, None).snapshot(Duration::from_secs(0));
+        assert_eq!(empty.eta_seconds, None);
+    }
+}
@@ -0,0 +1,74 @@
+// src/release_metrics.rs
+//! Releases of a project, counted from the repository's tags.
+use chrono::NaiveDate;
+use git2::Repository;
+
+use crate::metrics::{MetricProvider, Metrics, Window};
+use crate::utils::convert_time;
+
+/// The commit date of each tag of the repository, in the order of the tag names
+pub fn tag_dates(repo: &Repository) -> Vec<NaiveDate> {
+    repo.tag_names(None)
+        .map(|names| {
+            names
+                .iter()
+                .flatten()
+                .filter_map(|name| {
+                    let tag = repo.revparse_single(&format!("refs/tags/{}", name)).ok()?;
+                    let commit = tag.peel_to_commit().ok()?;
+                    let when = commit.committer().when();
+                    Some(convert_time(&when).date_naive())
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The number of releases of a window: the tags whose commit was made between the window's dates
+pub struct ReleasesProvider {
+    tag_dates: Vec<NaiveDate>,
+}
+
+impl ReleasesProvider {
+    pub fn new(repo: &Repository) -> Self {
+        ReleasesProvider { tag_dates: tag_dates(repo) }
+    }
+
+    fn releases_between(&self, start_date: NaiveDate, end_date: NaiveDate) -> usize {
+        self.tag_dates
+            .iter()
+            .filter(|date| start_date <= **date && **date <= end_date)
+            .count()
+    }
+}
+
+impl MetricProvider for ReleasesProvider {
+    fn family(&self) -> &'static str {
+        "releases"
+    }
+
+    fn compute(&mut self, window: &Window, metrics: &mut Metrics) -> Result<(), Box<dyn std::error::Error>> {
+        metrics.releases = self.releases_between(window.start_date, window.end_date);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_repo::{repository, TestCommit};
+
+    #[test]
+    fn test_releases_between() {
+        let dir = tempfile::tempdir().unwrap();
+        // 2021-03-15 and 2021-05-02, UTC
+        let release = |seconds, tag| TestCommit { seconds, message: tag, tag: Some(tag), ..TestCommit::default() };
+        let (repo, _) = repository(dir.path(), &[release(1615766400, "v1.0"), release(1619913600, "v1.1")]);
+
+        let releases = ReleasesProvider::new(&repo);
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        assert_eq!(releases.releases_between(date("2021-03-01"), date("2021-03-31")), 1);
+        assert_eq!(releases.releases_between(date("2021-04-01"), date("2021-04-30")), 0);
+        assert_eq!(releases.releases_between(date("2021-03-15"), date("2021-05-02")), 2);
+    }
+}
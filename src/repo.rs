@@ -1,5 +1,7 @@
-use crate::{convert_time, utils, Args};
-use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use crate::windows::{self, WindowScheme};
+use crate::utils::convert_time;
+use crate::{diagnostics, release_metrics, utils, Args};
+use chrono::{Datelike, NaiveDate};
 use git2::{Commit, DiffOptions, Error, Repository};
 use indexmap::map::Entry;
 use indexmap::{IndexMap, IndexSet};
@@ -49,6 +51,9 @@ impl<'a> Repo<'a> {
             let last_commit_date = Self::find_last_commit_timestamp(repo);
             log::info!("{}: Ignore start end dates option enabled. First commit timestamp: {}. Last commit timestamp: {}", project, first_commit_date, last_commit_date);
             (first_commit_date, last_commit_date)
+        } else if end_date.is_empty() {
+            // without an end date, the history up to the last commit
+            (start_date.to_string(), Self::find_last_commit_timestamp(repo))
         } else {
             (start_date.to_string(), end_date.to_string())
         };
@@ -232,47 +237,24 @@ impl<'a> Repo<'a> {
         }
     }
 
-    pub fn commits_to_inc_months_with_time_windows(
-        &self,
-        time_window: i64,
-        commits: &Vec<Commit<'a>>,
-    ) -> Result<IndexMap<usize, Vec<Commit<'a>>>, Error> {
-        let incubation_months = self.parse_date_to_inc_months_with_time_window(time_window);
-
-        let mut output = IndexMap::<usize, Vec<Commit<'a>>>::new();
-
-        // we add all our incubation months with no commits yet to the output
-        for (incubation_month, _) in &incubation_months {
-            output.insert(*incubation_month, vec![]);
-        }
-
-        let all_commits = commits.clone();
-
-        for commit in all_commits {
-            let commit_time = NaiveDateTime::from_timestamp(commit.committer().when().seconds(), 0);
-            let commit_date =
-                NaiveDate::from_ymd(commit_time.year(), commit_time.month(), commit_time.day());
-            let mut commit_inc_month = None;
-
-            for (month, inc_month_type) in &incubation_months {
-                if inc_month_type.start_date <= commit_date
-                    && commit_date <= inc_month_type.end_date
-                {
-                    commit_inc_month = Some(*month)
-                }
-            }
+    /// The windows of `scheme` between the project's start and end dates; release windows end at the commit
+    /// dates of the repository's tags
+    pub fn windows(&self, scheme: &WindowScheme) -> Result<IndexMap<usize, IncubationMonth>, Error> {
+        let release_dates = if *scheme == WindowScheme::Releases {
+            release_metrics::tag_dates(self.repo)
+        } else {
+            vec![]
+        };
+        scheme
+            .windows(self.start_date, self.end_date, &release_dates)
+            .map_err(|e| Error::from_str(&format!("{}: {}", self.project, e)))
+    }
 
-            // if we find commits in a particular incubation month, then we add it to our output list
-            match output.entry(commit_inc_month.unwrap()) {
-                Entry::Occupied(mut entry) => {
-                    entry.get_mut().push(commit);
-                }
-                Entry::Vacant(entry) => {
-                    entry.insert(vec![commit]);
-                }
-            }
-        }
-        Ok(output)
+    /// Commits per window; every window has an entry, and a commit in overlapping windows is in each of them
+    pub fn commits_per_window(&self, windows: &IndexMap<usize, IncubationMonth>) -> IndexMap<usize, Vec<Commit<'a>>> {
+        windows::group_by_window(windows, &self.commits, |commit| {
+            convert_time(&commit.committer().when()).date_naive()
+        })
     }
 
     /// Returns an index map of commits per incubation month; the key is the incubation month as an integer
@@ -373,109 +355,31 @@ impl<'a> Repo<'a> {
                 .join(",")
         );
 
-        if self.project == "FreeMarker" {
-            match self.checkout_commit("2.3-gae") {
-                Ok(()) => {
-                    self.update_repo_state_after_checkout(args)?;
-                    return Ok(());
-                }
-                Err(_e) => {
-                    log::error!(
-                        "{} - has a branch named 2.3-gae, but I cannot check it out",
-                        self.project
-                    );
-                    return Ok(());
-                }
-            };
-        } else if self.project == "Dubbo" {
-            match self.checkout_commit("3.0") {
-                Ok(()) => {
-                    self.update_repo_state_after_checkout(args)?;
-                    return Ok(());
-                }
-                Err(_e) => {
-                    log::error!(
-                        "{} - has a branch named 3.0, but I cannot check it out",
-                        self.project
-                    );
-                    return Ok(());
-                }
-            };
-        } else if self.project == "DolphinScheduler" {
-            match self.checkout_commit("dev") {
-                Ok(()) => {
-                    self.update_repo_state_after_checkout(args)?;
-                    return Ok(());
-                }
-                Err(_e) => {
-                    log::error!(
-                        "{} - has a branch named dev, but I cannot check it out",
-                        self.project
-                    );
-                    return Ok(());
-                }
-            };
-        } else if repo_branches.contains("master") {
-            match self.checkout_commit("master") {
-                Ok(()) => {
-                    self.update_repo_state_after_checkout(args)?;
-                    return Ok(());
-                }
-                Err(_e) => {
-                    log::error!(
-                        "{} - has a branch named master, but I cannot check it out",
-                        self.project
-                    );
-                    return Ok(());
-                }
-            };
-        } else if repo_branches.contains("main") {
-            match self.checkout_commit("main") {
-                Ok(()) => {
-                    self.update_repo_state_after_checkout(args)?;
-                    return Ok(());
-                }
-                Err(_e) => {
-                    log::error!(
-                        "{} - has a branch named main, but I cannot check it out",
-                        self.project
-                    );
-                    return Ok(());
-                }
-            };
-        } else if repo_branches.contains("trunk") {
-            match self.checkout_commit("trunk") {
-                Ok(()) => {
-                    self.update_repo_state_after_checkout(args)?;
-                    return Ok(());
-                }
-                Err(_e) => {
-                    log::error!(
-                        "{} - has a branch named main, but I cannot check it out",
-                        self.project
-                    );
-                    return Ok(());
-                }
-            };
-        } else if repo_branches.contains("develop") {
-            match self.checkout_commit("develop") {
+        let branch = match self.project {
+            "FreeMarker" => Some("2.3-gae"),
+            "Dubbo" => Some("3.0"),
+            "DolphinScheduler" => Some("dev"),
+            _ => ["master", "main", "trunk", "develop"]
+                .into_iter()
+                .find(|branch| repo_branches.contains(*branch)),
+        };
+        match branch {
+            Some(branch) => match self.checkout_commit(branch) {
                 Ok(()) => {
+                    diagnostics::branch(self.project, branch);
                     self.update_repo_state_after_checkout(args)?;
-                    return Ok(());
                 }
                 Err(_e) => {
-                    log::error!(
-                        "{} - has a branch named develop, but I cannot check it out",
-                        self.project
-                    );
-                    return Ok(());
+                    let message = format!("has a branch named {}, but I cannot check it out", branch);
+                    log::error!("{} - {}", self.project, message);
+                    diagnostics::error(self.project, "checkout", &message);
                 }
-            };
-        } else {
-            log::error!(
-                "{} - has no branch named master/main/trunk/develop... cannot reset to main branch",
-                self.project
-            );
+            },
+            None => {
+                let message = "has no branch named master/main/trunk/develop... cannot reset to main branch";
+                log::error!("{} - {}", self.project, message);
+                diagnostics::error(self.project, "checkout", message);
+            }
         }
 
         Ok(())
@@ -537,24 +441,6 @@ impl<'a> Repo<'a> {
         result
     }
 
-    /// Parses the incubation start and end dates to a list of incubation months,
-    /// where the time window defines the number of days for each incubation month
-    /// The returned data is a hash map with the incubation month as keys. The date reflects the
-    /// last date of the incubation month. Values are the incubation month index
-    /// For example: time window = 30; start date = 2010-01-01, end_date = 2010-03-05
-    /// The result will be: 1 -> 2010-01-01, 2010-01-30, 2 -> 2010-01-31 - 2010-03-01, 3 -> 2010-03-02 - 2010-03-05, .
-    /// This is an ordered HashMap
-    pub fn parse_date_to_inc_months_with_time_window(
-        &self,
-        time_window: i64,
-    ) -> IndexMap<usize, IncubationMonth> {
-        utils::parse_date_to_inc_months_with_time_window(
-            self.start_date,
-            self.end_date,
-            time_window,
-        )
-    }
-
     /// Parses the incubation start and end dates to a list of incubation months.
     /// The returned data is a hash map with the date as 20101 - Jan 2010, as keys
     /// and integers (incubation month) as values
@@ -598,7 +484,7 @@ impl<'a> Repo<'a> {
 
 #[cfg(test)]
 mod test {
-    use crate::{repo::IncubationMonth, Args};
+    use crate::{repo::IncubationMonth, windows::WindowScheme, Args};
     use structopt::StructOpt;
 
     use super::{Repo, Repository};
@@ -677,7 +563,7 @@ mod test {
         let repository = Repo::new(&git_repo, "test", start_date, end_date, "graduated", &args);
         let inc_months = repository
             .unwrap()
-            .parse_date_to_inc_months_with_time_window(10);
+            .windows(&WindowScheme::Days(10)).unwrap();
         println!("{:?}", inc_months);
         let mut expected = indexmap::IndexMap::<usize, IncubationMonth>::new();
         expected.insert(
@@ -726,7 +612,7 @@ mod test {
         let end_date = "2022-01-08";
         let repository =
             Repo::new(&git_repo, "test", start_date, end_date, "graduated", &args).unwrap();
-        let inc_months = repository.parse_date_to_inc_months_with_time_window(10);
+        let inc_months = repository.windows(&WindowScheme::Days(10)).unwrap();
         expected.clear();
         expected.insert(
             1,
@@ -742,7 +628,7 @@ mod test {
         let end_date = "2022-01-10";
         let repository =
             Repo::new(&git_repo, "test", start_date, end_date, "graduated", &args).unwrap();
-        let inc_months = repository.parse_date_to_inc_months_with_time_window(10);
+        let inc_months = repository.windows(&WindowScheme::Days(10)).unwrap();
         expected.clear();
         expected.insert(
             1,
@@ -758,7 +644,7 @@ mod test {
         let end_date = "2022-01-11";
         let repository =
             Repo::new(&git_repo, "test", start_date, end_date, "graduated", &args).unwrap();
-        let inc_months = repository.parse_date_to_inc_months_with_time_window(10);
+        let inc_months = repository.windows(&WindowScheme::Days(10)).unwrap();
         expected.clear();
         expected.insert(
             1,
@@ -793,14 +679,9 @@ mod test {
             &args,
         );
 
-        let commits = &actual.as_ref().unwrap().commits;
-        let commits_inc_months = actual
-            .as_ref()
-            .unwrap()
-            .commits_to_inc_months_with_time_windows(30, &commits);
+        let actual = actual.as_ref().unwrap();
+        let commits_inc_months = actual.commits_per_window(&actual.windows(&WindowScheme::Days(30)).unwrap());
         let actual_commits = commits_inc_months
-            .as_ref()
-            .unwrap()
             .iter()
             .map(|x| x.1)
             .flatten()
@@ -810,8 +691,6 @@ mod test {
         let expected_months = 4;
         let expected_nr_commits = 5;
         let keys = &commits_inc_months
-            .as_ref()
-            .unwrap()
             .keys()
             .collect::<Vec<_>>();
         assert_eq!(keys.len(), expected_months);
@@ -0,0 +1,392 @@
+// sentiment.rs
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::OnceLock;
+
+use indexmap::{IndexMap, IndexSet};
+use serde::Serialize;
+
+use crate::output::{self, OutputFormat};
+
+/// The lexicon and incivility list that ship with the tool. Files with the same name in the
+/// current working directory take precedence, so the full VADER lexicon can be dropped in.
+const DEFAULT_LEXICON: &str = include_str!("../sentiment_lexicon.txt");
+const DEFAULT_INCIVILITY: &str = include_str!("../incivility.txt");
+
+// Constants from the VADER paper (Hutto & Gilbert, 2014)
+const BOOSTER_INCREMENT: f64 = 0.293;
+const BOOSTER_DECREMENT: f64 = -0.293;
+const CAPS_INCREMENT: f64 = 0.733;
+const NEGATION_SCALAR: f64 = -0.74;
+const NORMALIZATION_ALPHA: f64 = 15.0;
+
+const NEGATIONS: [&str; 16] = [
+    "not", "no", "never", "none", "nothing", "nobody", "neither", "nor", "cannot", "cant",
+    "dont", "doesnt", "didnt", "isnt", "wasnt", "wont",
+];
+
+const BOOSTERS_INCREMENT: [&str; 14] = [
+    "absolutely", "completely", "extremely", "highly", "incredibly", "really", "so", "totally",
+    "very", "most", "more", "quite", "too", "especially",
+];
+
+const BOOSTERS_DECREMENT: [&str; 8] = [
+    "barely", "hardly", "kinda", "less", "little", "marginally", "slightly", "somewhat",
+];
+
+/// Sentiment and incivility scores of a single message
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct SentimentScores {
+    /// Normalized sum of the valences, from -1 (most negative) to 1 (most positive)
+    pub compound: f64,
+    /// Share of the text that is positive
+    pub positive: f64,
+    /// Share of the text that is negative
+    pub negative: f64,
+    /// Share of the text that is neutral
+    pub neutral: f64,
+    /// Number of terms from the incivility word list
+    pub incivility_terms: usize,
+}
+
+impl SentimentScores {
+    /// VADER's usual threshold for a negative message
+    pub fn is_negative(&self) -> bool {
+        self.compound <= -0.05
+    }
+
+    pub fn is_uncivil(&self) -> bool {
+        self.incivility_terms > 0
+    }
+}
+
+/// The sentiment of the messages of a window, for its metric columns
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SentimentSummary {
+    pub messages: usize,
+    /// Average compound score, 0 without messages
+    pub compound_avg: f64,
+    pub negative: usize,
+    pub uncivil: usize,
+}
+
+impl SentimentSummary {
+    pub fn of<'s>(scores: impl IntoIterator<Item = &'s SentimentScores>) -> Self {
+        let mut summary = SentimentSummary::default();
+        for s in scores {
+            summary.messages += 1;
+            summary.compound_avg += s.compound;
+            summary.negative += s.is_negative() as usize;
+            summary.uncivil += s.is_uncivil() as usize;
+        }
+        if summary.messages > 0 {
+            summary.compound_avg /= summary.messages as f64;
+        }
+        summary
+    }
+}
+
+pub struct SentimentAnalyzer {
+    lexicon: HashMap<String, f64>,
+    incivility: IndexSet<String>,
+}
+
+impl SentimentAnalyzer {
+    /// Builds an analyzer from a VADER-formatted lexicon (word, tab, valence) and an incivility list (one term per line)
+    pub fn from_sources(lexicon: &str, incivility: &str) -> Self {
+        let lexicon = lexicon
+            .lines()
+            .filter(|l| !l.starts_with('#') && !l.trim().is_empty())
+            .filter_map(|l| {
+                let mut parts = l.split('\t');
+                let word = parts.next()?.trim().to_lowercase();
+                let valence = parts.next()?.trim().parse::<f64>().ok()?;
+                Some((word, valence))
+            })
+            .collect::<HashMap<_, _>>();
+        let incivility = incivility
+            .lines()
+            .map(|l| l.trim().to_lowercase())
+            .filter(|l| !l.starts_with('#') && !l.is_empty())
+            .collect::<IndexSet<_>>();
+        Self {
+            lexicon,
+            incivility,
+        }
+    }
+
+    /// Loads `sentiment_lexicon.txt` and `incivility.txt` from the current working directory,
+    /// falling back to the lists that ship with the tool
+    pub fn new() -> Self {
+        let lexicon = std::fs::read_to_string("sentiment_lexicon.txt")
+            .unwrap_or_else(|_| DEFAULT_LEXICON.to_string());
+        let incivility = std::fs::read_to_string("incivility.txt")
+            .unwrap_or_else(|_| DEFAULT_INCIVILITY.to_string());
+        Self::from_sources(&lexicon, &incivility)
+    }
+
+    /// A shared analyzer, so the lexicon is only loaded once per run
+    pub fn global() -> &'static SentimentAnalyzer {
+        static ANALYZER: OnceLock<SentimentAnalyzer> = OnceLock::new();
+        ANALYZER.get_or_init(SentimentAnalyzer::new)
+    }
+
+    fn booster(word: &str) -> f64 {
+        if BOOSTERS_INCREMENT.contains(&word) {
+            BOOSTER_INCREMENT
+        } else if BOOSTERS_DECREMENT.contains(&word) {
+            BOOSTER_DECREMENT
+        } else {
+            0.0
+        }
+    }
+
+    fn is_negation(word: &str) -> bool {
+        NEGATIONS.contains(&word) || word.ends_with("n't")
+    }
+
+    /// Scores a message with a simplified VADER: lexicon valences adjusted for boosters, negations,
+    /// capitalization, "but" and exclamation marks
+    pub fn score(&self, text: &str) -> SentimentScores {
+        let tokens = text
+            .split_whitespace()
+            .map(|t| t.trim_matches(|c: char| !c.is_alphanumeric() && c != '\''))
+            .filter(|t| !t.is_empty())
+            .collect::<Vec<_>>();
+        if tokens.is_empty() {
+            return SentimentScores::default();
+        }
+        let lowercase = tokens
+            .iter()
+            .map(|t| t.to_lowercase())
+            .collect::<Vec<_>>();
+        let is_shouting = |t: &str| {
+            t.len() > 1 && t.chars().all(|c| !c.is_lowercase()) && t.chars().any(|c| c.is_alphabetic())
+        };
+        let caps_differential =
+            tokens.iter().copied().any(is_shouting) && !tokens.iter().copied().all(is_shouting);
+
+        let mut valences = vec![0.0; tokens.len()];
+        for (i, word) in lowercase.iter().enumerate() {
+            let mut valence = match self.lexicon.get(word) {
+                Some(v) => *v,
+                None => continue,
+            };
+            if caps_differential && is_shouting(tokens[i]) {
+                valence += CAPS_INCREMENT * valence.signum();
+            }
+            for distance in 1..=3 {
+                if i < distance {
+                    break;
+                }
+                let previous = lowercase[i - distance].as_str();
+                let damping = match distance {
+                    1 => 1.0,
+                    2 => 0.95,
+                    _ => 0.9,
+                };
+                valence += Self::booster(previous) * damping * valence.signum();
+                if Self::is_negation(previous) {
+                    valence *= NEGATION_SCALAR;
+                }
+            }
+            valences[i] = valence;
+        }
+
+        // the sentiment after "but" dominates the one before it
+        if let Some(but) = lowercase.iter().position(|w| w == "but") {
+            for (i, v) in valences.iter_mut().enumerate() {
+                if i < but {
+                    *v *= 0.5;
+                } else if i > but {
+                    *v *= 1.5;
+                }
+            }
+        }
+
+        let mut sum = valences.iter().sum::<f64>();
+        let exclamations = text.matches('!').count().min(4) as f64;
+        if sum != 0.0 {
+            sum += exclamations * 0.292 * sum.signum();
+        }
+        let compound = (sum / (sum * sum + NORMALIZATION_ALPHA).sqrt()).clamp(-1.0, 1.0);
+
+        let positive_sum = valences.iter().filter(|v| **v > 0.0).map(|v| v + 1.0).sum::<f64>();
+        let negative_sum = valences.iter().filter(|v| **v < 0.0).map(|v| v.abs() + 1.0).sum::<f64>();
+        let neutral_count = valences.iter().filter(|v| **v == 0.0).count() as f64;
+        let total = positive_sum + negative_sum + neutral_count;
+
+        // terms and phrases match whole tokens, so "shut up" does not match "shut upstream"
+        let incivility_terms = self
+            .incivility
+            .iter()
+            .map(|term| {
+                let words = term.split_whitespace().collect::<Vec<_>>();
+                lowercase.windows(words.len()).filter(|window| window.iter().zip(&words).all(|(w, t)| w == t)).count()
+            })
+            .sum();
+
+        SentimentScores {
+            compound,
+            positive: positive_sum / total,
+            negative: negative_sum / total,
+            neutral: neutral_count / total,
+            incivility_terms,
+        }
+    }
+}
+
+impl Default for SentimentAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A row of the per-message scores table, written for validating the scores by hand
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct MessageScore {
+    pub project: String,
+    /// email, issue, comment or commit
+    pub source: String,
+    pub id: String,
+    pub author: String,
+    /// The window the message belongs to: an incubation month, or a yyyy-mm month
+    pub window: String,
+    pub date: String,
+    #[serde(flatten)]
+    pub scores: SentimentScores,
+}
+
+/// Sentiment aggregated per developer and window
+#[derive(Debug, Default, Serialize)]
+pub struct DeveloperSentiment {
+    pub project: String,
+    pub author: String,
+    pub window: String,
+    pub messages: usize,
+    pub compound_avg: f64,
+    pub negative_messages: usize,
+    pub uncivil_messages: usize,
+    pub incivility_terms: usize,
+}
+
+pub fn aggregate_by_developer(scores: &[MessageScore]) -> Vec<DeveloperSentiment> {
+    let mut output = IndexMap::<(String, String), DeveloperSentiment>::new();
+    for s in scores {
+        let entry = output
+            .entry((s.author.clone(), s.window.clone()))
+            .or_insert_with(|| DeveloperSentiment {
+                project: s.project.clone(),
+                author: s.author.clone(),
+                window: s.window.clone(),
+                ..Default::default()
+            });
+        entry.messages += 1;
+        entry.compound_avg += s.scores.compound;
+        if s.scores.is_negative() {
+            entry.negative_messages += 1;
+        }
+        if s.scores.is_uncivil() {
+            entry.uncivil_messages += 1;
+        }
+        entry.incivility_terms += s.scores.incivility_terms;
+    }
+    output
+        .into_values()
+        .map(|mut d| {
+            d.compound_avg /= d.messages as f64;
+            d
+        })
+        .collect()
+}
+
+/// Writes the per-message scores and their per-developer, per-window aggregation to the `scores_stem` and
+/// `developers_stem` tables
+pub fn write_scores(
+    scores: &[MessageScore],
+    scores_stem: &str,
+    developers_stem: &str,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    output::write_table(scores_stem, format, Some(&MessageScore::default()), scores.iter().cloned())?;
+    output::write_table(
+        developers_stem,
+        format,
+        Some(&DeveloperSentiment::default()),
+        aggregate_by_developer(scores),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn analyzer() -> SentimentAnalyzer {
+        SentimentAnalyzer::from_sources(DEFAULT_LEXICON, DEFAULT_INCIVILITY)
+    }
+
+    #[test]
+    fn test_polarity() {
+        let a = analyzer();
+        assert!(a.score("This patch is great").compound > 0.05);
+        assert!(a.score("This patch is terrible").compound < -0.05);
+        assert_eq!(a.score("The build runs on Java 8").compound, 0.0);
+        assert_eq!(a.score("").compound, 0.0);
+    }
+
+    #[test]
+    fn test_negation_boosters_and_emphasis() {
+        let a = analyzer();
+        let great = a.score("This patch is great").compound;
+        assert!(a.score("This patch is not great").compound < 0.0);
+        assert!(a.score("This patch is very great").compound > great);
+        assert!(a.score("This patch is GREAT").compound > great);
+        assert!(a.score("This patch is great!!!").compound > great);
+        // the part after "but" dominates
+        assert!(a.score("The idea is good but the code is terrible").compound < 0.0);
+    }
+
+    #[test]
+    fn test_proportions_and_incivility() {
+        let a = analyzer();
+        let scores = a.score("What the hell, this stupid test is broken");
+        assert_eq!(scores.incivility_terms, 2);
+        assert!(scores.is_uncivil());
+        // only whole words and phrases count
+        assert_eq!(a.score("Shut upstream down; the stupidity-check and hellish tests pass").incivility_terms, 0);
+        assert_eq!(a.score("Please, shut up. Shut... up!").incivility_terms, 2);
+        assert!(scores.is_negative());
+        assert!((scores.positive + scores.negative + scores.neutral - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_aggregate_by_developer() {
+        let a = analyzer();
+        let row = |author: &str, window: &str, text: &str| MessageScore {
+            project: "test".to_string(),
+            source: "comment".to_string(),
+            id: "".to_string(),
+            author: author.to_string(),
+            window: window.to_string(),
+            date: "".to_string(),
+            scores: a.score(text),
+        };
+        let scores = vec![
+            row("alice", "1", "thanks, great work"),
+            row("alice", "1", "this is stupid"),
+            row("bob", "1", "ok"),
+            row("alice", "2", "nice"),
+        ];
+        let aggregated = aggregate_by_developer(&scores);
+        assert_eq!(aggregated.len(), 3);
+        assert_eq!(aggregated[0].author, "alice");
+        assert_eq!(aggregated[0].messages, 2);
+        assert_eq!(aggregated[0].uncivil_messages, 1);
+        assert_eq!(aggregated[0].negative_messages, 1);
+
+        let summary = SentimentSummary::of(scores.iter().map(|s| &s.scores));
+        assert_eq!((summary.messages, summary.negative, summary.uncivil), (4, 1, 1));
+        assert_eq!(SentimentSummary::of(&[]), SentimentSummary::default());
+    }
+}
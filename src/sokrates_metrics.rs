@@ -1,10 +1,18 @@
-use indexmap::IndexMap;
-use serde::Serialize;
+use git2::{BranchType, Commit, Repository, WorktreeAddOptions, WorktreePruneOptions};
+use indexmap::{IndexMap, IndexSet};
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::process::Command;
-
-use crate::utils;
-#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+use crate::code_analysis;
+use crate::metrics::{Imputation, MetricProvider, Metrics, Nullable, Window};
+use crate::{utils, Args};
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 pub struct SokratesMetrics {
     most_complex_unit_loc: f64,
     most_complex_unit_mcabe_index: f64,
@@ -182,21 +190,112 @@ impl From<IndexMap<String, f64>> for SokratesMetrics {
     }
 }
 
+/// How Sokrates runs: the JVM arguments, the wall-clock limit of each of its commands, and where the parsed results
+/// are cached by commit
+#[derive(Clone, Debug, PartialEq)]
+pub struct SokratesSettings {
+    pub jvm_args: Vec<String>,
+    pub timeout: Duration,
+    /// The folder of the cached results, one `<sha>.json` per analyzed commit; none to always run Sokrates
+    pub cache: Option<String>,
+}
+
+impl Default for SokratesSettings {
+    fn default() -> Self {
+        SokratesSettings {
+            jvm_args: vec!["-Xmx2g".to_string(), "-Xms2g".to_string()],
+            timeout: Duration::from_secs(3600),
+            cache: None,
+        }
+    }
+}
+
+impl SokratesSettings {
+    /// The settings of the `--sokrates-*` flags; the cache is in the output folder unless set elsewhere
+    pub fn from_args(args: &Args) -> Self {
+        let defaults = SokratesSettings::default();
+        SokratesSettings {
+            jvm_args: args
+                .flag_sokrates_jvm_args
+                .as_deref()
+                .map(|a| a.split_whitespace().map(String::from).collect())
+                .unwrap_or(defaults.jvm_args),
+            timeout: args.flag_sokrates_timeout.map(Duration::from_secs).unwrap_or(defaults.timeout),
+            cache: Some(args.flag_sokrates_cache.clone().unwrap_or_else(|| {
+                format!("{}/sokrates-cache", args.flag_output_folder.as_deref().unwrap_or("data"))
+            })),
+        }
+    }
+}
+
 pub struct Sokrates {
     java_path: String,
     path: String,
-    pub metrics: SokratesMetrics,
+    settings: SokratesSettings,
 }
 
 impl Sokrates {
     pub fn new(path: &str, java_path: String) -> Self {
+        Sokrates::with_settings(path, java_path, SokratesSettings::default())
+    }
+
+    pub fn with_settings(path: &str, java_path: String, settings: SokratesSettings) -> Self {
         Sokrates {
             java_path,
             path: path.to_string(),
-            metrics: SokratesMetrics::default(),
+            settings,
         }
     }
 
+    /// Runs a Sokrates command, killing it after the timeout. A failed command is an error with the end of its stderr
+    fn run(&self, command: &str, args: &[&str]) -> Result<Output, std::io::Error> {
+        let mut child = Command::new(&self.java_path)
+            .args(&self.settings.jvm_args)
+            .arg("-jar")
+            .arg("tools/sokrates.jar")
+            .arg(command)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // read the pipes while waiting, so that a chatty command cannot block on a full pipe
+        let stdout = child.stdout.take().map(read_pipe);
+        let stderr = child.stderr.take().map(read_pipe);
+
+        let started = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if started.elapsed() >= self.settings.timeout {
+                child.kill().ok();
+                child.wait().ok();
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("{} timed out after {}s", command, self.settings.timeout.as_secs()),
+                ));
+            }
+            std::thread::sleep(Duration::from_millis(200));
+        };
+        let output = Output {
+            status,
+            stdout: stdout.and_then(|t| t.join().ok()).unwrap_or_default(),
+            stderr: stderr.and_then(|t| t.join().ok()).unwrap_or_default(),
+        };
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let lines: Vec<&str> = stderr.lines().collect();
+            return Err(std::io::Error::other(format!(
+                "{} exited with {}: {}",
+                command,
+                output.status,
+                lines[lines.len().saturating_sub(STDERR_LINES)..].join("\n")
+            )));
+        }
+        Ok(output)
+    }
+
     pub fn extract_history(
         &self,
         project: &str,
@@ -209,18 +308,7 @@ impl Sokrates {
             month,
             hash
         );
-
-        let output = Command::new(&self.java_path)
-            .arg("-jar")
-            .arg("-Xmx2g")
-            .arg("-Xms2g")
-            .arg("tools/sokrates.jar")
-            .arg("extractGitHistory")
-            .arg("-analysisRoot")
-            .arg(self.path.as_str())
-            .output()?;
-
-        Ok(output)
+        self.run("extractGitHistory", &["-analysisRoot", self.path.as_str()])
     }
 
     pub fn init(
@@ -235,15 +323,7 @@ impl Sokrates {
             month,
             hash
         );
-        Ok(Command::new(&self.java_path)
-            .arg("-jar")
-            .arg("-Xmx2g")
-            .arg("-Xms2g")
-            .arg("tools/sokrates.jar")
-            .arg("init")
-            .arg("-srcRoot")
-            .arg(self.path.as_str())
-            .output()?)
+        self.run("init", &["-srcRoot", self.path.as_str()])
     }
 
     pub fn adjust_analysis(&self) -> Result<(), std::io::Error> {
@@ -299,30 +379,9 @@ impl Sokrates {
             month,
             hash
         );
-        Ok(Command::new(&self.java_path)
-            .arg("-jar")
-            .arg("-Xmx2g")
-            .arg("-Xms2g")
-            .arg("tools/sokrates.jar")
-            .arg("generateReports")
-            .arg("-confFile")
-            .arg(format!("{}/_sokrates/config.json", self.path).as_str())
-            .arg("-outputFolder")
-            .arg(format!("{}/_sokrates", self.path).as_str())
-            .output()?)
-    }
-
-    pub fn cleanup(&self, project: &str, month: &usize, hash: &str) -> Result<(), std::io::Error> {
-        log::info!(
-            "{} month: {} - sokrates cleanup at {}",
-            project,
-            month,
-            hash
-        );
-        std::fs::remove_dir_all(format!("{}/_sokrates", self.path))?;
-        std::fs::remove_file(format!("{}/git-history.txt", self.path))?;
-
-        Ok(())
+        let conf_file = format!("{}/_sokrates/config.json", self.path);
+        let output_folder = format!("{}/_sokrates", self.path);
+        self.run("generateReports", &["-confFile", &conf_file, "-outputFolder", &output_folder])
     }
 
     pub fn metrics(&self) -> Result<SokratesMetrics, std::io::Error> {
@@ -385,6 +444,257 @@ impl Sokrates {
     }
 }
 
+/// How many of the last stderr lines a failed Sokrates command reports
+const STDERR_LINES: usize = 20;
+
+fn read_pipe(mut pipe: impl Read + Send + 'static) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buffer = vec![];
+        pipe.read_to_end(&mut buffer).ok();
+        buffer
+    })
+}
+
+/// A worktree of the analyzed repository at a window's last commit, in its own temporary folder, where Sokrates
+/// writes its `_sokrates` folder and `git-history.txt`. Parallel analyses of the same repository thus never share
+/// files, and nothing is left in the repository: dropping the scratch prunes the worktree and its branch.
+struct Scratch<'r> {
+    repo: &'r Repository,
+    name: String,
+    path: PathBuf,
+    _dir: TempDir,
+}
+
+impl<'r> Scratch<'r> {
+    fn new(repo: &'r Repository, project: &str, month: usize, commit: &Commit) -> Result<Self, git2::Error> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "{}{}-{}-{}-{}",
+            SCRATCH_PREFIX,
+            project.replace(|c: char| !c.is_ascii_alphanumeric(), "_"),
+            month,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let dir = tempfile::tempdir().map_err(|e| git2::Error::from_str(&e.to_string()))?;
+        let path = dir.path().join(project.replace(['/', '\\'], "_"));
+        let branch = repo.branch(&name, commit, true)?;
+        let mut options = WorktreeAddOptions::new();
+        options.reference(Some(branch.get()));
+        if let Err(e) = repo.worktree(&name, &path, Some(&options)) {
+            repo.find_branch(&name, BranchType::Local).and_then(|mut b| b.delete()).ok();
+            return Err(e);
+        }
+        Ok(Scratch { repo, name, path, _dir: dir })
+    }
+
+    fn path(&self) -> &str {
+        self.path.to_str().unwrap_or("")
+    }
+}
+
+impl Drop for Scratch<'_> {
+    fn drop(&mut self) {
+        if let Ok(worktree) = self.repo.find_worktree(&self.name) {
+            worktree.prune(Some(WorktreePruneOptions::new().valid(true).working_tree(true))).ok();
+        }
+        if let Ok(mut branch) = self.repo.find_branch(&self.name, BranchType::Local) {
+            if branch.delete().is_err() {
+                log::error!("Cannot delete the Sokrates branch {}", self.name);
+            }
+        }
+    }
+}
+
+/// The prefix of the branches and worktrees of the scratches
+const SCRATCH_PREFIX: &str = "miner-sokrates-";
+
+/// Whether the process that created a scratch is gone. Where `/proc` is not available, processes are taken as
+/// running, and only the scratches whose folder was removed are stale.
+fn scratch_process_is_gone(name: &str) -> bool {
+    // miner-sokrates-{project}-{month}-{pid}-{counter}
+    let pid = name.rsplit('-').nth(1).and_then(|pid| pid.parse::<u32>().ok());
+    match pid {
+        Some(pid) if pid == std::process::id() => false,
+        Some(pid) => Path::new("/proc").is_dir() && !Path::new("/proc").join(pid.to_string()).exists(),
+        None => false,
+    }
+}
+
+/// Removes the scratch worktrees and branches that a killed or crashed run left in the repository: those of
+/// processes that are no longer running, and the worktrees whose folder is gone. The scratches of runs still
+/// analyzing the repository are kept.
+pub fn sweep_stale_scratches(repo: &Repository) {
+    let mut swept = IndexSet::new();
+    let worktrees = repo.worktrees().map(|names| names.iter().flatten().map(String::from).collect::<Vec<_>>());
+    for name in worktrees.unwrap_or_default().into_iter().filter(|n| n.starts_with(SCRATCH_PREFIX)) {
+        let worktree = match repo.find_worktree(&name) {
+            Ok(worktree) => worktree,
+            Err(_) => continue,
+        };
+        let folder_is_gone = worktree.validate().is_err();
+        if folder_is_gone || scratch_process_is_gone(&name) {
+            log::info!("Removing the stale Sokrates worktree {}", name);
+            let temporary = worktree.path().parent().map(Path::to_path_buf);
+            worktree.prune(Some(WorktreePruneOptions::new().valid(true).working_tree(true))).ok();
+            // the temporary folder of the scratch, left behind by the run
+            if let Some(temporary) = temporary {
+                std::fs::remove_dir(temporary).ok();
+            }
+            swept.insert(name);
+        }
+    }
+    let branches = repo.branches(Some(BranchType::Local)).map(|branches| {
+        branches.flatten().filter_map(|(b, _)| b.name().ok().flatten().map(String::from)).collect::<Vec<_>>()
+    });
+    for name in branches.unwrap_or_default().into_iter().filter(|n| n.starts_with(SCRATCH_PREFIX)) {
+        if swept.contains(&name) || scratch_process_is_gone(&name) {
+            if let Ok(mut branch) = repo.find_branch(&name, BranchType::Local) {
+                if branch.delete().is_err() {
+                    log::error!("Cannot delete the stale Sokrates branch {}", name);
+                }
+            }
+        }
+    }
+}
+
+/// The cache file of a commit's results. Analyses restricted to some languages are cached apart, per set of
+/// extensions, so that a change of `extensions.toml` does not reuse the results of the previous set.
+fn cache_file(cache: &str, hash: &str, extensions: Option<&IndexSet<String>>) -> PathBuf {
+    match extensions {
+        Some(extensions) => Path::new(cache).join(format!("{}-restricted-{}.json", hash, extensions_digest(extensions))),
+        None => Path::new(cache).join(format!("{}.json", hash)),
+    }
+}
+
+/// A digest of a set of extensions that does not depend on their order, nor on the Rust version (FNV-1a)
+fn extensions_digest(extensions: &IndexSet<String>) -> String {
+    let mut sorted: Vec<&String> = extensions.iter().collect();
+    sorted.sort();
+    let mut digest: u64 = 0xcbf29ce484222325;
+    for byte in sorted.iter().flat_map(|e| e.bytes().chain(std::iter::once(0))) {
+        digest ^= byte as u64;
+        digest = digest.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", digest)
+}
+
+fn read_cache(file: &Path) -> Option<SokratesMetrics> {
+    serde_json::from_str(&std::fs::read_to_string(file).ok()?).ok()
+}
+
+fn write_cache(file: &Path, metrics: &SokratesMetrics) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(folder) = file.parent() {
+        std::fs::create_dir_all(folder)?;
+    }
+    // through a temporary file, so that a parallel run never reads a partial file
+    let temporary = file.with_extension(format!("{}.tmp", std::process::id()));
+    std::fs::write(&temporary, serde_json::to_string(metrics)?)?;
+    std::fs::rename(&temporary, file)?;
+    Ok(())
+}
+
+/// What computes the Sokrates metrics: the Sokrates JAR, or the native analyzer of `code_analysis`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CodeAnalyzer {
+    #[default]
+    Sokrates,
+    Native,
+}
+
+impl std::str::FromStr for CodeAnalyzer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sokrates" => Ok(CodeAnalyzer::Sokrates),
+            "native" => Ok(CodeAnalyzer::Native),
+            other => Err(format!("unknown code analyzer {}, expected sokrates or native", other)),
+        }
+    }
+}
+
+/// Sokrates' size, complexity, duplication and file metrics of the source tree at the end of a window
+pub struct SokratesProvider {
+    java_path: String,
+    analyzer: CodeAnalyzer,
+    settings: SokratesSettings,
+}
+
+impl SokratesProvider {
+    pub fn new(java_path: &str, analyzer: CodeAnalyzer, settings: SokratesSettings) -> Self {
+        SokratesProvider { java_path: java_path.to_string(), analyzer, settings }
+    }
+
+    /// Runs Sokrates on a scratch worktree at the commit
+    fn analyze(&self, window: &Window, commit: &Commit) -> Result<SokratesMetrics, Box<dyn std::error::Error>> {
+        let (project, month) = (window.project, &window.month);
+        let hash = commit.id().to_string();
+        let scratch = Scratch::new(window.repo.repo, project, window.month, commit)
+            .map_err(|e| format!("cannot create the sokrates worktree: {}", e))?;
+        let sokrates = Sokrates::with_settings(scratch.path(), self.java_path.clone(), self.settings.clone());
+
+        sokrates
+            .extract_history(project, month, &hash)
+            .map_err(|e| format!("sokrates failed to extract history: {}", e))?;
+        sokrates.init(project, month, &hash).map_err(|e| format!("sokrates failed initialization: {}", e))?;
+        // remove some basic analysis on duplication, dependencies, caching source fiels
+        sokrates.adjust_analysis().ok();
+        if window.args.flag_restrict_languages {
+            // change the files we're analyzing, skip duplication, and skip caching source files
+            sokrates.adjust_files_to_be_analyzed().ok();
+        }
+        sokrates
+            .generate_reports(project, month, &hash)
+            .map_err(|e| format!("sokrates failed to generate reports: {}", e))?;
+        Ok(sokrates.metrics().map_err(|e| format!("cannot read the sokrates results: {}", e))?)
+    }
+}
+
+impl MetricProvider for SokratesProvider {
+    fn reads_source(&self) -> bool {
+        true
+    }
+
+    fn family(&self) -> &'static str {
+        "sokrates"
+    }
+
+    fn compute(&mut self, window: &Window, metrics: &mut Metrics) -> Result<(), Box<dyn std::error::Error>> {
+        let extensions = if window.args.flag_restrict_languages { Some(utils::find_lang_extensions()?) } else { None };
+        if self.analyzer == CodeAnalyzer::Native {
+            let sokrates_metrics = code_analysis::metrics(window.repo.repo, extensions.as_ref())
+                .map_err(|e| format!("the native code analysis failed: {}", e))?;
+            metrics.sokrates_metrics = Nullable(Some(sokrates_metrics));
+            return Ok(());
+        }
+        let commit = window.commits.last().ok_or("the window has no commits")?;
+        let cache = self
+            .settings
+            .cache
+            .as_deref()
+            .map(|cache| cache_file(cache, &commit.id().to_string(), extensions.as_ref()));
+        if let Some(cached) = cache.as_deref().and_then(read_cache) {
+            log::info!("{} month: {} - sokrates results cached at {}", window.project, window.month, commit.id());
+            metrics.sokrates_metrics = Nullable(Some(cached));
+            return Ok(());
+        }
+
+        let sokrates_metrics = self.analyze(window, commit)?;
+        if let Some(file) = cache {
+            if let Err(e) = write_cache(&file, &sokrates_metrics) {
+                log::error!("Cannot cache the sokrates results in {}: {}", file.display(), e);
+            }
+        }
+        metrics.sokrates_metrics = Nullable(Some(sokrates_metrics));
+        Ok(())
+    }
+
+    fn impute(&self, metrics: &mut Metrics, imputation: Imputation) {
+        metrics.sokrates_metrics = Nullable::imputed(imputation);
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -392,7 +702,7 @@ mod test {
     #[test]
     fn test_complex_unit_loc() {
         let java_path = crate::java_path();
-        let analysis_filename = format!("test_resources/analysisResults.json");
+        let analysis_filename = "test_resources/analysisResults.json";
         let sokrates = Sokrates::new("test_resources/git_repo", java_path);
 
         let data = std::fs::read_to_string(analysis_filename);
@@ -422,9 +732,91 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_failed_and_timed_out_commands() {
+        // a shell in place of java, whose "JVM arguments" are the script to run
+        let settings = |script: &str, timeout: u64| SokratesSettings {
+            jvm_args: vec!["-c".to_string(), script.to_string(), "--".to_string()],
+            timeout: Duration::from_secs(timeout),
+            cache: None,
+        };
+        let sokrates = Sokrates::with_settings(".", "/bin/sh".to_string(), settings("echo fine", 10));
+        assert!(sokrates.init("git_repo", &1, "hash").is_ok());
+
+        let sokrates = Sokrates::with_settings(".", "/bin/sh".to_string(), settings("echo boom >&2; exit 3", 10));
+        let error = sokrates.init("git_repo", &1, "hash").unwrap_err();
+        assert!(error.to_string().contains("exit status: 3"));
+        assert!(error.to_string().ends_with("boom"));
+
+        let sokrates = Sokrates::with_settings(".", "/bin/sh".to_string(), settings("sleep 10", 1));
+        let started = Instant::now();
+        let error = sokrates.init("git_repo", &1, "hash").unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_scratch_and_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let (repo, ids) = crate::test_repo::repository(
+            dir.path(),
+            &[crate::test_repo::TestCommit { files: vec![("Main.java", "class Main {}")], ..Default::default() }],
+        );
+        let oid = ids[0];
+        let commit = repo.find_commit(oid).unwrap();
+
+        let path = {
+            let scratch = Scratch::new(&repo, "apache/hunter", 1, &commit).unwrap();
+            assert_eq!(std::fs::read_to_string(Path::new(scratch.path()).join("Main.java")).unwrap(), "class Main {}");
+            std::fs::write(Path::new(scratch.path()).join("git-history.txt"), "").unwrap();
+            scratch.path().to_string()
+        };
+        // nothing is left, neither in the repository nor in the scratch folder
+        assert!(!Path::new(&path).exists());
+        assert!(!dir.path().join("git-history.txt").exists());
+        assert!(repo.worktrees().unwrap().is_empty());
+        assert_eq!(repo.branches(Some(BranchType::Local)).unwrap().count(), 1);
+
+        // a scratch of a run that was killed is swept, one of a running process is kept
+        let head = repo.head().unwrap().shorthand().unwrap().to_string();
+        let stale = format!("{}hunter-1-{}-0", SCRATCH_PREFIX, u32::MAX);
+        let running = format!("{}hunter-1-{}-0", SCRATCH_PREFIX, std::process::id());
+        let scratches = tempfile::tempdir().unwrap();
+        for name in [&stale, &running] {
+            let branch = repo.branch(name, &commit, true).unwrap();
+            let mut options = WorktreeAddOptions::new();
+            options.reference(Some(branch.get()));
+            repo.worktree(name, &scratches.path().join(name), Some(&options)).unwrap();
+        }
+        sweep_stale_scratches(&repo);
+        let worktrees = repo.worktrees().unwrap();
+        let worktrees: Vec<&str> = worktrees.iter().flatten().collect();
+        if Path::new("/proc").is_dir() {
+            assert_eq!(worktrees, vec![running.as_str()]);
+            assert!(repo.find_branch(&stale, BranchType::Local).is_err());
+            assert!(!scratches.path().join(&stale).exists());
+        }
+        assert!(repo.find_branch(&running, BranchType::Local).is_ok());
+        assert!(repo.find_branch(&head, BranchType::Local).is_ok());
+
+        let extensions: IndexSet<String> = ["java", "kt"].iter().map(|e| e.to_string()).collect();
+        let reordered: IndexSet<String> = ["kt", "java"].iter().map(|e| e.to_string()).collect();
+        let cache = dir.path().join("cache");
+        let file = cache_file(cache.to_str().unwrap(), &oid.to_string(), Some(&extensions));
+        assert_eq!(file, cache_file(cache.to_str().unwrap(), &oid.to_string(), Some(&reordered)));
+        assert!(file.file_name().unwrap().to_str().unwrap().starts_with(&format!("{}-restricted-", oid)));
+        let java_only: IndexSet<String> = ["java"].iter().map(|e| e.to_string()).collect();
+        assert_ne!(file, cache_file(cache.to_str().unwrap(), &oid.to_string(), Some(&java_only)));
+        assert!(cache_file(cache.to_str().unwrap(), &oid.to_string(), None).ends_with(format!("{}.json", oid)));
+        assert_eq!(read_cache(&file), None);
+        let metrics = SokratesMetrics::from(IndexMap::from([("lines_of_code_main".to_string(), 42.0)]));
+        write_cache(&file, &metrics).unwrap();
+        assert_eq!(read_cache(&file), Some(metrics));
+    }
+
     #[test]
     fn test_parse_json() {
-        let analysis_filename = format!("test_resources/analysisResults.json");
+        let analysis_filename = "test_resources/analysisResults.json";
         let java_path = crate::java_path();
 
         let sokrates = Sokrates::new("test_resources/git_repo", java_path);
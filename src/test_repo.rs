@@ -0,0 +1,60 @@
+// src/test_repo.rs
+//! Git repositories built commit by commit, for the tests of the analyses
+use git2::{Oid, Repository, RepositoryInitOptions, Signature, Time};
+use std::path::Path;
+
+/// A commit of a test repository: its author, time, message, the files it writes, and a tag
+pub struct TestCommit {
+    pub name: &'static str,
+    pub email: &'static str,
+    /// Seconds since the epoch
+    pub seconds: i64,
+    /// The author's UTC offset, in minutes
+    pub offset: i32,
+    pub message: &'static str,
+    /// The files written, by path from the working copy, and their contents
+    pub files: Vec<(&'static str, &'static str)>,
+    pub tag: Option<&'static str>,
+}
+
+impl Default for TestCommit {
+    fn default() -> Self {
+        TestCommit {
+            name: "dev",
+            email: "dev@example.org",
+            // 2021-03-15 08:00 UTC, a Monday
+            seconds: 1615795200,
+            offset: 0,
+            message: "commit",
+            files: vec![],
+            tag: None,
+        }
+    }
+}
+
+/// Initializes a repository on `main` in `path` and makes the commits in order, each on top of the previous
+/// one. Returns the repository and the ids of the commits.
+pub fn repository(path: &Path, commits: &[TestCommit]) -> (Repository, Vec<Oid>) {
+    let repo = Repository::init_opts(path, RepositoryInitOptions::new().initial_head("main")).unwrap();
+    let mut ids = vec![];
+    for commit in commits {
+        let mut index = repo.index().unwrap();
+        for (file, contents) in &commit.files {
+            let full_path = path.join(file);
+            std::fs::create_dir_all(full_path.parent().unwrap()).unwrap();
+            std::fs::write(full_path, contents).unwrap();
+            index.add_path(Path::new(file)).unwrap();
+        }
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::new(commit.name, commit.email, &Time::new(commit.seconds, commit.offset)).unwrap();
+        let parents = ids.last().map(|id| repo.find_commit(*id).unwrap()).into_iter().collect::<Vec<_>>();
+        let parent_refs: Vec<_> = parents.iter().collect();
+        let id = repo.commit(Some("HEAD"), &signature, &signature, commit.message, &tree, &parent_refs).unwrap();
+        if let Some(tag) = commit.tag {
+            repo.tag_lightweight(tag, &repo.find_object(id, None).unwrap(), false).unwrap();
+        }
+        ids.push(id);
+    }
+    (repo, ids)
+}
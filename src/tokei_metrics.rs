@@ -1,8 +1,117 @@
+use crate::code_analysis::is_test_path;
+use crate::metrics::{Imputation, MetricProvider, Metrics, Nullable, Window};
+use crate::utils::{directories, top_level_directories};
 use crate::{repo::Repo, Args};
+use serde::Serialize;
 use tokei::{Config, Language, Languages};
+
+/// The number of directories of the source tree
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DirectoryMetrics {
+    pub directories: usize,
+    pub top_level_dirs: usize,
+}
+
+/// Size of the source tree: Tokei's code, comment and blank lines, files and main programming language
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct CodeMetrics {
+    /// SLOC
+    pub code: usize,
+    /// The number of blank lines
+    pub blanks: usize,
+    /// The number of files
+    pub files: usize,
+    /// The number of comments
+    pub comments: usize,
+    /// The number of lines
+    pub lines: usize,
+    /// The programming language with the most code
+    pub programming_lang: String,
+}
+
+/// The mix of languages of the source tree, its comment density and its share of test code. These columns
+/// come after the other metrics, so the earlier columns keep their positions.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct LanguageMetrics {
+    /// The number of languages with code
+    pub languages: usize,
+    /// The Shannon entropy, in bits, of the shares of code of the languages: 0 for a single language, and
+    /// log2(languages) for code split evenly
+    pub language_entropy: f64,
+    /// The share of comments in the code and comment lines
+    pub comment_density: f64,
+    /// The share of code in test files, by path convention (test folders, `FooTest.java`, `test_foo.py`...)
+    pub test_code_ratio: f64,
+    /// The counts of each language, most code first, written to the `-languages` table
+    #[serde(skip)]
+    pub language_counts: Vec<LanguageCount>,
+}
+
+/// Tokei's counts of one language of the source tree
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct LanguageCount {
+    pub language: String,
+    pub code: usize,
+    pub comments: usize,
+    pub blanks: usize,
+    pub files: usize,
+}
+
+/// A row of the long-format `-languages` table: the counts of one language in one window
+#[derive(Debug, Serialize)]
+pub struct LanguageRow<'a> {
+    pub project: &'a str,
+    pub measurement_month: usize,
+    pub window_start_date: &'a str,
+    pub window_end_date: &'a str,
+    /// Whether the window's code was not observed, and the counts were carried forward from an earlier window
+    pub is_imputed: bool,
+    #[serde(flatten)]
+    pub counts: &'a LanguageCount,
+}
+
+/// The counts of the template row, which gives the columns of a table without rows
+static NO_COUNTS: LanguageCount = LanguageCount { language: String::new(), code: 0, comments: 0, blanks: 0, files: 0 };
+
+impl Default for LanguageRow<'_> {
+    fn default() -> Self {
+        LanguageRow {
+            project: "",
+            measurement_month: 0,
+            window_start_date: "",
+            window_end_date: "",
+            is_imputed: false,
+            counts: &NO_COUNTS,
+        }
+    }
+}
+
+impl<'a> LanguageRow<'a> {
+    /// The rows of the windows' languages; windows with empty code metrics have none, and the rows of windows
+    /// whose code metrics were imputed are flagged
+    pub fn of(project: &'a str, metrics: impl IntoIterator<Item = &'a Metrics>) -> Vec<LanguageRow<'a>> {
+        metrics
+            .into_iter()
+            .flat_map(|m| {
+                let languages = m.language_metrics.0.as_ref().map(|c| c.language_counts.as_slice()).unwrap_or_default();
+                let is_imputed = m.imputed_families.split(';').any(|family| family == "tokei");
+                languages.iter().map(move |counts| LanguageRow {
+                    project,
+                    measurement_month: m.measurement_month,
+                    window_start_date: &m.window_start_date,
+                    window_end_date: &m.window_end_date,
+                    is_imputed,
+                    counts,
+                })
+            })
+            .collect()
+    }
+}
+
 pub struct TokeiMetrics {
     stats: Language,
-    programming_lang: String,
+    languages: Vec<LanguageCount>,
+    test_code: usize,
 }
 
 impl<'a> TokeiMetrics {
@@ -21,27 +130,34 @@ impl<'a> TokeiMetrics {
 
             let mut languages = Languages::new();
 
-            languages.get_statistics(&vec![path], &vec![], &config);
-            let total = languages.total().clone();
+            languages.get_statistics(&[path], &[], &config);
+            let total = languages.total();
 
-            for (_, ref mut language) in &mut languages {
-                language.sort_by(tokei::Sort::Code);
+            let mut test_code = 0;
+            let mut counts = vec![];
+            for (language_type, language) in languages.iter().filter(|(_, l)| !l.reports.is_empty()) {
+                for report in &language.reports {
+                    let relative = report.name.strip_prefix(path).unwrap_or(&report.name);
+                    let relative: Vec<_> = relative.components().map(|c| c.as_os_str().to_string_lossy()).collect();
+                    if is_test_path(&relative.join("/")) {
+                        test_code += report.stats.code;
+                    }
+                }
+                counts.push(LanguageCount {
+                    language: language_type.name().to_string(),
+                    code: language.code,
+                    comments: language.comments,
+                    blanks: language.blanks,
+                    files: language.reports.len(),
+                });
             }
+            counts.sort_by(|a, b| b.code.cmp(&a.code).then_with(|| a.language.cmp(&b.language)));
 
-            let mut languages: Vec<_> = languages.iter().collect();
-            languages.sort_by(|a, b| b.1.code.cmp(&a.1.code));
-
-            if !languages.is_empty() {
-                Some(TokeiMetrics {
-                    stats: total,
-                    programming_lang: languages.first().unwrap().0.name().to_string(),
-                })
-            } else {
-                Some(TokeiMetrics {
-                    stats: total,
-                    programming_lang: "".to_string(),
-                })
-            }
+            Some(TokeiMetrics {
+                stats: total,
+                languages: counts,
+                test_code,
+            })
         } else {
             None
         }
@@ -63,11 +179,152 @@ impl<'a> TokeiMetrics {
         self.stats.lines()
     }
 
+    /// The number of files counted, of all languages
     pub fn files(&self) -> usize {
-        self.stats.children.values().map(Vec::len).sum::<usize>()
+        self.languages.iter().map(|l| l.files).sum()
     }
 
-    pub fn programming_language(self) -> String {
-        self.programming_lang
+    pub fn programming_language(&self) -> String {
+        self.languages.first().map(|l| l.language.clone()).unwrap_or_default()
+    }
+
+    pub fn test_code_ratio(&self) -> f64 {
+        share(self.test_code, self.code())
+    }
+
+    pub fn comment_density(&self) -> f64 {
+        share(self.comments(), self.code() + self.comments())
+    }
+
+    pub fn languages(self) -> Vec<LanguageCount> {
+        self.languages
+    }
+}
+
+fn share(part: usize, whole: usize) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+/// The Shannon entropy, in bits, of the languages' shares of code
+pub fn language_entropy(languages: &[LanguageCount]) -> f64 {
+    let total: usize = languages.iter().map(|l| l.code).sum();
+    let entropy: f64 = languages
+        .iter()
+        .filter(|l| l.code > 0)
+        .map(|l| {
+            let p = share(l.code, total);
+            -p * p.log2()
+        })
+        .sum();
+    // avoid -0.0 for a single language
+    entropy.max(0.0)
+}
+
+/// The `CodeMetrics` of the source tree at the end of a window
+pub struct TokeiProvider;
+
+impl MetricProvider for TokeiProvider {
+    fn reads_source(&self) -> bool {
+        true
+    }
+
+    fn family(&self) -> &'static str {
+        "tokei"
+    }
+
+    fn compute(&mut self, window: &Window, metrics: &mut Metrics) -> Result<(), Box<dyn std::error::Error>> {
+        let path = window.repo.repo.path().parent().and_then(|p| p.to_str()).ok_or("the repository has no working directory")?;
+        metrics.directory_metrics = Nullable(Some(DirectoryMetrics {
+            directories: directories(path),
+            top_level_dirs: top_level_directories(path),
+        }));
+        let mut code_metrics = CodeMetrics::default();
+        let mut language_metrics = LanguageMetrics::default();
+        if let Some(tm) = TokeiMetrics::new(window.repo, window.args) {
+            code_metrics.code = tm.code();
+            code_metrics.files = tm.files();
+            code_metrics.lines = tm.lines();
+            code_metrics.blanks = tm.blanks();
+            code_metrics.comments = tm.comments();
+            code_metrics.programming_lang = tm.programming_language();
+            language_metrics.comment_density = tm.comment_density();
+            language_metrics.test_code_ratio = tm.test_code_ratio();
+            let languages = tm.languages();
+            language_metrics.languages = languages.iter().filter(|l| l.code > 0).count();
+            language_metrics.language_entropy = language_entropy(&languages);
+            language_metrics.language_counts = languages;
+        }
+        metrics.code_metrics = Nullable(Some(code_metrics));
+        metrics.language_metrics = Nullable(Some(language_metrics));
+        Ok(())
+    }
+
+    fn impute(&self, metrics: &mut Metrics, imputation: Imputation) {
+        metrics.directory_metrics = Nullable::imputed(imputation);
+        metrics.code_metrics = Nullable::imputed(imputation);
+        metrics.language_metrics = Nullable::imputed(imputation);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(language: &str, code: usize) -> LanguageCount {
+        LanguageCount { language: language.to_string(), code, comments: 1, blanks: 2, files: 3 }
+    }
+
+    #[test]
+    fn test_language_entropy_and_rows() {
+        assert_eq!(language_entropy(&[]), 0.0);
+        assert_eq!(language_entropy(&[count("Java", 100)]), 0.0);
+        assert_eq!(language_entropy(&[count("Java", 50), count("Kotlin", 50), count("XML", 0)]), 1.0);
+        assert_eq!(language_entropy(&[count("Java", 25), count("Kotlin", 25), count("Go", 25), count("C", 25)]), 2.0);
+
+        let with_languages = Metrics {
+            measurement_month: 1,
+            language_metrics: Nullable(Some(LanguageMetrics {
+                language_counts: vec![count("Java", 80), count("Python", 20)],
+                ..LanguageMetrics::default()
+            })),
+            ..Metrics::default()
+        };
+        let empty = Metrics { measurement_month: 2, language_metrics: Nullable(None), ..Metrics::default() };
+        let carried = Metrics {
+            measurement_month: 3,
+            is_imputed: true,
+            imputed_families: "sokrates;tokei".to_string(),
+            ..with_languages.clone()
+        };
+        let metrics = [with_languages, empty, carried];
+        let rows = LanguageRow::of("hunter", &metrics);
+        assert_eq!(rows.len(), 4);
+        assert_eq!((rows[1].measurement_month, rows[1].counts), (1, &count("Python", 20)));
+        assert!(!rows[1].is_imputed);
+        assert_eq!((rows[3].measurement_month, rows[3].is_imputed), (3, true));
+
+        let cells = crate::output::cells(&rows[0]).unwrap();
+        let columns: Vec<&str> = cells.iter().map(|(column, _)| column.as_str()).collect();
+        assert_eq!(
+            columns,
+            vec![
+                "project",
+                "measurement_month",
+                "window_start_date",
+                "window_end_date",
+                "is_imputed",
+                "language",
+                "code",
+                "comments",
+                "blanks",
+                "files"
+            ]
+        );
+        let template = crate::output::cells(&LanguageRow::default()).unwrap();
+        assert_eq!(template.len(), columns.len());
     }
 }
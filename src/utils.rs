@@ -1,4 +1,4 @@
-use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDate, Offset, TimeZone, Utc};
 use git2::Time;
 use indexmap::{IndexMap, IndexSet};
 use serde::Deserialize;
@@ -11,6 +11,12 @@ pub fn convert_time(time: &Time) -> DateTime<Utc> {
     tz.timestamp(time.seconds(), 0).with_timezone(&Utc)
 }
 
+/// The time in its own time zone, e.g. the author's local time of a commit; `convert_time` converts it to UTC
+pub fn local_time(time: &Time) -> DateTime<FixedOffset> {
+    let tz = FixedOffset::east_opt(time.offset_minutes() * 60).unwrap_or_else(|| Utc.fix());
+    tz.timestamp_opt(time.seconds(), 0).unwrap()
+}
+
 pub fn is_hidden(entry: &DirEntry) -> bool {
     entry
         .file_name()
@@ -148,7 +154,7 @@ struct Languages {
     types: Vec<String>,
 }
 
-pub(crate) fn find_lang_extensions() -> Result<IndexSet<String>, serde_json::Error> {
+pub fn find_lang_extensions() -> Result<IndexSet<String>, serde_json::Error> {
     let mut extensions: IndexSet<String> = IndexSet::new();
 
     let exts_filename = "extensions.toml";
@@ -252,10 +258,40 @@ pub fn inc_month_to_date(start_date: &str, inc_month: usize) -> String {
     }
 }
 
+/// Hours elapsed between two RFC 3339 timestamps
+pub fn hours_between(from: &str, to: &str) -> Option<f64> {
+    let from = DateTime::parse_from_rfc3339(from).ok()?;
+    let to = DateTime::parse_from_rfc3339(to).ok()?;
+    Some((to - from).num_seconds() as f64 / 3600.0)
+}
+
+/// Median of the values, 0.0 when there are none
+pub fn median(mut values: Vec<f64>) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn test_median_and_hours_between() {
+        assert_eq!(median(vec![]), 0.0);
+        assert_eq!(median(vec![3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(median(vec![4.0, 1.0, 2.0, 3.0]), 2.5);
+        assert_eq!(hours_between("2021-03-01T10:00:00Z", "2021-03-02T12:30:00Z"), Some(26.5));
+        assert_eq!(hours_between("2021-03-01", "2021-03-02T12:30:00Z"), None);
+    }
+
     #[test]
     fn test_directories() {
         assert_eq!(4, directories("test_resources/test_directories"));
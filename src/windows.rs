@@ -0,0 +1,293 @@
+// src/windows.rs
+//! Measurement windows shared by every analysis (Stats, DevStats, emails and the forge API analysis), so all
+//! output tables number their windows the same way. Windows are numbered from 1, like incubation months,
+//! and are cut at the project's start and end dates.
+use std::str::FromStr;
+
+use chrono::{Datelike, Duration, NaiveDate};
+use indexmap::IndexMap;
+
+use crate::repo::IncubationMonth;
+use crate::{utils, Args};
+
+/// How the project's lifetime is split into windows, parsed from `--window`
+#[derive(Clone, Debug, PartialEq)]
+pub enum WindowScheme {
+    /// Calendar months, the default incubation months
+    Monthly,
+    /// Consecutive windows of N days from the start date, as with `--time-window`
+    Days(i64),
+    /// ISO weeks, from Monday to Sunday
+    Weekly,
+    /// Calendar quarters
+    Quarterly,
+    /// Windows of `size` days starting every `stride` days; they overlap when the stride is shorter than the size
+    Sliding { size: i64, stride: i64 },
+    /// From the start date to the first release, then from one release to the next; the last one runs until
+    /// the end date. A window ends on the commit date of its release's tag.
+    Releases,
+}
+
+impl WindowScheme {
+    /// The scheme of `--window`, else N-day windows with `--time-window`, else calendar months
+    pub fn from_args(args: &Args) -> WindowScheme {
+        match (&args.flag_window, args.flag_time_window) {
+            (Some(scheme), _) => scheme.clone(),
+            (None, Some(days)) => WindowScheme::Days(days),
+            (None, None) => WindowScheme::Monthly,
+        }
+    }
+
+    pub fn is_monthly(&self) -> bool {
+        *self == WindowScheme::Monthly
+    }
+
+    /// A short name of the scheme, for logs and file names
+    pub fn label(&self) -> String {
+        match self {
+            WindowScheme::Monthly => "monthly".to_string(),
+            WindowScheme::Days(days) => format!("{}days-time-window", days),
+            WindowScheme::Weekly => "weekly".to_string(),
+            WindowScheme::Quarterly => "quarterly".to_string(),
+            WindowScheme::Sliding { size, stride } => format!("{}days-sliding-window-every-{}days", size, stride),
+            WindowScheme::Releases => "releases".to_string(),
+        }
+    }
+
+    /// The windows between the start and end dates (Y-m-d). `release_dates` are only used by release windows;
+    /// those outside the dates are ignored. An empty or malformed date is an error.
+    pub fn windows(
+        &self,
+        start_date: &str,
+        end_date: &str,
+        release_dates: &[NaiveDate],
+    ) -> Result<IndexMap<usize, IncubationMonth>, String> {
+        let parse = |date: &str, name: &str| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|_| format!("invalid {} date '{}', expected YYYY-MM-DD", name, date))
+        };
+        let start = parse(start_date, "start")?;
+        let end = parse(end_date, "end")?;
+        let ranges = match self {
+            WindowScheme::Days(days) => {
+                return Ok(utils::parse_date_to_inc_months_with_time_window(start_date, end_date, *days));
+            }
+            WindowScheme::Monthly => periods(start, end, first_day_of_next_month),
+            WindowScheme::Weekly => periods(start, end, |date| {
+                date + Duration::days(7 - date.weekday().num_days_from_monday() as i64)
+            }),
+            WindowScheme::Quarterly => periods(start, end, first_day_of_next_quarter),
+            WindowScheme::Sliding { size, stride } => sliding(start, end, *size, *stride),
+            WindowScheme::Releases => between_releases(start, end, release_dates),
+        };
+        Ok(ranges
+            .into_iter()
+            .enumerate()
+            .map(|(index, (start_date, end_date))| {
+                let incubation_month = index + 1;
+                (incubation_month, IncubationMonth { start_date, end_date, incubation_month })
+            })
+            .collect())
+    }
+}
+
+impl FromStr for WindowScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let days = |part: &str| {
+            part.parse::<i64>()
+                .ok()
+                .filter(|days| *days > 0)
+                .ok_or_else(|| format!("invalid number of days {} in window {}", part, s))
+        };
+        let parts: Vec<&str> = s.split(':').map(str::trim).collect();
+        match parts.as_slice() {
+            ["month"] => Ok(WindowScheme::Monthly),
+            ["week"] => Ok(WindowScheme::Weekly),
+            ["quarter"] => Ok(WindowScheme::Quarterly),
+            ["release"] => Ok(WindowScheme::Releases),
+            ["days", size] => Ok(WindowScheme::Days(days(size)?)),
+            ["sliding", size, stride] => Ok(WindowScheme::Sliding { size: days(size)?, stride: days(stride)? }),
+            _ => Err(format!(
+                "unknown window {}, expected month, week, quarter, release, days:N or sliding:N:M",
+                s
+            )),
+        }
+    }
+}
+
+/// The numbers of the windows that contain a date: none outside the start and end dates, and several when
+/// sliding windows overlap
+pub fn windows_of(windows: &IndexMap<usize, IncubationMonth>, date: NaiveDate) -> Vec<usize> {
+    windows
+        .values()
+        .filter(|window| window.start_date <= date && date <= window.end_date)
+        .map(|window| window.incubation_month)
+        .collect()
+}
+
+/// Groups items by the windows that contain their date. Every window gets an entry, empty when it has no
+/// items, and an item in overlapping windows is in each of them.
+pub fn group_by_window<T: Clone>(
+    windows: &IndexMap<usize, IncubationMonth>,
+    items: &[T],
+    date_of: impl Fn(&T) -> NaiveDate,
+) -> IndexMap<usize, Vec<T>> {
+    let mut output: IndexMap<usize, Vec<T>> = windows.keys().map(|window| (*window, vec![])).collect();
+    for item in items {
+        for window in windows_of(windows, date_of(item)) {
+            output[&window].push(item.clone());
+        }
+    }
+    output
+}
+
+/// Consecutive periods from `start` to `end`, where `next` gives the first day of the period after a date
+fn periods(start: NaiveDate, end: NaiveDate, next: impl Fn(NaiveDate) -> NaiveDate) -> Vec<(NaiveDate, NaiveDate)> {
+    let mut ranges = vec![];
+    let mut from = start;
+    while from <= end {
+        let next_from = next(from);
+        ranges.push((from, (next_from - Duration::days(1)).min(end)));
+        from = next_from;
+    }
+    ranges
+}
+
+fn first_day_of_next_month(date: NaiveDate) -> NaiveDate {
+    let (year, month) = if date.month() == 12 { (date.year() + 1, 1) } else { (date.year(), date.month() + 1) };
+    NaiveDate::from_ymd_opt(year, month, 1).unwrap()
+}
+
+fn first_day_of_next_quarter(date: NaiveDate) -> NaiveDate {
+    let month0 = (date.month0() / 3 + 1) * 3;
+    let (year, month0) = if month0 == 12 { (date.year() + 1, 0) } else { (date.year(), month0) };
+    NaiveDate::from_ymd_opt(year, month0 + 1, 1).unwrap()
+}
+
+/// Windows of `size` days starting every `stride` days, until the first one that reaches the end date
+fn sliding(start: NaiveDate, end: NaiveDate, size: i64, stride: i64) -> Vec<(NaiveDate, NaiveDate)> {
+    let mut ranges = vec![];
+    let mut from = start;
+    while from <= end {
+        let to = from + Duration::days(size - 1);
+        ranges.push((from, to.min(end)));
+        if to >= end {
+            break;
+        }
+        from += Duration::days(stride);
+    }
+    ranges
+}
+
+fn between_releases(start: NaiveDate, end: NaiveDate, release_dates: &[NaiveDate]) -> Vec<(NaiveDate, NaiveDate)> {
+    let mut releases: Vec<NaiveDate> = release_dates
+        .iter()
+        .copied()
+        .filter(|date| start <= *date && *date < end)
+        .collect();
+    releases.sort();
+    releases.dedup();
+    let mut ranges = vec![];
+    let mut from = start;
+    for release in releases {
+        ranges.push((from, release));
+        from = release + Duration::days(1);
+    }
+    if from <= end {
+        ranges.push((from, end));
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn ranges(windows: &IndexMap<usize, IncubationMonth>) -> Vec<(String, String)> {
+        windows
+            .values()
+            .map(|w| (w.start_date.to_string(), w.end_date.to_string()))
+            .collect()
+    }
+
+    fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        expected.iter().map(|(s, e)| (s.to_string(), e.to_string())).collect()
+    }
+
+    #[test]
+    fn test_parse_window_scheme() {
+        assert_eq!("month".parse::<WindowScheme>(), Ok(WindowScheme::Monthly));
+        assert_eq!("days:30".parse::<WindowScheme>(), Ok(WindowScheme::Days(30)));
+        assert_eq!("sliding:90:30".parse::<WindowScheme>(), Ok(WindowScheme::Sliding { size: 90, stride: 30 }));
+        assert!("sliding:90".parse::<WindowScheme>().is_err());
+        assert!("days:0".parse::<WindowScheme>().is_err());
+        assert!("year".parse::<WindowScheme>().is_err());
+    }
+
+    #[test]
+    fn test_calendar_windows() {
+        let months = WindowScheme::Monthly.windows("2010-01-15", "2010-03-05", &[]).unwrap();
+        assert_eq!(
+            ranges(&months),
+            pairs(&[("2010-01-15", "2010-01-31"), ("2010-02-01", "2010-02-28"), ("2010-03-01", "2010-03-05")])
+        );
+
+        // 2021-01-01 is a Friday
+        let weeks = WindowScheme::Weekly.windows("2021-01-01", "2021-01-12", &[]).unwrap();
+        assert_eq!(
+            ranges(&weeks),
+            pairs(&[("2021-01-01", "2021-01-03"), ("2021-01-04", "2021-01-10"), ("2021-01-11", "2021-01-12")])
+        );
+
+        let quarters = WindowScheme::Quarterly.windows("2020-11-15", "2021-05-01", &[]).unwrap();
+        assert_eq!(
+            ranges(&quarters),
+            pairs(&[("2020-11-15", "2020-12-31"), ("2021-01-01", "2021-03-31"), ("2021-04-01", "2021-05-01")])
+        );
+    }
+
+    #[test]
+    fn test_sliding_windows_overlap() {
+        let windows = WindowScheme::Sliding { size: 10, stride: 5 }.windows("2021-01-01", "2021-01-18", &[]).unwrap();
+        assert_eq!(
+            ranges(&windows),
+            pairs(&[("2021-01-01", "2021-01-10"), ("2021-01-06", "2021-01-15"), ("2021-01-11", "2021-01-18")])
+        );
+        assert_eq!(windows_of(&windows, date("2021-01-07")), vec![1, 2]);
+        assert_eq!(windows_of(&windows, date("2021-01-19")), Vec::<usize>::new());
+
+        let days = vec![date("2021-01-02"), date("2021-01-12"), date("2021-01-17")];
+        let grouped = group_by_window(&windows, &days, |d| *d);
+        assert_eq!(grouped[&1].len(), 1);
+        assert_eq!(grouped[&2].len(), 1);
+        assert_eq!(grouped[&3].len(), 2);
+    }
+
+    #[test]
+    fn test_release_windows() {
+        let releases = [date("2021-03-15"), date("2021-01-10"), date("2020-12-01"), date("2021-03-15")];
+        let windows = WindowScheme::Releases.windows("2021-01-01", "2021-06-30", &releases).unwrap();
+        assert_eq!(
+            ranges(&windows),
+            pairs(&[("2021-01-01", "2021-01-10"), ("2021-01-11", "2021-03-15"), ("2021-03-16", "2021-06-30")])
+        );
+        let windows = WindowScheme::Releases.windows("2021-01-01", "2021-06-30", &[]).unwrap();
+        assert_eq!(ranges(&windows), pairs(&[("2021-01-01", "2021-06-30")]));
+    }
+
+    #[test]
+    fn test_invalid_dates() {
+        assert_eq!(
+            WindowScheme::Monthly.windows("", "2021-06-30", &[]).unwrap_err(),
+            "invalid start date '', expected YYYY-MM-DD"
+        );
+        let error = WindowScheme::Days(30).windows("2021-01-01", "30/06/2021", &[]).unwrap_err();
+        assert_eq!(error, "invalid end date '30/06/2021', expected YYYY-MM-DD");
+    }
+}